@@ -0,0 +1,90 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use mandelbrot_compute::{
+    colorize::Colorizer,
+    palette::{builtin_palettes, ColorMapping},
+    Computer, DVec2, GPUInterface, SampleLocation, UVec2,
+};
+
+const SIZES: [u32; 3] = [512, 1024, 4096];
+const ITERATIONS: [i32; 3] = [100, 1_000, 10_000];
+
+/// `Computer::run_blocking` end to end, submit to completion, for every size and iteration
+/// count. The view straddles the boundary of the set so both escaping and capped pixels are
+/// timed. Compare runs with `cargo bench -- --save-baseline before` and `--baseline before`.
+fn compute(c: &mut Criterion) {
+    let gpu = match GPUInterface::headless() {
+        Ok(gpu) => gpu,
+        Err(e) => {
+            eprintln!("Skipping compute benchmarks: {}", e);
+            return;
+        }
+    };
+    let view = SampleLocation::new(DVec2::new(-0.745, 0.11), 0.05);
+    for size in SIZES {
+        // One computer per size, so texture allocation isn't part of the measurement.
+        let mut computer = Computer::new(UVec2::new(size, size), &gpu);
+        let mut group = c.benchmark_group(format!("compute {}x{}", size, size));
+        group.throughput(Throughput::Elements(size as u64 * size as u64));
+        if size >= 4096 {
+            group.sample_size(10);
+        }
+        for iterations in ITERATIONS {
+            let params = view.to_mandlebrot_params(iterations);
+            group.bench_with_input(
+                BenchmarkId::new(format!("{:?}", computer.precision()), iterations),
+                &params,
+                |b, params| {
+                    b.iter(|| {
+                        computer.run_blocking(&gpu, params);
+                    })
+                },
+            );
+        }
+        group.finish();
+    }
+}
+
+/// Per-frame cost of colorizing with the bind group made once, as the renderer does, against
+/// rebuilding it every frame as it used to. Small, so the fixed overhead dominates like it
+/// does at high frame rates.
+fn frame_overhead(c: &mut Criterion) {
+    let gpu = match GPUInterface::headless() {
+        Ok(gpu) => gpu,
+        Err(e) => {
+            eprintln!("Skipping frame overhead benchmarks: {}", e);
+            return;
+        }
+    };
+    let size = UVec2::new(256, 256);
+    let computer = Computer::new(size, &gpu);
+    let mut colorizer = Colorizer::new(
+        &gpu,
+        size,
+        &computer.iteration_view,
+        computer.histogram.cdf_buffer(),
+        computer.range.range_buffer(),
+    );
+    colorizer.set_palette(&gpu, &builtin_palettes()[0]);
+    let color_params = ColorMapping::default().color_params();
+    let mut group = c.benchmark_group("colorize 256x256");
+    group.bench_function("cached bind group", |b| {
+        b.iter(|| {
+            colorizer.run(&gpu, &color_params);
+            gpu.device.poll(wgpu::Maintain::Wait);
+        })
+    });
+    group.bench_function("bind group per frame", |b| {
+        b.iter(|| {
+            let view = computer
+                .iteration_texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            colorizer.set_input(&gpu, &view);
+            colorizer.run(&gpu, &color_params);
+            gpu.device.poll(wgpu::Maintain::Wait);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, compute, frame_overhead);
+criterion_main!(benches);
@@ -0,0 +1,197 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    colorize::OutputReadback, computer::compute_work_group_count, gpu_interface::GPUInterface,
+    math::UVec2, readback::Readback,
+};
+
+/// Jittered frames averaged before the image is left alone.
+pub const ACCUMULATION_FRAMES: u32 = 32;
+
+const ACCUMULATION_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Uniforms of `accumulate.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct AccumulateParams {
+    frame_index: u32,
+    _padding: [u32; 3],
+}
+
+/// Sub-pixel offset in pixels for accumulated frame `frame_index`: the Halton (2, 3)
+/// sequence, which covers the pixel evenly at any count, shifted by an offset derived from
+/// `seed` and wrapped back into the pixel. Seed 0 doesn't shift, so its frame 0 is unjittered.
+pub fn jitter(frame_index: u32, seed: u32) -> [f32; 2] {
+    // Odd multipliers scatter nearby seeds; the top 24 bits fit an f32 exactly.
+    let offset = |multiplier: u32| (seed.wrapping_mul(multiplier) >> 8) as f32 / (1 << 24) as f32;
+    [
+        (halton(frame_index, 2) + offset(0x9E37_79B9)).fract(),
+        (halton(frame_index, 3) + offset(0x85EB_CA6B)).fract(),
+    ]
+}
+
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Running average of colorized frames for temporal anti-aliasing. Each frame blends into a
+/// history texture with equal weight, so still views converge towards a supersampled image
+/// without any one frame costing more.
+pub struct Accumulator {
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+    params_buffer: wgpu::Buffer,
+    history_texture: wgpu::Texture,
+    output_texture: wgpu::Texture,
+    output_view: wgpu::TextureView,
+    texture_size: wgpu::Extent3d,
+}
+
+impl Accumulator {
+    /// Averages the frames colorized into `frame_view`, a `size` texture.
+    pub fn new(gpu: &GPUInterface, size: UVec2, frame_view: &wgpu::TextureView) -> Accumulator {
+        let texture_size = wgpu::Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        };
+        let create_texture = |label, usage| {
+            gpu.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: texture_size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: ACCUMULATION_TEXTURE_FORMAT,
+                usage,
+            })
+        };
+        let history_texture = create_texture(
+            "Accumulation history texture",
+            wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+        let output_texture = create_texture(
+            "Accumulation output texture",
+            wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+        let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let params_buffer = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Accumulate params buffer"),
+                contents: bytemuck::bytes_of(&AccumulateParams::zeroed()),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let shader = gpu
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Accumulate shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("shaders/accumulate.wgsl").into()),
+            });
+        let pipeline = gpu
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Accumulate pipeline"),
+                layout: None,
+                module: &shader,
+                entry_point: "main",
+            });
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Accumulate bind group"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(frame_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(
+                        &history_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&output_view),
+                },
+            ],
+        });
+
+        Accumulator {
+            pipeline,
+            bind_group,
+            params_buffer,
+            history_texture,
+            output_texture,
+            output_view,
+            texture_size,
+        }
+    }
+
+    /// Records blending the current frame in as frame `frame_index` of the average; 0 starts
+    /// over. The result is copied to the history for the next frame.
+    pub fn encode(&self, gpu: &GPUInterface, encoder: &mut wgpu::CommandEncoder, frame_index: u32) {
+        gpu.queue.write_buffer(
+            &self.params_buffer,
+            0,
+            bytemuck::bytes_of(&AccumulateParams {
+                frame_index,
+                _padding: [0; 3],
+            }),
+        );
+        {
+            let (dispatch_width, dispatch_height) = compute_work_group_count(
+                (self.texture_size.width, self.texture_size.height),
+                (16, 16),
+            );
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Accumulate pass"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+        }
+        encoder.copy_texture_to_texture(
+            self.output_texture.as_image_copy(),
+            self.history_texture.as_image_copy(),
+            self.texture_size,
+        );
+    }
+
+    /// View of the average so far.
+    pub fn output_view(&self) -> &wgpu::TextureView {
+        &self.output_view
+    }
+
+    /// Records a copy of the average so far into `encoder`, like `Colorizer::encode_readback`.
+    pub fn encode_readback(
+        &self,
+        gpu: &GPUInterface,
+        encoder: &mut wgpu::CommandEncoder,
+        readback: &mut Readback,
+    ) -> Option<OutputReadback> {
+        OutputReadback::encode(
+            gpu,
+            encoder,
+            readback,
+            &self.output_texture,
+            UVec2::new(self.texture_size.width, self.texture_size.height),
+            ACCUMULATION_TEXTURE_FORMAT,
+        )
+    }
+}
@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+use crate::{
+    colorize::{ColorParams, Colorizer},
+    computer::{ComputeSetup, Computer, MandelbrotParams},
+    gpu_interface::GPUInterface,
+    math::UVec2,
+    palette::{MappingMode, Palette},
+};
+
+/// Renders a precomputed sequence of views to `frame_00000.png`, `frame_00001.png`, ... in a
+/// directory, one frame per `step`. Like `BracketJob` it has its own `Computer` and
+/// `Colorizer`, so the live view keeps running while it records.
+pub struct AnimationJob {
+    computer: Computer,
+    colorizer: Colorizer,
+    color_params: ColorParams,
+    frames: Vec<MandelbrotParams>,
+    next: usize,
+    pub directory: PathBuf,
+}
+
+impl AnimationJob {
+    /// Creates `directory` if needed. `frames` hold one set of params per frame, in order.
+    /// `formula` is the one `FractalKind::Custom` params render.
+    pub fn new(
+        gpu: &GPUInterface,
+        size: UVec2,
+        frames: Vec<MandelbrotParams>,
+        color_params: ColorParams,
+        setup: &ComputeSetup,
+        palette: &Palette,
+        directory: PathBuf,
+    ) -> anyhow::Result<AnimationJob> {
+        std::fs::create_dir_all(&directory)
+            .with_context(|| format!("could not create {}", directory.display()))?;
+        let computer = setup.build(gpu, size)?;
+        let colorizer = Colorizer::new(
+            gpu,
+            size,
+            &computer.iteration_view,
+            computer.histogram.cdf_buffer(),
+            computer.range.range_buffer(),
+        );
+        colorizer.set_palette(gpu, palette);
+        Ok(AnimationJob {
+            computer,
+            colorizer,
+            color_params,
+            frames,
+            next: 0,
+            directory,
+        })
+    }
+
+    /// Number of frames written and the total.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.next, self.frames.len())
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next >= self.frames.len()
+    }
+
+    /// Renders and saves the next frame.
+    pub fn step(&mut self, gpu: &GPUInterface) -> anyhow::Result<()> {
+        let params = &self.frames[self.next];
+        self.computer.run(gpu, params);
+        if self.color_params.mapping_mode == MappingMode::Histogram as u32 {
+            self.computer.build_histogram(gpu, params.max_iterations);
+        }
+        if self.color_params.auto_range == 1 {
+            self.computer.build_range(gpu);
+        }
+        self.colorizer.run(gpu, &self.color_params);
+        let path = self.directory.join(format!("frame_{:05}.png", self.next));
+        self.colorizer
+            .read_output(gpu)?
+            .save(&path)
+            .with_context(|| format!("could not write {}", path.display()))?;
+        self.next += 1;
+        Ok(())
+    }
+}
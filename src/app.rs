@@ -1,72 +1,2735 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
 use winit::{
     dpi::{PhysicalPosition, PhysicalSize},
-    event::{VirtualKeyCode, WindowEvent},
-    event_loop::EventLoop,
-    window::{Window, WindowBuilder},
+    event::{
+        ElementState, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+    },
+    window::{Fullscreen, Window},
 };
 
-use crate::{
-    computer::{Computer, SampleLocation},
-    gpu_interface::GPUInterface,
-    math::UVec2,
-    renderer::Renderer,
+#[cfg(feature = "hot-reload")]
+use mandelbrot_compute::hot_reload::ShaderWatcher;
+use mandelbrot_compute::{
+    accumulate::{self, ACCUMULATION_FRAMES},
+    animation::AnimationJob,
+    bracket::BracketJob,
+    colorize::{ColorParams, OutputReadback},
+    compare,
+    computer::{
+        AutoIterations, ColoringMode, Computer, FractalKind, FractalMode, MandelbrotParams,
+        SampleLocation, Tiling, DEFAULT_STRIPE_DENSITY, DEFAULT_STRIPE_MIX, ESCAPE_RADIUS,
+        PREVIEW_SIZE, SUPERSAMPLING_FACTORS,
+    },
+    config::Config,
+    cpu_reference,
+    deep::{DeepComputer, PERTURBATION_ZOOM},
+    detail::{block_center, DetailReduction, DetailScores},
+    formula::CustomFormula,
+    gpu_interface::{GPUInterface, GpuInitError},
+    history::History,
+    keymap::{Action, Keymap},
+    math::{
+        content_to_target, format_fixed, format_length, grid_spacing, letterbox, nice_length,
+        target_to_content, DVec2, FVec2, UVec2,
+    },
+    palette::{
+        builtin_palettes, load_palette_dir, newest_palette_file, palette_file_name, ColorMapping,
+        MappingMode, Palette, PaletteCycling, PaletteEditor, PALETTE_DIR,
+    },
+    poster::PosterJob,
+    postprocess::Bloom,
+    probe::{PixelProbe, PixelSample},
+    readback::Readback,
+    renderer::{FrameSource, Renderer},
+    screenshot::{self, screenshot_path},
+    state::{
+        bookmarks_path, thumbnail_path, view_state_path, Bookmarks, StartupOptions, ViewState,
+    },
+    stats::{FrameStats, StatsReduction},
+    touch::{TouchGesture, TouchTracker},
 };
 
+use crate::timing::FrameTimer;
+
+pub const WINDOW_TITLE: &str = "GPU_Automata";
+
+/// Largest difference in smooth iteration count between the GPU and the CPU reference
+/// that is still attributed to f32 rounding rather than a shader bug.
+const PROBE_TOLERANCE: f64 = 0.01;
+
+/// Height in logical pixels of on-screen labels.
+const LABEL_SIZE: f32 = 16.0;
+
+/// Crosshair arm length and the gap left at its center, in pixels.
+const CROSSHAIR_SIZE: f32 = 10.0;
+const CROSSHAIR_GAP: f32 = 3.0;
+/// Longest the scale bar gets as a fraction of the window width; it is rounded down to a
+/// length of 1, 2 or 5 times a power of ten.
+const SCALE_BAR_FRACTION: f64 = 0.25;
+
+/// Colors of the grid lines and of the wider axes drawn over them.
+const GRID_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.25];
+const AXIS_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.85];
+const AXIS_WIDTH: f32 = 2.0;
+
+/// Range the iteration count is clamped to.
+const MIN_ITERATIONS: i32 = 16;
+const MAX_ITERATIONS: i32 = 1_000_000;
+/// Limit of the manual multiplier on top of automatic iterations, in either direction.
+const MAX_ITERATION_MULTIPLIER: f32 = 64.0;
+
+/// Multibrot exponent range and the step the ; key changes it by.
+const MIN_POWER: f32 = 1.25;
+const MAX_POWER: f32 = 8.0;
+const POWER_STEP: f32 = 0.25;
+
+/// Bailout radius range; backslash doubles and Shift+backslash halves it. The radius is
+/// squared while iterating, so the upper bound keeps that well inside f32.
+const MIN_ESCAPE_RADIUS: f32 = 2.0;
+const MAX_ESCAPE_RADIUS: f32 = 65536.0;
+
+/// Stripe frequency range, stepped by one with N, and the step H changes the stripe mix by.
+const MIN_STRIPE_DENSITY: f32 = 1.0;
+const MAX_STRIPE_DENSITY: f32 = 32.0;
+const STRIPE_MIX_STEP: f32 = 0.125;
+
+/// Iteration count of the Julia preview, which always shows the whole set.
+const PREVIEW_ITERATIONS: i32 = 200;
+
+/// Amount Ctrl+arrow keys move the Julia constant by; Shift makes it ten times finer.
+const JULIA_C_STEP: f32 = 0.01;
+
+/// Trackpad scroll distance in pixels that counts as one mouse wheel notch.
+const PIXELS_PER_NOTCH: f64 = 50.0;
+
+/// Iteration counts rendered by a bracketed screenshot unless overridden on the command line.
+const DEFAULT_BRACKET_ITERATIONS: [i32; 4] = [500, 1000, 2000, 5000];
+
+/// Length of a recorded zoom animation unless overridden on the command line.
+const DEFAULT_ANIMATION_FRAMES: u32 = 300;
+const DEFAULT_ANIMATION_FPS: f32 = 30.0;
+
+/// How long the view has to stay still before a draft is replaced by a full-resolution frame.
+const DRAFT_SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// How long the cursor has to rest before the pixel under it is read back for the title.
+const HOVER_SETTLE_TIME: Duration = Duration::from_millis(100);
+
+/// Pixel probes that can be in flight at once, hover and click probes together.
+const PROBE_BUFFERS: usize = 4;
+
+/// Shortest time between two window title updates.
+const TITLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Most steps of a Ctrl+clicked orbit that are drawn.
+const ORBIT_LENGTH: i32 = 500;
+
+/// Views kept for Backspace to go back through.
+const HISTORY_LENGTH: usize = 100;
+
+/// Duration of the animated jump to a bookmark.
+const BOOKMARK_FLIGHT_SECONDS: f64 = 1.0;
+
+/// Edge of the bookmark thumbnails saved next to the bookmarks file.
+const THUMBNAIL_SIZE: u32 = 128;
+
+/// Size of a poster rendered with Shift+S unless overridden on the command line.
+const DEFAULT_POSTER_SIZE: u32 = 8192;
+
+/// Relief light height range and the factor one Alt+wheel notch changes it by.
+const MIN_LIGHT_HEIGHT: f32 = 0.1;
+const MAX_LIGHT_HEIGHT: f32 = 10.0;
+const LIGHT_HEIGHT_STEP: f32 = 1.25;
+
+/// Arrow key steps per second while an arrow is held, see `SampleLocation::pan_steps`.
+const PAN_STEPS_PER_SECOND: f32 = 10.0;
+
+/// Doublings of the zoom per second while a zoom key is held.
+const ZOOM_DOUBLINGS_PER_SECOND: f32 = 2.0;
+
+/// Speed of held navigation keys with Shift, for fine positioning.
+const FINE_NAVIGATION_SPEED: f32 = 0.1;
+
+/// An animated jump between two views, advanced by `App::update`.
+struct BookmarkFlight {
+    from: SampleLocation,
+    to: SampleLocation,
+    started: Instant,
+}
+
+/// Navigation keys currently held. `App::update` moves the view every frame they are down,
+/// scaled by the frame time, so holding a key glides instead of following the OS key repeat.
+#[derive(Default)]
+struct InputState {
+    left: bool,
+    right: bool,
+    up: bool,
+    down: bool,
+    zoom_in: bool,
+    zoom_out: bool,
+}
+
+impl InputState {
+    /// Records the key bound to `action` going down or up. Other actions are ignored.
+    fn set(&mut self, action: Action, pressed: bool) {
+        let held = match action {
+            Action::PanLeft => &mut self.left,
+            Action::PanRight => &mut self.right,
+            Action::PanUp => &mut self.up,
+            Action::PanDown => &mut self.down,
+            Action::ZoomIn => &mut self.zoom_in,
+            Action::ZoomOut => &mut self.zoom_out,
+            _ => return,
+        };
+        *held = pressed;
+    }
+
+    fn is_active(&self) -> bool {
+        self.pan_direction().is_some() || self.zoom_direction() != 0.0
+    }
+
+    /// Unit screen direction of the held arrows, so two arrows don't pan √2 faster than one.
+    /// None when no arrow is held or opposite ones cancel out.
+    fn pan_direction(&self) -> Option<FVec2> {
+        let axis = |negative: bool, positive: bool| positive as i32 as f32 - negative as i32 as f32;
+        let direction = FVec2::new(axis(self.left, self.right), axis(self.up, self.down));
+        let length = direction.length();
+        (length > 0.0).then(|| direction / length)
+    }
+
+    /// -1 while zooming in, 1 while zooming out, 0 otherwise.
+    fn zoom_direction(&self) -> f32 {
+        self.zoom_out as i32 as f32 - self.zoom_in as i32 as f32
+    }
+}
+
 pub struct App {
     pub gpu: GPUInterface,
     pub computer: Computer,
+    deep_computer: DeepComputer,
     pub renderer: Renderer,
     pub sample_location: SampleLocation,
+    pub fractal_kind: FractalKind,
+    pub fractal_mode: FractalMode,
+    /// Escape time, an orbit trap, distance estimation or stripes, cycled with F10.
+    pub coloring_mode: ColoringMode,
+    /// Light the set as a relief on top of the coloring mode, toggled with L. Alt with the
+    /// mouse moves the light: the cursor sets its direction, the wheel its height.
+    pub relief: bool,
+    /// Stripe frequency and mix of `ColoringMode::Stripes`, changed with N and H.
+    pub stripe_density: f32,
+    pub stripe_mix: f32,
+    pub light_angle: f32,
+    pub light_height: f32,
+    /// Constant c used in Julia mode.
+    pub julia_c: FVec2,
+    /// The Mandelbrot view to return to when leaving Julia mode.
+    mandelbrot_view: Option<SampleLocation>,
+    /// Exponent d of z^d + c.
+    pub power: f32,
+    /// Bailout radius of the iteration.
+    pub escape_radius: f32,
+    /// Skip iterating the main cardioid and period-2 bulb where that is valid.
+    pub interior_check: bool,
+    /// Wait for the next fractal compute and print how long it took, set when toggling
+    /// `interior_check` so the difference can be compared.
+    time_next_compute: bool,
+    /// Frame time overlay toggled with F3. Keeps redrawing while shown so it measures
+    /// throughput rather than idle time.
+    show_timing: bool,
+    frame_timer: FrameTimer,
+    /// Crosshair at the window center and a scale bar, toggled with X.
+    show_scale: bool,
+    /// Real and imaginary axes over a grid at round intervals, toggled with G.
+    show_grid: bool,
+    /// Show the Julia set of the point under the cursor in an inset while in Mandelbrot mode.
+    pub julia_preview: bool,
+    /// Params the preview texture was last computed with.
+    preview_params: Option<MandelbrotParams>,
+    /// Show the low-resolution draft texture while the view is changing, toggled with W.
+    pub progressive: bool,
+    /// Params the draft texture was last computed with.
+    draft_params: Option<MandelbrotParams>,
+    /// Params of the last frame drawn, draft or not.
+    shown_params: Option<MandelbrotParams>,
+    /// When the view last differed from the frame before, see `DRAFT_SETTLE_TIME`.
+    view_changed_at: Instant,
+    /// Average jittered frames of a still view for anti-aliasing, toggled with F8.
+    pub accumulation: bool,
+    /// Shifts the jitter of accumulated frames, see `accumulate::jitter`.
+    pub accumulation_seed: u32,
+    /// Frames averaged so far for the current view and `accumulated_colors`.
+    accumulated: u32,
+    accumulated_colors: Option<ColorParams>,
+    /// Iteration count used while automatic iterations are off.
+    pub max_iterations: i32,
+    pub auto_iterations: AutoIterations,
+    /// Manual adjustment applied on top of automatic iterations.
+    pub iteration_multiplier: f32,
+    /// Zoom factor applied per mouse wheel notch towards the cursor; below 1 zooms in.
+    pub wheel_zoom_factor: f32,
+    /// Arrow key pan distance given to every view, see `SampleLocation::set_move_speed`.
+    move_speed: f32,
+    pub palettes: Vec<Palette>,
+    pub palette_index: usize,
+    palette_editor: Option<PaletteEditor>,
+    pub color_mapping: ColorMapping,
+    pub palette_cycling: PaletteCycling,
+    /// When `update` last ran, for advancing animations by real time.
+    last_update: Instant,
+    /// Set while the window has a zero size; nothing is animated or drawn then.
+    minimized: bool,
+    /// F11 or Alt+Enter was pressed; `update` toggles fullscreen since it has the window.
+    fullscreen_requested: bool,
+    /// Inner size and position of the window before it went fullscreen, restored after.
+    windowed_geometry: Option<(PhysicalSize<u32>, Option<PhysicalPosition<i32>>)>,
+    cursor_position: PhysicalPosition<f64>,
+    /// Whether the cursor is over the window, so the title shows what is under it.
+    cursor_in_window: bool,
+    /// When the cursor last moved, see `HOVER_SETTLE_TIME`.
+    cursor_moved_at: Instant,
+    /// Readback of the pixel under the resting cursor, and the latest result it gave.
+    hover_probe: Option<PixelProbe>,
+    hover: Option<(UVec2, MandelbrotParams, PixelSample)>,
+    /// Cursor position at the last drag update while the left button is held.
+    drag_position: Option<PhysicalPosition<f64>>,
+    /// Where the right button went down for a zoom rectangle that is being dragged out.
+    selection_start: Option<PhysicalPosition<f64>>,
+    modifiers: ModifiersState,
+    input: InputState,
+    /// Which action each key performs, from the `[keys]` config section.
+    keymap: Keymap,
+    touches: TouchTracker,
+    /// Held keys or a zoom animation moved the view in the last update.
+    navigating: bool,
+    probe: Option<PixelProbe>,
+    /// Buffers `probe` and `hover_probe` are copied into.
+    probes: Readback,
+    /// Orbit of the point last Ctrl+clicked, drawn over the fractal until Escape.
+    orbit: Option<Vec<DVec2>>,
+    /// S was pressed; the colors are copied with the next full-resolution frame.
+    screenshot_requested: bool,
+    /// Fixed size of the computed image from --compute-size; None follows the window.
+    compute_size: Option<UVec2>,
+    screenshot: Option<OutputReadback>,
+    stats_reduction: StatsReduction,
+    /// The latest statistics and the params of the view they were computed for.
+    stats: Option<(FrameStats, MandelbrotParams)>,
+    stats_params: Option<MandelbrotParams>,
+    detail_reduction: DetailReduction,
+    /// The latest detail scores and the params of the view they were computed for.
+    detail: Option<(DetailScores, MandelbrotParams)>,
+    detail_params: Option<MandelbrotParams>,
+    last_params: Option<MandelbrotParams>,
+    /// Params the histogram was last built for, and whether from the draft texture.
+    histogram_params: Option<(MandelbrotParams, bool)>,
+    /// Params the auto range was last reduced for, and whether from the draft texture.
+    range_params: Option<(MandelbrotParams, bool)>,
+    /// Set by any window event and cleared once a frame has been drawn.
+    dirty: bool,
+    frame: u64,
+    title: String,
+    /// When `title` was last set on the window, and whether it has changed since.
+    title_set_at: Instant,
+    title_pending: bool,
+    /// Counts used by the next bracketed screenshot.
+    pub bracket_iterations: Vec<i32>,
+    bracket: Option<BracketJob>,
+    /// Size of the next poster.
+    pub poster_size: UVec2,
+    poster: Option<PosterJob>,
+    /// View marked with F6 that the next animation starts from.
+    animation_start: Option<SampleLocation>,
+    pub animation_frames: u32,
+    pub animation_fps: f32,
+    animation: Option<AnimationJob>,
+    bookmarks: Bookmarks,
+    /// Fly to bookmarks and the home view rather than jumping; with Shift they always jump.
+    pub animate_bookmarks: bool,
+    bookmark_flight: Option<BookmarkFlight>,
+    /// Palette the bookmark thumbnails were last checked against. When the palette changes,
+    /// the thumbnails drawn with another one are queued again.
+    thumbnail_palette: Option<String>,
+    /// Bookmark slots whose thumbnail is still to be rendered.
+    pending_thumbnails: Vec<u8>,
+    /// Views that navigation came to rest at, for undo and redo.
+    history: History<ViewState>,
+    #[cfg(feature = "hot-reload")]
+    shader_watcher: Option<ShaderWatcher>,
 }
 
 impl App {
-    pub fn new(size: UVec2, window: &Window) -> App {
-        let gpu = GPUInterface::new(window);
-        let computer = Computer::new(size, &gpu);
-        let renderer = Renderer::new(&gpu, size, window);
-        App {
+    pub fn new(
+        size: UVec2,
+        window: &Window,
+        startup: &StartupOptions,
+        config: &Config,
+    ) -> Result<App, GpuInitError> {
+        let gpu = GPUInterface::new(
+            window,
+            config.rendering.present_mode,
+            config.rendering.adapter.as_ref(),
+        )?;
+        let info = &gpu.adapter_info;
+        println!(
+            "Using {} ({:?}, {:?})",
+            info.name, info.backend, info.device_type
+        );
+        if gpu.is_software() {
+            eprintln!(
+                "Warning: {} renders in software, so expect slow frames; see --gpu-info",
+                info.name
+            );
+        }
+        let workgroup_size = config.compute.workgroup_size.resolve(&gpu);
+        let mut computer = Computer::with_workgroup_size(size, &gpu, workgroup_size);
+        // Tiles of the live view are only split into submissions; waiting on them would stall
+        // the frame.
+        computer.set_tiling(config.compute.tile_size.map(|tile_size| Tiling {
+            tile_size,
+            wait_between: false,
+        }));
+        // Computes write one texture while the last frame may still be sampling the other.
+        computer.set_double_buffered(&gpu, true);
+        let deep_computer = DeepComputer::new(&gpu);
+        let mut renderer = Renderer::new(&gpu, size, window, &computer);
+        let [r, g, b] = config.rendering.clear_color.map(f64::from);
+        renderer.clear_color = wgpu::Color { r, g, b, a: 1.0 };
+        let stats_reduction = StatsReduction::new(&gpu, &computer);
+        let detail_reduction = DetailReduction::new(&gpu, &computer);
+        let mut palettes = builtin_palettes();
+        palettes.extend(load_palette_dir(Path::new(PALETTE_DIR)));
+        let palette_index = match &config.rendering.palette {
+            Some(name) => palettes
+                .iter()
+                .position(|palette| &palette.name == name)
+                .unwrap_or_else(|| {
+                    eprintln!("Warning: no palette named '{}'; using the default", name);
+                    0
+                }),
+            None => 0,
+        };
+        let (keymap, warnings) = Keymap::with_overrides(&config.keys);
+        for warning in warnings {
+            eprintln!("Warning: {}", warning);
+        }
+        renderer.set_palette(&gpu, &palettes[palette_index]);
+        let mut sample_location = SampleLocation::default();
+        sample_location.set_output_size(size);
+        let mut app = App {
             gpu,
             computer,
-            renderer: renderer,
-            sample_location: SampleLocation::default(),
+            deep_computer,
+            renderer,
+            sample_location,
+            fractal_kind: FractalKind::Mandelbrot,
+            fractal_mode: FractalMode::Mandelbrot,
+            coloring_mode: ColoringMode::EscapeTime,
+            relief: false,
+            stripe_density: DEFAULT_STRIPE_DENSITY,
+            stripe_mix: DEFAULT_STRIPE_MIX,
+            light_angle: std::f32::consts::FRAC_PI_4,
+            light_height: 1.0,
+            julia_c: FVec2 { x: 0.0, y: 0.0 },
+            mandelbrot_view: None,
+            power: 2.0,
+            escape_radius: ESCAPE_RADIUS,
+            interior_check: true,
+            time_next_compute: false,
+            show_timing: false,
+            show_scale: false,
+            show_grid: false,
+            frame_timer: FrameTimer::default(),
+            julia_preview: false,
+            preview_params: None,
+            progressive: true,
+            draft_params: None,
+            shown_params: None,
+            view_changed_at: Instant::now(),
+            accumulation: false,
+            accumulation_seed: 0,
+            accumulated: 0,
+            accumulated_colors: None,
+            max_iterations: FractalKind::Mandelbrot.default_iterations(),
+            auto_iterations: AutoIterations::default(),
+            iteration_multiplier: 1.0,
+            wheel_zoom_factor: config.controls.zoom_step,
+            move_speed: config.controls.move_speed,
+            palettes,
+            palette_index,
+            palette_editor: None,
+            color_mapping: ColorMapping::default(),
+            palette_cycling: PaletteCycling::default(),
+            last_update: Instant::now(),
+            minimized: false,
+            fullscreen_requested: false,
+            windowed_geometry: None,
+            cursor_position: PhysicalPosition::new(0.0, 0.0),
+            cursor_in_window: false,
+            cursor_moved_at: Instant::now(),
+            hover_probe: None,
+            hover: None,
+            drag_position: None,
+            selection_start: None,
+            modifiers: ModifiersState::empty(),
+            input: InputState::default(),
+            keymap,
+            touches: TouchTracker::default(),
+            navigating: false,
+            probe: None,
+            probes: Readback::new("Pixel probe buffer", PROBE_BUFFERS),
+            orbit: None,
+            screenshot_requested: false,
+            compute_size: None,
+            screenshot: None,
+            stats_reduction,
+            stats: None,
+            stats_params: None,
+            detail_reduction,
+            detail: None,
+            detail_params: None,
+            last_params: None,
+            histogram_params: None,
+            range_params: None,
+            dirty: true,
+            frame: 0,
+            title: WINDOW_TITLE.to_string(),
+            title_set_at: Instant::now(),
+            title_pending: false,
+            bracket_iterations: DEFAULT_BRACKET_ITERATIONS.to_vec(),
+            bracket: None,
+            poster_size: UVec2::new(DEFAULT_POSTER_SIZE, DEFAULT_POSTER_SIZE),
+            poster: None,
+            animation_start: None,
+            animation_frames: DEFAULT_ANIMATION_FRAMES,
+            animation_fps: DEFAULT_ANIMATION_FPS,
+            animation: None,
+            bookmarks: Bookmarks::load(&bookmarks_path()).unwrap_or_else(|e| {
+                eprintln!("Warning: {:#}; starting without bookmarks", e);
+                Bookmarks::default()
+            }),
+            animate_bookmarks: true,
+            bookmark_flight: None,
+            thumbnail_palette: None,
+            pending_thumbnails: Vec::new(),
+            history: History::new(HISTORY_LENGTH),
+            #[cfg(feature = "hot-reload")]
+            shader_watcher: None,
+        };
+        if let Some(formula) = &startup.formula {
+            app.set_formula(formula.clone());
+        }
+        let mut state = startup.view_state();
+        if let (true, Some(iterations)) = (state.auto_iterations, config.compute.default_iterations)
+        {
+            state.max_iterations = iterations;
+        }
+        app.apply_view_state(state);
+        if startup.julia {
+            app.toggle_fractal_mode();
+        }
+        Ok(app)
+    }
+
+    /// Reloads shaders from src/shaders whenever one is saved. `wake` is called from the
+    /// watcher thread so the event loop runs `update` even while idle.
+    #[cfg(feature = "hot-reload")]
+    pub fn watch_shaders(&mut self, wake: impl Fn() + Send + 'static) {
+        match ShaderWatcher::new(wake) {
+            Ok(watcher) => {
+                println!("Watching shaders for changes");
+                self.shader_watcher = Some(watcher);
+            }
+            Err(e) => eprintln!("Could not watch shaders: {}", e),
         }
     }
 
     pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        // Any event may change what is shown, see `needs_redraw`.
+        self.dirty = true;
         match event {
-            WindowEvent::KeyboardInput {
-                device_id,
-                input,
-                is_synthetic,
-            } => match input.state {
-                winit::event::ElementState::Pressed => {
-                    if input.virtual_keycode == Some(VirtualKeyCode::Left) {
-                        self.sample_location.left();
+            WindowEvent::KeyboardInput { input, .. } => match input.state {
+                ElementState::Pressed => {
+                    if input.virtual_keycode == Some(VirtualKeyCode::Escape)
+                        && (self.selection_start.take().is_some() || self.orbit.take().is_some())
+                    {
+                        return true;
                     }
-                    if input.virtual_keycode == Some(VirtualKeyCode::Right) {
-                        self.sample_location.right();
+
+                    if self.palette_editor.is_some()
+                        && self.handle_editor_key(input.virtual_keycode)
+                    {
+                        return true;
                     }
 
-                    if input.virtual_keycode == Some(VirtualKeyCode::Up) {
-                        self.sample_location.up();
+                    // Shortcuts with modifiers that stay fixed whatever the keymap says.
+                    let key = input.virtual_keycode;
+                    if key == Some(VirtualKeyCode::Return) && self.modifiers.alt() {
+                        self.fullscreen_requested = true;
+                        return true;
                     }
 
-                    if input.virtual_keycode == Some(VirtualKeyCode::Down) {
-                        self.sample_location.down();
+                    if self.modifiers.ctrl()
+                        && (key == Some(VirtualKeyCode::Z) || key == Some(VirtualKeyCode::Y))
+                    {
+                        self.step_history(key == Some(VirtualKeyCode::Y));
+                        return true;
                     }
 
-                    if input.virtual_keycode == Some(VirtualKeyCode::NumpadAdd) {
-                        self.sample_location.zoom_in();
+                    if key == Some(VirtualKeyCode::C) && self.modifiers.ctrl() {
+                        // Printed rather than copied, as there is no clipboard access yet; the
+                        // line can be passed back with --location.
+                        println!("Location: {}", self.view_state().location_text());
+                        return true;
                     }
 
-                    if input.virtual_keycode == Some(VirtualKeyCode::NumpadSubtract) {
-                        self.sample_location.zoom_out();
+                    if let Some(slot) = key.and_then(bookmark_slot) {
+                        if self.modifiers.ctrl() {
+                            self.save_bookmark(slot);
+                        } else {
+                            self.jump_to_bookmark(
+                                slot,
+                                self.animate_bookmarks && !self.modifiers.shift(),
+                            );
+                        }
+                        return true;
+                    }
+
+                    if let Some(action) = key.and_then(|key| self.keymap.action(key)) {
+                        return self.perform(action);
+                    }
+                }
+                ElementState::Released => {
+                    if let Some(action) = input
+                        .virtual_keycode
+                        .and_then(|key| self.keymap.action(key))
+                    {
+                        self.input.set(action, false);
+                    }
+                }
+            },
+            // Releases while unfocused never arrive, so keys would stay held.
+            WindowEvent::Focused(false) => {
+                self.input = InputState::default();
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = *position;
+                self.cursor_in_window = true;
+                self.cursor_moved_at = Instant::now();
+                if self.relief && self.modifiers.alt() {
+                    self.point_light_at_cursor();
+                }
+                // Touch screens also move the cursor, which must not drag a second time.
+                if self.touches.is_active() {
+                    self.drag_position = None;
+                }
+                if let Some(last) = self.drag_position.replace(*position) {
+                    let from = self.image_point(FVec2::from(last));
+                    let to = self.image_point(FVec2::from(*position));
+                    self.sample_location.drag(from, to, self.image_size());
+                }
+            }
+            WindowEvent::Touch(touch) => {
+                let gesture = self.touches.update(
+                    touch.id,
+                    touch.phase,
+                    FVec2::from(touch.location),
+                    Instant::now(),
+                );
+                let size = self.image_size();
+                match gesture {
+                    Some(TouchGesture::Pan { from, to }) => {
+                        let (from, to) = (self.image_point(from), self.image_point(to));
+                        self.sample_location.drag(from, to, size);
+                    }
+                    Some(TouchGesture::Pinch { from, to, scale }) => {
+                        let (from, to) = (self.image_point(from), self.image_point(to));
+                        self.sample_location.drag(from, to, size);
+                        self.sample_location.zoom_about(to, size, scale);
+                    }
+                    Some(TouchGesture::DoubleTap(position)) => {
+                        let position = self.image_point(position);
+                        self.sample_location.animate_zoom_about(position, size, 0.5);
+                    }
+                    None => {}
+                }
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor_in_window = false;
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let notches = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => {
+                        (position.y / PIXELS_PER_NOTCH) as f32
                     }
+                };
+                if self.relief && self.modifiers.alt() {
+                    self.light_height = (self.light_height * LIGHT_HEIGHT_STEP.powf(notches))
+                        .clamp(MIN_LIGHT_HEIGHT, MAX_LIGHT_HEIGHT);
+                    return true;
                 }
-                winit::event::ElementState::Released => {}
+                let cursor = self.cursor_image_point();
+                self.sample_location.animate_zoom_about(
+                    cursor,
+                    self.image_size(),
+                    self.wheel_zoom_factor.powf(notches),
+                );
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = *modifiers;
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } if self.modifiers.ctrl() => {
+                self.probe_cursor();
+                self.select_orbit();
+                return true;
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Right,
+                ..
+            } => match state {
+                ElementState::Pressed => self.selection_start = Some(self.cursor_position),
+                ElementState::Released => self.finish_selection(),
             },
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.drag_position = match state {
+                    ElementState::Pressed if !self.touches.is_active() => {
+                        Some(self.cursor_position)
+                    }
+                    _ => None,
+                };
+            }
             _ => {}
         }
-        return false;
+        false
+    }
+
+    /// Does what a key bound to `action` does when pressed. Returns true if the press was
+    /// consumed, which every bound key is.
+    fn perform(&mut self, action: Action) -> bool {
+        let shift = self.modifiers.shift();
+        match action {
+            Action::PanLeft | Action::PanRight | Action::PanUp | Action::PanDown
+                if self.fractal_mode == FractalMode::Julia && self.modifiers.ctrl() =>
+            {
+                let step = if shift {
+                    JULIA_C_STEP / 10.0
+                } else {
+                    JULIA_C_STEP
+                };
+                let (x, y) = match action {
+                    Action::PanLeft => (-step, 0.0),
+                    Action::PanRight => (step, 0.0),
+                    Action::PanUp => (0.0, step),
+                    _ => (0.0, -step),
+                };
+                self.julia_c.x += x;
+                self.julia_c.y += y;
+                println!("Julia c: {} {:+}i", self.julia_c.x, self.julia_c.y);
+            }
+            Action::PanLeft
+            | Action::PanRight
+            | Action::PanUp
+            | Action::PanDown
+            | Action::ZoomIn
+            | Action::ZoomOut => {
+                // Panning and zooming are applied by update for as long as the key is held.
+                self.input.set(action, true);
+            }
+            Action::IterationsDown => {
+                if self.auto_iterations.enabled {
+                    self.iteration_multiplier =
+                        (self.iteration_multiplier * 0.5).max(1.0 / MAX_ITERATION_MULTIPLIER);
+                } else {
+                    self.max_iterations = (self.max_iterations / 2).max(MIN_ITERATIONS);
+                }
+            }
+            Action::IterationsUp => {
+                if self.auto_iterations.enabled {
+                    self.iteration_multiplier =
+                        (self.iteration_multiplier * 2.0).min(MAX_ITERATION_MULTIPLIER);
+                } else {
+                    self.max_iterations = self.max_iterations.saturating_mul(2).min(MAX_ITERATIONS);
+                }
+            }
+            Action::AutoIterations => {
+                self.auto_iterations.enabled = !self.auto_iterations.enabled;
+                if !self.auto_iterations.enabled {
+                    // Continue from the automatic count rather than jumping back.
+                    self.max_iterations = self.iterations();
+                }
+                println!("Automatic iterations: {}", self.auto_iterations.enabled);
+            }
+            Action::ResetView => self.reset_view(self.animate_bookmarks && !shift),
+            Action::Undo => self.step_history(shift),
+            Action::Screenshot => {
+                if self.modifiers.ctrl() && shift {
+                    self.save_palette();
+                } else if shift {
+                    if self.poster.take().is_some() {
+                        println!("Poster cancelled");
+                    } else {
+                        self.start_poster();
+                    }
+                } else if !self.modifiers.ctrl() {
+                    self.screenshot_requested = true;
+                }
+            }
+            // Plain F12 does nothing; a bracket is slow enough to be worth the modifier.
+            Action::BracketScreenshot if !shift => {}
+            Action::BracketScreenshot => {
+                if self.bracket.take().is_some() {
+                    println!("Bracketed screenshot cancelled");
+                } else {
+                    self.start_bracket();
+                }
+            }
+            Action::SaveView => {
+                let path = view_state_path();
+                match self.view_state().save(&path) {
+                    Ok(()) => println!("Saved view to {}", path.display()),
+                    Err(e) => eprintln!("Could not save the view: {:#}", e),
+                }
+            }
+            Action::LoadView => {
+                let path = view_state_path();
+                let state = ViewState::load(&path).unwrap_or_else(|e| {
+                    eprintln!("Warning: {:#}; using the default view", e);
+                    ViewState::default()
+                });
+                self.apply_view_state(state);
+                println!("Loaded view from {}", path.display());
+            }
+            Action::AnimationStart => {
+                self.animation_start = Some(self.sample_location.clone());
+                println!("Animation start set; F7 records from here to the current view");
+            }
+            Action::Animation => {
+                if self.animation.take().is_some() {
+                    println!("Animation cancelled");
+                } else {
+                    self.start_animation();
+                }
+            }
+            Action::Supersampling => {
+                let index = SUPERSAMPLING_FACTORS
+                    .iter()
+                    .position(|&f| f == self.computer.supersampling())
+                    .map_or(0, |i| (i + 1) % SUPERSAMPLING_FACTORS.len());
+                self.set_supersampling(SUPERSAMPLING_FACTORS[index]);
+            }
+            Action::Accumulation => {
+                self.accumulation = !self.accumulation;
+                self.accumulated_colors = None;
+                println!("Accumulation anti-aliasing: {}", self.accumulation);
+            }
+            Action::ColoringMode => {
+                self.coloring_mode = self.coloring_mode.next();
+                println!("Coloring: {:?}", self.coloring_mode);
+            }
+            Action::StripeDensity => {
+                let delta = if shift { -1.0 } else { 1.0 };
+                self.stripe_density =
+                    (self.stripe_density + delta).clamp(MIN_STRIPE_DENSITY, MAX_STRIPE_DENSITY);
+                println!("Stripe density: {}", self.stripe_density);
+            }
+            Action::StripeMix => {
+                let delta = if shift {
+                    -STRIPE_MIX_STEP
+                } else {
+                    STRIPE_MIX_STEP
+                };
+                self.stripe_mix = (self.stripe_mix + delta).clamp(0.0, 1.0);
+                println!("Stripe mix: {}", self.stripe_mix);
+            }
+            Action::Relief => {
+                self.relief = !self.relief;
+                println!("Relief lighting: {}", self.relief);
+            }
+            Action::Fullscreen => self.fullscreen_requested = true,
+            Action::Timing => {
+                self.show_timing = !self.show_timing;
+                self.frame_timer.clear();
+            }
+            Action::ScaleOverlay => self.show_scale = !self.show_scale,
+            Action::Grid => self.show_grid = !self.show_grid,
+            Action::PaletteEditor => {
+                self.palette_editor = match self.palette_editor {
+                    Some(_) => None,
+                    None => Some(PaletteEditor::default()),
+                };
+                println!(
+                    "Palette editor {}",
+                    if self.palette_editor.is_some() {
+                        "on: ,/. move phase, N add stop, Space select, H/S/L adjust (Shift lowers), Delete removes"
+                    } else {
+                        "off"
+                    }
+                );
+            }
+            Action::Palette => {
+                if shift {
+                    self.load_newest_palette();
+                } else {
+                    self.palette_index = (self.palette_index + 1) % self.palettes.len();
+                    let palette = &self.palettes[self.palette_index];
+                    self.renderer.set_palette(&self.gpu, palette);
+                    println!("Palette: {}", palette.name);
+                }
+            }
+            Action::ColorVision => {
+                let simulation = self.renderer.cvd_simulation.next();
+                self.renderer.set_cvd_simulation(&self.gpu, simulation);
+                println!("Color vision simulation: {:?}", simulation);
+            }
+            Action::Density => {
+                self.color_mapping
+                    .adjust_density(if shift { -1 } else { 1 });
+                self.color_mapping.auto_range = false;
+            }
+            Action::Offset => {
+                self.color_mapping
+                    .adjust_offset(if shift { -0.05 } else { 0.05 });
+            }
+            Action::MappingMode => {
+                self.color_mapping.mode = self.color_mapping.mode.next();
+                println!("Color mapping: {:?}", self.color_mapping.mode);
+            }
+            Action::Exponent => {
+                self.color_mapping
+                    .adjust_exponent(if shift { -0.05 } else { 0.05 });
+            }
+            Action::PaletteCycling => {
+                if shift {
+                    let mode = self.gpu.cycle_present_mode();
+                    println!("Present mode: {:?}", mode);
+                } else {
+                    self.palette_cycling.enabled = !self.palette_cycling.enabled;
+                    println!("Palette cycling: {}", self.palette_cycling.enabled);
+                }
+            }
+            Action::CyclingSlower | Action::CyclingFaster => {
+                let steps = if action == Action::CyclingSlower {
+                    -1
+                } else {
+                    1
+                };
+                self.palette_cycling.adjust_speed(steps);
+                println!(
+                    "Palette cycling speed: {:.3} cycles/s",
+                    self.palette_cycling.speed
+                );
+            }
+            Action::AutoRange => {
+                self.color_mapping.auto_range = !self.color_mapping.auto_range;
+                println!("Auto range: {}", self.color_mapping.auto_range);
+            }
+            Action::Bloom => {
+                if let Some(enabled) = self.renderer.post_chain.toggle(Bloom::NAME) {
+                    println!("Bloom: {}", enabled);
+                }
+            }
+            Action::BloomThreshold => {
+                let delta = if shift { -0.05 } else { 0.05 };
+                if let Some(bloom) = self.renderer.post_chain.pass_mut::<Bloom>() {
+                    bloom.adjust_threshold(&self.gpu, delta);
+                    println!("Bloom threshold: {:.2}", bloom.threshold);
+                }
+            }
+            Action::BloomStrength => {
+                let delta = if shift { -0.1 } else { 0.1 };
+                if let Some(bloom) = self.renderer.post_chain.pass_mut::<Bloom>() {
+                    bloom.adjust_strength(&self.gpu, delta);
+                    println!("Bloom strength: {:.2}", bloom.strength);
+                }
+            }
+            Action::FractalMode => {
+                if shift {
+                    self.jump_to_detail();
+                } else {
+                    self.toggle_fractal_mode();
+                }
+            }
+            Action::FractalKind => self.cycle_fractal_kind(shift),
+            Action::Power => {
+                // Newton's method only has whole degrees.
+                let step = match self.fractal_kind {
+                    FractalKind::Newton => 1.0,
+                    _ => POWER_STEP,
+                };
+                let delta = if shift { -step } else { step };
+                self.power = (self.power + delta).clamp(MIN_POWER, MAX_POWER);
+                println!("Power: {}", self.power);
+            }
+            Action::EscapeRadius => {
+                let factor = if shift { 0.5 } else { 2.0 };
+                self.escape_radius =
+                    (self.escape_radius * factor).clamp(MIN_ESCAPE_RADIUS, MAX_ESCAPE_RADIUS);
+                println!("Escape radius: {}", self.escape_radius);
+            }
+            Action::InteriorCheck => {
+                self.interior_check = !self.interior_check;
+                self.time_next_compute = true;
+                println!("Interior check: {}", self.interior_check);
+            }
+            Action::Progressive => {
+                self.progressive = !self.progressive;
+                println!("Progressive refinement: {}", self.progressive);
+            }
+            Action::Precision if shift => self.compare_precisions(),
+            Action::Precision => {
+                let precision = self.computer.cycle_precision();
+                // Force a recompute even though the view params didn't change.
+                self.last_params = None;
+                self.draft_params = None;
+                self.preview_params = None;
+                println!("Precision: {:?}", precision);
+            }
+            Action::JuliaPreview => {
+                self.julia_preview = !self.julia_preview;
+                println!("Julia preview: {}", self.julia_preview);
+            }
+            Action::RotateLeft => self.sample_location.rotate(-std::f32::consts::PI / 16.0),
+            Action::RotateRight => self.sample_location.rotate(std::f32::consts::PI / 16.0),
+            Action::PrintKeys => print!("{}", self.keymap.describe()),
+        }
+        true
+    }
+
+    /// Applies a palette editor key to the current palette. Returns true if the key was consumed.
+    fn handle_editor_key(&mut self, key: Option<VirtualKeyCode>) -> bool {
+        const PHASE_STEP: f32 = 0.02;
+        const COLOR_STEP: f32 = 0.02;
+        let editor = match &mut self.palette_editor {
+            Some(editor) => editor,
+            None => return false,
+        };
+        let palette = &mut self.palettes[self.palette_index];
+        let sign = if self.modifiers.shift() { -1.0 } else { 1.0 };
+        match key {
+            Some(VirtualKeyCode::Comma) => {
+                editor.phase = (editor.phase - PHASE_STEP).max(0.0);
+            }
+            Some(VirtualKeyCode::Period) => {
+                editor.phase = (editor.phase + PHASE_STEP).min(1.0);
+            }
+            Some(VirtualKeyCode::N) => editor.selected = Some(palette.add_stop(editor.phase)),
+            Some(VirtualKeyCode::Space) => editor.selected = palette.nearest_stop(editor.phase),
+            Some(VirtualKeyCode::Delete) => {
+                if let Some(index) = editor.selected {
+                    if palette.remove_stop(index) {
+                        editor.selected = None;
+                    }
+                }
+            }
+            Some(VirtualKeyCode::H) | Some(VirtualKeyCode::S) | Some(VirtualKeyCode::L)
+                if !self.modifiers.ctrl() =>
+            {
+                if let Some(index) = editor.selected {
+                    let step = sign * COLOR_STEP;
+                    match key {
+                        Some(VirtualKeyCode::H) => palette.nudge_hsl(index, step, 0.0, 0.0),
+                        Some(VirtualKeyCode::S) => palette.nudge_hsl(index, 0.0, step, 0.0),
+                        _ => palette.nudge_hsl(index, 0.0, 0.0, step),
+                    }
+                }
+            }
+            _ => return false,
+        }
+        self.renderer.set_palette(&self.gpu, palette);
+        let selected = editor
+            .selected
+            .and_then(|i| palette.stops.get(i))
+            .map(|stop| format!("{:.2} {:?}", stop.position, stop.color))
+            .unwrap_or_else(|| "none".to_string());
+        println!(
+            "Palette editor: phase {:.2}, {} stops, selected {}",
+            editor.phase,
+            palette.stops.len(),
+            selected
+        );
+        true
+    }
+
+    /// Zooms to the rectangle dragged out with the right button, ignoring accidental clicks.
+    fn finish_selection(&mut self) {
+        const MIN_SELECTION: f64 = 4.0;
+        let start = match self.selection_start.take() {
+            Some(start) => start,
+            None => return,
+        };
+        let end = self.cursor_position;
+        if (end.x - start.x).abs() < MIN_SELECTION || (end.y - start.y).abs() < MIN_SELECTION {
+            return;
+        }
+        let start = self.image_point(FVec2::from(start));
+        let end = self.image_point(FVec2::from(end));
+        self.sample_location
+            .zoom_to_rect(start, end, self.image_size());
+    }
+
+    /// Outlines the zoom rectangle while it is being dragged.
+    fn draw_selection(&mut self) {
+        if let Some(start) = self.selection_start {
+            let end = self.cursor_position;
+            let min = FVec2 {
+                x: start.x.min(end.x) as f32,
+                y: start.y.min(end.y) as f32,
+            };
+            let max = FVec2 {
+                x: start.x.max(end.x) as f32,
+                y: start.y.max(end.y) as f32,
+            };
+            self.renderer
+                .overlay
+                .add_rect(min, max, [1.0, 1.0, 1.0, 0.15]);
+            self.renderer
+                .overlay
+                .add_rect_outline(min, max, [1.0, 1.0, 1.0, 0.9]);
+        }
+    }
+
+    /// Computes the orbit of the point under the cursor on the CPU for `draw_orbit`.
+    fn select_orbit(&mut self) {
+        if matches!(self.fractal_kind, FractalKind::Newton | FractalKind::Custom) {
+            println!("Orbits are only drawn for the built-in escape-time fractals");
+            return;
+        }
+        let point = self
+            .sample_location
+            .screen_to_plane(self.cursor_image_point(), self.image_size());
+        let (z0, c) = match self.fractal_mode {
+            FractalMode::Mandelbrot => ((0.0, 0.0), (point.x, point.y)),
+            FractalMode::Julia => (
+                (point.x, point.y),
+                (self.julia_c.x as f64, self.julia_c.y as f64),
+            ),
+        };
+        let formula = cpu_reference::Formula {
+            power: self.power as f64,
+            conjugate: self.fractal_kind == FractalKind::Tricorn,
+        };
+        let orbit = cpu_reference::orbit(
+            z0,
+            c,
+            formula,
+            self.iterations().min(ORBIT_LENGTH),
+            self.escape_radius as f64,
+        );
+        println!("Orbit: {} points", orbit.len());
+        self.orbit = Some(orbit.into_iter().map(|(x, y)| DVec2 { x, y }).collect());
+    }
+
+    /// Draws the selected orbit as a polyline through dots, projected with the current view
+    /// so it follows pans and zooms.
+    fn draw_orbit(&mut self) {
+        let Some(orbit) = &self.orbit else {
+            return;
+        };
+        let size = self.image_size();
+        // The escaping point may be too far out for f32 screen coordinates.
+        let points: Vec<FVec2> = orbit
+            .iter()
+            .map(|&point| self.sample_location.plane_to_screen(point, size))
+            .filter(|p| p.x.is_finite() && p.y.is_finite())
+            .map(|p| content_to_target(p, size, self.renderer.size))
+            .collect();
+        let overlay = &mut self.renderer.overlay;
+        overlay.add_wide_polyline(&points, 1.5, [1.0, 1.0, 1.0, 0.8]);
+        for p in &points {
+            overlay.add_rect(
+                *p - FVec2::new(2.0, 2.0),
+                *p + FVec2::new(2.0, 2.0),
+                [1.0, 0.8, 0.2, 1.0],
+            );
+        }
+    }
+
+    /// Shows the palette editor's state in the top-left corner while it is open.
+    fn draw_editor_label(&mut self) {
+        let editor = match &self.palette_editor {
+            Some(editor) => editor,
+            None => return,
+        };
+        let palette = &self.palettes[self.palette_index];
+        let label = format!(
+            "Editing {}: phase {:.2}, {} stops",
+            palette.name,
+            editor.phase,
+            palette.stops.len()
+        );
+        let text = &mut self.renderer.text;
+        let width = text.measure(&label, LABEL_SIZE);
+        let height = text.line_height(LABEL_SIZE);
+        self.renderer.overlay.add_rect(
+            FVec2 { x: 4.0, y: 4.0 },
+            FVec2 {
+                x: 12.0 + width,
+                y: 12.0 + height,
+            },
+            [0.0, 0.0, 0.0, 0.6],
+        );
+        text.add_text(
+            &mut self.renderer.overlay,
+            &label,
+            FVec2 { x: 8.0, y: 8.0 },
+            LABEL_SIZE,
+            [1.0, 1.0, 1.0, 1.0],
+        );
+    }
+
+    /// Shows frame rate and CPU frame times in the top-right corner while F3 is on.
+    fn draw_timing(&mut self) {
+        if !self.show_timing {
+            return;
+        }
+        let mut lines = match self.frame_timer.summary() {
+            Some(summary) => vec![
+                format!("{:.0} fps", summary.fps),
+                format!("frame {:.2} ms", summary.frame_ms),
+                format!("compute {:.2} ms", summary.compute_ms),
+                format!("present {:.2} ms", summary.present_ms),
+            ],
+            None => vec!["measuring...".to_string()],
+        };
+        lines.insert(0, self.gpu.adapter_info.name.clone());
+        let text = &mut self.renderer.text;
+        let width = lines
+            .iter()
+            .map(|line| text.measure(line, LABEL_SIZE))
+            .fold(0.0, f32::max);
+        let line_height = text.line_height(LABEL_SIZE);
+        let right = self.renderer.size.x as f32 - 4.0;
+        self.renderer.overlay.add_rect(
+            FVec2 {
+                x: right - width - 8.0,
+                y: 4.0,
+            },
+            FVec2 {
+                x: right,
+                y: 12.0 + line_height * lines.len() as f32,
+            },
+            [0.0, 0.0, 0.0, 0.6],
+        );
+        for (i, line) in lines.iter().enumerate() {
+            text.add_text(
+                &mut self.renderer.overlay,
+                line,
+                FVec2 {
+                    x: right - width - 4.0,
+                    y: 8.0 + line_height * i as f32,
+                },
+                LABEL_SIZE,
+                [1.0, 1.0, 1.0, 1.0],
+            );
+        }
+    }
+
+    /// Draws the real and imaginary axes where they cross the view, over a faint grid whose
+    /// spacing is picked from the zoom so 5 to 15 lines cross the image.
+    fn draw_grid(&mut self) {
+        if !self.show_grid {
+            return;
+        }
+        let size = self.image_size();
+        let view = &self.sample_location;
+        // Bounds of the image on the plane, which a rotated view only touches at the corners.
+        let corners = [
+            (0.0, 0.0),
+            (size.x as f32, 0.0),
+            (0.0, size.y as f32),
+            (size.x as f32, size.y as f32),
+        ]
+        .map(|(x, y)| view.screen_to_plane(FVec2::new(x, y), size));
+        let min = corners.iter().fold(DVec2::new(f64::MAX, f64::MAX), |a, c| {
+            DVec2::new(a.x.min(c.x), a.y.min(c.y))
+        });
+        let max = corners.iter().fold(DVec2::new(f64::MIN, f64::MIN), |a, c| {
+            DVec2::new(a.x.max(c.x), a.y.max(c.y))
+        });
+        let spacing = grid_spacing(view.pixel_size(size) as f64 * size.x as f64);
+
+        let target = self.renderer.size;
+        let to_window =
+            |point: DVec2| content_to_target(view.plane_to_screen(point, size), size, target);
+        let overlay = &mut self.renderer.overlay;
+        let (first, last) = (
+            (min.x / spacing).ceil() as i64,
+            (max.x / spacing).floor() as i64,
+        );
+        for k in first..=last {
+            let re = k as f64 * spacing;
+            let (a, b) = (
+                to_window(DVec2::new(re, min.y)),
+                to_window(DVec2::new(re, max.y)),
+            );
+            overlay.add_line(a, b, GRID_COLOR);
+        }
+        let (first, last) = (
+            (min.y / spacing).ceil() as i64,
+            (max.y / spacing).floor() as i64,
+        );
+        for k in first..=last {
+            let im = k as f64 * spacing;
+            let (a, b) = (
+                to_window(DVec2::new(min.x, im)),
+                to_window(DVec2::new(max.x, im)),
+            );
+            overlay.add_line(a, b, GRID_COLOR);
+        }
+        if (min.x..=max.x).contains(&0.0) {
+            let (a, b) = (
+                to_window(DVec2::new(0.0, min.y)),
+                to_window(DVec2::new(0.0, max.y)),
+            );
+            overlay.add_wide_line(a, b, AXIS_WIDTH, AXIS_COLOR);
+        }
+        if (min.y..=max.y).contains(&0.0) {
+            let (a, b) = (
+                to_window(DVec2::new(min.x, 0.0)),
+                to_window(DVec2::new(max.x, 0.0)),
+            );
+            overlay.add_wide_line(a, b, AXIS_WIDTH, AXIS_COLOR);
+        }
+    }
+
+    /// Marks the window center, which keyboard zooms close in on, and draws a scale bar in the
+    /// bottom-left corner labeled with the length it spans on the complex plane.
+    fn draw_scale(&mut self) {
+        if !self.show_scale {
+            return;
+        }
+        let size = self.renderer.size;
+        let center = FVec2::new((size.x / 2) as f32 + 0.5, (size.y / 2) as f32 + 0.5);
+        let overlay = &mut self.renderer.overlay;
+        for direction in [
+            FVec2::new(1.0, 0.0),
+            FVec2::new(-1.0, 0.0),
+            FVec2::new(0.0, 1.0),
+            FVec2::new(0.0, -1.0),
+        ] {
+            overlay.add_line(
+                center + direction * CROSSHAIR_GAP,
+                center + direction * CROSSHAIR_SIZE,
+                [1.0, 1.0, 1.0, 0.9],
+            );
+        }
+
+        // Complex-plane length of one window pixel, through the letterbox scaling.
+        let image_size = self.image_size();
+        let (min, max) = letterbox(image_size, size);
+        let plane_per_pixel = self.sample_location.pixel_size(image_size) as f64
+            * image_size.x as f64
+            / (max.x - min.x) as f64;
+        let length = nice_length(plane_per_pixel * size.x as f64 * SCALE_BAR_FRACTION);
+        let bar = (length / plane_per_pixel) as f32;
+        let label = format_length(length);
+        let text = &mut self.renderer.text;
+        let label_width = text.measure(&label, LABEL_SIZE);
+        let line_height = text.line_height(LABEL_SIZE);
+        let left = 12.0;
+        let bottom = size.y as f32 - 12.0;
+        self.renderer.overlay.add_rect(
+            FVec2::new(left - 8.0, bottom - line_height - 16.0),
+            FVec2::new(left + bar.max(label_width) + 8.0, bottom + 6.0),
+            [0.0, 0.0, 0.0, 0.6],
+        );
+        text.add_text(
+            &mut self.renderer.overlay,
+            &label,
+            FVec2::new(left, bottom - line_height - 10.0),
+            LABEL_SIZE,
+            [1.0, 1.0, 1.0, 1.0],
+        );
+        let overlay = &mut self.renderer.overlay;
+        let color = [1.0, 1.0, 1.0, 1.0];
+        overlay.add_rect(
+            FVec2::new(left, bottom - 2.0),
+            FVec2::new(left + bar, bottom),
+            color,
+        );
+        for x in [left, left + bar] {
+            overlay.add_rect(
+                FVec2::new(x - 1.0, bottom - 8.0),
+                FVec2::new(x + 1.0, bottom),
+                color,
+            );
+        }
+    }
+
+    /// Writes the current palette to the palettes directory so it is loaded on next start.
+    fn save_palette(&self) {
+        let palette = &self.palettes[self.palette_index];
+        let path = Path::new(PALETTE_DIR).join(palette_file_name(&palette.name));
+        let result = std::fs::create_dir_all(PALETTE_DIR)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| palette.save_json(&path));
+        match result {
+            Ok(()) => println!("Saved palette {} to {}", palette.name, path.display()),
+            Err(e) => eprintln!("Could not save palette to {}: {}", path.display(), e),
+        }
+    }
+
+    /// Centers and zooms the view on the block with the most detail in the current frame.
+    fn jump_to_detail(&mut self) {
+        let best = match &self.detail {
+            Some((scores, params)) if self.last_params == Some(*params) => {
+                scores.best().map(|best| (best, scores.frame))
+            }
+            _ => {
+                println!("Detail scores aren't ready for this view yet");
+                return;
+            }
+        };
+        if let Some(((block, score), frame)) = best {
+            let size = UVec2::new(
+                self.computer.texture_size.width,
+                self.computer.texture_size.height,
+            );
+            println!(
+                "Jumping to block ({}, {}) with detail score {:.3} (frame {})",
+                block.x, block.y, score, frame
+            );
+            self.sample_location
+                .center_on_pixel(block_center(block, size), size);
+            self.sample_location.zoom_in();
+        }
+    }
+
+    /// Starts rendering the current view at `poster_size`. The view is refit to the poster's
+    /// aspect ratio, so a square poster of a wide window shows the same height.
+    pub fn start_poster(&mut self) {
+        let mut view = self.sample_location.clone();
+        view.set_output_size(self.poster_size);
+        let mut params = self.params_for(&view);
+        params.pixel_size = view.pixel_size(self.poster_size);
+        let job = PosterJob::new(
+            &self.gpu,
+            self.poster_size,
+            params,
+            self.color_params(),
+            &self.computer.setup(),
+            &self.palettes[self.palette_index],
+            screenshot_path(SystemTime::now()),
+        );
+        match job {
+            Ok(job) => {
+                let (_, tiles) = job.progress();
+                println!(
+                    "Poster: {}x{} in {} tiles (Shift+S cancels)",
+                    self.poster_size.x, self.poster_size.y, tiles
+                );
+                self.poster = Some(job);
+            }
+            Err(e) => eprintln!("Could not start the poster: {:#}", e),
+        }
+    }
+
+    /// Starts recording `animation_frames` frames from the view marked with F6 to the current
+    /// one, into a new timestamped directory. With automatic iterations the count follows
+    /// the zoom of each frame.
+    fn start_animation(&mut self) {
+        let Some(start) = &self.animation_start else {
+            println!("Press F6 to mark where the animation starts");
+            return;
+        };
+        let frames = (0..self.animation_frames)
+            .map(|i| {
+                let t = i as f64 / (self.animation_frames.max(2) - 1) as f64;
+                self.params_for(&start.interpolate(&self.sample_location, t))
+            })
+            .collect();
+        let timestamp = SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let job = AnimationJob::new(
+            &self.gpu,
+            self.image_size(),
+            frames,
+            self.color_params(),
+            &self.computer.setup(),
+            &self.palettes[self.palette_index],
+            PathBuf::from(format!("animation_{}", timestamp)),
+        );
+        match job {
+            Ok(job) => {
+                println!(
+                    "Recording {} frames to {} (F7 cancels)",
+                    self.animation_frames,
+                    job.directory.display()
+                );
+                self.animation = Some(job);
+            }
+            Err(e) => eprintln!("Could not start the animation: {:#}", e),
+        }
+    }
+
+    /// Starts rendering the current view once per count in `bracket_iterations`.
+    /// Images are rendered at the computed image's size so the view's aspect ratio is preserved.
+    pub fn start_bracket(&mut self) {
+        let job = BracketJob::new(
+            &self.gpu,
+            self.image_size(),
+            self.params(),
+            self.color_params(),
+            &self.computer.setup(),
+            &self.palettes[self.palette_index],
+            self.bracket_iterations.clone(),
+        )
+        .map(|job| job.with_location(self.view_state().location_text()));
+        match job {
+            Ok(job) => {
+                println!(
+                    "Bracketed screenshot: {:?} iterations (Shift+F12 cancels)",
+                    self.bracket_iterations
+                );
+                self.bracket = Some(job);
+            }
+            Err(e) => eprintln!("Could not start the bracketed screenshot: {:#}", e),
+        }
+    }
+
+    /// Loads the most recently modified file in the palettes directory and switches to it.
+    /// Errors are logged and leave the current palette in place.
+    fn load_newest_palette(&mut self) {
+        let path = match newest_palette_file(Path::new(PALETTE_DIR)) {
+            Some(path) => path,
+            None => {
+                eprintln!("No palette files in {}", PALETTE_DIR);
+                return;
+            }
+        };
+        match Palette::load_file(&path) {
+            Ok(palette) => {
+                println!("Palette: {} (from {})", palette.name, path.display());
+                self.add_palette(palette);
+            }
+            Err(e) => eprintln!("Could not load palette {}: {}", path.display(), e),
+        }
+    }
+
+    /// Adds `palette` to the registry and switches to it.
+    pub fn add_palette(&mut self, palette: Palette) {
+        self.renderer.set_palette(&self.gpu, &palette);
+        self.palettes.push(palette);
+        self.palette_index = self.palettes.len() - 1;
+    }
+
+    /// Iteration count for the current view: derived from the zoom times the manual
+    /// multiplier in automatic mode, otherwise the manual count.
+    pub fn iterations(&self) -> i32 {
+        self.iterations_at(self.sample_location.zoom())
+    }
+
+    /// Iteration count for a view at `zoom` with the current settings.
+    fn iterations_at(&self, zoom: f32) -> i32 {
+        if self.auto_iterations.enabled {
+            let automatic = self.auto_iterations.iterations(zoom);
+            ((automatic * self.iteration_multiplier) as i32).clamp(MIN_ITERATIONS, MAX_ITERATIONS)
+        } else {
+            self.max_iterations
+        }
+    }
+
+    pub fn params(&self) -> MandelbrotParams {
+        self.params_for(&self.sample_location)
+    }
+
+    /// Params of the current fractal seen through `view`.
+    fn params_for(&self, view: &SampleLocation) -> MandelbrotParams {
+        let mut params = view.to_mandlebrot_params(self.iterations_at(view.zoom()));
+        params.kind = self.fractal_kind as u32;
+        params.fractal_mode = self.fractal_mode as u32;
+        params.julia_c = self.julia_c.into();
+        params.power = self.power;
+        params.escape_radius = self.escape_radius;
+        params.coloring_mode = self.coloring_mode as u32;
+        params.stripe_density = self.stripe_density;
+        params.stripe_mix = self.stripe_mix;
+        params.pixel_size = view.pixel_size(self.image_size());
+        params.relief = self.relief as u32;
+        params.interior_check = (self.interior_check && params.interior_check_applies()) as u32;
+        params
+    }
+
+    /// The current view, as saved with F5.
+    pub fn view_state(&self) -> ViewState {
+        ViewState {
+            fractal_kind: self.fractal_kind,
+            max_iterations: self.max_iterations,
+            auto_iterations: self.auto_iterations.enabled,
+            view: self.sample_location.clone(),
+            color_mapping: self.color_mapping,
+        }
+    }
+
+    /// Switches to `state`, refitting the view to the window.
+    pub fn apply_view_state(&mut self, mut state: ViewState) {
+        if state.fractal_kind == FractalKind::Custom && self.computer.formula().is_none() {
+            eprintln!(
+                "Warning: no custom formula is set (see --formula); showing the Mandelbrot set"
+            );
+            state.fractal_kind = FractalKind::Mandelbrot;
+        }
+        if state.fractal_kind != self.fractal_kind {
+            self.power = self.kind_power(state.fractal_kind);
+        }
+        self.fractal_kind = state.fractal_kind;
+        self.auto_iterations.base = state.fractal_kind.base_iterations();
+        self.auto_iterations.enabled = state.auto_iterations;
+        self.max_iterations = state.max_iterations;
+        self.sample_location = state.view;
+        self.sample_location.set_output_size(self.image_size());
+        self.sample_location.set_move_speed(self.move_speed);
+        self.color_mapping = state.color_mapping;
+        // Recompute even if the params happen to match the last frame.
+        self.last_params = None;
+    }
+
+    /// Computes `factor` x `factor` samples per pixel, see `Computer::set_supersampling`.
+    /// Everything bound to the old iteration texture is rebuilt.
+    pub fn set_supersampling(&mut self, factor: u32) {
+        let applied = self.computer.set_supersampling(&self.gpu, factor);
+        if applied != factor {
+            eprintln!(
+                "Warning: {}x supersampling exceeds the texture size limit; using {}x",
+                factor, applied
+            );
+        }
+        self.renderer.set_iteration_view(&self.gpu, &self.computer);
+        self.stats_reduction = StatsReduction::new(&self.gpu, &self.computer);
+        self.detail_reduction = DetailReduction::new(&self.gpu, &self.computer);
+        self.stats_params = None;
+        self.detail_params = None;
+        self.last_params = None;
+        println!("Supersampling: {}x", applied);
+    }
+
+    /// Renders the view in the current precision and the next one, prints how much they
+    /// disagree and saves the per-pixel difference as a heat map.
+    fn compare_precisions(&mut self) {
+        let precisions = [self.computer.precision(), self.computer.next_precision()];
+        if precisions[0] == precisions[1] {
+            println!("Only {:?} is supported; nothing to compare", precisions[0]);
+            return;
+        }
+        let params = self.params();
+        let result = compare::compare_precisions(
+            &self.gpu,
+            &self.computer.setup(),
+            &params,
+            self.image_size(),
+            precisions,
+        );
+        match result {
+            Ok(difference) => {
+                println!("{}", difference.summary());
+                let path = screenshot_path(SystemTime::now());
+                let path = path.with_file_name(format!(
+                    "{}_{:?}_vs_{:?}.png",
+                    path.file_stem().unwrap_or_default().to_string_lossy(),
+                    precisions[0],
+                    precisions[1]
+                ));
+                screenshot::save_in_background(difference.heat_map(), path);
+            }
+            Err(e) => eprintln!("Could not compare precisions: {:#}", e),
+        }
+    }
+
+    /// Stores the current view in `slot` and writes all bookmarks to disk. Its thumbnail is
+    /// rendered on the next frame.
+    fn save_bookmark(&mut self, slot: u8) {
+        self.bookmarks.set(slot, self.view_state());
+        let path = bookmarks_path();
+        match self.bookmarks.save(&path) {
+            Ok(()) => println!("Saved bookmark {} to {}", slot, path.display()),
+            Err(e) => eprintln!("Could not save bookmark {}: {:#}", slot, e),
+        }
+        self.pending_thumbnails.retain(|&pending| pending != slot);
+        self.pending_thumbnails.push(slot);
+    }
+
+    /// Renders the view in bookmark `slot` with the current palette, like a headless render,
+    /// and saves it next to the bookmarks file.
+    fn render_thumbnail(&mut self, slot: u8) {
+        let Some(state) = self.bookmarks.get(slot) else {
+            return;
+        };
+        let size = UVec2::new(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+        let setup = self.computer.setup();
+        let params = state.render_params(size, setup.formula.as_ref());
+        let color_params = ColorParams {
+            roots: params.newton_roots(),
+            ..state.color_mapping.color_params()
+        };
+        let palette = &self.palettes[self.palette_index];
+        let bookmarks = bookmarks_path();
+        let path = thumbnail_path(&bookmarks, slot);
+        let result = PosterJob::new(
+            &self.gpu,
+            size,
+            params,
+            color_params,
+            &setup,
+            palette,
+            path.clone(),
+        )
+        .and_then(|mut job| {
+            while !job.is_done() {
+                job.step(&self.gpu)?;
+            }
+            Ok(job.into_image().save(&path)?)
+        });
+        match result {
+            Ok(()) => {
+                self.bookmarks.set_thumbnail_palette(slot, &palette.name);
+                if let Err(e) = self.bookmarks.save(&bookmarks) {
+                    eprintln!("Could not save bookmarks: {:#}", e);
+                }
+            }
+            Err(e) => eprintln!("Could not save the thumbnail of bookmark {}: {:#}", slot, e),
+        }
+    }
+
+    /// Switches to the view in `slot`, flying there over `BOOKMARK_FLIGHT_SECONDS` if
+    /// `animate` is set.
+    fn jump_to_bookmark(&mut self, slot: u8, animate: bool) {
+        let Some(state) = self.bookmarks.get(slot).cloned() else {
+            println!("Bookmark {} is empty; Ctrl+{} saves it", slot, slot);
+            return;
+        };
+        let from = self.sample_location.clone();
+        self.apply_view_state(state);
+        if animate {
+            self.fly_from(from);
+        }
+        println!("Bookmark {}", slot);
+    }
+
+    /// Moves one view back in the history, or forward again with `forward`.
+    fn step_history(&mut self, forward: bool) {
+        // Whatever is in motion would otherwise be recorded over the restored view.
+        self.record_history();
+        self.bookmark_flight = None;
+        let state = if forward {
+            self.history.forward()
+        } else {
+            self.history.back()
+        };
+        match state.cloned() {
+            Some(mut state) => {
+                // The history only tracks where the view was, so keep the current colors.
+                state.color_mapping = self.color_mapping;
+                self.apply_view_state(state);
+                println!("{}", if forward { "Redo view" } else { "Undo view" });
+            }
+            None => println!("No view to {}", if forward { "redo" } else { "undo" }),
+        }
+    }
+
+    /// Pushes the current view onto the history if it moved since the last entry. Called once
+    /// navigation is at rest, so a drag or an animated zoom is recorded as one step.
+    fn record_history(&mut self) {
+        let moved = self
+            .history
+            .current()
+            .is_none_or(|state| !state.view.same_view(&self.sample_location));
+        if moved {
+            self.history.push(self.view_state());
+        }
+    }
+
+    /// Puts the view back to `from` and flies from there to the view that replaced it.
+    fn fly_from(&mut self, from: SampleLocation) {
+        self.bookmark_flight = Some(BookmarkFlight {
+            from: from.clone(),
+            to: self.sample_location.clone(),
+            started: Instant::now(),
+        });
+        self.sample_location = from;
+    }
+
+    /// Returns to the default framing and iteration count of the current fractal kind, or of
+    /// its Julia sets in Julia mode. The rotation is reset too.
+    fn reset_view(&mut self, animate: bool) {
+        let from = self.sample_location.clone();
+        self.sample_location = match self.fractal_mode {
+            FractalMode::Mandelbrot => self.fractal_kind.default_view(),
+            FractalMode::Julia => self.fractal_kind.julia_view(),
+        };
+        self.sample_location.set_output_size(self.image_size());
+        self.sample_location.set_move_speed(self.move_speed);
+        self.max_iterations = self.fractal_kind.default_iterations();
+        self.iteration_multiplier = 1.0;
+        if animate {
+            self.fly_from(from);
+        }
+        println!("View reset");
+    }
+
+    /// Cycles the iteration formula and its iteration counts. The view resets to the new
+    /// kind's default framing unless `keep_view` is set.
+    fn cycle_fractal_kind(&mut self, keep_view: bool) {
+        self.fractal_kind = self.fractal_kind.next();
+        if self.fractal_kind == FractalKind::Custom && self.computer.formula().is_none() {
+            self.fractal_kind = self.fractal_kind.next();
+        }
+        self.power = self.kind_power(self.fractal_kind);
+        self.max_iterations = self.fractal_kind.default_iterations();
+        self.auto_iterations.base = self.fractal_kind.base_iterations();
+        if !keep_view {
+            let mut view = self.fractal_kind.default_view();
+            view.set_output_size(self.image_size());
+            view.set_move_speed(self.move_speed);
+            match self.fractal_mode {
+                // Leaving Julia mode should land on the new kind's framing too.
+                FractalMode::Julia => self.mandelbrot_view = Some(view),
+                FractalMode::Mandelbrot => self.sample_location = view,
+            }
+        }
+        println!("Fractal kind: {:?}", self.fractal_kind);
+    }
+
+    /// Power to switch to along with `kind`, see `FractalKind::default_power`.
+    fn kind_power(&self, kind: FractalKind) -> f32 {
+        match (kind, self.computer.formula()) {
+            (FractalKind::Custom, Some(formula)) => formula.escape_power(),
+            _ => kind.default_power(),
+        }
+    }
+
+    /// Compiles `formula` and switches to it. Errors are reported and the previous formula, if
+    /// any, keeps running.
+    pub fn set_formula(&mut self, formula: CustomFormula) {
+        if let Err(e) = self.computer.set_formula(&self.gpu, formula) {
+            eprintln!("Could not compile the formula: {:#}", e);
+            return;
+        }
+        let formula = self.computer.formula().expect("the formula was just set");
+        println!("Formula: z = {}", formula);
+        self.fractal_kind = FractalKind::Custom;
+        self.power = formula.escape_power();
+        self.last_params = None;
+    }
+
+    /// Params of the Julia preview for the point under the cursor, if it is shown.
+    /// They only change when the cursor moves, which is when the preview is recomputed.
+    fn julia_preview_params(&self) -> Option<MandelbrotParams> {
+        if !self.julia_preview || self.fractal_mode != FractalMode::Mandelbrot {
+            return None;
+        }
+        let c = self
+            .sample_location
+            .screen_to_plane(self.cursor_image_point(), self.image_size());
+        let view = SampleLocation::default();
+        let mut params = view.to_mandlebrot_params(PREVIEW_ITERATIONS);
+        params.kind = self.fractal_kind as u32;
+        params.fractal_mode = FractalMode::Julia as u32;
+        params.julia_c = FVec2::from(c).into();
+        params.power = self.power;
+        params.escape_radius = self.escape_radius;
+        params.coloring_mode = self.coloring_mode as u32;
+        params.stripe_density = self.stripe_density;
+        params.stripe_mix = self.stripe_mix;
+        params.pixel_size = view.pixel_size(UVec2::new(PREVIEW_SIZE, PREVIEW_SIZE));
+        params.relief = self.relief as u32;
+        params.interior_check = 0;
+        Some(params)
+    }
+
+    /// Switches between the Mandelbrot and Julia sets. Entering Julia mode takes the center
+    /// of the current view as c and starts from the default view, leaving it restores the
+    /// Mandelbrot view.
+    fn toggle_fractal_mode(&mut self) {
+        match self.fractal_mode {
+            FractalMode::Mandelbrot => {
+                self.julia_c = self.params().center.into();
+                let mut julia_view = self.fractal_kind.julia_view();
+                julia_view.set_output_size(self.image_size());
+                julia_view.set_move_speed(self.move_speed);
+                self.mandelbrot_view =
+                    Some(std::mem::replace(&mut self.sample_location, julia_view));
+                self.fractal_mode = FractalMode::Julia;
+            }
+            FractalMode::Julia => {
+                if let Some(view) = self.mandelbrot_view.take() {
+                    self.sample_location = view;
+                    // The window may have been resized in the meantime.
+                    self.sample_location.set_output_size(self.image_size());
+                }
+                self.fractal_mode = FractalMode::Mandelbrot;
+            }
+        }
+        println!("Fractal: {:?}", self.fractal_mode);
+    }
+
+    pub fn color_params(&self) -> ColorParams {
+        ColorParams {
+            color_interior: self.coloring_mode.colors_interior() as u32,
+            boundary_only: (self.coloring_mode == ColoringMode::Boundary) as u32,
+            relief: self.relief as u32,
+            light_angle: self.light_angle,
+            light_height: self.light_height,
+            roots: self.params().newton_roots(),
+            ..self.color_mapping.color_params()
+        }
+    }
+
+    /// Points the relief light from the center of the window towards the cursor. The angle is
+    /// taken on the complex plane, so it follows the view's rotation.
+    fn point_light_at_cursor(&mut self) {
+        let offset = self
+            .sample_location
+            .screen_offset(self.cursor_image_point(), self.image_size());
+        self.light_angle = offset.y.atan2(offset.x) as f32;
+    }
+
+    /// Whether the view is deep enough to switch from `Computer` to `DeepComputer`.
+    fn uses_perturbation(&self, params: &MandelbrotParams) -> bool {
+        self.sample_location.zoom() < PERTURBATION_ZOOM && DeepComputer::supports(params)
+    }
+
+    /// Computes and presents one frame.
+    /// The fractal is only recomputed when the view changed; coloring reruns every frame.
+    /// While the view keeps changing, only the draft texture is computed and shown; the full
+    /// resolution follows once it has been still for `DRAFT_SETTLE_TIME`. With accumulation
+    /// on, a still view is then recomputed with a new jitter each frame and averaged, for
+    /// `ACCUMULATION_FRAMES` frames.
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        // Some platforms still ask minimized windows to redraw, but there is no surface to draw to.
+        if self.minimized {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let params = self.params();
+        if self.shown_params.is_some_and(|shown| shown != params) {
+            self.view_changed_at = start;
+        }
+        let moving = start.duration_since(self.view_changed_at) < DRAFT_SETTLE_TIME;
+        let mut draft = false;
+        let mut recomputed = false;
+        let color_params = self.color_params();
+        self.draw_editor_label();
+        self.draw_timing();
+        self.draw_grid();
+        self.draw_scale();
+        self.draw_selection();
+        self.draw_orbit();
+        if self.julia_preview_params().is_some() {
+            let (min, max) = self.renderer.preview_rect();
+            self.renderer
+                .overlay
+                .add_rect_outline(min, max, [1.0, 1.0, 1.0, 0.9]);
+        }
+        if self.uses_perturbation(&params) {
+            // Pans below f64 resolution leave the f32 params unchanged, so check the center too.
+            let center = self.sample_location.center();
+            if self.last_params != Some(params) || !self.deep_computer.is_current(center) {
+                self.deep_computer.run(
+                    &self.gpu,
+                    &params,
+                    center,
+                    self.sample_location.zoom(),
+                    self.computer.target_texture(),
+                    self.computer.texture_size,
+                );
+                self.computer.swap();
+                recomputed = true;
+            }
+        } else if self.last_params == Some(params) {
+            // The iteration texture is still current.
+        } else if self.progressive && moving {
+            // The full texture is left alone, so the draft shows until it is recomputed.
+            draft = true;
+            if self.draft_params != Some(params) {
+                self.draft_params = Some(params);
+                self.computer.run_draft(&self.gpu, &params);
+            }
+        } else if self.time_next_compute {
+            recomputed = true;
+            self.time_next_compute = false;
+            let start = Instant::now();
+            self.computer.run_blocking(&self.gpu, &params);
+            println!(
+                "Compute took {:.2} ms",
+                start.elapsed().as_secs_f64() * 1000.0
+            );
+        } else {
+            recomputed = true;
+            self.computer.run(&self.gpu, &params);
+        }
+        if self.color_mapping.mode == MappingMode::Histogram
+            && (recomputed || self.histogram_params != Some((params, draft)))
+        {
+            if draft {
+                self.computer.histogram.build(
+                    &self.gpu,
+                    &self.computer.draft_view,
+                    self.computer.draft_size,
+                    params.max_iterations,
+                );
+            } else {
+                self.computer
+                    .build_histogram(&self.gpu, params.max_iterations);
+            }
+            self.histogram_params = Some((params, draft));
+        }
+        if self.color_mapping.auto_range
+            && (recomputed || self.range_params != Some((params, draft)))
+        {
+            if draft {
+                self.computer
+                    .range
+                    .build(&self.gpu, &self.computer.draft_view);
+            } else {
+                self.computer.build_range(&self.gpu);
+            }
+            self.range_params = Some((params, draft));
+        }
+        let source = if draft {
+            FrameSource::Draft
+        } else if self.accumulation {
+            if recomputed || self.accumulated_colors != Some(color_params) {
+                // The unjittered frame, or the last jittered one, starts the average over.
+                self.accumulated = 0;
+                self.accumulated_colors = Some(color_params);
+            }
+            let frame_index = self.accumulated;
+            if frame_index < ACCUMULATION_FRAMES {
+                if frame_index > 0 {
+                    self.compute_jittered(&params, frame_index);
+                }
+                self.accumulated += 1;
+                FrameSource::Accumulate(frame_index)
+            } else {
+                FrameSource::Accumulated
+            }
+        } else {
+            FrameSource::Full
+        };
+        let preview_params = self.julia_preview_params();
+        if let Some(preview_params) = preview_params {
+            if self.preview_params != Some(preview_params) {
+                self.preview_params = Some(preview_params);
+                self.computer.run_preview(&self.gpu, &preview_params);
+            }
+        }
+        self.renderer.sync_iteration_view(&self.gpu, &self.computer);
+        let compute_done = Instant::now();
+        // Screenshots read the full-resolution colors, so they wait for the view to settle,
+        // and for accumulation to finish averaging.
+        let screenshot = self.renderer.render(
+            &self.gpu,
+            &color_params,
+            source,
+            preview_params.is_some(),
+            !draft && self.screenshot_requested && !matches!(source, FrameSource::Accumulate(_)),
+        )?;
+        if self.show_timing {
+            self.frame_timer
+                .record(start, compute_done - start, compute_done.elapsed());
+        }
+        // Without a free buffer the request stays, to be copied with a later frame.
+        if screenshot.is_some() {
+            self.screenshot = screenshot;
+            self.screenshot_requested = false;
+        }
+        self.frame += 1;
+
+        // Only reduce once the view has settled, i.e. it didn't change since the last frame.
+        let settled = self.last_params == Some(params);
+        if settled
+            && self.stats_params != Some(params)
+            && self
+                .stats_reduction
+                .dispatch(&self.gpu, &self.computer.iteration_view, self.frame)
+        {
+            self.stats_params = Some(params);
+        }
+        if settled
+            && self.detail_params != Some(params)
+            && self
+                .detail_reduction
+                .dispatch(&self.gpu, &self.computer.iteration_view, self.frame)
+        {
+            self.detail_params = Some(params);
+        }
+        self.shown_params = Some(params);
+        if !draft {
+            self.last_params = Some(params);
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Recomputes the iteration texture for `params` offset by the jitter of accumulated frame
+    /// `frame_index`.
+    fn compute_jittered(&mut self, params: &MandelbrotParams, frame_index: u32) {
+        let jittered = MandelbrotParams {
+            jitter: accumulate::jitter(frame_index, self.accumulation_seed),
+            ..*params
+        };
+        if self.uses_perturbation(params) {
+            // The reference orbit doesn't depend on the jitter, so it is reused.
+            self.deep_computer.run(
+                &self.gpu,
+                &jittered,
+                self.sample_location.center(),
+                self.sample_location.zoom(),
+                self.computer.target_texture(),
+                self.computer.texture_size,
+            );
+            self.computer.swap();
+        } else {
+            self.computer.run(&self.gpu, &jittered);
+        }
+    }
+
+    /// Called once per frame before redrawing.
+    /// Resizes the surface and, unless `compute_size` fixes it, the computed image, and keeps
+    /// the view's aspect ratio in step with the image.
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        self.minimized = new_size.width == 0 || new_size.height == 0;
+        if self.minimized {
+            // Nothing can be configured at 0x0; restoring resizes again.
+            return;
+        }
+        self.dirty = true;
+        let size = UVec2::new(new_size.width, new_size.height);
+        if size == self.renderer.size {
+            // Only the surface needs configuring again, after a restore or when it went out of
+            // date. Every texture is kept, so the last image shows again without a black frame.
+            self.gpu.configure_surface();
+            return;
+        }
+        let image_size = self.compute_size.unwrap_or(size);
+        if image_size != self.computer.output_size() {
+            self.set_output_size(image_size);
+        }
+        self.renderer
+            .resize(new_size, &mut self.gpu, &self.computer);
+        self.sample_location.set_output_size(self.image_size());
+    }
+
+    /// Computes at `size` from now on whatever the window size, letterboxed into the window,
+    /// or at the window size again with None.
+    pub fn set_compute_size(&mut self, size: Option<UVec2>) {
+        let max = self.gpu.device.limits().max_texture_dimension_2d;
+        if let Some(size) = size.filter(|size| size.x > max || size.y > max) {
+            eprintln!(
+                "Warning: compute size {}x{} exceeds the texture size limit of {}; following the window",
+                size.x, size.y, max
+            );
+            self.compute_size = None;
+        } else {
+            self.compute_size = size;
+        }
+        let image_size = self.compute_size.unwrap_or(self.renderer.size);
+        if image_size != self.computer.output_size() {
+            self.set_output_size(image_size);
+            self.renderer.set_image_size(&self.gpu, &self.computer);
+            self.sample_location.set_output_size(image_size);
+            self.dirty = true;
+        }
+    }
+
+    /// Resizes the computed image and drops everything derived from the old one.
+    fn set_output_size(&mut self, size: UVec2) {
+        self.computer.set_output_size(&self.gpu, size);
+        self.stats_reduction = StatsReduction::new(&self.gpu, &self.computer);
+        self.detail_reduction = DetailReduction::new(&self.gpu, &self.computer);
+        self.stats_params = None;
+        self.detail_params = None;
+        self.last_params = None;
+        self.draft_params = None;
+        self.histogram_params = None;
+        self.range_params = None;
+        self.accumulated_colors = None;
+    }
+
+    /// Catches up with the window's `size` if it changed without a `Resized` event, as
+    /// minimizing does on some platforms.
+    pub fn sync_size(&mut self, size: PhysicalSize<u32>) {
+        let minimized = size.width == 0 || size.height == 0;
+        if minimized != self.minimized
+            || (!minimized && UVec2::new(size.width, size.height) != self.renderer.size)
+        {
+            self.resize(size);
+        }
+    }
+
+    /// Switches between the window and borderless fullscreen on the monitor the window is on.
+    /// The resize that follows goes through `resize` like any other.
+    fn toggle_fullscreen(&mut self, window: &Window) {
+        if window.fullscreen().is_some() {
+            window.set_fullscreen(None);
+            if let Some((size, position)) = self.windowed_geometry.take() {
+                window.set_inner_size(size);
+                if let Some(position) = position {
+                    window.set_outer_position(position);
+                }
+            }
+        } else {
+            // Not every platform reports the window position.
+            self.windowed_geometry = Some((window.inner_size(), window.outer_position().ok()));
+            window.set_fullscreen(Some(Fullscreen::Borderless(window.current_monitor())));
+        }
+    }
+
+    /// Whether another frame is needed: after input, or while something is still changing
+    /// or waiting on the GPU. Otherwise the event loop can sleep until the next event.
+    /// A minimized window has nothing to show.
+    pub fn needs_redraw(&self) -> bool {
+        let params = Some(self.params());
+        let busy = self.palette_cycling.enabled
+            || self.show_timing
+            || self.bracket.is_some()
+            || self.poster.is_some()
+            || self.animation.is_some()
+            || self.bookmark_flight.is_some()
+            || !self.pending_thumbnails.is_empty()
+            || self.input.is_active()
+            || self.sample_location.is_animating()
+            || self.probe.is_some()
+            || self.hover_probe.is_some()
+            || self.hover_is_stale()
+            || self.title_pending
+            || self.screenshot_requested
+            || self.screenshot.is_some()
+            || self.stats_reduction.is_pending()
+            || self.detail_reduction.is_pending()
+            || (self.accumulation && self.accumulated < ACCUMULATION_FRAMES)
+            // The reductions are only dispatched once the view has settled for a frame.
+            || self.last_params != params
+            || self.stats_params != params
+            || self.detail_params != params;
+        !self.minimized && (self.dirty || busy)
+    }
+
+    pub fn update(&mut self, window: &Window) {
+        let now = Instant::now();
+        // Capped so that waking up after idling doesn't jump the palette.
+        let elapsed = now.duration_since(self.last_update).as_secs_f32().min(0.1);
+        self.last_update = now;
+        if !self.minimized {
+            self.palette_cycling
+                .advance(&mut self.color_mapping, elapsed);
+        }
+
+        #[cfg(feature = "hot-reload")]
+        if self
+            .shader_watcher
+            .as_ref()
+            .is_some_and(|watcher| watcher.poll_changed())
+        {
+            self.computer.reload_shaders(&self.gpu);
+            self.renderer.reload_shaders(&self.gpu);
+            // Recompute with the new pipelines even though the view didn't change.
+            self.last_params = None;
+            self.draft_params = None;
+            self.dirty = true;
+        }
+
+        if !self.minimized {
+            // Motion starting after an idle spell is timed from now, not from the last event.
+            let elapsed = if self.navigating { elapsed } else { 0.0 };
+            self.apply_held_keys(elapsed);
+            self.sample_location.update(elapsed);
+            self.navigating = self.input.is_active() || self.sample_location.is_animating();
+        }
+
+        if let Some(flight) = &self.bookmark_flight {
+            let t = (flight.started.elapsed().as_secs_f64() / BOOKMARK_FLIGHT_SECONDS).min(1.0);
+            // Eased so the flight starts and lands gently.
+            let eased = t * t * (3.0 - 2.0 * t);
+            self.sample_location = flight.from.interpolate(&flight.to, eased);
+            if t >= 1.0 {
+                self.bookmark_flight = None;
+            }
+        }
+
+        let at_rest = !self.navigating
+            && self.bookmark_flight.is_none()
+            && self.drag_position.is_none()
+            && !self.touches.is_active()
+            && self.animation.is_none();
+        if at_rest {
+            self.record_history();
+        }
+
+        if let Some(stats) = self.stats_reduction.poll(&self.gpu) {
+            if let Some(params) = self.stats_params {
+                self.stats = Some((stats, params));
+            }
+        }
+        if let Some(scores) = self.detail_reduction.poll(&self.gpu) {
+            if let Some(params) = self.detail_params {
+                self.detail = Some((scores, params));
+            }
+        }
+        if std::mem::take(&mut self.fullscreen_requested) {
+            self.toggle_fullscreen(window);
+        }
+        self.update_hover();
+        // Titles are cheap to build but not to set, so changes are applied at most every
+        // TITLE_INTERVAL; needs_redraw keeps frames coming until the last one is shown.
+        let title = self.status_title();
+        self.title_pending = title != self.title;
+        if self.title_pending && now.duration_since(self.title_set_at) >= TITLE_INTERVAL {
+            window.set_title(&title);
+            self.title = title;
+            self.title_set_at = now;
+            self.title_pending = false;
+        }
+
+        // One bracket image per frame keeps the live view responsive and the job cancellable.
+        if let Some(job) = &mut self.bracket {
+            match job.step(&self.gpu) {
+                Ok(path) => {
+                    let (done, total) = job.progress();
+                    println!("Bracket {}/{}: saved {}", done, total, path.display());
+                }
+                Err(e) => {
+                    eprintln!("Bracketed screenshot failed: {}", e);
+                    self.bracket = None;
+                }
+            }
+            if self.bracket.as_ref().is_some_and(BracketJob::is_done) {
+                self.bracket = None;
+            }
+        }
+
+        // Like brackets, one animation frame per frame.
+        if let Some(job) = &mut self.animation {
+            match job.step(&self.gpu) {
+                Ok(()) => {
+                    let (done, total) = job.progress();
+                    println!("Animation frame {}/{}", done, total);
+                }
+                Err(e) => {
+                    eprintln!("Animation failed: {:#}", e);
+                    self.animation = None;
+                }
+            }
+        }
+        if self.animation.as_ref().is_some_and(AnimationJob::is_done) {
+            if let Some(job) = self.animation.take() {
+                println!(
+                    "Animation done; encode with: ffmpeg -framerate {} -i {}/frame_%05d.png animation.mp4",
+                    self.animation_fps,
+                    job.directory.display()
+                );
+            }
+        }
+
+        // Like brackets, one poster tile per frame.
+        if let Some(job) = &mut self.poster {
+            match job.step(&self.gpu) {
+                Ok(()) => {
+                    let (done, total) = job.progress();
+                    println!("Poster tile {}/{}", done, total);
+                }
+                Err(e) => {
+                    eprintln!("Poster failed: {}", e);
+                    self.poster = None;
+                }
+            }
+        }
+        if self.poster.as_ref().is_some_and(PosterJob::is_done) {
+            if let Some(job) = self.poster.take() {
+                let path = job.path.clone();
+                screenshot::save_in_background(job.into_image(), path);
+            }
+        }
+
+        // Thumbnails show the palette, so they are checked again whenever it changes. Edits
+        // in the palette editor keep the name and don't count, or every drag would re-render.
+        let palette = &self.palettes[self.palette_index].name;
+        if self.thumbnail_palette.as_ref() != Some(palette) {
+            self.pending_thumbnails = self.bookmarks.stale_thumbnails(&bookmarks_path(), palette);
+            self.thumbnail_palette = Some(palette.clone());
+        }
+        // Like brackets, one thumbnail per frame.
+        if let Some(slot) = self.pending_thumbnails.pop() {
+            self.render_thumbnail(slot);
+        }
+
+        if let Some(screenshot) = &self.screenshot {
+            if let Some(result) = self.renderer.poll_screenshot(&self.gpu, screenshot) {
+                match result {
+                    Ok(image) => {
+                        screenshot::save_in_background(image, screenshot_path(SystemTime::now()))
+                    }
+                    Err(e) => eprintln!("Screenshot failed: {:?}", e),
+                }
+                self.screenshot = None;
+            }
+        }
+
+        if let Some(probe) = &self.probe {
+            if let Some(result) = probe.poll(&self.gpu, &mut self.probes) {
+                match result {
+                    Ok(sample) => report_probe(probe, sample),
+                    Err(e) => eprintln!("Pixel probe failed: {:?}", e),
+                }
+                self.probe = None;
+            }
+        }
+    }
+
+    fn status_title(&self) -> String {
+        let curve = match self.color_mapping.mode {
+            MappingMode::Power => format!("x^{:.2}", self.color_mapping.exponent),
+            mode => format!("{:?}", mode).to_lowercase(),
+        };
+        let mapping = format!(
+            "{} {} offset {:.2}{}",
+            curve,
+            if self.color_mapping.auto_range {
+                "auto range".to_string()
+            } else {
+                format!("density {:.1}", self.color_mapping.density)
+            },
+            self.color_mapping.offset,
+            if self.palette_cycling.enabled {
+                " (cycling)"
+            } else {
+                ""
+            }
+        );
+        let stats = match &self.stats {
+            Some((stats, params)) => {
+                let stale = if self.last_params == Some(*params) {
+                    ""
+                } else {
+                    " (stale)"
+                };
+                format!(
+                    " | escaped {:.1}% | capped {:.1}% | mean {:.1} it | frame {}{}",
+                    stats.escaped_fraction * 100.0,
+                    stats.capped_fraction * 100.0,
+                    stats.mean_iterations,
+                    stats.frame,
+                    stale
+                )
+            }
+            None => String::new(),
+        };
+        let mut fractal = match self.fractal_kind {
+            FractalKind::Mandelbrot => String::new(),
+            kind => format!(" | {:?}", kind),
+        };
+        fractal += &match self.fractal_mode {
+            FractalMode::Mandelbrot => String::new(),
+            FractalMode::Julia => format!(" | Julia {:.4} {:+.4}i", self.julia_c.x, self.julia_c.y),
+        };
+        if self.fractal_kind == FractalKind::Newton {
+            fractal += &format!(" | z^{} - 1", self.params().newton_roots());
+        } else if let (FractalKind::Custom, Some(formula)) =
+            (self.fractal_kind, self.computer.formula())
+        {
+            fractal += &format!(" | z = {}", formula);
+        } else if self.power != 2.0 {
+            fractal += &format!(" | z^{}", self.power);
+        }
+        if self.escape_radius != ESCAPE_RADIUS {
+            fractal += &format!(" | R {}", self.escape_radius);
+        }
+        let (center, center_lo) = self.sample_location.center();
+        let digits = self.coordinate_digits() + 1;
+        let magnification = self.sample_location.magnification();
+        let im = format_fixed((center.y, center_lo.y), digits);
+        let view = format!(
+            "{} {}{}i | {}",
+            format_fixed((center.x, center_lo.x), digits),
+            if im.starts_with('-') { "" } else { "+" },
+            im,
+            if magnification < 1000.0 {
+                format!("{:.1}×", magnification)
+            } else {
+                format!("{:.1e}×", magnification)
+            }
+        );
+        format!(
+            "{}{} | {} | {:?} | {} | {} it{} | {}{}{}",
+            WINDOW_TITLE,
+            fractal,
+            view,
+            self.gpu.present_mode(),
+            if self.uses_perturbation(&self.params()) {
+                "Perturbation".to_string()
+            } else {
+                format!("{:?}", self.computer.precision())
+            },
+            self.iterations(),
+            if self.auto_iterations.enabled {
+                format!(" (auto x{})", self.iteration_multiplier)
+            } else {
+                String::new()
+            },
+            mapping,
+            stats,
+            self.hover_readout()
+        )
+    }
+
+    /// Decimal places that tell neighbouring pixels apart at the current zoom.
+    fn coordinate_digits(&self) -> usize {
+        let pixel_size = self.sample_location.pixel_size(self.image_size());
+        (-pixel_size.log10()).ceil().max(0.0) as usize + 1
+    }
+
+    /// The value read back for the pixel under the cursor, if it is of the current view.
+    fn hover_sample(&self) -> Option<PixelSample> {
+        let current = (self.cursor_pixel().0, self.params());
+        self.hover
+            .filter(|&(pixel, params, _)| (pixel, params) == current)
+            .map(|(_, _, sample)| sample)
+    }
+
+    /// Whether the title lacks the value of the pixel under the cursor.
+    fn hover_is_stale(&self) -> bool {
+        self.cursor_in_window && self.hover_sample().is_none()
+    }
+
+    /// Moves the view for the navigation keys held over the last `elapsed` seconds.
+    fn apply_held_keys(&mut self, elapsed: f32) {
+        let speed = if self.modifiers.shift() {
+            FINE_NAVIGATION_SPEED
+        } else {
+            1.0
+        };
+        if let Some(direction) = self.input.pan_direction() {
+            self.sample_location
+                .pan_steps(direction, PAN_STEPS_PER_SECOND * speed * elapsed);
+        }
+        let zoom = self.input.zoom_direction();
+        if zoom != 0.0 {
+            self.sample_location
+                .zoom_by(2f32.powf(zoom * ZOOM_DOUBLINGS_PER_SECOND * speed * elapsed));
+        }
+    }
+
+    /// Reads back the pixel under the cursor once it has rested for `HOVER_SETTLE_TIME` over a
+    /// full-resolution frame of the current view. One readback at a time, so moving the mouse
+    /// never queues more than one copy.
+    fn update_hover(&mut self) {
+        if let Some(probe) = &self.hover_probe {
+            if let Some(result) = probe.poll(&self.gpu, &mut self.probes) {
+                match result {
+                    Ok(sample) => self.hover = Some((probe.pixel, probe.params, sample)),
+                    Err(e) => eprintln!("Hover readback failed: {:?}", e),
+                }
+                self.hover_probe = None;
+            }
+        }
+        let params = self.params();
+        if self.hover_probe.is_none()
+            && self.hover_is_stale()
+            && self.cursor_moved_at.elapsed() >= HOVER_SETTLE_TIME
+            && self.last_params == Some(params)
+        {
+            let (pixel, point) = self.cursor_pixel();
+            self.hover_probe = PixelProbe::new(
+                &self.gpu,
+                &mut self.probes,
+                &self.computer.iteration_texture,
+                pixel,
+                point,
+                params,
+            );
+        }
+    }
+
+    /// Title suffix with the point under the cursor and, once read back, its value.
+    fn hover_readout(&self) -> String {
+        if !self.cursor_in_window {
+            return String::new();
+        }
+        let point = self
+            .sample_location
+            .screen_to_plane(self.cursor_image_point(), self.image_size());
+        // The cursor point is only computed in f64.
+        let digits = self.coordinate_digits().min(16);
+        let value = match self.hover_sample() {
+            Some(sample) if self.coloring_mode != ColoringMode::EscapeTime => {
+                format!(" value {:.2}", sample.smooth_iterations)
+            }
+            Some(sample) if sample.escaped => format!(" {:.2} it", sample.smooth_iterations),
+            Some(_) => " inside".to_string(),
+            None => String::new(),
+        };
+        format!(
+            " | {:.*} {:+.*}i{}",
+            digits, point.x, digits, point.y, value
+        )
+    }
+
+    /// Size of the computed image: the window size, or `compute_size` when set.
+    fn image_size(&self) -> UVec2 {
+        self.renderer.image_size
+    }
+
+    /// Pixel of the computed image under `position` in the window. The two differ when the
+    /// image is letterboxed; positions on the bars land outside the image.
+    fn image_point(&self, position: FVec2) -> FVec2 {
+        target_to_content(position, self.image_size(), self.renderer.size)
+    }
+
+    fn cursor_image_point(&self) -> FVec2 {
+        self.image_point(FVec2::from(self.cursor_position))
+    }
+
+    /// The iteration texture pixel under the cursor, clamped to the texture, and its point.
+    fn cursor_pixel(&self) -> (UVec2, DVec2) {
+        let texture_size = self.computer.texture_size;
+        let image_size = self.image_size();
+        let cursor = self.cursor_image_point();
+        let x = cursor.x as f64 * texture_size.width as f64 / image_size.x as f64;
+        let y = cursor.y as f64 * texture_size.height as f64 / image_size.y as f64;
+        let pixel = UVec2::new(
+            (x.max(0.0) as u32).min(texture_size.width - 1),
+            (y.max(0.0) as u32).min(texture_size.height - 1),
+        );
+        let point = self.sample_location.screen_to_plane(
+            FVec2::from(pixel),
+            UVec2::new(texture_size.width, texture_size.height),
+        );
+        (pixel, point)
+    }
+
+    /// Starts a readback of the iteration texture at the pixel under the cursor.
+    fn probe_cursor(&mut self) {
+        let (pixel, point) = self.cursor_pixel();
+        let params = self.params();
+        self.probe = PixelProbe::new(
+            &self.gpu,
+            &mut self.probes,
+            &self.computer.iteration_texture,
+            pixel,
+            point,
+            params,
+        );
+        if self.probe.is_none() {
+            eprintln!("Pixel probe skipped: earlier probes are still being read back");
+        }
+    }
+}
+
+fn report_probe(probe: &PixelProbe, sample: PixelSample) {
+    let params = &probe.params;
+    if params.coloring_mode != ColoringMode::EscapeTime as u32
+        || params.kind == FractalKind::Custom as u32
+    {
+        // The CPU reference only has iteration counts of the built-in formulas to compare
+        // against.
+        println!(
+            "Pixel ({}, {}) at {} {:+}i: palette value {:.4}, |z| {:.4}, escaped {}",
+            probe.pixel.x,
+            probe.pixel.y,
+            probe.point.x,
+            probe.point.y,
+            sample.smooth_iterations,
+            sample.z_abs,
+            sample.escaped
+        );
+        return;
+    }
+    if params.kind == FractalKind::Newton as u32 {
+        report_newton_probe(probe, sample);
+        return;
+    }
+    let (re, im) = (probe.point.x, probe.point.y);
+    let escape_radius = params.escape_radius as f64;
+    let formula = cpu_reference::Formula {
+        power: params.power as f64,
+        conjugate: params.kind == FractalKind::Tricorn as u32,
+    };
+    let reference = if params.fractal_mode == FractalMode::Julia as u32 {
+        let c = (params.julia_c[0] as f64, params.julia_c[1] as f64);
+        cpu_reference::julia(re, im, c, formula, params.max_iterations, escape_radius)
+    } else {
+        cpu_reference::mandelbrot(re, im, formula, params.max_iterations, escape_radius)
+    };
+    println!(
+        "Pixel ({}, {}) at {} {:+}i: smooth iterations {:.4}, |z| {:.4}, escaped {}",
+        probe.pixel.x,
+        probe.pixel.y,
+        probe.point.x,
+        probe.point.y,
+        sample.smooth_iterations,
+        sample.z_abs,
+        sample.escaped
+    );
+    println!(
+        "    CPU reference: smooth iterations {:.4}, |z| {:.4}, escaped {}",
+        reference.smooth_iterations, reference.z_abs, reference.escaped
+    );
+    let difference = (sample.smooth_iterations as f64 - reference.smooth_iterations).abs();
+    if sample.escaped != reference.escaped || difference > PROBE_TOLERANCE {
+        eprintln!(
+            "    !!! MISMATCH: GPU differs from CPU reference by {:.4} iterations",
+            difference
+        );
+    }
+}
+
+/// `report_probe` for `FractalKind::Newton`, whose texels hold the root and step count.
+fn report_newton_probe(probe: &PixelProbe, sample: PixelSample) {
+    let params = &probe.params;
+    let c = if params.fractal_mode == FractalMode::Julia as u32 {
+        (params.julia_c[0] as f64, params.julia_c[1] as f64)
+    } else {
+        (0.0, 0.0)
+    };
+    let reference = cpu_reference::newton(
+        (probe.point.x, probe.point.y),
+        c,
+        params.newton_roots(),
+        params.max_iterations,
+        params.newton_tolerance as f64,
+    );
+    // The root index is written where the other kinds write |z|.
+    let root = sample.escaped.then_some(sample.z_abs as u32);
+    println!(
+        "Pixel ({}, {}) at {} {:+}i: root {:?} after {:.4} steps",
+        probe.pixel.x, probe.pixel.y, probe.point.x, probe.point.y, root, sample.smooth_iterations
+    );
+    println!(
+        "    CPU reference: root {:?} after {} steps",
+        reference.root, reference.iterations
+    );
+    if root != reference.root {
+        eprintln!("    !!! MISMATCH: GPU converged to a different root than the CPU reference");
+    }
+}
+
+/// Bookmark slot of a number key 1..9.
+fn bookmark_slot(key: VirtualKeyCode) -> Option<u8> {
+    match key {
+        VirtualKeyCode::Key1 => Some(1),
+        VirtualKeyCode::Key2 => Some(2),
+        VirtualKeyCode::Key3 => Some(3),
+        VirtualKeyCode::Key4 => Some(4),
+        VirtualKeyCode::Key5 => Some(5),
+        VirtualKeyCode::Key6 => Some(6),
+        VirtualKeyCode::Key7 => Some(7),
+        VirtualKeyCode::Key8 => Some(8),
+        VirtualKeyCode::Key9 => Some(9),
+        _ => None,
     }
 }
@@ -0,0 +1,157 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::{
+    colorize::{ColorParams, Colorizer},
+    computer::{ComputeSetup, Computer, FractalMode, MandelbrotParams},
+    gpu_interface::GPUInterface,
+    math::UVec2,
+    palette::{MappingMode, Palette},
+};
+
+/// Settings shared by every image of a bracket, written next to them as JSON.
+#[derive(Serialize)]
+struct BracketMetadata {
+    width: u32,
+    height: u32,
+    /// The view as `ViewState::location_text` prints it. Unlike `center`, which only holds
+    /// the f32 high part, this is exact at any zoom and can be passed back to `--location`.
+    location: String,
+    center: [f32; 2],
+    transform: [f32; 4],
+    /// Constant c when the view is of a Julia set.
+    julia_c: Option<[f32; 2]>,
+    power: f32,
+    /// `FractalKind` discriminant.
+    kind: u32,
+    /// Formula of a `FractalKind::Custom` view.
+    formula: Option<String>,
+    palette: String,
+    palette_density: f32,
+    palette_offset: f32,
+    mapping_mode: u32,
+    mapping_exponent: f32,
+    iterations: Vec<i32>,
+    /// Images written so far, in the same order as `iterations`.
+    files: Vec<String>,
+}
+
+/// Renders one view at several max_iterations counts, one image per `step`.
+/// It has its own `Computer` and `Colorizer`, so the live view is untouched and memory stays at
+/// one output texture however many counts are requested.
+pub struct BracketJob {
+    computer: Computer,
+    colorizer: Colorizer,
+    params: MandelbrotParams,
+    color_params: ColorParams,
+    next: usize,
+    stem: String,
+    metadata: BracketMetadata,
+}
+
+impl BracketJob {
+    pub fn new(
+        gpu: &GPUInterface,
+        size: UVec2,
+        params: MandelbrotParams,
+        color_params: ColorParams,
+        setup: &ComputeSetup,
+        palette: &Palette,
+        iterations: Vec<i32>,
+    ) -> anyhow::Result<BracketJob> {
+        let computer = setup.build(gpu, size)?;
+        let colorizer = Colorizer::new(
+            gpu,
+            size,
+            &computer.iteration_view,
+            computer.histogram.cdf_buffer(),
+            computer.range.range_buffer(),
+        );
+        colorizer.set_palette(gpu, palette);
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Ok(BracketJob {
+            computer,
+            colorizer,
+            params,
+            color_params,
+            next: 0,
+            stem: format!("bracket_{}", timestamp),
+            metadata: BracketMetadata {
+                width: size.x,
+                height: size.y,
+                location: String::new(),
+                center: params.center,
+                transform: params.transform,
+                power: params.power,
+                kind: params.kind,
+                formula: setup.formula.as_ref().map(|formula| formula.to_string()),
+                julia_c: (params.fractal_mode == FractalMode::Julia as u32)
+                    .then_some(params.julia_c),
+                palette: palette.name.clone(),
+                palette_density: color_params.palette_density,
+                palette_offset: color_params.palette_offset,
+                mapping_mode: color_params.mapping_mode,
+                mapping_exponent: color_params.mapping_exponent,
+                iterations,
+                files: Vec::new(),
+            },
+        })
+    }
+
+    /// Records `location`, from `ViewState::location_text`, in the metadata.
+    pub fn with_location(mut self, location: String) -> BracketJob {
+        self.metadata.location = location;
+        self
+    }
+
+    /// Number of images finished and the total requested.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.next, self.metadata.iterations.len())
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next >= self.metadata.iterations.len()
+    }
+
+    /// Renders and saves the next image, then rewrites the metadata so a cancelled
+    /// bracket still describes the files it produced. Returns the path written.
+    pub fn step(&mut self, gpu: &GPUInterface) -> anyhow::Result<PathBuf> {
+        let max_iterations = self.metadata.iterations[self.next];
+        let params = MandelbrotParams {
+            max_iterations,
+            ..self.params
+        };
+        self.computer.run(gpu, &params);
+        if self.color_params.mapping_mode == MappingMode::Histogram as u32 {
+            self.computer.build_histogram(gpu, max_iterations);
+        }
+        if self.color_params.auto_range == 1 {
+            self.computer.build_range(gpu);
+        }
+        self.colorizer.run(gpu, &self.color_params);
+        let path = PathBuf::from(format!("{}_{}.png", self.stem, max_iterations));
+        self.colorizer.read_output(gpu)?.save(&path)?;
+        self.next += 1;
+
+        self.metadata.files.push(path.display().to_string());
+        let metadata_path = PathBuf::from(format!("{}.json", self.stem));
+        std::fs::write(metadata_path, serde_json::to_string_pretty(&self.metadata)?)?;
+        Ok(path)
+    }
+}
+
+/// Parses a comma separated list of iteration counts such as "500,1000,2000".
+pub fn parse_iterations(list: &str) -> Result<Vec<i32>, String> {
+    list.split(',')
+        .map(|s| {
+            s.trim()
+                .parse::<i32>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("invalid iteration count '{}'", s.trim()))
+        })
+        .collect()
+}
@@ -0,0 +1,507 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    computer::compute_work_group_count,
+    gpu_interface::GPUInterface,
+    math::UVec2,
+    palette::{Palette, LUT_SIZE},
+    readback::{Readback, ReadbackId},
+};
+
+/// Uniforms of `colorize.wgsl`: how smooth iteration counts map onto the palette.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+pub struct ColorParams {
+    /// Smooth iterations per full palette cycle.
+    pub palette_density: f32,
+    /// Palette phase shift in cycles.
+    pub palette_offset: f32,
+    /// `palette::MappingMode` applied before density and offset.
+    pub mapping_mode: u32,
+    pub mapping_exponent: f32,
+    /// 1 to color points that never escaped through the palette too, for orbit traps.
+    pub color_interior: u32,
+    /// 1 to draw black boundary lines on white instead of using the palette, for
+    /// `ColoringMode::Boundary`.
+    pub boundary_only: u32,
+    /// 1 to light escaped pixels as a relief, from the normal angle the compute shader writes
+    /// when `MandelbrotParams::relief` is set.
+    pub relief: u32,
+    /// Direction the light comes from in the complex plane, in radians.
+    pub light_angle: f32,
+    /// Height of the light over the plane relative to its horizontal distance; lower lights
+    /// give deeper shadows.
+    pub light_height: f32,
+    /// 1 to stretch one palette cycle over the escaped iteration range found by
+    /// `range::RangeReduction`, in place of density.
+    pub auto_range: u32,
+    /// Number of roots of a `FractalKind::Newton` image, whose pixels take one palette color
+    /// per root shaded by their convergence speed; 0 for the other kinds. See
+    /// `MandelbrotParams::newton_roots`.
+    pub roots: u32,
+    pub _padding: u32,
+}
+
+/// Turns the iteration texture written by `Computer` into colors through the palette lookup
+/// texture. It is a single texture read per pixel, so palette and mapping changes only rerun
+/// this pass and never the fractal itself.
+pub struct Colorizer {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    params_buffer: wgpu::Buffer,
+    palette_texture: wgpu::Texture,
+    palette_view: wgpu::TextureView,
+    output_texture: wgpu::Texture,
+    output_view: wgpu::TextureView,
+    /// `output_format` on this device.
+    format: wgpu::TextureFormat,
+    /// Binds the iteration texture being colorized; rebuilt only by `set_input`.
+    bind_group: wgpu::BindGroup,
+    /// Binds the `histogram::Histogram` distribution used by `MappingMode::Histogram` and the
+    /// `range::RangeReduction` result used by `ColorParams::auto_range`.
+    analysis_bind_group: wgpu::BindGroup,
+    texture_size: wgpu::Extent3d,
+}
+
+impl Colorizer {
+    /// Colorizes `iteration_view` into a `size` image. The iteration texture must be `size`
+    /// or, when supersampled, an integer multiple of it; the samples of each pixel are averaged.
+    /// `histogram` is the `Histogram::cdf_buffer` histogram mapping reads and `range` the
+    /// `RangeReduction::range_buffer` auto range reads.
+    pub fn new(
+        gpu: &GPUInterface,
+        size: UVec2,
+        iteration_view: &wgpu::TextureView,
+        histogram: &wgpu::Buffer,
+        range: &wgpu::Buffer,
+    ) -> Colorizer {
+        let texture_size = wgpu::Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        };
+        let format = output_format(gpu);
+        let output_texture = create_output_texture(gpu, texture_size, format);
+        let palette_texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Palette texture"),
+            size: wgpu::Extent3d {
+                width: LUT_SIZE,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let params_buffer = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Color params buffer"),
+                contents: bytemuck::bytes_of(&ColorParams::zeroed()),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let shader = gpu
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Colorize shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    include_str!("shaders/colorize.wgsl")
+                        .replace("rgba8unorm", storage_format_name(format))
+                        .into(),
+                ),
+            });
+
+        // Float32 textures aren't filterable, so the layout can't be derived from the shader.
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        };
+        let bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Colorize bind group layout"),
+                    entries: &[
+                        texture_entry(0),
+                        texture_entry(1),
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 3,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::StorageTexture {
+                                access: wgpu::StorageTextureAccess::WriteOnly,
+                                format,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+        let analysis_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let analysis_bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Colorize analysis bind group layout"),
+                    entries: &[analysis_entry(0), analysis_entry(1)],
+                });
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Colorize pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout, &analysis_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let pipeline = gpu
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Colorize pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "main",
+            });
+
+        let palette_view = palette_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = create_bind_group(
+            gpu,
+            &bind_group_layout,
+            iteration_view,
+            &palette_view,
+            &params_buffer,
+            &output_view,
+        );
+        let analysis_bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Colorize analysis bind group"),
+            layout: &analysis_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: histogram.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: range.as_entire_binding(),
+                },
+            ],
+        });
+
+        Colorizer {
+            pipeline,
+            bind_group_layout,
+            params_buffer,
+            palette_texture,
+            palette_view,
+            output_texture,
+            output_view,
+            format,
+            bind_group,
+            analysis_bind_group,
+            texture_size,
+        }
+    }
+
+    /// Colorizes `iteration_view` from now on, for when the iteration texture is replaced.
+    /// Its size follows the same rule as in `new`.
+    pub fn set_input(&mut self, gpu: &GPUInterface, iteration_view: &wgpu::TextureView) {
+        self.bind_group = create_bind_group(
+            gpu,
+            &self.bind_group_layout,
+            iteration_view,
+            &self.palette_view,
+            &self.params_buffer,
+            &self.output_view,
+        );
+    }
+
+    /// Colorizes `iteration_view` into a `size` image from now on, for when the output is
+    /// resized. The sizes follow the same rule as in `new`; the palette is kept.
+    pub fn resize(&mut self, gpu: &GPUInterface, size: UVec2, iteration_view: &wgpu::TextureView) {
+        self.texture_size = wgpu::Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        };
+        self.output_texture = create_output_texture(gpu, self.texture_size, self.format);
+        self.output_view = self
+            .output_texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.set_input(gpu, iteration_view);
+    }
+
+    /// View of the colorized texture written by `encode` and `run`.
+    pub fn output_view(&self) -> &wgpu::TextureView {
+        &self.output_view
+    }
+
+    /// Uploads `palette` as the lookup texture used by subsequent runs.
+    pub fn set_palette(&self, gpu: &GPUInterface, palette: &Palette) {
+        let lut = palette.to_lut();
+        gpu.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &self.palette_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            bytemuck::cast_slice(&lut),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(LUT_SIZE * 4),
+                rows_per_image: std::num::NonZeroU32::new(1),
+            },
+            wgpu::Extent3d {
+                width: LUT_SIZE,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Records the colorize pass into `encoder`.
+    pub fn encode(
+        &self,
+        gpu: &GPUInterface,
+        encoder: &mut wgpu::CommandEncoder,
+        params: &ColorParams,
+    ) {
+        gpu.queue
+            .write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(params));
+        let (dispatch_width, dispatch_height) = compute_work_group_count(
+            (self.texture_size.width, self.texture_size.height),
+            (16, 16),
+        );
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Colorize pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.analysis_bind_group, &[]);
+        compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+    }
+
+    /// Colorizes the iteration texture in its own submission.
+    pub fn run(&self, gpu: &GPUInterface, params: &ColorParams) {
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Colorize encoder"),
+            });
+        self.encode(gpu, &mut encoder, params);
+        gpu.queue.submit(Some(encoder.finish()));
+    }
+
+    /// Records a copy of the output of the last run into `encoder`, to be read back once the
+    /// encoder is submitted. Returns None when every buffer of `readback` is in flight.
+    pub fn encode_readback(
+        &self,
+        gpu: &GPUInterface,
+        encoder: &mut wgpu::CommandEncoder,
+        readback: &mut Readback,
+    ) -> Option<OutputReadback> {
+        let size = UVec2::new(self.texture_size.width, self.texture_size.height);
+        OutputReadback::encode(
+            gpu,
+            encoder,
+            readback,
+            &self.output_texture,
+            size,
+            self.format,
+        )
+    }
+
+    /// Copies the output of the last run back to the CPU, blocking until the GPU is done.
+    pub fn read_output(&self, gpu: &GPUInterface) -> anyhow::Result<image::RgbaImage> {
+        let mut readback = Readback::new("Output readback buffer", 1);
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Output readback encoder"),
+            });
+        let output = self
+            .encode_readback(gpu, &mut encoder, &mut readback)
+            .expect("a new ring has a free buffer");
+        gpu.queue.submit(Some(encoder.finish()));
+        readback.submitted();
+        let bytes = readback
+            .wait(gpu, output.id)
+            .map_err(|e| anyhow::anyhow!("could not read back the image: {:?}", e))?;
+        Ok(output.to_image(bytes))
+    }
+}
+
+/// Format of the colorized image: Rgba8Unorm, or Rgba16Float on adapters that can't write
+/// Rgba8Unorm from a compute shader. Everything downstream samples it, so only readbacks see
+/// the difference. Rgba16Float storage is required anyway, by `accumulate` and `postprocess`.
+fn output_format(gpu: &GPUInterface) -> wgpu::TextureFormat {
+    if gpu.supports_storage(wgpu::TextureFormat::Rgba8Unorm) {
+        wgpu::TextureFormat::Rgba8Unorm
+    } else {
+        wgpu::TextureFormat::Rgba16Float
+    }
+}
+
+/// WGSL name of an `output_format`.
+fn storage_format_name(format: wgpu::TextureFormat) -> &'static str {
+    match format {
+        wgpu::TextureFormat::Rgba16Float => "rgba16float",
+        _ => "rgba8unorm",
+    }
+}
+
+/// Bytes per pixel of an `output_format`.
+fn bytes_per_pixel(format: wgpu::TextureFormat) -> usize {
+    match format {
+        wgpu::TextureFormat::Rgba16Float => 8,
+        _ => 4,
+    }
+}
+
+fn create_output_texture(
+    gpu: &GPUInterface,
+    size: wgpu::Extent3d,
+    format: wgpu::TextureFormat,
+) -> wgpu::Texture {
+    gpu.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Colorized texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::TEXTURE_BINDING,
+    })
+}
+
+fn create_bind_group(
+    gpu: &GPUInterface,
+    layout: &wgpu::BindGroupLayout,
+    iteration_view: &wgpu::TextureView,
+    palette_view: &wgpu::TextureView,
+    params_buffer: &wgpu::Buffer,
+    output_view: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Colorize bind group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(iteration_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(palette_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: params_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(output_view),
+            },
+        ],
+    })
+}
+
+/// An in-flight copy of a colorized image, made through a [`Readback`] ring; call `poll`
+/// once per frame.
+pub struct OutputReadback {
+    id: ReadbackId,
+    size: UVec2,
+    format: wgpu::TextureFormat,
+}
+
+impl OutputReadback {
+    /// Records a copy of `texture`, a `size` color texture in RGBA8 or Rgba16Float, into
+    /// `encoder`. Returns None when every buffer of `readback` is in flight.
+    pub fn encode(
+        gpu: &GPUInterface,
+        encoder: &mut wgpu::CommandEncoder,
+        readback: &mut Readback,
+        texture: &wgpu::Texture,
+        size: UVec2,
+        format: wgpu::TextureFormat,
+    ) -> Option<OutputReadback> {
+        let id = readback.copy(
+            gpu,
+            encoder,
+            texture,
+            UVec2::new(0, 0),
+            size,
+            bytes_per_pixel(format),
+        )?;
+        Some(OutputReadback { id, size, format })
+    }
+
+    /// Returns Some once the copy has completed, with the pixels converted to RGBA8.
+    pub fn poll(
+        &self,
+        gpu: &GPUInterface,
+        readback: &mut Readback,
+    ) -> Option<Result<image::RgbaImage, wgpu::BufferAsyncError>> {
+        Some(
+            readback
+                .take(gpu, self.id)?
+                .map(|bytes| self.to_image(bytes)),
+        )
+    }
+
+    fn to_image(&self, bytes: Vec<u8>) -> image::RgbaImage {
+        let pixels = if bytes_per_pixel(self.format) == 4 {
+            bytes
+        } else {
+            bytes
+                .chunks_exact(2)
+                .map(|half| {
+                    let value = f16_to_f32(u16::from_le_bytes([half[0], half[1]]));
+                    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+                })
+                .collect()
+        };
+        image::RgbaImage::from_raw(self.size.x, self.size.y, pixels)
+            .expect("readback holds exactly one RGBA image")
+    }
+}
+
+/// Decodes an IEEE half float, as read back from an Rgba16Float texture.
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{
+    computer::{ComputeSetup, MandelbrotParams, Precision},
+    gpu_interface::GPUInterface,
+    math::UVec2,
+};
+
+/// Disagreement of one smooth iteration count that saturates the heat map. Differences worth
+/// looking at are far below one iteration, so the map is amplified.
+const HEAT_MAP_FULL_SCALE: f32 = 0.01;
+
+/// Per-pixel disagreement between two renders of one view in different precisions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrecisionDifference {
+    pub precisions: [Precision; 2],
+    pub size: UVec2,
+    /// Absolute difference in smooth iterations, row-major. Zero where both stayed inside
+    /// the set, None where only one of them escaped.
+    pub differences: Vec<Option<f32>>,
+}
+
+impl PrecisionDifference {
+    /// Compares two results of `Computer::compute_to_vec`, as `[smooth, _, escaped, _]` texels.
+    pub fn from_texels(
+        precisions: [Precision; 2],
+        size: UVec2,
+        a: &[[f32; 4]],
+        b: &[[f32; 4]],
+    ) -> PrecisionDifference {
+        let differences = a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| match (a[2] > 0.5, b[2] > 0.5) {
+                (true, true) => Some((a[0] - b[0]).abs()),
+                (false, false) => Some(0.0),
+                _ => None,
+            })
+            .collect();
+        PrecisionDifference {
+            precisions,
+            size,
+            differences,
+        }
+    }
+
+    /// Largest difference over the pixels that agree on escaping.
+    pub fn max(&self) -> f32 {
+        self.differences
+            .iter()
+            .flatten()
+            .fold(0.0, |max, &d| max.max(d))
+    }
+
+    /// Mean difference over the pixels that agree on escaping.
+    pub fn mean(&self) -> f64 {
+        let (sum, count) = self
+            .differences
+            .iter()
+            .flatten()
+            .fold((0.0, 0), |(sum, count), &d| (sum + d as f64, count + 1));
+        if count == 0 {
+            0.0
+        } else {
+            sum / count as f64
+        }
+    }
+
+    /// Pixels that escaped in one precision but not the other.
+    pub fn escape_mismatches(&self) -> usize {
+        self.differences.iter().filter(|d| d.is_none()).count()
+    }
+
+    /// The differences as black through red and yellow to white at `HEAT_MAP_FULL_SCALE`,
+    /// with escape mismatches in blue.
+    pub fn heat_map(&self) -> image::RgbaImage {
+        let pixels = self
+            .differences
+            .iter()
+            .flat_map(|difference| match difference {
+                Some(d) => {
+                    let t = 3.0 * (d / HEAT_MAP_FULL_SCALE).min(1.0);
+                    let channel = |offset: f32| ((t - offset).clamp(0.0, 1.0) * 255.0) as u8;
+                    [channel(0.0), channel(1.0), channel(2.0), 255]
+                }
+                None => [0, 128, 255, 255],
+            })
+            .collect();
+        image::RgbaImage::from_raw(self.size.x, self.size.y, pixels)
+            .expect("one difference per pixel")
+    }
+
+    /// One line with the statistics, for printing.
+    pub fn summary(&self) -> String {
+        let [a, b] = self.precisions;
+        format!(
+            "{:?} vs {:?}: max {:.3e}, mean {:.3e} smooth iterations; {} pixels escaped in only one",
+            a,
+            b,
+            self.max(),
+            self.mean(),
+            self.escape_mismatches()
+        )
+    }
+}
+
+/// Computes `params` at `size` once in each of `precisions`, with `setup`'s formula, and
+/// compares the results. Fails if the device lacks either precision.
+pub fn compare_precisions(
+    gpu: &GPUInterface,
+    setup: &ComputeSetup,
+    params: &MandelbrotParams,
+    size: UVec2,
+    precisions: [Precision; 2],
+) -> anyhow::Result<PrecisionDifference> {
+    let mut texels = Vec::new();
+    for precision in precisions {
+        let setup = ComputeSetup {
+            precision,
+            ..setup.clone()
+        };
+        let computer = setup.build(gpu, size)?;
+        anyhow::ensure!(
+            computer.precision() == precision,
+            "this device doesn't support {:?}",
+            precision
+        );
+        let bytes = computer.compute_to_vec(gpu, params, size)?;
+        texels.push(bytemuck::cast_slice::<u8, [f32; 4]>(&bytes).to_vec());
+    }
+    Ok(PrecisionDifference::from_texels(
+        precisions, size, &texels[0], &texels[1],
+    ))
+}
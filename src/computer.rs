@@ -1,247 +1,1672 @@
+use std::{fmt, str::FromStr, sync::mpsc::channel, time::Instant};
+
 use bytemuck::{Pod, Zeroable};
-use wgpu::{util::DeviceExt, BufferBindingType, Extent3d, TextureFormat, TextureSampleType};
+use serde::{Deserialize, Serialize};
+use wgpu::Extent3d;
+use winit::dpi::PhysicalPosition;
 
+#[cfg(feature = "hot-reload")]
+use crate::hot_reload;
 use crate::{
+    formula::CustomFormula,
     gpu_interface::GPUInterface,
-    math::{FVec2, UVec2},
+    histogram::Histogram,
+    math::{DVec2, FVec2, Mat2, UVec2, Viewport},
+    range::RangeReduction,
 };
 
-#[derive(Debug)]
+/// Serialized as part of `state::ViewState`; fields missing from a file keep their defaults.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SampleLocation {
-    position: FVec2,
+    /// Kept in f64 so deep zooms can still be centered precisely; see `DVec2::split`.
+    position: DVec2,
+    /// Rounding error of `position`, so the center survives zooms past f64 resolution.
+    /// Only changed through `translate`.
+    position_lo: DVec2,
     zoom: f32,
+    rotation: f32,
     move_speed: f32,
+    /// Width over height of the output the view is displayed in. Follows the window, so it
+    /// isn't saved.
+    #[serde(skip)]
+    aspect: f32,
+    /// Zoom being animated towards by `update`, None once settled. Not saved, so a view
+    /// saved mid-animation reloads where it was.
+    #[serde(skip)]
+    zoom_target: Option<ZoomTarget>,
+}
+
+/// Zoom an animating view eases towards, see `SampleLocation::update`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ZoomTarget {
+    zoom: f32,
+    /// Normalized screen position that stays fixed while zooming.
+    anchor: DVec2,
 }
 
+/// Rate at which an animated zoom closes the remaining (logarithmic) distance to its target,
+/// per second. About 95% of the way after a quarter second.
+const ZOOM_ANIMATION_RATE: f32 = 12.0;
+
+/// Ratio to the target below which an animated zoom snaps to it and settles.
+const ZOOM_SETTLE_RATIO: f32 = 1e-3;
+
 impl Default for SampleLocation {
     fn default() -> Self {
         Self {
-            position: FVec2 { x: 0.0, y: 0.0 },
+            position: DVec2::new(0.0, 0.0),
+            position_lo: DVec2::new(0.0, 0.0),
             zoom: 1.0,
+            rotation: 0.0,
             move_speed: 0.05,
+            aspect: 1.0,
+            zoom_target: None,
         }
     }
 }
 
 impl SampleLocation {
+    /// A view centered on `position` with `zoom`, unrotated.
+    pub fn new(position: DVec2, zoom: f32) -> SampleLocation {
+        SampleLocation {
+            position,
+            zoom,
+            ..Default::default()
+        }
+    }
+
+    /// A view centered on the double-double `(position, position_lo)` with `zoom`, unrotated.
+    pub fn from_center((position, position_lo): (DVec2, DVec2), zoom: f32) -> SampleLocation {
+        SampleLocation {
+            position,
+            position_lo,
+            zoom,
+            ..Default::default()
+        }
+    }
+
     pub fn to_mandlebrot_params(&self, max_iterations: i32) -> MandelbrotParams {
-        let x_min = self.position.x - (self.zoom);
-        let x_max = self.position.x + (self.zoom);
-        let y_min = self.position.y - (self.zoom);
-        let y_max = self.position.y + (self.zoom);
+        let (center, center_lo) = self.position.split();
+        let center_lo = center_lo + FVec2::from(self.position_lo);
         MandelbrotParams {
-            x_min,
-            x_max,
-            y_min,
-            y_max,
+            transform: self.transform().as_vec4(),
+            center: center.into(),
             max_iterations,
+            escape_radius: ESCAPE_RADIUS,
+            julia_c: [0.0, 0.0],
+            fractal_mode: FractalMode::Mandelbrot as u32,
+            power: 2.0,
+            kind: FractalKind::Mandelbrot as u32,
+            interior_check: 1,
+            center_lo: center_lo.into(),
+            jitter: [0.0, 0.0],
+            coloring_mode: ColoringMode::EscapeTime as u32,
+            pixel_size: 0.0,
+            trap_params: DEFAULT_TRAP_PARAMS,
+            relief: 0,
+            newton_tolerance: NEWTON_TOLERANCE,
+            tile_origin: [0, 0],
+            stripe_density: DEFAULT_STRIPE_DENSITY,
+            stripe_mix: DEFAULT_STRIPE_MIX,
+            _padding: [0, 0],
+        }
+    }
+
+    /// Rejects views that can't be rendered, e.g. ones read from a hand-edited file.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let finite = [
+            self.position.x,
+            self.position.y,
+            self.position_lo.x,
+            self.position_lo.y,
+        ]
+        .iter()
+        .all(|v| v.is_finite());
+        anyhow::ensure!(finite, "position is not finite");
+        anyhow::ensure!(
+            self.zoom.is_finite() && self.zoom > 0.0,
+            "zoom must be positive, got {}",
+            self.zoom
+        );
+        anyhow::ensure!(self.rotation.is_finite(), "rotation is not finite");
+        anyhow::ensure!(
+            self.move_speed.is_finite() && self.move_speed > 0.0,
+            "move_speed must be positive, got {}",
+            self.move_speed
+        );
+        Ok(())
+    }
+
+    /// Whether both frame the same part of the plane, whatever output they are shown in.
+    pub fn same_view(&self, other: &SampleLocation) -> bool {
+        self.position == other.position
+            && self.position_lo == other.position_lo
+            && self.zoom == other.zoom
+            && self.rotation == other.rotation
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    pub fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    /// How many times larger than the default view everything appears.
+    pub fn magnification(&self) -> f64 {
+        1.0 / self.zoom as f64
+    }
+
+    /// View center as an unevaluated sum of two f64, for the perturbation reference orbit.
+    pub fn center(&self) -> (DVec2, DVec2) {
+        (self.position, self.position_lo)
+    }
+
+    /// The view a fraction `t` of the way from `self` to `end`. Zoom is interpolated
+    /// exponentially so every frame zooms by the same factor. The center moves in proportion
+    /// to how much of the zoom has happened, which keeps the end center at a fixed spot on
+    /// screen rather than letting it drift out of view while zooming in.
+    pub fn interpolate(&self, end: &SampleLocation, t: f64) -> SampleLocation {
+        let (start_zoom, end_zoom) = (self.zoom as f64, end.zoom as f64);
+        let zoom = start_zoom * (end_zoom / start_zoom).powf(t);
+        let weight = if start_zoom == end_zoom {
+            t
+        } else {
+            (start_zoom - zoom) / (start_zoom - end_zoom)
+        };
+        let mut view = self.clone();
+        view.translate(
+            ((end.position - self.position) + (end.position_lo - self.position_lo)) * weight,
+        );
+        view.zoom = zoom as f32;
+        view.zoom_target = None;
+        view.rotation = self.rotation + (end.rotation - self.rotation) * t as f32;
+        view
+    }
+
+    /// Moves the center by `delta`, keeping the rounding error in `position_lo`.
+    fn translate(&mut self, delta: DVec2) {
+        let two_sum = |a: f64, b: f64| {
+            let s = a + b;
+            let v = s - a;
+            (s, (a - (s - v)) + (b - v))
+        };
+        for (hi, lo, d) in [
+            (&mut self.position.x, &mut self.position_lo.x, delta.x),
+            (&mut self.position.y, &mut self.position_lo.y, delta.y),
+        ] {
+            let (sum, error) = two_sum(*hi, d);
+            (*hi, *lo) = two_sum(sum, *lo + error);
+        }
+    }
+
+    /// Offset from the view center of the point under `pixel`.
+    pub fn screen_offset(&self, pixel: FVec2, size: UVec2) -> DVec2 {
+        self.viewport(size).screen_offset(pixel_position(pixel))
+    }
+
+    /// `transform` applied in f64. Navigation steps through this rather than the f32 matrix,
+    /// whose rounding would otherwise accumulate into drift over many pans.
+    fn plane_offset(&self, uv: DVec2) -> DVec2 {
+        // Offsets of normalized coordinates don't depend on the output size.
+        self.viewport(UVec2::new(1, 1)).offset(uv)
+    }
+
+    /// The mapping between the pixels of a `size` sized output and the complex plane.
+    pub fn viewport(&self, size: UVec2) -> Viewport {
+        let extent = self.extent();
+        Viewport {
+            center: self.position,
+            scale: DVec2::new(
+                self.zoom as f64 * extent.x as f64,
+                self.zoom as f64 * extent.y as f64,
+            ),
+            rotation: self.rotation as f64,
+            size,
+        }
+    }
+
+    /// Sets the aspect ratio from the size of the output the view is shown in.
+    pub fn set_output_size(&mut self, size: UVec2) {
+        self.aspect = size.x.max(1) as f32 / size.y.max(1) as f32;
+    }
+
+    /// Half extent of the view along each screen axis in units of `zoom`.
+    /// The shorter axis spans `position ± zoom` and the longer one is widened to match the aspect.
+    fn extent(&self) -> FVec2 {
+        FVec2 {
+            x: self.aspect.max(1.0),
+            y: (1.0 / self.aspect).max(1.0),
         }
     }
 
+    /// Maps normalized screen coordinates ([-1, 1] on both axes) onto the complex plane,
+    /// relative to the view center.
+    pub fn transform(&self) -> Mat2 {
+        self.viewport(UVec2::new(1, 1)).transform()
+    }
+
+    /// Complex-plane distance between neighbouring pixels of a `size` sized output.
+    pub fn pixel_size(&self, size: UVec2) -> f32 {
+        2.0 * self.zoom * self.extent().y / size.y.max(1) as f32
+    }
+
+    /// Converts a pixel position in a `size` sized output to a point on the complex plane.
+    pub fn screen_to_plane(&self, pixel: FVec2, size: UVec2) -> DVec2 {
+        self.viewport(size).screen_to_complex(pixel_position(pixel))
+    }
+
+    /// Inverse of `screen_to_plane`.
+    pub fn plane_to_screen(&self, point: DVec2, size: UVec2) -> FVec2 {
+        self.viewport(size).complex_to_screen(point)
+    }
+
+    /// Moves the view by `screen_delta` in normalized screen units, so panning follows the
+    /// screen axes even when the view is rotated. The delta is divided by the axis extent, so
+    /// equal steps cover the same distance horizontally and vertically.
+    fn pan(&mut self, screen_delta: FVec2) {
+        let extent = self.extent();
+        let delta = self.plane_offset(DVec2::new(
+            screen_delta.x as f64 / extent.x as f64,
+            screen_delta.y as f64 / extent.y as f64,
+        ));
+        self.translate(delta);
+    }
+
+    /// Moves the view center to the point under `pixel`.
+    pub fn center_on_pixel(&mut self, pixel: FVec2, size: UVec2) {
+        self.translate(self.screen_offset(pixel, size));
+    }
+
     pub fn left(&mut self) {
-        self.position.x -= self.zoom * self.move_speed;
+        self.pan(FVec2::new(-self.move_speed, 0.0));
     }
 
     pub fn right(&mut self) {
-        self.position.x += self.zoom * self.move_speed;
+        self.pan(FVec2::new(self.move_speed, 0.0));
     }
 
     pub fn up(&mut self) {
-        self.position.y -= self.zoom * self.move_speed;
+        self.pan(FVec2::new(0.0, -self.move_speed));
     }
 
     pub fn down(&mut self) {
-        self.position.y += self.zoom * self.move_speed;
+        self.pan(FVec2::new(0.0, self.move_speed));
+    }
+
+    /// Pans `steps` arrow key steps along the screen `direction`, for movement that is applied
+    /// a fraction of a step per frame.
+    pub fn pan_steps(&mut self, direction: FVec2, steps: f32) {
+        self.pan(direction * (self.move_speed * steps));
+    }
+
+    /// Centers the view on the pixel rectangle spanned by `a` and `b`, zooming so the whole
+    /// rectangle fits. The longer side relative to the window decides, which letterboxes the other.
+    pub fn zoom_to_rect(&mut self, a: FVec2, b: FVec2, size: UVec2) {
+        let center = a.lerp(b, 0.5);
+        let fraction_x = (a.x - b.x).abs() / size.x as f32;
+        let fraction_y = (a.y - b.y).abs() / size.y as f32;
+        self.center_on_pixel(center, size);
+        self.zoom *= fraction_x.max(fraction_y);
+        self.zoom_target = None;
+    }
+
+    /// Moves the view so the point that was under pixel `from` ends up under pixel `to`.
+    pub fn drag(&mut self, from: FVec2, to: FVec2, size: UVec2) {
+        let a = self.screen_offset(from, size);
+        let b = self.screen_offset(to, size);
+        self.translate(a - b);
+    }
+
+    /// Scales the zoom by `factor` while keeping the point under `pixel` fixed on screen.
+    pub fn zoom_about(&mut self, pixel: FVec2, size: UVec2, factor: f32) {
+        self.zoom_target = None;
+        let anchor = self.viewport(size).normalize(pixel_position(pixel));
+        self.set_zoom_about(anchor, self.zoom * factor);
+    }
+
+    /// Like `zoom_about`, but eases into the new zoom over the next `update`s. Zooming again
+    /// before the animation settles scales its target, so quick wheel notches add up.
+    pub fn animate_zoom_about(&mut self, pixel: FVec2, size: UVec2, factor: f32) {
+        let anchor = self.viewport(size).normalize(pixel_position(pixel));
+        self.animate_zoom(anchor, factor);
     }
 
+    fn animate_zoom(&mut self, anchor: DVec2, factor: f32) {
+        let zoom = self.zoom_target.map_or(self.zoom, |target| target.zoom);
+        self.zoom_target = Some(ZoomTarget {
+            zoom: zoom * factor,
+            anchor,
+        });
+    }
+
+    /// Whether an animated zoom has yet to reach its target.
+    pub fn is_animating(&self) -> bool {
+        self.zoom_target.is_some()
+    }
+
+    /// Advances an animated zoom by `elapsed` seconds. The zoom closes a fixed fraction of the
+    /// remaining ratio per unit of time, so every frame of it looks equally fast.
+    pub fn update(&mut self, elapsed: f32) {
+        let Some(target) = self.zoom_target else {
+            return;
+        };
+        let remaining = (target.zoom / self.zoom).ln() * (-ZOOM_ANIMATION_RATE * elapsed).exp();
+        if remaining.abs() < ZOOM_SETTLE_RATIO {
+            self.set_zoom_about(target.anchor, target.zoom);
+            self.zoom_target = None;
+        } else {
+            self.set_zoom_about(target.anchor, target.zoom / remaining.exp());
+        }
+    }
+
+    /// Changes the zoom while keeping the point at normalized screen position `anchor` fixed.
+    fn set_zoom_about(&mut self, anchor: DVec2, zoom: f32) {
+        let before = self.plane_offset(anchor);
+        self.zoom = zoom;
+        let after = self.plane_offset(anchor);
+        self.translate(before - after);
+    }
+
+    /// Sets the distance `move_left` and friends pan by, as a fraction of the view.
+    pub fn set_move_speed(&mut self, move_speed: f32) {
+        self.move_speed = move_speed;
+    }
+
+    /// Halves the zoom about the view center, animated by `update`.
     pub fn zoom_in(&mut self) {
-        self.zoom *= 0.5;
+        self.animate_zoom(DVec2::new(0.0, 0.0), 0.5);
     }
 
+    /// Doubles the zoom about the view center, animated by `update`.
     pub fn zoom_out(&mut self) {
-        self.zoom *= 2.0;
+        self.animate_zoom(DVec2::new(0.0, 0.0), 2.0);
+    }
+
+    /// Scales the zoom by `factor` about the view center at once; below 1 zooms in.
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.zoom *= factor;
+        self.zoom_target = None;
+    }
+
+    pub fn rotate(&mut self, angle: f32) {
+        self.rotation = (self.rotation + angle) % std::f32::consts::TAU;
     }
 }
 
+fn pixel_position(pixel: FVec2) -> PhysicalPosition<f64> {
+    PhysicalPosition::new(pixel.x as f64, pixel.y as f64)
+}
+
+/// Compute shader of each precision: file name under `src/shaders` and the embedded source.
+const SHADERS: [(Precision, &str, &str); 3] = [
+    (
+        Precision::F32,
+        "mandelbrot.wgsl",
+        include_str!("shaders/mandelbrot.wgsl"),
+    ),
+    (
+        Precision::DoubleSingle,
+        "mandelbrot_ds.wgsl",
+        include_str!("shaders/mandelbrot_ds.wgsl"),
+    ),
+    (
+        Precision::F64,
+        "mandelbrot_f64.wgsl",
+        include_str!("shaders/mandelbrot_f64.wgsl"),
+    ),
+];
+
+/// Workgroup size the compute shaders are written for.
+pub const DEFAULT_WORKGROUP_SIZE: UVec2 = UVec2 { x: 16, y: 16 };
+
+/// Invocations per workgroup every device supports.
+pub const MAX_WORKGROUP_INVOCATIONS: u32 = 256;
+
+/// Workgroup sizes `WorkgroupSize::Auto` chooses between.
+pub const AUTOTUNE_CANDIDATES: [UVec2; 4] = [
+    UVec2 { x: 8, y: 8 },
+    UVec2 { x: 16, y: 8 },
+    UVec2 { x: 16, y: 16 },
+    UVec2 { x: 32, y: 8 },
+];
+
+/// Side of the image each autotune candidate is timed on.
+const AUTOTUNE_IMAGE_SIZE: u32 = 512;
+/// Timed dispatches per candidate; the fastest counts.
+const AUTOTUNE_RUNS: usize = 3;
+
+/// How `Computer` splits a compute into dispatches. A single dispatch over a huge texture at
+/// high iteration counts can run past the OS's GPU watchdog, which resets the device.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tiling {
+    /// Side of the square tiles in iteration texture pixels; edge tiles are cut to fit.
+    pub tile_size: u32,
+    /// Wait for each tile before submitting the next, so the desktop gets the GPU in between.
+    pub wait_between: bool,
+}
+
+impl Tiling {
+    /// Used by the offline renders, whose sizes and iteration counts are unbounded.
+    pub const EXPORT: Tiling = Tiling {
+        tile_size: 512,
+        wait_between: true,
+    };
+
+    /// Origin and size of each tile covering `size`, row by row.
+    pub fn tiles(&self, size: UVec2) -> impl Iterator<Item = (UVec2, UVec2)> {
+        let tile = self.tile_size.max(1);
+        (0..size.y).step_by(tile as usize).flat_map(move |y| {
+            (0..size.x).step_by(tile as usize).map(move |x| {
+                (
+                    UVec2::new(x, y),
+                    UVec2::new(tile.min(size.x - x), tile.min(size.y - y)),
+                )
+            })
+        })
+    }
+}
+
+/// Workgroup size of the iteration shaders: "WxH", or "auto" to time `AUTOTUNE_CANDIDATES`
+/// at startup and take the fastest.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum WorkgroupSize {
+    Fixed(UVec2),
+    Auto,
+}
+
+impl WorkgroupSize {
+    /// The fixed size, or the autotuned one.
+    pub fn resolve(self, gpu: &GPUInterface) -> UVec2 {
+        match self {
+            WorkgroupSize::Fixed(size) => size,
+            WorkgroupSize::Auto => Computer::autotune_workgroup_size(gpu),
+        }
+    }
+}
+
+impl Default for WorkgroupSize {
+    fn default() -> Self {
+        WorkgroupSize::Fixed(DEFAULT_WORKGROUP_SIZE)
+    }
+}
+
+impl FromStr for WorkgroupSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().eq_ignore_ascii_case("auto") {
+            return Ok(WorkgroupSize::Auto);
+        }
+        let size: UVec2 = s.parse()?;
+        if size.x * size.y > MAX_WORKGROUP_INVOCATIONS {
+            return Err(format!(
+                "workgroup size {}x{} has more than {} invocations",
+                size.x, size.y, MAX_WORKGROUP_INVOCATIONS
+            ));
+        }
+        Ok(WorkgroupSize::Fixed(size))
+    }
+}
+
+impl TryFrom<String> for WorkgroupSize {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for WorkgroupSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkgroupSize::Fixed(size) => write!(f, "{}x{}", size.x, size.y),
+            WorkgroupSize::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+impl From<WorkgroupSize> for String {
+    fn from(size: WorkgroupSize) -> Self {
+        size.to_string()
+    }
+}
+
+/// Escape radius used for interactive views.
+pub const ESCAPE_RADIUS: f32 = 256.0;
+
+/// Convergence threshold of `FractalKind::Newton`: a few times f32 resolution around the
+/// unit circle, where the roots of z^d - 1 lie.
+pub const NEWTON_TOLERANCE: f32 = 1.0e-5;
+
+/// Derives max_iterations from the zoom level, so deeper views get more iterations.
+#[derive(Copy, Clone, Debug)]
+pub struct AutoIterations {
+    pub enabled: bool,
+    /// Iterations at the default zoom of 1.
+    pub base: f32,
+    /// Upper limit of the derived count, before any manual multiplier.
+    pub cap: f32,
+}
+
+impl Default for AutoIterations {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            base: 100.0,
+            cap: 50_000.0,
+        }
+    }
+}
+
+impl AutoIterations {
+    /// Grows quadratically with the number of halvings of `zoom`:
+    /// about 100 at the full set and a few thousand by a zoom of 1e-5.
+    pub fn iterations(&self, zoom: f32) -> f32 {
+        let octaves = (1.0 / zoom).log2().max(0.0);
+        (self.base * (1.0 + octaves / 4.0).powi(2)).min(self.cap)
+    }
+}
+
+/// Uniforms of the compute shaders: everything that decides the image apart from its size.
+/// Usually made with `SampleLocation::to_mandlebrot_params`.
 #[repr(C)]
-#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
 pub struct MandelbrotParams {
-    pub x_min: f32,
-    pub x_max: f32,
-    pub y_min: f32,
-    pub y_max: f32,
+    /// Column-major 2x2 view transform, see `SampleLocation::transform`.
+    pub transform: [f32; 4],
+    pub center: [f32; 2],
     pub max_iterations: i32,
+    /// Bailout radius. Far larger than 2 so the smooth iteration count has no visible bands.
+    pub escape_radius: f32,
+    /// Constant c of the Julia set, ignored in Mandelbrot mode.
+    pub julia_c: [f32; 2],
+    /// `FractalMode` to render.
+    pub fractal_mode: u32,
+    /// Exponent d of z^d + c; 2 is the classic set.
+    pub power: f32,
+    /// `FractalKind` iteration formula.
+    pub kind: u32,
+    /// 1 to skip points in the main cardioid and period-2 bulb, see `interior_check_applies`.
+    pub interior_check: u32,
+    /// Rounding error of `center`, for the double precision paths.
+    pub center_lo: [f32; 2],
+    /// Sub-pixel offset of every sample, in pixels. Zero except for the jittered frames of
+    /// `accumulate::Accumulator`.
+    pub jitter: [f32; 2],
+    /// `ColoringMode` of the value written for the palette.
+    pub coloring_mode: u32,
+    /// Complex-plane step between output pixels, see `SampleLocation::pixel_size`. Distance
+    /// estimates are written in units of it.
+    pub pixel_size: f32,
+    /// Orbit trap shape: position (x, y), circle radius and line angle in radians.
+    pub trap_params: [f32; 4],
+    /// 1 to write the surface normal angle in place of |z| for relief lighting, see
+    /// `colorize::ColorParams::relief`.
+    pub relief: u32,
+    /// Step size below which `FractalKind::Newton` counts a point as converged.
+    pub newton_tolerance: f32,
+    /// Pixel of the iteration texture the dispatch starts at, nonzero for all but the first
+    /// tile of a tiled compute, see `Tiling`.
+    pub tile_origin: [u32; 2],
+    /// Frequency k of the sin(k arg z) that `ColoringMode::Stripes` averages over the orbit.
+    pub stripe_density: f32,
+    /// How much of the stripe average `ColoringMode::Stripes` mixes into the smooth iteration
+    /// count, from 0 for none to 1 for stripes only.
+    pub stripe_mix: f32,
+    pub _padding: [u32; 2],
+}
+
+impl MandelbrotParams {
+    /// Whether the cardioid and bulb shortcut is valid, which only holds for the classic
+    /// Mandelbrot set. Julia sets, other powers and other kinds have a different interior,
+    /// and orbit traps color the interior by its orbits.
+    pub fn interior_check_applies(&self) -> bool {
+        let traps = ColoringMode::PointTrap as u32..=ColoringMode::CircleTrap as u32;
+        self.fractal_mode == FractalMode::Mandelbrot as u32
+            && self.kind == FractalKind::Mandelbrot as u32
+            && self.power == 2.0
+            && !traps.contains(&self.coloring_mode)
+    }
+
+    /// Number of roots `FractalKind::Newton` colors by, the power rounded to a whole degree of
+    /// at least 2, or 0 for the other kinds. Becomes `colorize::ColorParams::roots`.
+    pub fn newton_roots(&self) -> u32 {
+        if self.kind == FractalKind::Newton as u32 {
+            self.power.round().max(2.0) as u32
+        } else {
+            0
+        }
+    }
+}
+
+// Must match the MandelbrotParams struct in the compute shaders, whose uniform size is rounded
+// up to a multiple of 16.
+const _: () = assert!(std::mem::size_of::<MandelbrotParams>() == 128);
+
+/// Point trap at the origin, line trap along the real axis and the unit circle.
+pub const DEFAULT_TRAP_PARAMS: [f32; 4] = [0.0, 0.0, 1.0, 0.0];
+
+/// Stripe frequency and mix of `ColoringMode::Stripes` until changed with the keys.
+pub const DEFAULT_STRIPE_DENSITY: f32 = 5.0;
+pub const DEFAULT_STRIPE_MIX: f32 = 0.75;
+
+/// What the compute shaders write as the palette value of each pixel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColoringMode {
+    /// Smooth iteration count; points that never escape are black.
+    EscapeTime = 0,
+    /// Closest approach of the orbit to the point at `MandelbrotParams::trap_params`.
+    PointTrap = 1,
+    /// Closest approach to the line through the trap position at the trap angle.
+    LineTrap = 2,
+    /// Closest approach to the circle around the trap position with the trap radius.
+    CircleTrap = 3,
+    /// Estimated distance to the boundary of the set in pixels, from the derivative dz/dc.
+    /// Resolves filaments that escape time coloring smears out.
+    DistanceEstimate = 4,
+    /// Only dark lines where the boundary is closer than a pixel, on white.
+    Boundary = 5,
+    /// Average of sin(k arg z) over the orbit, mixed into the smooth iteration count. Brings
+    /// out the spiral structure around the set; see `MandelbrotParams::stripe_density`.
+    Stripes = 6,
 }
 
+impl ColoringMode {
+    pub fn next(self) -> ColoringMode {
+        match self {
+            ColoringMode::EscapeTime => ColoringMode::PointTrap,
+            ColoringMode::PointTrap => ColoringMode::LineTrap,
+            ColoringMode::LineTrap => ColoringMode::CircleTrap,
+            ColoringMode::CircleTrap => ColoringMode::DistanceEstimate,
+            ColoringMode::DistanceEstimate => ColoringMode::Boundary,
+            ColoringMode::Boundary => ColoringMode::Stripes,
+            ColoringMode::Stripes => ColoringMode::EscapeTime,
+        }
+    }
+
+    /// Whether every point gets a palette value, including those that never escape.
+    pub fn colors_interior(self) -> bool {
+        matches!(
+            self,
+            ColoringMode::PointTrap | ColoringMode::LineTrap | ColoringMode::CircleTrap
+        )
+    }
+}
+
+/// Which set the compute shader iterates. Both share the view, so navigation is the same.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FractalMode {
+    /// z starts at 0 and c is the pixel.
+    Mandelbrot = 0,
+    /// z starts at the pixel and c is `MandelbrotParams::julia_c`.
+    Julia = 1,
+}
+
+/// Iteration formula. Independent of `FractalMode`, so every kind has Julia sets too.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FractalKind {
+    /// z^d + c.
+    Mandelbrot = 0,
+    /// conj(z)^d + c, also called the Mandelbar.
+    Tricorn = 1,
+    /// Newton's method for z^d - 1, colored by the root each point converges to. The degree
+    /// is the power rounded to a whole number, and Julia mode adds c after every step, which
+    /// gives the Nova fractal. Iterated in f32 at every precision.
+    Newton = 2,
+    /// `formula::CustomFormula` set with `Computer::set_formula`, iterated in f32 from z = c
+    /// in Mandelbrot mode.
+    Custom = 3,
+}
+
+/// Center and zoom of a default framing, see `FractalKind::default_view`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HomeView {
+    pub center: DVec2,
+    /// Half extent of the short axis, as in `SampleLocation::zoom`.
+    pub zoom: f32,
+}
+
+impl HomeView {
+    pub fn view(self) -> SampleLocation {
+        SampleLocation::new(self.center, self.zoom)
+    }
+}
+
+impl FractalKind {
+    /// The whole Mandelbrot set, [-2.5, 1.5] along the real axis.
+    pub const MANDELBROT_HOME: HomeView = HomeView {
+        center: DVec2 { x: -0.5, y: 0.0 },
+        zoom: 2.0,
+    };
+    pub const TRICORN_HOME: HomeView = HomeView {
+        center: DVec2 { x: -0.25, y: 0.0 },
+        zoom: 1.75,
+    };
+    /// The roots of z^d - 1 on the unit circle with their basins around them.
+    pub const NEWTON_HOME: HomeView = HomeView {
+        center: DVec2 { x: 0.0, y: 0.0 },
+        zoom: 1.5,
+    };
+    pub const CUSTOM_HOME: HomeView = HomeView {
+        center: DVec2 { x: 0.0, y: 0.0 },
+        zoom: 1.5,
+    };
+    /// Julia sets centered on the origin. None reaches past |z| = 2, which c = -2 touches.
+    pub const JULIA_HOME: HomeView = HomeView {
+        center: DVec2 { x: 0.0, y: 0.0 },
+        zoom: 2.0,
+    };
+
+    pub fn next(self) -> FractalKind {
+        match self {
+            FractalKind::Mandelbrot => FractalKind::Tricorn,
+            FractalKind::Tricorn => FractalKind::Newton,
+            FractalKind::Newton => FractalKind::Custom,
+            FractalKind::Custom => FractalKind::Mandelbrot,
+        }
+    }
+
+    /// Framing that shows the whole set.
+    pub fn default_view(self) -> SampleLocation {
+        match self {
+            FractalKind::Mandelbrot => FractalKind::MANDELBROT_HOME,
+            FractalKind::Tricorn => FractalKind::TRICORN_HOME,
+            FractalKind::Newton => FractalKind::NEWTON_HOME,
+            FractalKind::Custom => FractalKind::CUSTOM_HOME,
+        }
+        .view()
+    }
+
+    /// Framing of the kind's Julia sets, which are symmetric about the origin.
+    pub fn julia_view(self) -> SampleLocation {
+        FractalKind::JULIA_HOME.view()
+    }
+
+    /// Manual iteration count that resolves the default view.
+    pub fn default_iterations(self) -> i32 {
+        match self {
+            FractalKind::Mandelbrot => 180,
+            FractalKind::Tricorn => 120,
+            FractalKind::Newton => 64,
+            FractalKind::Custom => 180,
+        }
+    }
+
+    /// `AutoIterations::base` for this kind; the Tricorn's filaments need fewer iterations,
+    /// and Newton's method converges within a few dozen nearly everywhere.
+    pub fn base_iterations(self) -> f32 {
+        match self {
+            FractalKind::Mandelbrot => 100.0,
+            FractalKind::Tricorn => 64.0,
+            FractalKind::Newton => 48.0,
+            FractalKind::Custom => 100.0,
+        }
+    }
+
+    /// Power to switch to along with the kind: z^2 + c, and the three roots of z^3 - 1. A
+    /// custom formula renormalizes with `CustomFormula::escape_power` instead.
+    pub fn default_power(self) -> f32 {
+        match self {
+            FractalKind::Mandelbrot | FractalKind::Tricorn | FractalKind::Custom => 2.0,
+            FractalKind::Newton => 3.0,
+        }
+    }
+}
+
+/// How offline jobs compute, taken from the live view with `Computer::setup`.
+#[derive(Clone, Debug)]
+pub struct ComputeSetup {
+    /// Falls back to the device's default precision if unsupported.
+    pub precision: Precision,
+    /// Formula that `FractalKind::Custom` params render.
+    pub formula: Option<CustomFormula>,
+}
+
+impl ComputeSetup {
+    /// A `size` computer for posters, brackets and animations, tiled with `Tiling::EXPORT`.
+    pub fn build(&self, gpu: &GPUInterface, size: UVec2) -> anyhow::Result<Computer> {
+        let mut computer = Computer::new(size, gpu);
+        computer.set_precision(self.precision);
+        computer.set_tiling(Some(Tiling::EXPORT));
+        if let Some(formula) = &self.formula {
+            computer.set_formula(gpu, formula.clone())?;
+        }
+        Ok(computer)
+    }
+}
+
+/// Float type the compute shader iterates in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Precision {
+    F32,
+    /// Pairs of f32 with error-free arithmetic; clean down to zooms around 1e-10 on any device,
+    /// at several times the cost of F32.
+    DoubleSingle,
+    /// Needs `wgpu::Features::SHADER_FLOAT64`; resolves zooms far beyond 1e-5.
+    F64,
+}
+
+pub const ITERATION_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+/// Bytes per texel of `ITERATION_TEXTURE_FORMAT`.
+pub const ITERATION_TEXEL_SIZE: usize = 16;
+
+/// Why `Computer::compute_to_vec` failed.
+#[derive(Debug)]
+pub enum ComputeError {
+    /// The size is zero or larger than the device's texture limit `max`.
+    InvalidSize { size: UVec2, max: u32 },
+    /// Mapping the result for reading failed.
+    Readback(wgpu::BufferAsyncError),
+}
+
+impl fmt::Display for ComputeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComputeError::InvalidSize { size, max } => write!(
+                f,
+                "cannot compute {}x{}: sizes must be between 1 and {}",
+                size.x, size.y, max
+            ),
+            ComputeError::Readback(e) => write!(f, "could not read back the result: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ComputeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ComputeError::Readback(e) => Some(e),
+            ComputeError::InvalidSize { .. } => None,
+        }
+    }
+}
+
+/// Width and height of the Julia preview inset.
+pub const PREVIEW_SIZE: u32 = 256;
+
+/// Supersampling factors `Computer::set_supersampling` accepts.
+pub const SUPERSAMPLING_FACTORS: [u32; 3] = [1, 2, 4];
+
+/// Factor each side of the draft texture is smaller than the iteration texture by, so drafts
+/// cost about a quarter of a full compute.
+pub const DRAFT_SCALE: u32 = 2;
+
+/// Runs the iteration compute shader into `iteration_texture`, at the chosen `Precision`.
 pub struct Computer {
-    pipeline: wgpu::ComputePipeline,
-    output_texture: wgpu::Texture,
-    texture_size: Extent3d,
+    /// One pipeline per precision the device supports, least precise first.
+    pipelines: Vec<(Precision, wgpu::ComputePipeline)>,
+    precision: Precision,
+    binding: ParamsBinding,
+    preview_binding: ParamsBinding,
+    draft_binding: ParamsBinding,
+    /// Kept to bind the scratch textures of `compute_to_vec` and supersampled textures.
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Kept to build the custom formula's pipeline and to rebuild the pipelines when their
+    /// shaders change on disk.
+    pipeline_layout: wgpu::PipelineLayout,
+    /// `FractalKind::Custom` formula with its pipeline, see `set_formula`.
+    custom: Option<(CustomFormula, wgpu::ComputePipeline)>,
+    workgroup_size: UVec2,
+    /// Splits dispatches into tiles when set, see `set_tiling`.
+    tiling: Option<Tiling>,
+    /// Size of the image the iteration texture is colored into.
+    output_size: UVec2,
+    /// Samples per output pixel along each side, see `set_supersampling`.
+    supersampling: u32,
+    /// Raw per-pixel results: (palette value, final |z|, escaped, glitched). The palette value
+    /// is the smooth iteration count, a trap value or a distance depending on `ColoringMode`;
+    /// with relief lighting |z| is replaced by the surface normal angle.
+    /// Only `deep::DeepComputer` sets the glitched flag.
+    /// Colored separately by `colorize::Colorizer`.
+    pub iteration_texture: wgpu::Texture,
+    pub iteration_view: wgpu::TextureView,
+    /// Size of `iteration_texture`: the output size times the supersampling factor.
+    pub texture_size: Extent3d,
+    /// Second iteration texture with its view and binding when double buffered, see
+    /// `set_double_buffered`.
+    back: Option<(wgpu::Texture, wgpu::TextureView, ParamsBinding)>,
+    /// Count of `ParamsBinding`s made so far, see `bindings_created`.
+    bindings_created: usize,
+    /// Which of the two textures is `iteration_texture`; flips with every swap.
+    front: usize,
+    /// Small square iteration texture for the Julia preview, same layout as `iteration_texture`.
+    pub preview_texture: wgpu::Texture,
+    pub preview_view: wgpu::TextureView,
+    /// Lower resolution iteration texture shown while the view is moving, same layout as
+    /// `iteration_texture`. See `run_draft`.
+    pub draft_texture: wgpu::Texture,
+    pub draft_view: wgpu::TextureView,
+    pub draft_size: Extent3d,
+    /// Iteration histogram for `palette::MappingMode::Histogram`, built on request after a
+    /// compute.
+    pub histogram: Histogram,
+    /// Iteration range for `colorize::ColorParams::auto_range`, built on request after a
+    /// compute like `histogram`.
+    pub range: RangeReduction,
 }
 
 impl Computer {
     pub fn new(size: UVec2, gpu: &GPUInterface) -> Computer {
+        Computer::with_workgroup_size(size, gpu, DEFAULT_WORKGROUP_SIZE)
+    }
+
+    /// Like `new`, but with `workgroup_size` substituted into the shaders and dispatched.
+    pub fn with_workgroup_size(size: UVec2, gpu: &GPUInterface, workgroup_size: UVec2) -> Computer {
         let texture_size = wgpu::Extent3d {
             width: size.x,
             height: size.y,
             depth_or_array_layers: 1,
         };
-        let output_texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("output texture"),
-            size: texture_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::COPY_SRC
-                | wgpu::TextureUsages::STORAGE_BINDING
-                | wgpu::TextureUsages::TEXTURE_BINDING,
-        });
+        let iteration_texture = create_iteration_texture(gpu, "iteration texture", texture_size);
+        let draft_size = draft_size(size);
+        let draft_texture = create_iteration_texture(gpu, "draft iteration texture", draft_size);
+        let preview_texture = create_iteration_texture(
+            gpu,
+            "preview iteration texture",
+            wgpu::Extent3d {
+                width: PREVIEW_SIZE,
+                height: PREVIEW_SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
 
-        let shader = gpu
+        // Explicit so that every precision's pipeline accepts the same bind groups.
+        let bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Mandelbrot bind group layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::StorageTexture {
+                                access: wgpu::StorageTextureAccess::WriteOnly,
+                                format: ITERATION_TEXTURE_FORMAT,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+        let pipeline_layout = gpu
             .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some("Mandelbrot shader"),
-                source: wgpu::ShaderSource::Wgsl(include_str!("shaders/mandelbrot.wgsl").into()),
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Mandelbrot pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
             });
+        let pipelines = SHADERS
+            .iter()
+            .filter(|(precision, _, _)| *precision != Precision::F64 || gpu.supports_f64())
+            .map(|&(precision, _, source)| {
+                let pipeline =
+                    create_compute_pipeline(gpu, &pipeline_layout, workgroup_size, source);
+                (precision, pipeline)
+            })
+            .collect();
+        // Native f64 when there is one, otherwise plain f32 since emulation is much slower.
+        let precision = if gpu.supports_f64() {
+            Precision::F64
+        } else {
+            Precision::F32
+        };
 
-        let pipeline = gpu
-            .device
-            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                label: Some("Mandelbrot compute pipeline"),
-                layout: None,
-                module: &shader,
-                entry_point: "main",
-            });
+        let iteration_view = iteration_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let preview_view = preview_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let draft_view = draft_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let binding = ParamsBinding::new(gpu, &bind_group_layout, &iteration_view);
+        let preview_binding = ParamsBinding::new(gpu, &bind_group_layout, &preview_view);
+        let draft_binding = ParamsBinding::new(gpu, &bind_group_layout, &draft_view);
 
         Computer {
-            pipeline,
-            output_texture,
+            pipelines,
+            bind_group_layout,
+            pipeline_layout,
+            custom: None,
+            precision,
+            workgroup_size,
+            tiling: None,
+            output_size: size,
+            supersampling: 1,
+            binding,
+            back: None,
+            bindings_created: 3,
+            front: 0,
+            preview_binding,
+            draft_binding,
+            iteration_texture,
+            iteration_view,
             texture_size,
+            preview_texture,
+            preview_view,
+            draft_texture,
+            draft_view,
+            draft_size,
+            histogram: Histogram::new(gpu),
+            range: RangeReduction::new(gpu),
         }
     }
 
-    pub fn run(&self, gpu: &GPUInterface, mandelbot_params: &MandelbrotParams) -> &wgpu::Texture {
-        let mut encoder = gpu
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    /// Times a small dispatch of the default view with each of `AUTOTUNE_CANDIDATES` the
+    /// device allows and returns the fastest size.
+    pub fn autotune_workgroup_size(gpu: &GPUInterface) -> UVec2 {
+        let limits = gpu.device.limits();
+        let size = UVec2::new(AUTOTUNE_IMAGE_SIZE, AUTOTUNE_IMAGE_SIZE);
+        let mut view = SampleLocation::default();
+        view.set_output_size(size);
+        let params = view.to_mandlebrot_params(FractalKind::Mandelbrot.default_iterations());
+        let timed = AUTOTUNE_CANDIDATES
+            .iter()
+            .filter(|candidate| {
+                candidate.x <= limits.max_compute_workgroup_size_x
+                    && candidate.y <= limits.max_compute_workgroup_size_y
+                    && candidate.x * candidate.y <= limits.max_compute_invocations_per_workgroup
+            })
+            .map(|&candidate| {
+                let mut computer = Computer::with_workgroup_size(size, gpu, candidate);
+                // The first dispatch includes pipeline warm-up.
+                computer.run_blocking(gpu, &params);
+                let fastest = (0..AUTOTUNE_RUNS)
+                    .map(|_| {
+                        let start = Instant::now();
+                        computer.run_blocking(gpu, &params);
+                        start.elapsed()
+                    })
+                    .min()
+                    .unwrap_or_default();
+                (candidate, fastest)
+            })
+            .min_by_key(|&(_, time)| time);
+        match timed {
+            Some((best, time)) => {
+                println!(
+                    "Autotuned workgroup size: {}x{} ({:.2} ms)",
+                    best.x,
+                    best.y,
+                    time.as_secs_f64() * 1000.0
+                );
+                best
+            }
+            None => DEFAULT_WORKGROUP_SIZE,
+        }
+    }
 
-        let m_params_buffer = gpu
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Params Buffer"),
-                contents: bytemuck::bytes_of(mandelbot_params),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    /// Rebuilds every pipeline from the shader files on disk. A shader that fails to load or
+    /// validate is reported and its old pipeline keeps running.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shaders(&mut self, gpu: &GPUInterface) {
+        for (precision, pipeline) in &mut self.pipelines {
+            let Some(&(_, file_name, _)) = SHADERS.iter().find(|(p, _, _)| p == precision) else {
+                continue;
+            };
+            let result = hot_reload::read_shader(file_name).and_then(|source| {
+                gpu.validated(|| {
+                    create_compute_pipeline(
+                        gpu,
+                        &self.pipeline_layout,
+                        self.workgroup_size,
+                        &source,
+                    )
+                })
             });
+            match result {
+                Ok(reloaded) => {
+                    *pipeline = reloaded;
+                    println!("Reloaded {}", file_name);
+                }
+                Err(e) => eprintln!("Could not reload {}: {:#}", file_name, e),
+            }
+        }
+        if let Some((formula, pipeline)) = &mut self.custom {
+            let result = hot_reload::read_shader(SHADERS[0].1).and_then(|template| {
+                let source = formula.splice(&template)?;
+                gpu.validated(|| {
+                    create_compute_pipeline(
+                        gpu,
+                        &self.pipeline_layout,
+                        self.workgroup_size,
+                        &source,
+                    )
+                })
+            });
+            match result {
+                Ok(reloaded) => *pipeline = reloaded,
+                Err(e) => eprintln!("Could not reload the custom formula: {:#}", e),
+            }
+        }
+    }
 
-        let compute_bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Compute bind group"),
-            layout: &self.pipeline.get_bind_group_layout(0),
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(
-                        &self
-                            .output_texture
-                            .create_view(&wgpu::TextureViewDescriptor::default()),
-                    ),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: m_params_buffer.as_entire_binding(),
-                },
-            ],
-        });
+    /// Precision and formula for offline jobs to compute the same fractal with.
+    pub fn setup(&self) -> ComputeSetup {
+        ComputeSetup {
+            precision: self.precision,
+            formula: self.formula().cloned(),
+        }
+    }
 
-        {
-            let (dispatch_with, dispatch_height) = compute_work_group_count(
-                (self.texture_size.width, self.texture_size.height),
-                (16, 16),
-            );
-            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("Grayscale pass"),
-            });
-            compute_pass.set_pipeline(&self.pipeline);
-            compute_pass.set_bind_group(0, &compute_bind_group, &[]);
-            compute_pass.dispatch_workgroups(dispatch_with, dispatch_height, 1);
+    pub fn formula(&self) -> Option<&CustomFormula> {
+        self.custom.as_ref().map(|(formula, _)| formula)
+    }
+
+    /// Compiles `formula` into the f32 shader for `FractalKind::Custom`, which runs in f32
+    /// whatever the precision. On an error, e.g. one wgpu finds in the generated shader, the
+    /// previous formula keeps running.
+    pub fn set_formula(
+        &mut self,
+        gpu: &GPUInterface,
+        formula: CustomFormula,
+    ) -> anyhow::Result<()> {
+        let source = formula.splice(SHADERS[0].2)?;
+        let pipeline = gpu.validated(|| {
+            create_compute_pipeline(gpu, &self.pipeline_layout, self.workgroup_size, &source)
+        })?;
+        self.custom = Some((formula, pipeline));
+        Ok(())
+    }
+
+    pub fn tiling(&self) -> Option<Tiling> {
+        self.tiling
+    }
+
+    /// Splits every compute into tiles of `tiling`, each submitted separately, or dispatches
+    /// the whole texture at once with None.
+    pub fn set_tiling(&mut self, tiling: Option<Tiling>) {
+        self.tiling = tiling;
+    }
+
+    pub fn supersampling(&self) -> u32 {
+        self.supersampling
+    }
+
+    /// Size of the image the iteration texture is colored into.
+    pub fn output_size(&self) -> UVec2 {
+        self.output_size
+    }
+
+    /// Resizes the output to `size`, for when the window changes size. Supersampling is kept
+    /// unless the larger texture no longer fits the device's limit.
+    ///
+    /// Replaces `iteration_texture` and `draft_texture`, so anything bound to their views has
+    /// to be rebound.
+    pub fn set_output_size(&mut self, gpu: &GPUInterface, size: UVec2) {
+        self.output_size = size;
+        self.supersampling = self.fitting_supersampling(gpu, self.supersampling);
+        self.recreate_iteration_texture(gpu);
+        self.draft_size = draft_size(size);
+        self.draft_texture =
+            create_iteration_texture(gpu, "draft iteration texture", self.draft_size);
+        self.draft_view = self
+            .draft_texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.draft_binding = ParamsBinding::new(gpu, &self.bind_group_layout, &self.draft_view);
+        self.bindings_created += 1;
+    }
+
+    /// The largest of `SUPERSAMPLING_FACTORS` up to `factor` whose texture fits the device.
+    fn fitting_supersampling(&self, gpu: &GPUInterface, factor: u32) -> u32 {
+        let max = gpu.device.limits().max_texture_dimension_2d;
+        let largest_side = self.output_size.x.max(self.output_size.y);
+        SUPERSAMPLING_FACTORS
+            .into_iter()
+            .filter(|&f| f <= factor.max(1) && largest_side * f <= max)
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// Recreates `iteration_texture` for the current output size and supersampling.
+    fn recreate_iteration_texture(&mut self, gpu: &GPUInterface) {
+        self.texture_size = Extent3d {
+            width: self.output_size.x * self.supersampling,
+            height: self.output_size.y * self.supersampling,
+            depth_or_array_layers: 1,
+        };
+        self.iteration_texture =
+            create_iteration_texture(gpu, "iteration texture", self.texture_size);
+        self.iteration_view = self
+            .iteration_texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.binding = ParamsBinding::new(gpu, &self.bind_group_layout, &self.iteration_view);
+        self.bindings_created += 1;
+        if self.back.is_some() {
+            self.back = Some(self.create_back_texture(gpu));
+        }
+    }
+
+    fn create_back_texture(
+        &mut self,
+        gpu: &GPUInterface,
+    ) -> (wgpu::Texture, wgpu::TextureView, ParamsBinding) {
+        let texture = create_iteration_texture(gpu, "back iteration texture", self.texture_size);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let binding = ParamsBinding::new(gpu, &self.bind_group_layout, &view);
+        self.bindings_created += 1;
+        (texture, view, binding)
+    }
+
+    /// Number of params uniform buffers and bind groups created so far. Each output texture
+    /// gets one that every run rewrites, so this only grows when a texture is replaced.
+    pub fn bindings_created(&self) -> usize {
+        self.bindings_created
+    }
+
+    /// Computes into a second iteration texture and swaps it with `iteration_texture` after
+    /// each run, so a compute never writes the texture the last frame is still sampling. Costs
+    /// a second texture; anything bound to `iteration_view` has to follow `front`.
+    pub fn set_double_buffered(&mut self, gpu: &GPUInterface, enabled: bool) {
+        if enabled != self.back.is_some() {
+            self.back = if enabled {
+                Some(self.create_back_texture(gpu))
+            } else {
+                None
+            };
+        }
+    }
+
+    /// Which of the two iteration textures `iteration_texture` is. Changes whenever a double
+    /// buffered run makes the other one current.
+    pub fn front(&self) -> usize {
+        self.front
+    }
+
+    /// The texture the next compute writes: the back texture when double buffered, otherwise
+    /// `iteration_texture`. For computes outside `run`, which then call `swap`.
+    pub fn target_texture(&self) -> &wgpu::Texture {
+        self.back
+            .as_ref()
+            .map_or(&self.iteration_texture, |(texture, _, _)| texture)
+    }
+
+    /// Makes the freshly computed `target_texture` current. Does nothing unless double
+    /// buffered.
+    pub fn swap(&mut self) {
+        if let Some((texture, view, binding)) = &mut self.back {
+            std::mem::swap(texture, &mut self.iteration_texture);
+            std::mem::swap(view, &mut self.iteration_view);
+            std::mem::swap(binding, &mut self.binding);
+            self.front = 1 - self.front;
+        }
+    }
+
+    /// Computes `factor` x `factor` samples per output pixel, which `colorize::Colorizer`
+    /// averages back down. The factor is lowered until the texture fits the device's limit,
+    /// and the one used is returned.
+    ///
+    /// Replaces `iteration_texture`, so anything bound to its view has to be rebound.
+    pub fn set_supersampling(&mut self, gpu: &GPUInterface, factor: u32) -> u32 {
+        let factor = self.fitting_supersampling(gpu, factor);
+        if factor == self.supersampling {
+            return factor;
+        }
+        self.supersampling = factor;
+        self.recreate_iteration_texture(gpu);
+        factor
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// Switches to `precision`. Returns false and keeps the current one if the device lacks it.
+    pub fn set_precision(&mut self, precision: Precision) -> bool {
+        let available = self.pipelines.iter().any(|(p, _)| *p == precision);
+        if available {
+            self.precision = precision;
         }
+        available
+    }
+
+    /// The precision after the current one among those the device supports, wrapping around.
+    pub fn next_precision(&self) -> Precision {
+        let index = self
+            .pipelines
+            .iter()
+            .position(|(p, _)| *p == self.precision)
+            .map_or(0, |i| (i + 1) % self.pipelines.len());
+        self.pipelines[index].0
+    }
+
+    /// Switches to the next precision the device supports and returns it.
+    pub fn cycle_precision(&mut self) -> Precision {
+        self.precision = self.next_precision();
+        self.precision
+    }
+
+    /// The custom formula's pipeline for `FractalKind::Custom`, otherwise the precision's.
+    fn pipeline(&self, params: &MandelbrotParams) -> &wgpu::ComputePipeline {
+        if let Some((_, pipeline)) = &self.custom {
+            if params.kind == FractalKind::Custom as u32 {
+                return pipeline;
+            }
+        }
+        self.pipelines
+            .iter()
+            .find(|(p, _)| *p == self.precision)
+            .map(|(_, pipeline)| pipeline)
+            .expect("precision is always one of the pipelines")
+    }
+
+    /// Computes into `iteration_texture` and returns its view. Double buffered, the other
+    /// texture is computed and becomes `iteration_texture`, see `front`.
+    pub fn run(
+        &mut self,
+        gpu: &GPUInterface,
+        mandelbot_params: &MandelbrotParams,
+    ) -> &wgpu::TextureView {
+        let binding = self.back.as_ref().map_or(&self.binding, |(_, _, b)| b);
+        self.dispatch(gpu, mandelbot_params, binding, self.texture_size);
+        self.swap();
+        &self.iteration_view
+    }
+
+    /// Rebuilds `histogram` from `iteration_texture`, last computed with `max_iterations`.
+    pub fn build_histogram(&self, gpu: &GPUInterface, max_iterations: i32) {
+        self.histogram
+            .build(gpu, &self.iteration_view, self.texture_size, max_iterations);
+    }
+
+    /// Rebuilds `range` from `iteration_texture`.
+    pub fn build_range(&self, gpu: &GPUInterface) {
+        self.range.build(gpu, &self.iteration_view);
+    }
+
+    /// Like `run`, but waits until the GPU has finished, for timing the compute pass.
+    pub fn run_blocking(
+        &mut self,
+        gpu: &GPUInterface,
+        mandelbot_params: &MandelbrotParams,
+    ) -> &wgpu::TextureView {
+        let view = self.run(gpu, mandelbot_params);
+        gpu.device.poll(wgpu::Maintain::Wait);
+        view
+    }
+
+    /// Computes `draft_texture`, the same view as `run` at `DRAFT_SCALE` times fewer pixels
+    /// per side, and returns its view.
+    pub fn run_draft(&self, gpu: &GPUInterface, params: &MandelbrotParams) -> &wgpu::TextureView {
+        self.dispatch(gpu, params, &self.draft_binding, self.draft_size);
+        &self.draft_view
+    }
 
-        // Get the result.
-        /*
-        println!("Finished computing. Saving file...");
-        let padded_bytes_per_row = padded_bytes_per_row(self.texture_size.width);
-        let unpadded_bytes_per_row = self.texture_size.width as usize * 4;
+    /// Computes the `PREVIEW_SIZE` square preview texture.
+    pub fn run_preview(&self, gpu: &GPUInterface, params: &MandelbrotParams) -> &wgpu::TextureView {
+        let size = wgpu::Extent3d {
+            width: PREVIEW_SIZE,
+            height: PREVIEW_SIZE,
+            depth_or_array_layers: 1,
+        };
+        self.dispatch(gpu, params, &self.preview_binding, size);
+        &self.preview_view
+    }
 
-        let output_buffer_size = padded_bytes_per_row as u64
-            * self.texture_size.height as u64
-            * std::mem::size_of::<u8>() as u64;
-        let output_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: output_buffer_size,
+    /// Computes `params` at `size` and reads the result back, blocking until the GPU is done.
+    /// Returns `ITERATION_TEXEL_SIZE` bytes per pixel in row-major order: the four f32 of
+    /// `iteration_texture`, which `bytemuck::cast_slice` turns back into floats.
+    ///
+    /// Renders into a scratch texture, so `size` need not match the computer's own.
+    pub fn compute_to_vec(
+        &self,
+        gpu: &GPUInterface,
+        params: &MandelbrotParams,
+        size: UVec2,
+    ) -> Result<Vec<u8>, ComputeError> {
+        let max = gpu.device.limits().max_texture_dimension_2d;
+        if size.x == 0 || size.y == 0 || size.x > max || size.y > max {
+            return Err(ComputeError::InvalidSize { size, max });
+        }
+        let texture_size = Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        };
+        let texture = create_iteration_texture(gpu, "scratch iteration texture", texture_size);
+        let binding = ParamsBinding::new(
+            gpu,
+            &self.bind_group_layout,
+            &texture.create_view(&wgpu::TextureViewDescriptor::default()),
+        );
+        self.dispatch(gpu, params, &binding, texture_size);
+
+        let bytes_per_row = padded_bytes_per_row(size.x, ITERATION_TEXEL_SIZE);
+        let buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Iteration readback buffer"),
+            size: (bytes_per_row * size.y as usize) as wgpu::BufferAddress,
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
             mapped_at_creation: false,
         });
-
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Iteration readback encoder"),
+            });
         encoder.copy_texture_to_buffer(
             wgpu::ImageCopyTexture {
                 aspect: wgpu::TextureAspect::All,
-                texture: &self.output_texture,
+                texture: &texture,
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
             wgpu::ImageCopyBuffer {
-                buffer: &output_buffer,
+                buffer: &buffer,
                 layout: wgpu::ImageDataLayout {
                     offset: 0,
-                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row as u32),
-                    rows_per_image: std::num::NonZeroU32::new(self.texture_size.height),
+                    bytes_per_row: std::num::NonZeroU32::new(bytes_per_row as u32),
+                    rows_per_image: std::num::NonZeroU32::new(size.y),
                 },
             },
-            self.texture_size,
+            texture_size,
         );
+        gpu.queue.submit(Some(encoder.finish()));
 
+        let (sender, receiver) = channel();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        gpu.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .unwrap_or(Err(wgpu::BufferAsyncError))
+            .map_err(ComputeError::Readback)?;
 
-        let buffer_slice = output_buffer.slice(..);
-        let mapping = buffer_slice.map_async(wgpu::MapMode::Read, |a| {});
+        let unpadded_bytes_per_row = size.x as usize * ITERATION_TEXEL_SIZE;
+        let padded_data = buffer.slice(..).get_mapped_range();
+        let mut texels = vec![0; unpadded_bytes_per_row * size.y as usize];
+        for (padded, row) in padded_data
+            .chunks_exact(bytes_per_row)
+            .zip(texels.chunks_exact_mut(unpadded_bytes_per_row))
+        {
+            row.copy_from_slice(&padded[..unpadded_bytes_per_row]);
+        }
+        drop(padded_data);
+        buffer.unmap();
+        Ok(texels)
+    }
 
-        gpu.device.poll(wgpu::Maintain::Wait);
+    fn dispatch(
+        &self,
+        gpu: &GPUInterface,
+        mandelbot_params: &MandelbrotParams,
+        binding: &ParamsBinding,
+        texture_size: Extent3d,
+    ) {
+        let size = UVec2::new(texture_size.width, texture_size.height);
+        let Some(tiling) = self
+            .tiling
+            .filter(|t| size.x > t.tile_size || size.y > t.tile_size)
+        else {
+            self.dispatch_tile(gpu, mandelbot_params, binding, UVec2::new(0, 0), size);
+            return;
+        };
+        for (origin, tile_size) in tiling.tiles(size) {
+            self.dispatch_tile(gpu, mandelbot_params, binding, origin, tile_size);
+            if tiling.wait_between {
+                gpu.device.poll(wgpu::Maintain::Wait);
+            }
+        }
+    }
 
-        let padded_data = buffer_slice.get_mapped_range();
+    /// Computes the `size` pixels at `origin` in a submission of their own.
+    fn dispatch_tile(
+        &self,
+        gpu: &GPUInterface,
+        mandelbot_params: &MandelbrotParams,
+        binding: &ParamsBinding,
+        origin: UVec2,
+        size: UVec2,
+    ) {
+        // Written before this tile's submission, so earlier tiles keep their own origin.
+        let params = MandelbrotParams {
+            tile_origin: [origin.x, origin.y],
+            ..*mandelbot_params
+        };
+        gpu.queue
+            .write_buffer(&binding.params_buffer, 0, bytemuck::bytes_of(&params));
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
-        let mut pixels: Vec<u8> =
-            vec![0; unpadded_bytes_per_row * self.texture_size.height as usize];
-        for (padded, pixels) in padded_data
-            .chunks_exact(padded_bytes_per_row)
-            .zip(pixels.chunks_exact_mut(unpadded_bytes_per_row))
         {
-            pixels.copy_from_slice(&padded[..unpadded_bytes_per_row]);
+            let (dispatch_with, dispatch_height) = compute_work_group_count(
+                (size.x, size.y),
+                (self.workgroup_size.x, self.workgroup_size.y),
+            );
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Grayscale pass"),
+            });
+            compute_pass.set_pipeline(self.pipeline(mandelbot_params));
+            compute_pass.set_bind_group(0, &binding.bind_group, &[]);
+            compute_pass.dispatch_workgroups(dispatch_with, dispatch_height, 1);
         }
 
-        if let Some(output_image) = image::ImageBuffer::<image::Rgba<u8>, _>::from_raw(
-            self.texture_size.width,
-            self.texture_size.height,
-            &pixels[..],
-        ) {
-            output_image.save("output.png").unwrap();
-        }*/
         gpu.queue.submit(Some(encoder.finish()));
-        &self.output_texture
     }
 }
 
-fn compute_work_group_count(
+/// Params uniform and bind group for one output view, created once and rewritten per run.
+/// The bind group has to be rebuilt if the texture is ever replaced.
+struct ParamsBinding {
+    params_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl ParamsBinding {
+    fn new(
+        gpu: &GPUInterface,
+        layout: &wgpu::BindGroupLayout,
+        view: &wgpu::TextureView,
+    ) -> ParamsBinding {
+        let params_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Params Buffer"),
+            size: std::mem::size_of::<MandelbrotParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Compute bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+            ],
+        });
+        ParamsBinding {
+            params_buffer,
+            bind_group,
+        }
+    }
+}
+
+/// Size of the draft texture for a `size` output, see `Computer::run_draft`.
+fn draft_size(size: UVec2) -> Extent3d {
+    Extent3d {
+        width: (size.x / DRAFT_SCALE).max(1),
+        height: (size.y / DRAFT_SCALE).max(1),
+        depth_or_array_layers: 1,
+    }
+}
+
+fn create_iteration_texture(
+    gpu: &GPUInterface,
+    label: &'static str,
+    size: Extent3d,
+) -> wgpu::Texture {
+    gpu.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: ITERATION_TEXTURE_FORMAT,
+        usage: wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::TEXTURE_BINDING,
+    })
+}
+
+fn create_compute_pipeline(
+    gpu: &GPUInterface,
+    layout: &wgpu::PipelineLayout,
+    workgroup_size: UVec2,
+    source: &str,
+) -> wgpu::ComputePipeline {
+    let shader = gpu
+        .device
+        .create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Mandelbrot shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                source
+                    .replace(
+                        "@workgroup_size(16,16)",
+                        &format!("@workgroup_size({},{})", workgroup_size.x, workgroup_size.y),
+                    )
+                    .into(),
+            ),
+        });
+    gpu.device
+        .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Mandelbrot compute pipeline"),
+            layout: Some(layout),
+            module: &shader,
+            entry_point: "main",
+        })
+}
+
+pub fn compute_work_group_count(
     (width, height): (u32, u32),
     (workgroup_width, workgroup_height): (u32, u32),
 ) -> (u32, u32) {
-    let x = (width + workgroup_width - 1) / workgroup_width;
-    let y = (height + workgroup_height - 1) / workgroup_height;
+    let x = width.div_ceil(workgroup_width);
+    let y = height.div_ceil(workgroup_height);
 
     (x, y)
 }
 
 /// Compute the next multiple of 256 for texture retrieval padding.
-fn padded_bytes_per_row(width: u32) -> usize {
-    let bytes_per_row = width as usize * 4;
+pub fn padded_bytes_per_row(width: u32, bytes_per_pixel: usize) -> usize {
+    let bytes_per_row = width as usize * bytes_per_pixel;
     let padding = (256 - bytes_per_row % 256) % 256;
     bytes_per_row + padding
 }
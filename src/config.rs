@@ -0,0 +1,171 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    computer::WorkgroupSize,
+    gpu_interface::{AdapterChoice, PresentMode},
+    state::path_next_to_binary,
+};
+
+/// File name looked for in the config directory and next to the executable.
+const CONFIG_FILE_NAME: &str = "config.toml";
+/// Directory under `$XDG_CONFIG_HOME` (or `~/.config`) the config file is kept in.
+const CONFIG_DIR_NAME: &str = "mandelbrot_compute";
+
+/// Settings read from `config.toml` at startup. Every section and key is optional, and
+/// `Config::default()` is the behavior without a file.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub controls: ControlsConfig,
+    pub rendering: RenderingConfig,
+    pub compute: ComputeConfig,
+    /// Key bindings over the defaults in `Keymap`: key names like "W" or "NumpadAdd" to
+    /// action names like "zoom_in", or "none" to unbind a key. See --print-keys.
+    pub keys: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControlsConfig {
+    /// Factor one mouse wheel notch scales the zoom by.
+    pub zoom_step: f32,
+    /// Distance the arrow keys pan per tenth of a second held, as a fraction of the view.
+    pub move_speed: f32,
+}
+
+impl Default for ControlsConfig {
+    fn default() -> Self {
+        ControlsConfig {
+            zoom_step: 0.8,
+            move_speed: 0.05,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderingConfig {
+    /// Name of the palette to start with; None is the first built-in one.
+    pub palette: Option<String>,
+    /// "fifo" (vsync), "mailbox" or "immediate"; unsupported modes fall back to the closest one.
+    pub present_mode: PresentMode,
+    /// GPU adapter to use, by index in the --gpu-info list or part of its name; None picks
+    /// the most powerful one that can present to the window.
+    pub adapter: Option<AdapterChoice>,
+    /// RGB the window is cleared to, each 0 to 1, visible around a letterboxed image.
+    pub clear_color: [f32; 3],
+}
+
+impl Default for RenderingConfig {
+    fn default() -> Self {
+        RenderingConfig {
+            palette: None,
+            present_mode: PresentMode::Fifo,
+            adapter: None,
+            clear_color: [0.0; 3],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ComputeConfig {
+    /// Manual iteration count to start with; None is the fractal kind's own default.
+    pub default_iterations: Option<i32>,
+    /// Workgroup size of the live view's compute shader, "WxH" or "auto".
+    pub workgroup_size: WorkgroupSize,
+    /// Side in pixels of the tiles the live view is computed in, one submission each; None
+    /// computes it in one dispatch. Offline renders are always tiled.
+    pub tile_size: Option<u32>,
+}
+
+impl Config {
+    /// Rejects settings that parse but can't be used.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let controls = &self.controls;
+        anyhow::ensure!(
+            controls.zoom_step > 0.0 && controls.zoom_step < 1.0,
+            "controls.zoom_step must be between 0 and 1, got {}",
+            controls.zoom_step
+        );
+        anyhow::ensure!(
+            controls.move_speed.is_finite() && controls.move_speed > 0.0,
+            "controls.move_speed must be positive, got {}",
+            controls.move_speed
+        );
+        anyhow::ensure!(
+            self.rendering
+                .clear_color
+                .iter()
+                .all(|c| (0.0..=1.0).contains(c)),
+            "rendering.clear_color components must be between 0 and 1, got {:?}",
+            self.rendering.clear_color
+        );
+        if let Some(tile_size) = self.compute.tile_size {
+            anyhow::ensure!(
+                tile_size > 0,
+                "compute.tile_size must be positive, got {}",
+                tile_size
+            );
+        }
+        if let Some(iterations) = self.compute.default_iterations {
+            anyhow::ensure!(
+                iterations > 0,
+                "compute.default_iterations must be positive, got {}",
+                iterations
+            );
+        }
+        Ok(())
+    }
+
+    /// Parses and validates a config file's contents.
+    pub fn from_toml(text: &str) -> anyhow::Result<Config> {
+        let config: Config = toml::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Config> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+        Config::from_toml(&text).with_context(|| format!("invalid {}", path.display()))
+    }
+
+    /// Loads the first config file in `config_paths`. No file means defaults; a broken one
+    /// is reported and ignored, so a typo doesn't keep the app from starting.
+    pub fn load_or_default() -> Config {
+        let Some(path) = config_paths().into_iter().find(|path| path.exists()) else {
+            return Config::default();
+        };
+        match Config::load(&path) {
+            Ok(config) => {
+                println!("Loaded settings from {}", path.display());
+                config
+            }
+            Err(e) => {
+                eprintln!("Warning: {:#}; using default settings", e);
+                Config::default()
+            }
+        }
+    }
+}
+
+/// Where the config file is looked for, in order: the XDG config directory, then next to
+/// the executable.
+pub fn config_paths() -> Vec<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+    config_home
+        .map(|dir| dir.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME))
+        .into_iter()
+        .chain(std::iter::once(path_next_to_binary(CONFIG_FILE_NAME)))
+        .collect()
+}
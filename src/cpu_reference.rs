@@ -0,0 +1,159 @@
+//! CPU implementation of the compute shader's escape-time math, used to check GPU results.
+
+#[derive(Copy, Clone, Debug)]
+pub struct EscapeResult {
+    pub smooth_iterations: f64,
+    pub z_abs: f64,
+    pub escaped: bool,
+}
+
+/// The map iterated, z = conj?(z)^power + c.
+#[derive(Copy, Clone, Debug)]
+pub struct Formula {
+    pub power: f64,
+    /// Conjugate z before raising it to the power, as the Tricorn does.
+    pub conjugate: bool,
+}
+
+/// Mandelbrot set: z starts at 0 and c is the point.
+pub fn mandelbrot(
+    re: f64,
+    im: f64,
+    formula: Formula,
+    max_iterations: i32,
+    escape_radius: f64,
+) -> EscapeResult {
+    escape_time((0.0, 0.0), (re, im), formula, max_iterations, escape_radius)
+}
+
+/// Julia set of `c`: z starts at the point.
+pub fn julia(
+    re: f64,
+    im: f64,
+    c: (f64, f64),
+    formula: Formula,
+    max_iterations: i32,
+    escape_radius: f64,
+) -> EscapeResult {
+    escape_time((re, im), c, formula, max_iterations, escape_radius)
+}
+
+/// z^d, with the same integer fast path as `complex_pow` in `mandelbrot.wgsl`.
+fn complex_pow((x, y): (f64, f64), power: f64) -> (f64, f64) {
+    if power == power.floor() && power <= 8.0 {
+        let mut result = (x, y);
+        for _ in 1..power as i32 {
+            result = (result.0 * x - result.1 * y, result.0 * y + result.1 * x);
+        }
+        return result;
+    }
+    let r = (x * x + y * y).sqrt();
+    if r == 0.0 {
+        return (0.0, 0.0);
+    }
+    let theta = y.atan2(x) * power;
+    let r = r.powf(power);
+    (r * theta.cos(), r * theta.sin())
+}
+
+/// One step of `formula`: z -> conj?(z)^power + c.
+fn step((x, y): (f64, f64), (re, im): (f64, f64), formula: Formula) -> (f64, f64) {
+    let y = if formula.conjugate { -y } else { y };
+    let (xp, yp) = complex_pow((x, y), formula.power);
+    (xp + re, yp + im)
+}
+
+/// Iterates `formula` from `z0` exactly like `mandelbrot.wgsl`, but in f64.
+fn escape_time(
+    z0: (f64, f64),
+    c: (f64, f64),
+    formula: Formula,
+    max_iterations: i32,
+    escape_radius: f64,
+) -> EscapeResult {
+    let (mut x, mut y) = z0;
+    let mut n = 0;
+    while (x * x + y * y).sqrt() <= escape_radius && n < max_iterations {
+        (x, y) = step((x, y), c, formula);
+        n += 1;
+    }
+    let z_abs = (x * x + y * y).sqrt();
+    let escaped = z_abs > escape_radius;
+    let smooth_iterations = if escaped {
+        n as f64 + 1.0 - z_abs.log2().log2() / formula.power.log2()
+    } else {
+        n as f64
+    };
+    EscapeResult {
+        smooth_iterations,
+        z_abs,
+        escaped,
+    }
+}
+
+/// The points z visits when iterating `formula` from `z0` with constant `c`: `z0` itself, then
+/// one point per step up to the first one beyond `escape_radius` or `max_iterations` steps.
+pub fn orbit(
+    z0: (f64, f64),
+    c: (f64, f64),
+    formula: Formula,
+    max_iterations: i32,
+    escape_radius: f64,
+) -> Vec<(f64, f64)> {
+    let mut points = vec![z0];
+    let mut z = z0;
+    while (z.0 * z.0 + z.1 * z.1).sqrt() <= escape_radius && points.len() <= max_iterations as usize
+    {
+        z = step(z, c, formula);
+        points.push(z);
+    }
+    points
+}
+
+/// Where Newton's method took a point, see `newton`.
+#[derive(Copy, Clone, Debug)]
+pub struct NewtonResult {
+    pub iterations: i32,
+    /// Index k of the root at angle 2 pi k / degree, None if it didn't converge in time.
+    pub root: Option<u32>,
+}
+
+/// Newton's method for z^degree - 1 from `z0`, adding `c` after every step, like `newton` in
+/// `mandelbrot.wgsl` but in f64. Stops once a step moves z less than `tolerance`.
+pub fn newton(
+    z0: (f64, f64),
+    c: (f64, f64),
+    degree: u32,
+    max_iterations: i32,
+    tolerance: f64,
+) -> NewtonResult {
+    let d = degree as f64;
+    let (mut x, mut y) = z0;
+    let mut n = 0;
+    let mut step = f64::INFINITY;
+    while step >= tolerance && n < max_iterations {
+        let (px, py) = complex_pow((x, y), d - 1.0);
+        let (dx, dy) = (d * px, d * py);
+        let derivative = dx * dx + dy * dy;
+        if derivative == 0.0 {
+            break;
+        }
+        let (fx, fy) = (px * x - py * y - 1.0, px * y + py * x);
+        let (qx, qy) = (
+            (fx * dx + fy * dy) / derivative,
+            (fy * dx - fx * dy) / derivative,
+        );
+        let (nx, ny) = (x - qx + c.0, y - qy + c.1);
+        step = ((nx - x).powi(2) + (ny - y).powi(2)).sqrt();
+        (x, y) = (nx, ny);
+        n += 1;
+    }
+    let root = (step < tolerance).then(|| {
+        let turn = y.atan2(x) / std::f64::consts::TAU;
+        ((turn * d).round() as i64).rem_euclid(degree as i64) as u32
+    });
+    NewtonResult {
+        iterations: n,
+        root,
+    }
+}
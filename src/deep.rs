@@ -0,0 +1,166 @@
+use wgpu::{util::DeviceExt, Extent3d};
+
+use crate::{
+    computer::{compute_work_group_count, FractalKind, FractalMode, MandelbrotParams},
+    gpu_interface::GPUInterface,
+    math::{DVec2, FVec2},
+    reference_orbit::{precision_bits, reference_orbit},
+};
+
+/// Below this zoom the view is rendered by perturbation, since even f64 pixel coordinates
+/// start to collapse into blocks.
+pub const PERTURBATION_ZOOM: f32 = 1e-12;
+
+/// Everything the reference orbit depends on. Zooming and rotating keep it, so only the cheap
+/// per-pixel pass reruns.
+#[derive(Clone, Debug, PartialEq)]
+struct OrbitKey {
+    center: (DVec2, DVec2),
+    max_iterations: i32,
+    fractal_mode: u32,
+    julia_c: [f32; 2],
+    kind: u32,
+    bits: usize,
+}
+
+/// Renders deep zooms by perturbation: one reference orbit of the view center is iterated on
+/// the CPU in arbitrary precision, and the compute shader only tracks each pixel's f32 offset
+/// from it. Writes the same iteration texture layout as `Computer`, with glitched pixels
+/// flagged in the fourth channel.
+pub struct DeepComputer {
+    pipeline: wgpu::ComputePipeline,
+    orbit_buffer: Option<wgpu::Buffer>,
+    orbit_key: Option<OrbitKey>,
+}
+
+impl DeepComputer {
+    pub fn new(gpu: &GPUInterface) -> DeepComputer {
+        let shader = gpu
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Perturbation shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("shaders/deep.wgsl").into()),
+            });
+        let pipeline = gpu
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Perturbation compute pipeline"),
+                layout: None,
+                module: &shader,
+                entry_point: "main",
+            });
+        DeepComputer {
+            pipeline,
+            orbit_buffer: None,
+            orbit_key: None,
+        }
+    }
+
+    /// Whether perturbation can render `params`. Only z^2 + c and its conjugate have the
+    /// simple delta formula.
+    pub fn supports(params: &MandelbrotParams) -> bool {
+        params.power == 2.0
+            && (params.kind == FractalKind::Mandelbrot as u32
+                || params.kind == FractalKind::Tricorn as u32)
+    }
+
+    /// Whether the last run was for this center. The f32 params can't tell apart centers
+    /// closer than f64 resolution, so callers check this as well.
+    pub fn is_current(&self, center: (DVec2, DVec2)) -> bool {
+        self.orbit_key
+            .as_ref()
+            .is_some_and(|key| key.center == center)
+    }
+
+    /// Renders `params` around the exact `center` into `texture`.
+    pub fn run(
+        &mut self,
+        gpu: &GPUInterface,
+        params: &MandelbrotParams,
+        center: (DVec2, DVec2),
+        zoom: f32,
+        texture: &wgpu::Texture,
+        texture_size: Extent3d,
+    ) {
+        let key = OrbitKey {
+            center,
+            max_iterations: params.max_iterations,
+            fractal_mode: params.fractal_mode,
+            julia_c: params.julia_c,
+            kind: params.kind,
+            bits: precision_bits(zoom),
+        };
+        if self.orbit_key.as_ref() != Some(&key) || self.orbit_buffer.is_none() {
+            let zero = (DVec2::default(), DVec2::default());
+            let julia_c = (DVec2::from(FVec2::from(params.julia_c)), DVec2::default());
+            let (z0, c) = if params.fractal_mode == FractalMode::Julia as u32 {
+                (center, julia_c)
+            } else {
+                (zero, center)
+            };
+            let orbit = reference_orbit(
+                z0,
+                c,
+                params.kind == FractalKind::Tricorn as u32,
+                params.max_iterations,
+                params.escape_radius,
+                key.bits,
+            );
+            self.orbit_buffer = Some(gpu.device.create_buffer_init(
+                &wgpu::util::BufferInitDescriptor {
+                    label: Some("Reference orbit buffer"),
+                    contents: bytemuck::cast_slice(&orbit),
+                    usage: wgpu::BufferUsages::STORAGE,
+                },
+            ));
+            self.orbit_key = Some(key);
+        }
+        let orbit_buffer = self.orbit_buffer.as_ref().expect("orbit was just computed");
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        let params_buffer = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Perturbation params buffer"),
+                contents: bytemuck::bytes_of(params),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Perturbation bind group"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(
+                        &texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: orbit_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        {
+            let (dispatch_width, dispatch_height) =
+                compute_work_group_count((texture_size.width, texture_size.height), (16, 16));
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Perturbation pass"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+    }
+}
@@ -0,0 +1,235 @@
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+use crate::{
+    computer::Computer,
+    gpu_interface::GPUInterface,
+    math::{FVec2, UVec2},
+};
+
+/// Number of blocks along each axis of the detail grid. Must match `DETAIL_GRID` in `detail.wgsl`.
+pub const DETAIL_GRID: u32 = 8;
+
+/// Size of the scores buffer, one f32 per block.
+const SCORES_SIZE: u64 = (DETAIL_GRID * DETAIL_GRID) as u64 * 4;
+
+/// Mean compressed gradient magnitude of the smooth iteration count per block, row-major,
+/// stamped with the frame they were computed for.
+#[derive(Clone, Debug)]
+pub struct DetailScores {
+    pub frame: u64,
+    pub scores: Vec<f32>,
+}
+
+impl DetailScores {
+    /// The block with the highest score and that score.
+    pub fn best(&self) -> Option<(UVec2, f32)> {
+        self.scores
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, &score)| {
+                let i = i as u32;
+                (UVec2::new(i % DETAIL_GRID, i / DETAIL_GRID), score)
+            })
+    }
+}
+
+/// Center in pixels of `block` in a `size` sized image.
+pub fn block_center(block: UVec2, size: UVec2) -> FVec2 {
+    let block_size = FVec2::from(size) / DETAIL_GRID as f32;
+    let block = FVec2::from(block) + FVec2::new(0.5, 0.5);
+    FVec2::new(block.x * block_size.x, block.y * block_size.y)
+}
+
+/// Sobel filter over the iteration texture followed by a per-block reduction into `DetailScores`.
+/// Like `StatsReduction`, at most one run is in flight and it is read back asynchronously.
+pub struct DetailReduction {
+    sobel_pipeline: wgpu::ComputePipeline,
+    blocks_pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    gradients_buffer: wgpu::Buffer,
+    scores_buffer: wgpu::Buffer,
+    staging_buffer: wgpu::Buffer,
+    workgroups: (u32, u32),
+    pending: Option<(u64, Receiver<Result<(), wgpu::BufferAsyncError>>)>,
+}
+
+impl DetailReduction {
+    pub fn new(gpu: &GPUInterface, computer: &Computer) -> DetailReduction {
+        let size = computer.texture_size;
+        let workgroups = (size.width.div_ceil(16), size.height.div_ceil(16));
+
+        let shader = gpu
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Detail shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("shaders/detail.wgsl").into()),
+            });
+
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Detail bind group layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            },
+                            count: None,
+                        },
+                        storage_entry(1),
+                        storage_entry(2),
+                    ],
+                });
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Detail pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let create_pipeline = |entry_point| {
+            gpu.device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Detail pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+        };
+        let sobel_pipeline = create_pipeline("sobel");
+        let blocks_pipeline = create_pipeline("reduce_blocks");
+
+        let gradients_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Detail gradients buffer"),
+            size: (size.width * size.height) as u64 * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let scores_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Detail scores buffer"),
+            size: SCORES_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Detail staging buffer"),
+            size: SCORES_SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        DetailReduction {
+            sobel_pipeline,
+            blocks_pipeline,
+            bind_group_layout,
+            gradients_buffer,
+            scores_buffer,
+            staging_buffer,
+            workgroups,
+            pending: None,
+        }
+    }
+
+    /// Scores the current contents of the iteration texture for `frame`.
+    /// Returns false without doing anything if the previous readback hasn't finished yet.
+    pub fn dispatch(
+        &mut self,
+        gpu: &GPUInterface,
+        iteration_view: &wgpu::TextureView,
+        frame: u64,
+    ) -> bool {
+        if self.pending.is_some() {
+            return false;
+        }
+        // Bound per dispatch, as a double buffered computer alternates between two textures.
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Detail bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(iteration_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.gradients_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.scores_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Detail encoder"),
+            });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Detail pass"),
+            });
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.set_pipeline(&self.sobel_pipeline);
+            compute_pass.dispatch_workgroups(self.workgroups.0, self.workgroups.1, 1);
+            compute_pass.set_pipeline(&self.blocks_pipeline);
+            compute_pass.dispatch_workgroups(DETAIL_GRID, DETAIL_GRID, 1);
+        }
+        encoder.copy_buffer_to_buffer(&self.scores_buffer, 0, &self.staging_buffer, 0, SCORES_SIZE);
+        gpu.queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = channel();
+        self.staging_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.pending = Some((frame, receiver));
+        true
+    }
+
+    /// Whether a readback is in flight, so `poll` still has work to do.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Returns the finished scores once the readback completes, without blocking.
+    pub fn poll(&mut self, gpu: &GPUInterface) -> Option<DetailScores> {
+        let (frame, receiver) = self.pending.as_ref()?;
+        gpu.device.poll(wgpu::Maintain::Poll);
+        let scores = match receiver.try_recv() {
+            Err(TryRecvError::Empty) => return None,
+            Ok(Ok(())) => {
+                let scores = DetailScores {
+                    frame: *frame,
+                    scores: bytemuck::cast_slice(&self.staging_buffer.slice(..).get_mapped_range())
+                        .to_vec(),
+                };
+                self.staging_buffer.unmap();
+                Some(scores)
+            }
+            Ok(Err(e)) => {
+                eprintln!("Detail readback failed: {:?}", e);
+                None
+            }
+            Err(TryRecvError::Disconnected) => None,
+        };
+        self.pending = None;
+        scores
+    }
+}
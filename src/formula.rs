@@ -0,0 +1,418 @@
+//! Custom iteration formulas typed at runtime, such as `z = z*z*z + c*sin(z)`.
+//!
+//! A [`CustomFormula`] is parsed from complex-valued expressions over `z`, `c`, numbers, `i`,
+//! `pi` and `e`, with `+ - * / ^`, parentheses and the functions `sin`, `cos`, `exp` and `abs`.
+//! It compiles to a WGSL expression that replaces the body of `custom_step` in
+//! `mandelbrot.wgsl`, see `Computer::set_formula`.
+
+use std::{fmt, str::FromStr};
+
+/// Line of `custom_step` in `mandelbrot.wgsl` that `CustomFormula::splice` replaces.
+const TEMPLATE_STEP: &str = "return complex_pow(z, 2.0) + c; // custom formula";
+
+/// Functions a formula can call, each taking one complex argument.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Function {
+    Sin,
+    Cos,
+    Exp,
+    /// |a| as a real number.
+    Abs,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Function> {
+        match name {
+            "sin" => Some(Function::Sin),
+            "cos" => Some(Function::Cos),
+            "exp" => Some(Function::Exp),
+            "abs" => Some(Function::Abs),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+/// Parsed formula expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Z,
+    C,
+    /// Real and imaginary part.
+    Constant(f32, f32),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Box<Expr>),
+}
+
+impl Expr {
+    /// WGSL expression of type `vec2<f32>` over `z` and `c`, using the complex helpers of
+    /// `mandelbrot.wgsl`.
+    fn to_wgsl(&self) -> String {
+        match self {
+            Expr::Z => "z".to_string(),
+            Expr::C => "c".to_string(),
+            Expr::Constant(re, im) => format!("vec2<f32>({:?}, {:?})", re, im),
+            Expr::Neg(a) => format!("(-{})", a.to_wgsl()),
+            Expr::Binary(op, a, b) => {
+                let (a, b_wgsl) = (a.to_wgsl(), b.to_wgsl());
+                match op {
+                    BinaryOp::Add => format!("({} + {})", a, b_wgsl),
+                    BinaryOp::Sub => format!("({} - {})", a, b_wgsl),
+                    BinaryOp::Mul => format!("complex_mult({}, {})", a, b_wgsl),
+                    BinaryOp::Div => format!("complex_div({}, {})", a, b_wgsl),
+                    // complex_pow is exact for whole powers, but only handles real ones from 1 up.
+                    BinaryOp::Pow => match **b {
+                        Expr::Constant(d, im) if im == 0.0 && d >= 1.0 => {
+                            format!("complex_pow({}, {:?})", a, d)
+                        }
+                        _ => format!("complex_powc({}, {})", a, b_wgsl),
+                    },
+                }
+            }
+            Expr::Call(function, a) => {
+                let a = a.to_wgsl();
+                match function {
+                    Function::Sin => format!("complex_sin({})", a),
+                    Function::Cos => format!("complex_cos({})", a),
+                    Function::Exp => format!("complex_exp({})", a),
+                    Function::Abs => format!("vec2<f32>(length({}), 0.0)", a),
+                }
+            }
+        }
+    }
+
+    /// Degree as a polynomial in z, None if it isn't one.
+    fn degree(&self) -> Option<f32> {
+        match self {
+            Expr::Z => Some(1.0),
+            Expr::C | Expr::Constant(..) => Some(0.0),
+            Expr::Neg(a) => a.degree(),
+            Expr::Binary(op, a, b) => {
+                let (a, b_degree) = (a.degree()?, b.degree()?);
+                match op {
+                    BinaryOp::Add | BinaryOp::Sub => Some(a.max(b_degree)),
+                    BinaryOp::Mul => Some(a + b_degree),
+                    BinaryOp::Div => Some(a - b_degree),
+                    BinaryOp::Pow => match **b {
+                        Expr::Constant(d, 0.0) => Some(a * d),
+                        _ if a == 0.0 && b_degree == 0.0 => Some(0.0),
+                        _ => None,
+                    },
+                }
+            }
+            Expr::Call(Function::Abs, a) => a.degree(),
+            Expr::Call(_, a) => (a.degree()? == 0.0).then_some(0.0),
+        }
+    }
+}
+
+/// Why a formula didn't parse.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormulaError {
+    /// Byte offset into the formula text.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for FormulaError {}
+
+/// Iteration formula z -> f(z, c), rendered by `computer::FractalKind::Custom`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomFormula {
+    /// The expression as typed, without any `z =`, for the title bar.
+    source: String,
+    expr: Expr,
+}
+
+impl CustomFormula {
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    /// WGSL expression of the next z.
+    pub fn to_wgsl(&self) -> String {
+        self.expr.to_wgsl()
+    }
+
+    /// `template` with `custom_step` returning this formula. Fails if the template lacks the
+    /// line to replace, e.g. after editing the shader with hot reloading.
+    pub fn splice(&self, template: &str) -> anyhow::Result<String> {
+        anyhow::ensure!(
+            template.contains(TEMPLATE_STEP),
+            "the shader has no '{}' line to replace",
+            TEMPLATE_STEP
+        );
+        Ok(template.replace(TEMPLATE_STEP, &format!("return {};", self.to_wgsl())))
+    }
+
+    /// Power the smooth iteration count is renormalized with: the degree in z, which
+    /// dominates once z is large. 2 for formulas that aren't polynomials of degree above 1.
+    pub fn escape_power(&self) -> f32 {
+        self.expr
+            .degree()
+            .filter(|&degree| degree > 1.0)
+            .unwrap_or(2.0)
+    }
+}
+
+impl fmt::Display for CustomFormula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl FromStr for CustomFormula {
+    type Err = FormulaError;
+
+    /// Parses `expr` or `z = expr`.
+    fn from_str(text: &str) -> Result<CustomFormula, FormulaError> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            index: 0,
+            end: text.len(),
+        };
+        let mut start = 0;
+        if let [(_, Token::Ident(name)), (equals, Token::Symbol('=')), ..] = tokens.as_slice() {
+            if name != "z" {
+                return Err(parser.error("only z can be assigned"));
+            }
+            parser.index = 2;
+            start = equals + 1;
+        }
+        let expr = parser.expr()?;
+        if parser.peek().is_some() {
+            return Err(parser.error("expected an operator"));
+        }
+        Ok(CustomFormula {
+            source: text[start..].trim().to_string(),
+            expr,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f32),
+    Ident(String),
+    /// One of `+ - * / ^ ( ) =`.
+    Symbol(char),
+}
+
+/// Splits `text` into tokens with their byte offsets.
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, FormulaError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, ch)) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+        } else if ch.is_ascii_digit() || ch == '.' {
+            let mut end = start;
+            while let Some(&(i, ch)) = chars.peek() {
+                if ch.is_ascii_digit() || ch == '.' {
+                    end = i + 1;
+                } else if (ch == 'e' || ch == 'E') && starts_exponent(&text[i + 1..]) {
+                    // 1e-5, but 2e is 2 times e.
+                    chars.next();
+                    let Some(&(sign, '+' | '-')) = chars.peek() else {
+                        continue;
+                    };
+                    end = sign + 1;
+                } else {
+                    break;
+                }
+                chars.next();
+            }
+            let value = text[start..end]
+                .parse::<f32>()
+                .ok()
+                .filter(|v| v.is_finite())
+                .ok_or_else(|| FormulaError {
+                    position: start,
+                    message: format!("invalid number '{}'", &text[start..end]),
+                })?;
+            tokens.push((start, Token::Number(value)));
+        } else if ch.is_ascii_alphabetic() {
+            let mut end = start;
+            while let Some(&(i, ch)) = chars.peek() {
+                if !ch.is_ascii_alphanumeric() {
+                    break;
+                }
+                end = i + 1;
+                chars.next();
+            }
+            tokens.push((start, Token::Ident(text[start..end].to_string())));
+        } else if "+-*/^()=".contains(ch) {
+            tokens.push((start, Token::Symbol(ch)));
+            chars.next();
+        } else {
+            return Err(FormulaError {
+                position: start,
+                message: format!("unexpected '{}'", ch),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+/// Whether `rest`, the text after an `e` in a number, is its exponent.
+fn starts_exponent(rest: &str) -> bool {
+    let digits = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+    digits.starts_with(|ch: char| ch.is_ascii_digit())
+}
+
+/// Recursive descent over the tokens, one method per precedence level.
+struct Parser<'a> {
+    tokens: &'a [(usize, Token)],
+    index: usize,
+    /// Length of the text, the position of errors at its end.
+    end: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        self.index += 1;
+        self.tokens.get(self.index - 1).map(|(_, token)| token)
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        let found = self.peek() == Some(&Token::Symbol(symbol));
+        if found {
+            self.index += 1;
+        }
+        found
+    }
+
+    /// An error at the current token.
+    fn error(&self, message: &str) -> FormulaError {
+        FormulaError {
+            position: self
+                .tokens
+                .get(self.index)
+                .map_or(self.end, |(position, _)| *position),
+            message: message.to_string(),
+        }
+    }
+
+    /// Sums and differences of terms.
+    fn expr(&mut self) -> Result<Expr, FormulaError> {
+        let mut expr = self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                BinaryOp::Add
+            } else if self.eat('-') {
+                BinaryOp::Sub
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.term()?));
+        }
+    }
+
+    /// Products and quotients. Juxtaposition multiplies too, as in `2z` or `c sin(z)`.
+    fn term(&mut self) -> Result<Expr, FormulaError> {
+        let mut expr = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                BinaryOp::Mul
+            } else if self.eat('/') {
+                BinaryOp::Div
+            } else if matches!(
+                self.peek(),
+                Some(Token::Number(_) | Token::Ident(_) | Token::Symbol('('))
+            ) {
+                BinaryOp::Mul
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, FormulaError> {
+        if self.eat('-') {
+            // Folded into constants, so z^-1 still has a constant exponent.
+            return Ok(match self.unary()? {
+                Expr::Constant(re, im) => Expr::Constant(-re, -im),
+                expr => Expr::Neg(Box::new(expr)),
+            });
+        }
+        if self.eat('+') {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    /// `^` binds tighter than the sign of its base and groups to the right.
+    fn power(&mut self) -> Result<Expr, FormulaError> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            let exponent = self.unary()?;
+            return Ok(Expr::Binary(
+                BinaryOp::Pow,
+                Box::new(base),
+                Box::new(exponent),
+            ));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Expr, FormulaError> {
+        let position = self.index;
+        match self.next().cloned() {
+            Some(Token::Number(value)) => Ok(Expr::Constant(value, 0.0)),
+            Some(Token::Symbol('(')) => {
+                let expr = self.expr()?;
+                if !self.eat(')') {
+                    return Err(self.error("expected ')'"));
+                }
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "z" => Ok(Expr::Z),
+                "c" => Ok(Expr::C),
+                "i" => Ok(Expr::Constant(0.0, 1.0)),
+                "pi" => Ok(Expr::Constant(std::f32::consts::PI, 0.0)),
+                "e" => Ok(Expr::Constant(std::f32::consts::E, 0.0)),
+                _ => {
+                    let Some(function) = Function::from_name(&name) else {
+                        self.index = position;
+                        return Err(self.error(&format!("unknown name '{}'", name)));
+                    };
+                    if !self.eat('(') {
+                        return Err(self.error(&format!("expected '(' after {}", name)));
+                    }
+                    let argument = self.expr()?;
+                    if !self.eat(')') {
+                        return Err(self.error("expected ')'"));
+                    }
+                    Ok(Expr::Call(function, Box::new(argument)))
+                }
+            },
+            Some(_) => {
+                self.index = position;
+                Err(self.error("expected a value"))
+            }
+            None => Err(self.error("expected a value")),
+        }
+    }
+}
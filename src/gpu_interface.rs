@@ -1,15 +1,201 @@
+use std::{fmt, fmt::Write, str::FromStr};
+
+use serde::{Deserialize, Serialize};
 use winit::window::Window;
 
+/// How frames are handed to the display. Shift+V cycles through the ones the surface supports.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresentMode {
+    /// Waits for vertical blank, capping the frame rate at the refresh rate. Always supported.
+    Fifo,
+    /// Replaces the queued frame with newer ones, so it is uncapped without tearing.
+    Mailbox,
+    /// Presents right away, uncapped and possibly tearing.
+    Immediate,
+}
+
+impl PresentMode {
+    const ALL: [PresentMode; 3] = [
+        PresentMode::Fifo,
+        PresentMode::Mailbox,
+        PresentMode::Immediate,
+    ];
+
+    fn to_wgpu(self) -> wgpu::PresentMode {
+        match self {
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+
+    /// None for the modes this app doesn't offer, like the Auto ones.
+    fn from_wgpu(mode: wgpu::PresentMode) -> Option<PresentMode> {
+        match mode {
+            wgpu::PresentMode::Fifo => Some(PresentMode::Fifo),
+            wgpu::PresentMode::Mailbox => Some(PresentMode::Mailbox),
+            wgpu::PresentMode::Immediate => Some(PresentMode::Immediate),
+            _ => None,
+        }
+    }
+
+    /// Closest mode to try when this one is unsupported; Fifo ends the chain.
+    fn fallback(self) -> PresentMode {
+        match self {
+            PresentMode::Immediate => PresentMode::Mailbox,
+            PresentMode::Mailbox | PresentMode::Fifo => PresentMode::Fifo,
+        }
+    }
+}
+
+/// Which adapter `GPUInterface::new` uses: an index into the list `--gpu-info` prints, or part
+/// of the adapter's name, ignoring case.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum AdapterChoice {
+    Index(usize),
+    Name(String),
+}
+
+impl AdapterChoice {
+    fn matches(&self, index: usize, info: &wgpu::AdapterInfo) -> bool {
+        match self {
+            AdapterChoice::Index(i) => *i == index,
+            AdapterChoice::Name(name) => info.name.to_lowercase().contains(&name.to_lowercase()),
+        }
+    }
+}
+
+impl FromStr for AdapterChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("expected an adapter index or name".to_string());
+        }
+        Ok(s.parse()
+            .map(AdapterChoice::Index)
+            .unwrap_or_else(|_| AdapterChoice::Name(s.to_string())))
+    }
+}
+
+impl TryFrom<String> for AdapterChoice {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for AdapterChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdapterChoice::Index(index) => write!(f, "{}", index),
+            AdapterChoice::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl From<AdapterChoice> for String {
+    fn from(choice: AdapterChoice) -> Self {
+        choice.to_string()
+    }
+}
+
+/// Why `GPUInterface` could not be created.
+#[derive(Debug)]
+pub enum GpuInitError {
+    /// There is no adapter for the requested backends at all.
+    NoAdapter,
+    /// Adapters exist, but none can present to the window's surface.
+    SurfaceIncompatible,
+    /// No adapter matches the requested `AdapterChoice`; lists the ones there are.
+    NoSuchAdapter {
+        choice: AdapterChoice,
+        available: Vec<String>,
+    },
+    /// The requested adapter can't present to the window; lists the ones that can.
+    AdapterCannotPresent {
+        adapter: String,
+        presentable: Vec<String>,
+    },
+    /// The adapter refused to create a device, e.g. a driver failure.
+    RequestDevice(wgpu::RequestDeviceError),
+}
+
+impl fmt::Display for GpuInitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GpuInitError::NoAdapter => write!(f, "no compatible GPU adapter found"),
+            GpuInitError::SurfaceIncompatible => write!(
+                f,
+                "surface incompatible: no GPU adapter can present to this window"
+            ),
+            GpuInitError::NoSuchAdapter { choice, available } => write!(
+                f,
+                "no GPU adapter matches '{}'; available: {}",
+                choice,
+                available.join("; ")
+            ),
+            GpuInitError::AdapterCannotPresent {
+                adapter,
+                presentable,
+            } => write!(
+                f,
+                "{} can't present to this window; adapters that can: {}",
+                adapter,
+                if presentable.is_empty() {
+                    "none".to_string()
+                } else {
+                    presentable.join("; ")
+                }
+            ),
+            GpuInitError::RequestDevice(e) => write!(f, "could not create a GPU device: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GpuInitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GpuInitError::RequestDevice(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for GpuInitError {
+    fn from(e: wgpu::RequestDeviceError) -> Self {
+        GpuInitError::RequestDevice(e)
+    }
+}
+
+/// The wgpu device and queue everything renders with, plus the window surface if there is one.
 pub struct GPUInterface {
-    pub surface: wgpu::Surface,
+    /// None when headless, in which case `config` only describes a nominal output format.
+    pub surface: Option<wgpu::Surface>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
+    /// Name, backend and type of the adapter in use, for logs and the timing overlay.
+    pub adapter_info: wgpu::AdapterInfo,
+    /// Kept to query texture format support, which fallback adapters limit.
+    adapter: wgpu::Adapter,
+    /// Present modes the surface accepts; empty when headless.
+    present_modes: Vec<PresentMode>,
 }
 
 impl GPUInterface {
-    pub fn new(window: &Window) -> GPUInterface {
+    /// Starts in `present_mode`, or the closest supported mode if the surface lacks it. Uses the
+    /// `adapter` asked for, otherwise the most powerful one that can present to the window.
+    pub fn new(
+        window: &Window,
+        present_mode: PresentMode,
+        adapter: Option<&AdapterChoice>,
+    ) -> Result<GPUInterface, GpuInitError> {
         let size = window.inner_size();
 
         // The instance is a handle to our GPU
@@ -17,30 +203,60 @@ impl GPUInterface {
         let instance = wgpu::Instance::new(wgpu::Backends::all());
         let surface = unsafe { instance.create_surface(window) };
 
-        let adapter = instance
-            .enumerate_adapters(wgpu::Backends::all())
-            .filter(|adapter| {
-                // Check if this adapter supports our surface
-                surface.get_supported_formats(&adapter).len() > 0
-            })
-            .next()
-            .unwrap();
-        let (device, queue) = pollster::block_on(adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                // WebGL doesn't support all of wgpu's features, so if
-                // we're building for the web we'll have to disable some.
-                limits: if cfg!(target_arch = "wasm32") {
-                    wgpu::Limits::downlevel_webgl2_defaults()
-                } else {
-                    wgpu::Limits::default()
-                },
-                label: None,
-            },
-            None, // Trace path
-        ))
-        .unwrap();
+        let mut adapters: Vec<_> = instance.enumerate_adapters(wgpu::Backends::all()).collect();
+        let can_present =
+            |adapter: &wgpu::Adapter| !surface.get_supported_formats(adapter).is_empty();
+        let adapter = match adapter {
+            Some(choice) => {
+                let index = adapters
+                    .iter()
+                    .enumerate()
+                    .position(|(i, adapter)| choice.matches(i, &adapter.get_info()))
+                    .ok_or_else(|| GpuInitError::NoSuchAdapter {
+                        choice: choice.clone(),
+                        available: describe_adapters(&adapters, |_| true),
+                    })?;
+                if !can_present(&adapters[index]) {
+                    return Err(GpuInitError::AdapterCannotPresent {
+                        adapter: describe_adapter(index, &adapters[index].get_info()),
+                        presentable: describe_adapters(&adapters, can_present),
+                    });
+                }
+                adapters.swap_remove(index)
+            }
+            None => {
+                // Like PowerPreference::HighPerformance: a discrete GPU over an integrated one.
+                let best = (0..adapters.len())
+                    .filter(|&i| can_present(&adapters[i]))
+                    .min_by_key(|&i| power_rank(adapters[i].get_info().device_type));
+                match best {
+                    Some(index) => adapters.swap_remove(index),
+                    // VMs and CI machines may have no hardware device at all, but a software
+                    // one the platform only hands out on request.
+                    None => {
+                        let fallback = pollster::block_on(instance.request_adapter(
+                            &wgpu::RequestAdapterOptions {
+                                power_preference: wgpu::PowerPreference::HighPerformance,
+                                compatible_surface: Some(&surface),
+                                force_fallback_adapter: true,
+                            },
+                        ));
+                        match fallback {
+                            Some(adapter) => adapter,
+                            None if adapters.is_empty() => return Err(GpuInitError::NoAdapter),
+                            None => return Err(GpuInitError::SurfaceIncompatible),
+                        }
+                    }
+                }
+            }
+        };
+        let (device, queue) = request_device(&adapter)?;
 
+        let present_modes = surface
+            .get_supported_modes(&adapter)
+            .into_iter()
+            .filter_map(PresentMode::from_wgpu)
+            .collect();
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface.get_supported_formats(&adapter)[0],
@@ -48,13 +264,215 @@ impl GPUInterface {
             height: size.height,
             present_mode: wgpu::PresentMode::Fifo,
         };
-        surface.configure(&device, &config);
-        GPUInterface {
-            surface,
+        let mut gpu = GPUInterface {
+            surface: Some(surface),
             device,
             queue,
             config,
             size,
+            adapter_info: adapter.get_info(),
+            adapter,
+            present_modes,
+        };
+        let used = gpu.set_present_mode(present_mode);
+        if used != present_mode {
+            eprintln!(
+                "Warning: present mode {:?} is unsupported; using {:?}",
+                present_mode, used
+            );
+        }
+        Ok(gpu)
+    }
+
+    /// A device without a window or surface, for rendering straight to files.
+    /// Only the primary backends are tried: without a surface the adapter can end up on GL,
+    /// whose wgpu 0.13 backend can't build the storage texture pipelines. Machines without a
+    /// hardware device get the platform's software fallback adapter if it has one.
+    pub fn headless() -> Result<GPUInterface, GpuInitError> {
+        let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
+        let request = |force_fallback_adapter| {
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: None,
+                force_fallback_adapter,
+            }))
+        };
+        let adapter = request(false)
+            .or_else(|| request(true))
+            .ok_or(GpuInitError::NoAdapter)?;
+        let (device, queue) = request_device(&adapter)?;
+        let size = winit::dpi::PhysicalSize::new(0, 0);
+        Ok(GPUInterface {
+            surface: None,
+            device,
+            queue,
+            config: wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                width: size.width,
+                height: size.height,
+                present_mode: wgpu::PresentMode::Fifo,
+            },
+            size,
+            adapter_info: adapter.get_info(),
+            adapter,
+            present_modes: Vec::new(),
+        })
+    }
+
+    /// Whether the adapter renders in software, like llvmpipe, WARP or SwiftShader.
+    pub fn is_software(&self) -> bool {
+        self.adapter_info.device_type == wgpu::DeviceType::Cpu
+    }
+
+    /// Whether compute shaders can write textures of `format`, which some fallback adapters
+    /// don't allow for every format.
+    pub fn supports_storage(&self, format: wgpu::TextureFormat) -> bool {
+        self.adapter
+            .get_texture_format_features(format)
+            .allowed_usages
+            .contains(wgpu::TextureUsages::STORAGE_BINDING)
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        // Only ever configured through `set_present_mode`.
+        PresentMode::from_wgpu(self.config.present_mode).unwrap_or(PresentMode::Fifo)
+    }
+
+    /// Switches to `mode`, or the closest supported one, and reconfigures the surface in
+    /// place. Returns the mode now in use.
+    pub fn set_present_mode(&mut self, mode: PresentMode) -> PresentMode {
+        let mut mode = mode;
+        while mode != PresentMode::Fifo && !self.present_modes.contains(&mode) {
+            mode = mode.fallback();
         }
+        self.config.present_mode = mode.to_wgpu();
+        self.configure_surface();
+        mode
     }
+
+    /// Configures the surface again with `config`, e.g. after it went out of date. A minimized
+    /// window has no size to configure, so this does nothing until a resize catches up.
+    pub fn configure_surface(&self) {
+        if let Some(surface) = &self.surface {
+            if self.config.width > 0 && self.config.height > 0 {
+                surface.configure(&self.device, &self.config);
+            }
+        }
+    }
+
+    /// Moves on to the next supported present mode, wrapping around.
+    pub fn cycle_present_mode(&mut self) -> PresentMode {
+        let current = self.present_mode();
+        let start = PresentMode::ALL
+            .iter()
+            .position(|&m| m == current)
+            .unwrap_or(0);
+        let next = (1..PresentMode::ALL.len())
+            .map(|i| PresentMode::ALL[(start + i) % PresentMode::ALL.len()])
+            .find(|mode| self.present_modes.contains(mode))
+            .unwrap_or(current);
+        self.set_present_mode(next)
+    }
+
+    /// Whether shaders may use f64.
+    pub fn supports_f64(&self) -> bool {
+        self.device
+            .features()
+            .contains(wgpu::Features::SHADER_FLOAT64)
+    }
+
+    /// Runs `create` inside a validation error scope, so a broken shader is returned as an
+    /// error instead of reaching the device's uncaptured error handler, which panics.
+    pub fn validated<T>(&self, create: impl FnOnce() -> T) -> anyhow::Result<T> {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let value = create();
+        match pollster::block_on(self.device.pop_error_scope()) {
+            Some(error) => Err(anyhow::anyhow!("{}", error)),
+            None => Ok(value),
+        }
+    }
+}
+
+/// Every adapter with its limits, in the order `AdapterChoice::Index` counts them, for
+/// --gpu-info. Whether one can present depends on the window, so that isn't listed.
+pub fn adapter_report() -> String {
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let mut report = String::new();
+    for (index, adapter) in instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .enumerate()
+    {
+        let limits = adapter.limits();
+        let _ = writeln!(report, "{}", describe_adapter(index, &adapter.get_info()));
+        let _ = writeln!(
+            report,
+            "    max texture {}, max workgroup {}x{}x{} ({} invocations), max storage buffer {} bytes, f64 shaders: {}",
+            limits.max_texture_dimension_2d,
+            limits.max_compute_workgroup_size_x,
+            limits.max_compute_workgroup_size_y,
+            limits.max_compute_workgroup_size_z,
+            limits.max_compute_invocations_per_workgroup,
+            limits.max_storage_buffer_binding_size,
+            adapter.features().contains(wgpu::Features::SHADER_FLOAT64),
+        );
+    }
+    if report.is_empty() {
+        report.push_str("No GPU adapters found\n");
+    }
+    report
+}
+
+/// "INDEX: NAME (BACKEND, TYPE)".
+pub fn describe_adapter(index: usize, info: &wgpu::AdapterInfo) -> String {
+    format!(
+        "{}: {} ({:?}, {:?})",
+        index, info.name, info.backend, info.device_type
+    )
+}
+
+fn describe_adapters(
+    adapters: &[wgpu::Adapter],
+    filter: impl Fn(&wgpu::Adapter) -> bool,
+) -> Vec<String> {
+    adapters
+        .iter()
+        .enumerate()
+        .filter(|(_, adapter)| filter(adapter))
+        .map(|(i, adapter)| describe_adapter(i, &adapter.get_info()))
+        .collect()
+}
+
+/// Order adapters are preferred in when none is chosen; lower is faster.
+fn power_rank(device_type: wgpu::DeviceType) -> u8 {
+    match device_type {
+        wgpu::DeviceType::DiscreteGpu => 0,
+        wgpu::DeviceType::IntegratedGpu => 1,
+        wgpu::DeviceType::VirtualGpu => 2,
+        wgpu::DeviceType::Other => 3,
+        wgpu::DeviceType::Cpu => 4,
+    }
+}
+
+fn request_device(
+    adapter: &wgpu::Adapter,
+) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+    pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            // Double precision is optional; Computer falls back to f32 without it.
+            features: adapter.features() & wgpu::Features::SHADER_FLOAT64,
+            // WebGL doesn't support all of wgpu's features, so if
+            // we're building for the web we'll have to disable some.
+            // Elsewhere take whatever the adapter offers: fallback and GL adapters often fall
+            // short of the defaults, and Computer checks the texture size against the real
+            // limits anyway.
+            limits: if cfg!(target_arch = "wasm32") {
+                wgpu::Limits::downlevel_webgl2_defaults()
+            } else {
+                adapter.limits()
+            },
+            label: None,
+        },
+        None, // Trace path
+    ))
 }
@@ -0,0 +1,128 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::{
+    accumulate::{self, ACCUMULATION_FRAMES},
+    colorize::ColorParams,
+    computer::{ComputeSetup, FractalMode, Precision},
+    gpu_interface::GPUInterface,
+    math::{FVec2, UVec2},
+    palette::{builtin_palettes, ColorMapping},
+    poster::{self, PosterJob},
+    state::StartupOptions,
+};
+
+/// What to render without a window, from the command line.
+#[derive(Clone, Debug)]
+pub struct HeadlessOptions {
+    pub startup: StartupOptions,
+    pub size: UVec2,
+    pub output: PathBuf,
+    /// Seed of a deterministic render, which averages `ACCUMULATION_FRAMES` frames jittered
+    /// by `accumulate::jitter` instead of rendering one.
+    pub deterministic: Option<u32>,
+    /// Continue from the tiles an interrupted render of the same image left in
+    /// `poster::checkpoint_dir`. Single pass renders always leave them until the image is saved.
+    pub resume: bool,
+}
+
+/// Written next to a deterministic render, with what it takes to render it again.
+#[derive(Serialize)]
+struct DeterministicMetadata {
+    seed: u32,
+    frames: u32,
+    location: String,
+}
+
+/// Renders one image with the default palette and writes it to `options.output`, along with
+/// a JSON file of the seed for a deterministic render.
+pub fn render(options: &HeadlessOptions) -> anyhow::Result<()> {
+    render_image(options)?
+        .save(&options.output)
+        .with_context(|| format!("could not write {}", options.output.display()))?;
+    println!("Saved {}", options.output.display());
+    let checkpoint = poster::checkpoint_dir(&options.output);
+    if checkpoint.exists() {
+        std::fs::remove_dir_all(&checkpoint)
+            .with_context(|| format!("could not remove {}", checkpoint.display()))?;
+    }
+    if let Some(seed) = options.deterministic {
+        let path = options.output.with_extension("json");
+        let metadata = DeterministicMetadata {
+            seed,
+            frames: ACCUMULATION_FRAMES,
+            location: options.startup.view_state().location_text(),
+        };
+        std::fs::write(&path, serde_json::to_string_pretty(&metadata)?)
+            .with_context(|| format!("could not write {}", path.display()))?;
+        println!("Seed {}, saved in {}", seed, path.display());
+    }
+    Ok(())
+}
+
+/// The image `render` writes. Goes through `PosterJob`, so sizes beyond the device's texture
+/// limit are tiled. A deterministic render gives the same pixels every time on one adapter.
+pub fn render_image(options: &HeadlessOptions) -> anyhow::Result<image::RgbaImage> {
+    let gpu = GPUInterface::headless()?;
+    let mut state = options.startup.view_state();
+    let (center, _) = state.view.center();
+    // Like the J key, Julia mode takes c from the center and frames the whole set.
+    if options.startup.julia {
+        state.view = state.fractal_kind.julia_view();
+    }
+    let mut params = state.render_params(options.size, options.startup.formula.as_ref());
+    if options.startup.julia {
+        params.fractal_mode = FractalMode::Julia as u32;
+        params.julia_c = FVec2::from(center).into();
+    }
+    let color_params = ColorParams {
+        roots: params.newton_roots(),
+        ..ColorMapping::default().color_params()
+    };
+    let palettes = builtin_palettes();
+    let mut job = PosterJob::new(
+        &gpu,
+        options.size,
+        params,
+        color_params,
+        &ComputeSetup {
+            // Falls back to the device's default precision if f64 is unsupported.
+            precision: Precision::F64,
+            formula: options.startup.formula.clone(),
+        },
+        &palettes[0],
+        options.output.clone(),
+    )?;
+    let Some(seed) = options.deterministic else {
+        job = job.with_checkpoint(options.resume)?;
+        let (done, total) = job.progress();
+        if done > 0 {
+            println!("Resuming after tile {}/{}", done, total);
+        }
+        while !job.is_done() {
+            job.step(&gpu)?;
+        }
+        return Ok(job.into_image());
+    };
+    // Summed as integers, so the average doesn't depend on anything but the frames.
+    let mut sums = vec![0u32; job.pixels().len()];
+    for frame_index in 0..ACCUMULATION_FRAMES {
+        job.restart(accumulate::jitter(frame_index, seed));
+        while !job.is_done() {
+            job.step(&gpu)?;
+        }
+        for (sum, &value) in sums.iter_mut().zip(job.pixels()) {
+            *sum += value as u32;
+        }
+    }
+    let pixels = sums
+        .into_iter()
+        .map(|sum| ((sum + ACCUMULATION_FRAMES / 2) / ACCUMULATION_FRAMES) as u8)
+        .collect();
+    Ok(
+        image::RgbaImage::from_raw(options.size.x, options.size.y, pixels)
+            .expect("pixels hold exactly one RGBA image"),
+    )
+}
@@ -0,0 +1,209 @@
+use std::sync::mpsc::channel;
+
+use wgpu::util::DeviceExt;
+
+use crate::{computer::compute_work_group_count, gpu_interface::GPUInterface};
+
+/// Bins of the iteration histogram. Must match `BINS` in `histogram.wgsl` and `colorize.wgsl`.
+pub const HISTOGRAM_BINS: usize = 1024;
+
+/// Bytes of the `Cdf` struct in `histogram.wgsl`: max_iterations and one f32 per bin.
+const CDF_SIZE: u64 = (4 + HISTOGRAM_BINS * 4) as u64;
+
+/// Histogram equalization for `MappingMode::Histogram`. Bins the smooth iteration counts of an
+/// iteration texture with atomics and scans them into a cumulative distribution, all on the
+/// GPU; `colorize::Colorizer` reads the distribution straight from `cdf_buffer`.
+pub struct Histogram {
+    count_pipeline: wgpu::ComputePipeline,
+    accumulate_pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    params_buffer: wgpu::Buffer,
+    bins_buffer: wgpu::Buffer,
+    cdf_buffer: wgpu::Buffer,
+}
+
+impl Histogram {
+    pub fn new(gpu: &GPUInterface) -> Histogram {
+        let shader = gpu
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Histogram shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("shaders/histogram.wgsl").into()),
+            });
+
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let storage = wgpu::BufferBindingType::Storage { read_only: false };
+        // Both entry points share one layout, which a derived layout wouldn't.
+        let bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Histogram bind group layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            },
+                            count: None,
+                        },
+                        buffer_entry(1, wgpu::BufferBindingType::Uniform),
+                        buffer_entry(2, storage),
+                        buffer_entry(3, storage),
+                    ],
+                });
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Histogram pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let create_pipeline = |entry_point| {
+            gpu.device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Histogram pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+        };
+
+        let params_buffer = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Histogram params buffer"),
+                contents: bytemuck::bytes_of(&[0.0f32; 4]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+        let bins_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Histogram bins buffer"),
+            size: (HISTOGRAM_BINS * 4) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // Zeroed until the first build, which colorizes everything with the first palette entry.
+        let cdf_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Histogram CDF buffer"),
+            size: CDF_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        Histogram {
+            count_pipeline: create_pipeline("count"),
+            accumulate_pipeline: create_pipeline("accumulate"),
+            bind_group_layout,
+            params_buffer,
+            bins_buffer,
+            cdf_buffer,
+        }
+    }
+
+    /// The cumulative distribution of the last `build`, bound by `colorize::Colorizer`.
+    pub fn cdf_buffer(&self) -> &wgpu::Buffer {
+        &self.cdf_buffer
+    }
+
+    /// Rebuilds the distribution from `iteration_view`, a `size` texture computed with
+    /// `max_iterations`. The bins are normalized by `max_iterations`, which is stored with the
+    /// distribution, so the count can change freely between builds.
+    pub fn build(
+        &self,
+        gpu: &GPUInterface,
+        iteration_view: &wgpu::TextureView,
+        size: wgpu::Extent3d,
+        max_iterations: i32,
+    ) {
+        gpu.queue.write_buffer(
+            &self.params_buffer,
+            0,
+            bytemuck::bytes_of(&[max_iterations as f32, 0.0, 0.0, 0.0]),
+        );
+        // Builds follow computes rather than frames, so the bind group isn't worth caching
+        // for every texture it may be built from.
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Histogram bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(iteration_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.bins_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.cdf_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Histogram encoder"),
+            });
+        encoder.clear_buffer(&self.bins_buffer, 0, None);
+        {
+            let (dispatch_width, dispatch_height) =
+                compute_work_group_count((size.width, size.height), (16, 16));
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Histogram pass"),
+            });
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.set_pipeline(&self.count_pipeline);
+            compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+            compute_pass.set_pipeline(&self.accumulate_pipeline);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+        }
+        gpu.queue.submit(Some(encoder.finish()));
+    }
+
+    /// Reads the distribution back, blocking until the GPU is done: the max_iterations it was
+    /// built for and the cumulative fraction of escaped pixels up to each bin.
+    pub fn read_cdf(&self, gpu: &GPUInterface) -> Result<(f32, Vec<f32>), wgpu::BufferAsyncError> {
+        let staging_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Histogram staging buffer"),
+            size: CDF_SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Histogram readback encoder"),
+            });
+        encoder.copy_buffer_to_buffer(&self.cdf_buffer, 0, &staging_buffer, 0, CDF_SIZE);
+        gpu.queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = channel();
+        staging_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        gpu.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().unwrap_or(Err(wgpu::BufferAsyncError))?;
+        let data = staging_buffer.slice(..).get_mapped_range();
+        let values: &[f32] = bytemuck::cast_slice(&data);
+        Ok((values[0], values[1..].to_vec()))
+    }
+}
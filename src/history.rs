@@ -0,0 +1,51 @@
+use std::collections::VecDeque;
+
+/// Bounded undo/redo list with browser semantics: going back and then pushing a new entry
+/// drops everything that was ahead, and the oldest entries fall off once `capacity` is reached.
+pub struct History<T> {
+    entries: VecDeque<T>,
+    /// Index of the entry that is shown, meaningless while `entries` is empty.
+    current: usize,
+    capacity: usize,
+}
+
+impl<T> History<T> {
+    pub fn new(capacity: usize) -> History<T> {
+        assert!(capacity > 0, "history capacity must be positive");
+        History {
+            entries: VecDeque::with_capacity(capacity),
+            current: 0,
+            capacity,
+        }
+    }
+
+    /// The entry last pushed or moved to.
+    pub fn current(&self) -> Option<&T> {
+        self.entries.get(self.current)
+    }
+
+    /// Makes `entry` the current one, dropping the entries ahead of the current one.
+    pub fn push(&mut self, entry: T) {
+        self.entries.truncate(self.current + 1);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+        self.current = self.entries.len() - 1;
+    }
+
+    /// Steps back to the previous entry. None at the oldest one.
+    pub fn back(&mut self) -> Option<&T> {
+        self.current = self.current.checked_sub(1)?;
+        self.entries.get(self.current)
+    }
+
+    /// Steps forward again after `back`. None at the newest entry.
+    pub fn forward(&mut self) -> Option<&T> {
+        if self.current + 1 >= self.entries.len() {
+            return None;
+        }
+        self.current += 1;
+        self.entries.get(self.current)
+    }
+}
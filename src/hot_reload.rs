@@ -0,0 +1,59 @@
+use std::{
+    path::Path,
+    sync::mpsc::{channel, Receiver},
+};
+
+use anyhow::Context;
+use notify::{RecursiveMode, Watcher};
+
+/// Shader sources on disk. Absolute, so the binary finds them from any working directory.
+const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders");
+
+/// Watches `SHADER_DIR` for edits to `.wgsl` files.
+pub struct ShaderWatcher {
+    _watcher: notify::RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+}
+
+impl ShaderWatcher {
+    /// Calls `wake` from the watcher thread on every event, so an idle event loop gets to
+    /// `poll_changed`.
+    pub fn new(wake: impl Fn() + Send + 'static) -> notify::Result<ShaderWatcher> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            // The receiver only goes away with the watcher.
+            let _ = sender.send(event);
+            wake();
+        })?;
+        watcher.watch(Path::new(SHADER_DIR), RecursiveMode::NonRecursive)?;
+        Ok(ShaderWatcher {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Whether a shader changed since the last call. Drains every pending event, so an
+    /// editor that writes a file in several steps triggers one reload.
+    pub fn poll_changed(&self) -> bool {
+        let mut changed = false;
+        for event in self.events.try_iter() {
+            match event {
+                Ok(event) => {
+                    changed |= (event.kind.is_modify() || event.kind.is_create())
+                        && event
+                            .paths
+                            .iter()
+                            .any(|path| path.extension().is_some_and(|ext| ext == "wgsl"));
+                }
+                Err(e) => eprintln!("Shader watcher: {}", e),
+            }
+        }
+        changed
+    }
+}
+
+/// Reads `file_name` from the shader directory.
+pub fn read_shader(file_name: &str) -> anyhow::Result<String> {
+    let path = Path::new(SHADER_DIR).join(file_name);
+    std::fs::read_to_string(&path).with_context(|| format!("could not read {}", path.display()))
+}
@@ -0,0 +1,542 @@
+use std::{collections::BTreeMap, collections::HashMap, fmt::Write, str::FromStr};
+
+use winit::event::VirtualKeyCode;
+
+/// Something a key does. The keymap only picks the action; modifiers are left to it, and
+/// Shift reverses or varies most of them (see `description`).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    PanLeft,
+    PanRight,
+    PanUp,
+    PanDown,
+    ZoomIn,
+    ZoomOut,
+    IterationsDown,
+    IterationsUp,
+    AutoIterations,
+    ResetView,
+    Undo,
+    Screenshot,
+    BracketScreenshot,
+    SaveView,
+    LoadView,
+    AnimationStart,
+    Animation,
+    Supersampling,
+    Accumulation,
+    ColoringMode,
+    StripeDensity,
+    StripeMix,
+    Relief,
+    Fullscreen,
+    Timing,
+    ScaleOverlay,
+    Grid,
+    PaletteEditor,
+    Palette,
+    ColorVision,
+    Density,
+    Offset,
+    MappingMode,
+    Exponent,
+    PaletteCycling,
+    CyclingSlower,
+    CyclingFaster,
+    AutoRange,
+    Bloom,
+    BloomThreshold,
+    BloomStrength,
+    FractalMode,
+    FractalKind,
+    Power,
+    EscapeRadius,
+    InteriorCheck,
+    Progressive,
+    Precision,
+    JuliaPreview,
+    RotateLeft,
+    RotateRight,
+    PrintKeys,
+}
+
+impl Action {
+    /// Every action with its name in the config file and what it does, in the order
+    /// `Keymap::describe` lists them.
+    const TABLE: [(Action, &'static str, &'static str); 52] = [
+        (
+            Action::PanLeft,
+            "pan_left",
+            "pan left while held; Ctrl moves the Julia c",
+        ),
+        (
+            Action::PanRight,
+            "pan_right",
+            "pan right while held; Ctrl moves the Julia c",
+        ),
+        (
+            Action::PanUp,
+            "pan_up",
+            "pan up while held; Ctrl moves the Julia c",
+        ),
+        (
+            Action::PanDown,
+            "pan_down",
+            "pan down while held; Ctrl moves the Julia c",
+        ),
+        (
+            Action::ZoomIn,
+            "zoom_in",
+            "zoom in while held; Shift is slower",
+        ),
+        (
+            Action::ZoomOut,
+            "zoom_out",
+            "zoom out while held; Shift is slower",
+        ),
+        (
+            Action::IterationsDown,
+            "iterations_down",
+            "halve the iterations",
+        ),
+        (
+            Action::IterationsUp,
+            "iterations_up",
+            "double the iterations",
+        ),
+        (
+            Action::AutoIterations,
+            "auto_iterations",
+            "toggle automatic iterations",
+        ),
+        (Action::ResetView, "reset_view", "fly home; Shift jumps"),
+        (
+            Action::Undo,
+            "undo",
+            "previous view; Shift goes forward again",
+        ),
+        (
+            Action::Screenshot,
+            "screenshot",
+            "save a screenshot; Shift renders a poster, Ctrl+Shift saves the palette",
+        ),
+        (
+            Action::BracketScreenshot,
+            "bracket_screenshot",
+            "with Shift, screenshots at several iteration counts",
+        ),
+        (Action::SaveView, "save_view", "save the view"),
+        (Action::LoadView, "load_view", "load the saved view"),
+        (
+            Action::AnimationStart,
+            "animation_start",
+            "mark where animations start",
+        ),
+        (
+            Action::Animation,
+            "animation",
+            "record an animation to the current view",
+        ),
+        (
+            Action::Supersampling,
+            "supersampling",
+            "cycle supersampling",
+        ),
+        (
+            Action::Accumulation,
+            "accumulation",
+            "toggle accumulation anti-aliasing",
+        ),
+        (
+            Action::ColoringMode,
+            "coloring_mode",
+            "cycle the coloring mode",
+        ),
+        (
+            Action::StripeDensity,
+            "stripe_density",
+            "more stripes in stripe coloring; Shift fewer",
+        ),
+        (
+            Action::StripeMix,
+            "stripe_mix",
+            "mix more stripes into the iteration count; Shift less",
+        ),
+        (
+            Action::Relief,
+            "relief",
+            "toggle relief lighting; Alt+mouse moves the light",
+        ),
+        (Action::Fullscreen, "fullscreen", "toggle fullscreen"),
+        (Action::Timing, "timing", "toggle the frame timing overlay"),
+        (
+            Action::ScaleOverlay,
+            "scale_overlay",
+            "toggle the center crosshair and scale bar",
+        ),
+        (Action::Grid, "grid", "toggle the axes and coordinate grid"),
+        (
+            Action::PaletteEditor,
+            "palette_editor",
+            "toggle the palette editor",
+        ),
+        (
+            Action::Palette,
+            "palette",
+            "next palette; Shift loads the newest palette file",
+        ),
+        (
+            Action::ColorVision,
+            "color_vision",
+            "cycle color vision simulation",
+        ),
+        (Action::Density, "density", "denser palette; Shift sparser"),
+        (Action::Offset, "offset", "shift the palette; Shift back"),
+        (
+            Action::MappingMode,
+            "mapping_mode",
+            "cycle the color mapping",
+        ),
+        (
+            Action::Exponent,
+            "exponent",
+            "raise the mapping exponent; Shift lowers",
+        ),
+        (
+            Action::PaletteCycling,
+            "palette_cycling",
+            "toggle palette cycling; Shift cycles the present mode",
+        ),
+        (
+            Action::CyclingSlower,
+            "cycling_slower",
+            "slower palette cycling",
+        ),
+        (
+            Action::CyclingFaster,
+            "cycling_faster",
+            "faster palette cycling",
+        ),
+        (
+            Action::AutoRange,
+            "auto_range",
+            "toggle fitting the palette to the view",
+        ),
+        (Action::Bloom, "bloom", "toggle bloom"),
+        (
+            Action::BloomThreshold,
+            "bloom_threshold",
+            "raise the bloom threshold; Shift lowers",
+        ),
+        (
+            Action::BloomStrength,
+            "bloom_strength",
+            "raise the bloom strength; Shift lowers",
+        ),
+        (
+            Action::FractalMode,
+            "fractal_mode",
+            "toggle the Julia set; Shift jumps to the most detailed block",
+        ),
+        (
+            Action::FractalKind,
+            "fractal_kind",
+            "next fractal kind; Shift keeps the view",
+        ),
+        (Action::Power, "power", "raise the power; Shift lowers"),
+        (
+            Action::EscapeRadius,
+            "escape_radius",
+            "double the escape radius; Shift halves",
+        ),
+        (
+            Action::InteriorCheck,
+            "interior_check",
+            "toggle the interior check",
+        ),
+        (
+            Action::Progressive,
+            "progressive",
+            "toggle progressive refinement",
+        ),
+        (
+            Action::Precision,
+            "precision",
+            "cycle the float precision; Shift compares it with the next one",
+        ),
+        (
+            Action::JuliaPreview,
+            "julia_preview",
+            "toggle the Julia preview",
+        ),
+        (
+            Action::RotateLeft,
+            "rotate_left",
+            "rotate counter-clockwise",
+        ),
+        (Action::RotateRight, "rotate_right", "rotate clockwise"),
+        (Action::PrintKeys, "print_keys", "print the key bindings"),
+    ];
+
+    /// Name in the `[keys]` section of the config file.
+    pub fn name(self) -> &'static str {
+        Self::entry(self).1
+    }
+
+    pub fn description(self) -> &'static str {
+        Self::entry(self).2
+    }
+
+    fn entry(self) -> &'static (Action, &'static str, &'static str) {
+        Self::TABLE
+            .iter()
+            .find(|(action, _, _)| *action == self)
+            .expect("every action is in the table")
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::TABLE
+            .iter()
+            .find(|(_, name, _)| *name == s)
+            .map(|(action, _, _)| *action)
+            .ok_or_else(|| format!("unknown action '{}'", s))
+    }
+}
+
+/// Keys that can be named in the config file, by their `VirtualKeyCode` names.
+const NAMED_KEYS: &[VirtualKeyCode] = {
+    use VirtualKeyCode::*;
+    &[
+        Key1,
+        Key2,
+        Key3,
+        Key4,
+        Key5,
+        Key6,
+        Key7,
+        Key8,
+        Key9,
+        Key0,
+        A,
+        B,
+        C,
+        D,
+        E,
+        F,
+        G,
+        H,
+        I,
+        J,
+        K,
+        L,
+        M,
+        N,
+        O,
+        P,
+        Q,
+        R,
+        S,
+        T,
+        U,
+        V,
+        W,
+        X,
+        Y,
+        Z,
+        Escape,
+        F1,
+        F2,
+        F3,
+        F4,
+        F5,
+        F6,
+        F7,
+        F8,
+        F9,
+        F10,
+        F11,
+        F12,
+        Insert,
+        Home,
+        Delete,
+        End,
+        PageDown,
+        PageUp,
+        Left,
+        Up,
+        Right,
+        Down,
+        Back,
+        Return,
+        Space,
+        Tab,
+        Numpad0,
+        Numpad1,
+        Numpad2,
+        Numpad3,
+        Numpad4,
+        Numpad5,
+        Numpad6,
+        Numpad7,
+        Numpad8,
+        Numpad9,
+        NumpadAdd,
+        NumpadSubtract,
+        NumpadMultiply,
+        NumpadDivide,
+        NumpadDecimal,
+        NumpadEnter,
+        Apostrophe,
+        Backslash,
+        Comma,
+        Equals,
+        Grave,
+        LBracket,
+        Minus,
+        Period,
+        Plus,
+        RBracket,
+        Semicolon,
+        Slash,
+        Asterisk,
+    ]
+};
+
+/// Parses a key name like "W", "F5", "NumpadAdd" or "Key1", ignoring case.
+pub fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+    NAMED_KEYS
+        .iter()
+        .copied()
+        .find(|key| format!("{:?}", key).eq_ignore_ascii_case(name.trim()))
+}
+
+/// Which action each key performs. Keys not in the map do nothing beyond the fixed
+/// shortcuts in `App::handle_event` (Escape, the bookmark digits, Ctrl+Z/Y/C and Alt+Enter).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keymap {
+    bindings: HashMap<VirtualKeyCode, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use VirtualKeyCode as Key;
+        let bindings = [
+            (Key::Left, Action::PanLeft),
+            (Key::Right, Action::PanRight),
+            (Key::Up, Action::PanUp),
+            (Key::Down, Action::PanDown),
+            // Both plus and minus keys, so keyboards without a numpad can zoom.
+            (Key::NumpadAdd, Action::ZoomIn),
+            (Key::Equals, Action::ZoomIn),
+            (Key::Plus, Action::ZoomIn),
+            (Key::NumpadSubtract, Action::ZoomOut),
+            (Key::Minus, Action::ZoomOut),
+            (Key::LBracket, Action::IterationsDown),
+            (Key::RBracket, Action::IterationsUp),
+            (Key::I, Action::AutoIterations),
+            (Key::Home, Action::ResetView),
+            (Key::R, Action::ResetView),
+            (Key::Back, Action::Undo),
+            (Key::S, Action::Screenshot),
+            (Key::F12, Action::BracketScreenshot),
+            (Key::F5, Action::SaveView),
+            (Key::F9, Action::LoadView),
+            (Key::F6, Action::AnimationStart),
+            (Key::F7, Action::Animation),
+            (Key::F4, Action::Supersampling),
+            (Key::F8, Action::Accumulation),
+            (Key::F10, Action::ColoringMode),
+            (Key::N, Action::StripeDensity),
+            (Key::H, Action::StripeMix),
+            (Key::L, Action::Relief),
+            (Key::F11, Action::Fullscreen),
+            (Key::F3, Action::Timing),
+            (Key::X, Action::ScaleOverlay),
+            (Key::G, Action::Grid),
+            (Key::F2, Action::PaletteEditor),
+            (Key::P, Action::Palette),
+            (Key::C, Action::ColorVision),
+            (Key::D, Action::Density),
+            (Key::O, Action::Offset),
+            (Key::M, Action::MappingMode),
+            (Key::K, Action::Exponent),
+            (Key::V, Action::PaletteCycling),
+            (Key::Comma, Action::CyclingSlower),
+            (Key::Period, Action::CyclingFaster),
+            (Key::A, Action::AutoRange),
+            (Key::B, Action::Bloom),
+            (Key::T, Action::BloomThreshold),
+            (Key::Apostrophe, Action::BloomStrength),
+            (Key::J, Action::FractalMode),
+            (Key::F, Action::FractalKind),
+            (Key::Semicolon, Action::Power),
+            (Key::Backslash, Action::EscapeRadius),
+            (Key::Z, Action::InteriorCheck),
+            (Key::W, Action::Progressive),
+            (Key::Y, Action::Precision),
+            (Key::U, Action::JuliaPreview),
+            (Key::Q, Action::RotateLeft),
+            (Key::E, Action::RotateRight),
+            (Key::F1, Action::PrintKeys),
+        ];
+        Keymap {
+            bindings: bindings.into_iter().collect(),
+        }
+    }
+}
+
+impl Keymap {
+    /// The default bindings with `overrides` from the config file's `[keys]` section applied:
+    /// key names to action names, or "none" to unbind a key. Entries that don't parse are
+    /// skipped and described in the returned warnings.
+    pub fn with_overrides(overrides: &BTreeMap<String, String>) -> (Keymap, Vec<String>) {
+        let mut keymap = Keymap::default();
+        let mut warnings = Vec::new();
+        for (key_name, action_name) in overrides {
+            let Some(key) = parse_key(key_name) else {
+                warnings.push(format!("unknown key '{}' in [keys]; ignored", key_name));
+                continue;
+            };
+            if action_name == "none" {
+                keymap.bindings.remove(&key);
+                continue;
+            }
+            match action_name.parse() {
+                Ok(action) => {
+                    keymap.bindings.insert(key, action);
+                }
+                Err(e) => warnings.push(format!("{} for key '{}' in [keys]; ignored", e, key_name)),
+            }
+        }
+        (keymap, warnings)
+    }
+
+    pub fn action(&self, key: VirtualKeyCode) -> Option<Action> {
+        self.bindings.get(&key).copied()
+    }
+
+    /// One line per action with the keys bound to it, for F1 and --print-keys.
+    pub fn describe(&self) -> String {
+        let mut text = String::new();
+        for (action, name, description) in Action::TABLE {
+            let mut keys: Vec<String> = self
+                .bindings
+                .iter()
+                .filter(|(_, bound)| **bound == action)
+                .map(|(key, _)| format!("{:?}", key))
+                .collect();
+            keys.sort();
+            let keys = if keys.is_empty() {
+                "-".to_string()
+            } else {
+                keys.join(", ")
+            };
+            let _ = writeln!(text, "{:<28} {:<20} {}", keys, name, description);
+        }
+        text
+    }
+}
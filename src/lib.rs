@@ -0,0 +1,57 @@
+//! GPU Mandelbrot and Julia set renderer built on wgpu compute shaders.
+//!
+//! The interactive explorer in `main.rs` is built on this library, but nothing here needs a
+//! window: create a device with [`GPUInterface::headless`], describe a view with
+//! [`SampleLocation`] and compute it with [`Computer`].
+//!
+//! ```
+//! use mandelbrot_compute::{Computer, GPUInterface, SampleLocation, UVec2};
+//!
+//! let Ok(gpu) = GPUInterface::headless() else {
+//!     return Ok(()); // No GPU on this machine.
+//! };
+//! let size = UVec2::new(64, 64);
+//! let computer = Computer::new(size, &gpu);
+//! let params = SampleLocation::default().to_mandlebrot_params(100);
+//! let texels = computer.compute_to_vec(&gpu, &params, size)?;
+//! assert_eq!(texels.len(), 64 * 64 * 16);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub mod accumulate;
+pub mod animation;
+pub mod bracket;
+pub mod colorize;
+pub mod compare;
+pub mod computer;
+pub mod config;
+pub mod cpu_reference;
+pub mod deep;
+pub mod detail;
+pub mod formula;
+pub mod gpu_interface;
+pub mod headless;
+pub mod histogram;
+pub mod history;
+#[cfg(feature = "hot-reload")]
+pub mod hot_reload;
+pub mod keymap;
+pub mod math;
+pub mod overlay;
+pub mod palette;
+pub mod poster;
+pub mod postprocess;
+pub mod probe;
+pub mod range;
+pub mod readback;
+pub mod reference_orbit;
+pub mod renderer;
+pub mod screenshot;
+pub mod state;
+pub mod stats;
+pub mod text;
+pub mod touch;
+
+pub use computer::{ComputeError, Computer, MandelbrotParams, SampleLocation};
+pub use gpu_interface::{GPUInterface, GpuInitError};
+pub use math::{DVec2, FVec2, IVec2, Mat2, UVec2, Viewport};
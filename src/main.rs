@@ -1,82 +1,380 @@
-use math::UVec2;
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+use mandelbrot_compute::{
+    bracket,
+    computer::{FractalKind, WorkgroupSize, SUPERSAMPLING_FACTORS},
+    config::Config,
+    formula::CustomFormula,
+    gpu_interface::{self, AdapterChoice},
+    headless,
+    keymap::Keymap,
+    palette,
+    palette::MappingMode,
+    poster,
+    state::StartupOptions,
+    DVec2, UVec2,
+};
 use winit::{
     dpi::{PhysicalPosition, PhysicalSize},
     event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    window::{Fullscreen, WindowBuilder},
 };
 
 use crate::app::App;
 
 mod app;
-mod computer;
-mod gpu_interface;
-mod math;
-mod renderer;
+mod timing;
+
+/// Window and image size when --size is not given.
+const DEFAULT_SIZE: u32 = 1024;
+
+/// GPU Mandelbrot explorer.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Window size, or the image size with --headless
+    #[arg(long, value_name = "WxH", value_parser = poster::parse_size)]
+    size: Option<UVec2>,
+    /// Fixed size to compute the fractal at, scaled to fit the window whatever its size
+    #[arg(long, value_name = "WxH", value_parser = poster::parse_size, conflicts_with = "headless")]
+    compute_size: Option<UVec2>,
+    /// Point to center the view on; in Julia mode, the constant c
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true)]
+    center: Option<DVec2>,
+    /// Location printed with Ctrl+C, e.g. "re=-0.74364 im=0.13183 zoom=3.5e-7 iters=4000";
+    /// missing fields keep the values of the other options
+    #[arg(long, value_name = "TEXT", allow_hyphen_values = true)]
+    location: Option<String>,
+    /// Zoom level, 1 shows the whole set and smaller values zoom in
+    #[arg(long, allow_hyphen_values = true, value_parser = positive_f32)]
+    zoom: Option<f32>,
+    /// Fixed iteration count, turning off automatic iterations
+    #[arg(long, visible_alias = "iters", value_parser = clap::value_parser!(i32).range(1..))]
+    iterations: Option<i32>,
+    /// Fractal to start with
+    #[arg(long, value_enum)]
+    fractal: Option<FractalArg>,
+    /// Iterate a formula of z and c instead, e.g. "z = z*z*z + c*sin(z)"; --fractal julia
+    /// draws its Julia sets
+    #[arg(long, allow_hyphen_values = true)]
+    formula: Option<CustomFormula>,
+    /// Start in borderless fullscreen on the current monitor
+    #[arg(long)]
+    fullscreen: bool,
+    /// Samples per pixel along each side for anti-aliasing: 1, 2 or 4 (F4 cycles)
+    #[arg(long, value_name = "N", value_parser = parse_supersampling)]
+    supersampling: Option<u32>,
+    /// Compute workgroup size, e.g. 16x8, or "auto" to time a few at startup
+    #[arg(long, value_name = "WxH|auto")]
+    workgroup_size: Option<WorkgroupSize>,
+    /// Render one image to -o without opening a window, then exit
+    #[arg(long)]
+    headless: bool,
+    /// PNG written by --headless
+    #[arg(short, value_name = "OUTPUT.png", required_if_eq("headless", "true"))]
+    output: Option<PathBuf>,
+    /// With --headless, average jittered frames reproducibly and save the seed as OUTPUT.json
+    #[arg(long, requires = "headless")]
+    deterministic: bool,
+    /// With --headless, finish the tiles an interrupted render of OUTPUT left in OUTPUT.tiles
+    #[arg(long, requires = "headless", conflicts_with = "deterministic")]
+    resume: bool,
+    /// Seed of the accumulation jitter, e.g. to replay a --deterministic render
+    #[arg(long)]
+    seed: Option<u32>,
+    /// Curve applied to smooth iteration values: linear, log, sqrt, power or histogram
+    #[arg(long)]
+    color_mapping: Option<MappingMode>,
+    /// Extra palette JSON file to load
+    #[arg(long, value_name = "FILE")]
+    palette: Option<PathBuf>,
+    /// Build a palette from the dominant colors of an image
+    #[arg(long, value_name = "IMAGE")]
+    palette_from_image: Option<PathBuf>,
+    /// Write the palette built by --palette-from-image to a JSON file
+    #[arg(long, value_name = "FILE", requires = "palette_from_image")]
+    save_palette: Option<PathBuf>,
+    /// Render the view at each of these iteration counts, e.g. 100,1000,10000
+    #[arg(long, value_name = "LIST", value_parser = bracket::parse_iterations)]
+    bracket_iterations: Option<::std::vec::Vec<i32>>,
+    /// Size of posters rendered with Shift+S
+    #[arg(long, value_name = "WxH", value_parser = poster::parse_size)]
+    poster_size: Option<UVec2>,
+    /// Number of frames recorded with F7
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    animation_frames: Option<u32>,
+    /// Frame rate the F7 recording is timed for
+    #[arg(long, value_parser = positive_f32)]
+    animation_fps: Option<f32>,
+    /// GPU adapter to use, by index in the --gpu-info list or part of its name
+    #[arg(long, value_name = "INDEX|NAME")]
+    adapter: Option<AdapterChoice>,
+    /// List the GPU adapters with their limits, then exit
+    #[arg(long)]
+    gpu_info: bool,
+    /// Print the key bindings, including those from the config file, then exit
+    #[arg(long)]
+    print_keys: bool,
+}
+
+/// Values of --fractal. Julia takes its constant from --center.
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum FractalArg {
+    Mandelbrot,
+    Julia,
+    Tricorn,
+    Newton,
+}
+
+impl Args {
+    fn startup_options(&self) -> StartupOptions {
+        let (mut fractal_kind, julia) = match self.fractal {
+            None => (None, false),
+            Some(FractalArg::Mandelbrot) => (Some(FractalKind::Mandelbrot), false),
+            Some(FractalArg::Julia) => (Some(FractalKind::Mandelbrot), true),
+            Some(FractalArg::Tricorn) => (Some(FractalKind::Tricorn), false),
+            Some(FractalArg::Newton) => (Some(FractalKind::Newton), false),
+        };
+        if self.formula.is_some() {
+            fractal_kind = Some(FractalKind::Custom);
+        }
+        StartupOptions {
+            fractal_kind,
+            julia,
+            center: self.center,
+            zoom: self.zoom,
+            iterations: self.iterations,
+            formula: self.formula.clone(),
+            location: self.location.clone(),
+        }
+    }
+}
+
+fn positive_f32(text: &str) -> Result<f32, String> {
+    text.parse::<f32>()
+        .ok()
+        .filter(|v| v.is_finite() && *v > 0.0)
+        .ok_or_else(|| format!("expected a positive number but got '{}'", text))
+}
+
+fn parse_supersampling(text: &str) -> Result<u32, String> {
+    text.parse::<u32>()
+        .ok()
+        .filter(|factor| SUPERSAMPLING_FACTORS.contains(factor))
+        .ok_or_else(|| {
+            format!(
+                "expected one of {:?} but got '{}'",
+                SUPERSAMPLING_FACTORS, text
+            )
+        })
+}
+
+/// Seed for a --deterministic render without --seed. It only has to differ between runs;
+/// the render records it.
+fn random_seed() -> u32 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos())
+}
 
 fn main() {
-    let size = UVec2::new(1024, 1024);
+    let args = Args::parse();
+    let startup = args.startup_options();
+    let size = args
+        .size
+        .unwrap_or_else(|| UVec2::new(DEFAULT_SIZE, DEFAULT_SIZE));
+
+    if args.gpu_info {
+        print!("{}", gpu_interface::adapter_report());
+        return;
+    }
+
+    if args.print_keys {
+        let (keymap, warnings) = Keymap::with_overrides(&Config::load_or_default().keys);
+        for warning in warnings {
+            eprintln!("Warning: {}", warning);
+        }
+        print!("{}", keymap.describe());
+        return;
+    }
+
+    // Render straight to a file and exit, without opening a window.
+    if args.headless {
+        let options = headless::HeadlessOptions {
+            startup,
+            size,
+            // Required by clap when --headless is given.
+            output: args.output.clone().unwrap(),
+            deterministic: args
+                .deterministic
+                .then(|| args.seed.unwrap_or_else(random_seed)),
+            resume: args.resume,
+        };
+        if let Err(e) = headless::render(&options) {
+            eprintln!("{:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new()
+    let mut builder = WindowBuilder::new()
         .with_inner_size(PhysicalSize::new(size.x, size.y))
-        .with_title("GPU_Automata")
-        .with_position(PhysicalPosition::new(0, 0))
-        .build(&event_loop)
-        .unwrap();
-    let mut app = App::new(size, &window);
+        .with_title(app::WINDOW_TITLE)
+        .with_position(PhysicalPosition::new(0, 0));
+    if args.fullscreen {
+        builder = builder.with_fullscreen(Some(Fullscreen::Borderless(None)));
+    }
+    let window = builder.build(&event_loop).unwrap();
+    let inner_size = window.inner_size();
+    let mut config = Config::load_or_default();
+    if let Some(workgroup_size) = args.workgroup_size {
+        config.compute.workgroup_size = workgroup_size;
+    }
+    if let Some(adapter) = args.adapter.clone() {
+        config.rendering.adapter = Some(adapter);
+    }
+    let mut app = App::new(
+        UVec2::new(inner_size.width, inner_size.height),
+        &window,
+        &startup,
+        &config,
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("Could not start: {}", e);
+        std::process::exit(1);
+    });
+
+    #[cfg(feature = "hot-reload")]
+    {
+        let proxy = event_loop.create_proxy();
+        app.watch_shaders(move || {
+            // Fails only once the event loop has exited.
+            let _ = proxy.send_event(());
+        });
+    }
+
+    if args.compute_size.is_some() {
+        app.set_compute_size(args.compute_size);
+    }
+
+    if let Some(factor) = args.supersampling {
+        app.set_supersampling(factor);
+    }
+
+    if let Some(mode) = args.color_mapping {
+        app.color_mapping.mode = mode;
+    }
+
+    if let Some(palette_path) = &args.palette {
+        match palette::Palette::load_file(palette_path) {
+            Ok(palette) => app.add_palette(palette),
+            Err(e) => eprintln!("Could not load palette {}: {}", palette_path.display(), e),
+        }
+    }
+
+    if let Some(image_path) = &args.palette_from_image {
+        match palette::palette_from_image(image_path, 8) {
+            Ok(palette) => {
+                if let Some(json_path) = &args.save_palette {
+                    if let Err(e) = palette.save_json(json_path) {
+                        eprintln!("Could not save palette to {}: {}", json_path.display(), e);
+                    }
+                }
+                app.add_palette(palette);
+            }
+            Err(e) => eprintln!(
+                "Could not build a palette from {}: {}",
+                image_path.display(),
+                e
+            ),
+        }
+    }
+
+    if let Some(frames) = args.animation_frames {
+        app.animation_frames = frames;
+    }
+
+    if let Some(seed) = args.seed {
+        app.accumulation_seed = seed;
+    }
+
+    if let Some(fps) = args.animation_fps {
+        app.animation_fps = fps;
+    }
+
+    if let Some(size) = args.poster_size {
+        app.poster_size = size;
+    }
+
+    if let Some(iterations) = args.bracket_iterations {
+        app.bracket_iterations = iterations;
+        app.start_bracket();
+    }
 
     event_loop.run(move |event, _, control_flow| {
-        //sim.renderer.handle_events(&event);
         match event {
+            // Handle app events first; anything they consume is skipped here
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == window.id() => {
-                //.Handle gui events
-
-                if !app.handle_event(event) {
-                    match event {
-                        WindowEvent::Resized(physical_size) => {
-                            app.renderer.resize(*physical_size, &mut app.gpu);
-                        }
-                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            // new_inner_size is &&mut so we have to dereference it twice
-                            app.renderer.resize(**new_inner_size, &mut app.gpu);
-                        }
-                        WindowEvent::CloseRequested
-                        | WindowEvent::KeyboardInput {
-                            input:
-                                KeyboardInput {
-                                    state: ElementState::Pressed,
-                                    virtual_keycode: Some(VirtualKeyCode::Escape),
-                                    ..
-                                },
-                            ..
-                        } => *control_flow = ControlFlow::Exit,
-                        _ => {}
+            } if window_id == window.id() && !app.handle_event(event) => {
+                match event {
+                    WindowEvent::Resized(physical_size) => {
+                        app.resize(*physical_size);
+                    }
+                    WindowEvent::Focused(_) => {
+                        app.sync_size(window.inner_size());
+                    }
+                    WindowEvent::ScaleFactorChanged {
+                        scale_factor,
+                        new_inner_size,
+                    } => {
+                        app.renderer.text.scale_factor = *scale_factor as f32;
+                        // new_inner_size is &&mut so we have to dereference it twice
+                        app.resize(**new_inner_size);
                     }
+                    WindowEvent::CloseRequested
+                    | WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } => *control_flow = ControlFlow::Exit,
+                    _ => {}
                 }
             }
             Event::RedrawRequested(window_id) if window_id == window.id() => {
-                let mandelbrot = app
-                    .computer
-                    .run(&app.gpu, &app.sample_location.to_mandlebrot_params(180));
-                match app.renderer.render(&app.gpu, mandelbrot) {
+                match app.render() {
                     Ok(_) => {}
-                    // Reconfigure the surface if lost
-                    Err(wgpu::SurfaceError::Lost) => {
-                        app.renderer.resize(app.gpu.size, &mut app.gpu)
+                    // Reconfigure the surface if lost or no longer matching the window. The
+                    // window's own size is used, as it may have been minimized meanwhile.
+                    Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                        app.resize(window.inner_size())
                     }
                     // The system is out of memory, we should probably quit
                     Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                    // All other errors (Outdated, Timeout) should be resolved by the next frame
+                    // Timeouts should be resolved by the next frame
                     Err(e) => eprintln!("{:?}", e),
                 }
             }
             Event::MainEventsCleared => {
-                // RedrawRequested will only trigger once, unless we manually
-                // request it.
-                window.request_redraw();
+                app.update(&window);
+                // Keep drawing while the app has something to show, otherwise sleep until
+                // the next event so an untouched window leaves the GPU idle.
+                if *control_flow != ControlFlow::Exit {
+                    if app.needs_redraw() {
+                        window.request_redraw();
+                        *control_flow = ControlFlow::Poll;
+                    } else {
+                        *control_flow = ControlFlow::Wait;
+                    }
+                }
             }
             _ => {}
         }
@@ -1,12 +1,215 @@
+use std::{
+    fmt,
+    ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign},
+    str::FromStr,
+};
+
 use bytemuck::{Pod, Zeroable};
+use dashu_float::{round::mode::HalfAway, DBig, FBig};
+use serde::{Deserialize, Serialize};
+use winit::dpi::PhysicalPosition;
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[derive(Copy, Clone, Debug, PartialEq, Default, Pod, Zeroable)]
 pub struct FVec2 {
     pub x: f32,
     pub y: f32,
 }
 
+/// Double precision point, for view positions that must survive deep zooms.
+#[derive(Copy, Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct DVec2 {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Arithmetic shared by the float vectors: componentwise `+`/`-`, scaling by `$scalar`,
+/// `length` and `lerp`.
+macro_rules! float_vec2 {
+    ($vec:ident, $scalar:ty) => {
+        impl $vec {
+            pub fn new(x: $scalar, y: $scalar) -> $vec {
+                $vec { x, y }
+            }
+
+            pub fn dot(self, other: $vec) -> $scalar {
+                self.x * other.x + self.y * other.y
+            }
+
+            pub fn length(self) -> $scalar {
+                self.dot(self).sqrt()
+            }
+
+            /// The point a fraction `t` of the way from `self` to `end`.
+            pub fn lerp(self, end: $vec, t: $scalar) -> $vec {
+                self + (end - self) * t
+            }
+        }
+
+        impl Add for $vec {
+            type Output = $vec;
+
+            fn add(self, rhs: $vec) -> $vec {
+                $vec::new(self.x + rhs.x, self.y + rhs.y)
+            }
+        }
+
+        impl Sub for $vec {
+            type Output = $vec;
+
+            fn sub(self, rhs: $vec) -> $vec {
+                $vec::new(self.x - rhs.x, self.y - rhs.y)
+            }
+        }
+
+        impl AddAssign for $vec {
+            fn add_assign(&mut self, rhs: $vec) {
+                *self = *self + rhs;
+            }
+        }
+
+        impl SubAssign for $vec {
+            fn sub_assign(&mut self, rhs: $vec) {
+                *self = *self - rhs;
+            }
+        }
+
+        impl Neg for $vec {
+            type Output = $vec;
+
+            fn neg(self) -> $vec {
+                $vec::new(-self.x, -self.y)
+            }
+        }
+
+        impl Mul<$scalar> for $vec {
+            type Output = $vec;
+
+            fn mul(self, rhs: $scalar) -> $vec {
+                $vec::new(self.x * rhs, self.y * rhs)
+            }
+        }
+
+        impl Div<$scalar> for $vec {
+            type Output = $vec;
+
+            fn div(self, rhs: $scalar) -> $vec {
+                $vec::new(self.x / rhs, self.y / rhs)
+            }
+        }
+
+        /// Formats as "X,Y", which `from_str` parses back.
+        impl fmt::Display for $vec {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{},{}", self.x, self.y)
+            }
+        }
+    };
+}
+
+float_vec2!(FVec2, f32);
+float_vec2!(DVec2, f64);
+
+impl DVec2 {
+    /// Splits into the nearest f32 values and the f32 rounded remainders, so that
+    /// `hi + lo` carries roughly twice the precision of a single f32.
+    pub fn split(self) -> (FVec2, FVec2) {
+        let hi = FVec2::from(self);
+        let lo = FVec2::from(self - DVec2::from(hi));
+        (hi, lo)
+    }
+}
+
+/// Parses "RE,IM", e.g. "-0.743,0.131" or "-7.43e-1, 1.31e-1".
+impl FromStr for DVec2 {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (x, y) = s
+            .split_once(',')
+            .ok_or_else(|| anyhow::anyhow!("expected RE,IM but got '{}'", s))?;
+        let parse = |part: &str| {
+            part.trim()
+                .parse::<f64>()
+                .map_err(|e| anyhow::anyhow!("invalid coordinate '{}': {}", part.trim(), e))
+        };
+        Ok(DVec2::new(parse(x)?, parse(y)?))
+    }
+}
+
+/// Parses "RE,IM" like `DVec2`, rounding to f32.
+impl FromStr for FVec2 {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<DVec2>().map(FVec2::from)
+    }
+}
+
+impl From<FVec2> for DVec2 {
+    fn from(v: FVec2) -> Self {
+        DVec2 {
+            x: v.x as f64,
+            y: v.y as f64,
+        }
+    }
+}
+
+impl From<DVec2> for FVec2 {
+    fn from(v: DVec2) -> Self {
+        FVec2 {
+            x: v.x as f32,
+            y: v.y as f32,
+        }
+    }
+}
+
+/// `hi + lo` in plain decimal with `digits` after the point, never in scientific notation.
+/// The sum is taken exactly, so a double-double center keeps the digits past f64 precision.
+pub fn format_fixed((hi, lo): (f64, f64), digits: usize) -> String {
+    let bits = 64 + 4 * digits;
+    let exact = |x: f64| -> FBig {
+        FBig::try_from(x)
+            .expect("coordinates are finite")
+            .with_precision(bits)
+            .value()
+    };
+    let ten = exact(10.0);
+    let mut scaled = exact(hi) + exact(lo);
+    for _ in 0..digits {
+        scaled *= &ten;
+    }
+    let scaled = scaled.round().to_int().value().to_string();
+    let (sign, magnitude) = match scaled.strip_prefix('-') {
+        Some(magnitude) => ("-", magnitude),
+        None => ("", scaled.as_str()),
+    };
+    let magnitude = format!("{:0>width$}", magnitude, width = digits + 1);
+    let (whole, fraction) = magnitude.split_at(magnitude.len() - digits);
+    if digits == 0 {
+        format!("{}{}", sign, whole)
+    } else {
+        format!("{}{}.{}", sign, whole, fraction)
+    }
+}
+
+/// Parses a decimal number into an unevaluated sum of two f64, the inverse of `format_fixed`.
+/// `hi` is the nearest f64 and `lo` the nearest f64 to what remains, so digits past f64
+/// precision survive.
+pub fn parse_fixed(text: &str) -> anyhow::Result<(f64, f64)> {
+    let decimal = DBig::from_str(text.trim())
+        .map_err(|e| anyhow::anyhow!("'{}' is not a number: {}", text.trim(), e))?;
+    let bits = 64 + 4 * text.len();
+    // Rounds to nearest, so `hi` is the closest f64 rather than the one towards zero.
+    let exact: FBig<HalfAway> = decimal.with_base_and_precision::<2>(bits).value();
+    let hi = exact.to_f64().value();
+    anyhow::ensure!(hi.is_finite(), "'{}' is out of range", text.trim());
+    let lo = (exact - FBig::<HalfAway>::try_from(hi).expect("hi is finite"))
+        .to_f64()
+        .value();
+    Ok((hi, lo))
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Pod, Zeroable, Default)]
 pub struct FVec3 {
@@ -32,7 +235,253 @@ impl From<FVec2> for FVec3 {
     }
 }
 
-#[derive(Clone, Copy)]
+/// Column-major 2x2 matrix used for the view's linear transform (rotation, scale, skew).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat2 {
+    pub cols: [[f32; 2]; 2],
+}
+
+impl Mat2 {
+    pub fn from_rotation(angle: f32) -> Mat2 {
+        let (sin, cos) = angle.sin_cos();
+        Mat2 {
+            cols: [[cos, sin], [-sin, cos]],
+        }
+    }
+
+    pub fn from_scale(x: f32, y: f32) -> Mat2 {
+        Mat2 {
+            cols: [[x, 0.0], [0.0, y]],
+        }
+    }
+
+    pub fn determinant(&self) -> f32 {
+        self.cols[0][0] * self.cols[1][1] - self.cols[1][0] * self.cols[0][1]
+    }
+
+    /// Returns None if the matrix is singular.
+    pub fn inverse(&self) -> Option<Mat2> {
+        let det = self.determinant();
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let [[a, c], [b, d]] = self.cols;
+        Some(Mat2 {
+            cols: [[d / det, -c / det], [-b / det, a / det]],
+        })
+    }
+
+    /// Packs the columns as (c0.x, c0.y, c1.x, c1.y) for upload as a WGSL vec4.
+    pub fn as_vec4(&self) -> [f32; 4] {
+        [
+            self.cols[0][0],
+            self.cols[0][1],
+            self.cols[1][0],
+            self.cols[1][1],
+        ]
+    }
+}
+
+impl Mul<Mat2> for Mat2 {
+    type Output = Mat2;
+
+    fn mul(self, rhs: Mat2) -> Mat2 {
+        Mat2 {
+            cols: [
+                (self * FVec2::from(rhs.cols[0])).into(),
+                (self * FVec2::from(rhs.cols[1])).into(),
+            ],
+        }
+    }
+}
+
+impl Mul<FVec2> for Mat2 {
+    type Output = FVec2;
+
+    fn mul(self, v: FVec2) -> FVec2 {
+        FVec2 {
+            x: self.cols[0][0] * v.x + self.cols[1][0] * v.y,
+            y: self.cols[0][1] * v.x + self.cols[1][1] * v.y,
+        }
+    }
+}
+
+/// Mapping between the pixels of an output and the complex plane. `center` sits in the middle
+/// of the `size` pixel output, and normalized screen coordinates ([-1, 1] on both axes, y down)
+/// are scaled by `scale` and then rotated by `rotation` into offsets from it. The y axis is
+/// flipped on the way, so the imaginary axis points up on screen as in the usual plots.
+/// `SampleLocation::viewport` builds the one that UI math and the shader params both go through.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    pub center: DVec2,
+    /// Half extent of the view along each screen axis, in plane units.
+    pub scale: DVec2,
+    pub rotation: f64,
+    pub size: UVec2,
+}
+
+impl Viewport {
+    /// The linear part of the mapping, from normalized screen coordinates to offsets from
+    /// `center`, as the f32 matrix the shaders apply.
+    pub fn transform(&self) -> Mat2 {
+        Mat2::from_rotation(self.rotation as f32)
+            * Mat2::from_scale(self.scale.x as f32, -self.scale.y as f32)
+    }
+
+    /// `transform` applied in f64, so offsets don't pick up f32 rounding.
+    pub fn offset(&self, uv: DVec2) -> DVec2 {
+        let x = uv.x * self.scale.x;
+        let y = -uv.y * self.scale.y;
+        let (sin, cos) = self.rotation.sin_cos();
+        DVec2 {
+            x: cos * x - sin * y,
+            y: sin * x + cos * y,
+        }
+    }
+
+    /// `position` in normalized screen coordinates. Positions off the output map outside
+    /// [-1, 1].
+    pub fn normalize(&self, position: PhysicalPosition<f64>) -> DVec2 {
+        DVec2 {
+            x: position.x / self.size.x as f64 * 2.0 - 1.0,
+            y: position.y / self.size.y as f64 * 2.0 - 1.0,
+        }
+    }
+
+    /// Offset from `center` of the point under `position`.
+    pub fn screen_offset(&self, position: PhysicalPosition<f64>) -> DVec2 {
+        self.offset(self.normalize(position))
+    }
+
+    /// The point of the complex plane under `position`, a pixel position in the output.
+    pub fn screen_to_complex(&self, position: PhysicalPosition<f64>) -> DVec2 {
+        self.center + self.screen_offset(position)
+    }
+
+    /// Inverse of `screen_to_complex`: the pixel position `point` is drawn at, which may lie
+    /// off the output.
+    pub fn complex_to_screen(&self, point: DVec2) -> FVec2 {
+        let (sin, cos) = self.rotation.sin_cos();
+        let DVec2 { x, y } = point - self.center;
+        let u = (cos * x + sin * y) / self.scale.x;
+        let v = (sin * x - cos * y) / self.scale.y;
+        FVec2 {
+            x: ((u + 1.0) * 0.5 * self.size.x as f64) as f32,
+            y: ((v + 1.0) * 0.5 * self.size.y as f64) as f32,
+        }
+    }
+}
+
+/// Pixel rectangle (min, max) of the largest `content` shaped area centered in `target`, with
+/// equal bars on the sides the aspect ratios leave over.
+pub fn letterbox(content: UVec2, target: UVec2) -> (FVec2, FVec2) {
+    let target = FVec2::from(target);
+    let scale = (target.x / content.x.max(1) as f32).min(target.y / content.y.max(1) as f32);
+    let half = FVec2::from(content) * (scale * 0.5);
+    let center = target * 0.5;
+    (center - half, center + half)
+}
+
+/// Maps a pixel `position` of `target` to `content` pixels, undoing `letterbox`. Positions on
+/// the bars land outside the content.
+pub fn target_to_content(position: FVec2, content: UVec2, target: UVec2) -> FVec2 {
+    let (min, max) = letterbox(content, target);
+    (position - min) / ((max.x - min.x) / content.x.max(1) as f32)
+}
+
+/// Maps a pixel `position` of `content` to where `letterbox` shows it in `target`.
+pub fn content_to_target(position: FVec2, content: UVec2, target: UVec2) -> FVec2 {
+    let (min, max) = letterbox(content, target);
+    min + position * ((max.x - min.x) / content.x.max(1) as f32)
+}
+
+/// Largest length of the form 1, 2 or 5 times a power of ten that is at most `max`, so scale
+/// bars and grids land on round numbers. `max` must be positive and finite.
+pub fn nice_length(max: f64) -> f64 {
+    // log10 may be off by one at exact powers of ten.
+    let mut exponent = max.log10().floor() as i32;
+    if decimal(1.0, exponent + 1) <= max {
+        exponent += 1;
+    } else if decimal(1.0, exponent) > max {
+        exponent -= 1;
+    }
+    [5.0, 2.0, 1.0]
+        .into_iter()
+        .map(|mantissa| decimal(mantissa, exponent))
+        .find(|&length| length <= max)
+        .unwrap_or_else(|| decimal(1.0, exponent))
+}
+
+/// Grid spacing of 1, 2 or 5 times a power of ten that splits `span` into 6 to 15 intervals.
+/// Consecutive nice lengths differ by at most 2.5 times, so the largest one up to a sixth of
+/// the span always fits.
+pub fn grid_spacing(span: f64) -> f64 {
+    nice_length(span / 6.0)
+}
+
+/// `mantissa` times ten to the `exponent`, rounded once, so 5e-5 comes out as the same double
+/// as the literal.
+fn decimal(mantissa: f64, exponent: i32) -> f64 {
+    if exponent >= 0 {
+        mantissa * 10f64.powi(exponent)
+    } else {
+        mantissa / 10f64.powi(-exponent)
+    }
+}
+
+/// A length from `nice_length` as a short label: plain digits near 1, e.g. "0.05" or "200",
+/// and scientific notation otherwise, e.g. "1e-5".
+pub fn format_length(length: f64) -> String {
+    if (1.0e-3..1.0e4).contains(&length) {
+        format!("{}", length)
+    } else {
+        format!("{:e}", length)
+    }
+}
+
+impl From<UVec2> for FVec2 {
+    fn from(v: UVec2) -> Self {
+        FVec2::new(v.x as f32, v.y as f32)
+    }
+}
+
+impl From<IVec2> for FVec2 {
+    fn from(v: IVec2) -> Self {
+        FVec2::new(v.x as f32, v.y as f32)
+    }
+}
+
+impl From<UVec2> for DVec2 {
+    fn from(v: UVec2) -> Self {
+        DVec2::new(v.x as f64, v.y as f64)
+    }
+}
+
+impl From<IVec2> for DVec2 {
+    fn from(v: IVec2) -> Self {
+        DVec2::new(v.x as f64, v.y as f64)
+    }
+}
+
+impl From<PhysicalPosition<f64>> for FVec2 {
+    fn from(position: PhysicalPosition<f64>) -> Self {
+        FVec2::new(position.x as f32, position.y as f32)
+    }
+}
+
+impl From<[f32; 2]> for FVec2 {
+    fn from(v: [f32; 2]) -> Self {
+        FVec2 { x: v[0], y: v[1] }
+    }
+}
+
+impl From<FVec2> for [f32; 2] {
+    fn from(v: FVec2) -> Self {
+        [v.x, v.y]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct UVec2 {
     pub x: u32,
     pub y: u32,
@@ -40,17 +489,28 @@ pub struct UVec2 {
 
 impl UVec2 {
     pub fn new(x: u32, y: u32) -> UVec2 {
-        UVec2 { x: x, y: y }
+        UVec2 { x, y }
     }
 }
 
-impl IVec2 {
-    pub fn new(x: i32, y: i32) -> IVec2 {
-        IVec2 { x: x, y: y }
+/// Parses "WIDTHxHEIGHT", both positive.
+impl FromStr for UVec2 {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("invalid size '{}', expected WIDTHxHEIGHT", s);
+        let (width, height) = s.split_once('x').ok_or_else(error)?;
+        let parse = |part: &str| part.trim().parse::<u32>().ok().filter(|&n| n > 0);
+        match (parse(width), parse(height)) {
+            (Some(width), Some(height)) => Ok(UVec2::new(width, height)),
+            _ => Err(error()),
+        }
     }
+}
 
-    pub fn as_slice(&self) -> [i32; 2] {
-        [self.x, self.y]
+impl IVec2 {
+    pub fn new(x: i32, y: i32) -> IVec2 {
+        IVec2 { x, y }
     }
 }
 
@@ -85,3 +545,42 @@ impl Vertex {
 
 unsafe impl bytemuck::Pod for Vertex {}
 unsafe impl bytemuck::Zeroable for Vertex {}
+
+/// Untextured, colored vertex used for overlay geometry drawn on top of the fractal.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct OverlayVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+    /// Wide line coverage data in pixels: (along, across, segment length, half width).
+    /// A half width of zero marks plain geometry that is drawn fully opaque, and a negative one
+    /// marks text, with the first two components holding the glyph atlas texel.
+    pub stroke: [f32; 4],
+}
+
+impl OverlayVertex {
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<OverlayVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 7]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{
+    gpu_interface::GPUInterface,
+    math::{FVec2, OverlayVertex, UVec2},
+};
+
+/// A vertex buffer that grows on demand and is otherwise reused between frames.
+pub struct GrowableBuffer {
+    label: &'static str,
+    buffer: Option<wgpu::Buffer>,
+    capacity: usize,
+    len: usize,
+}
+
+impl GrowableBuffer {
+    pub fn new(label: &'static str) -> GrowableBuffer {
+        GrowableBuffer {
+            label,
+            buffer: None,
+            capacity: 0,
+            len: 0,
+        }
+    }
+
+    /// Copies `vertices` to the GPU, reallocating only if they no longer fit.
+    pub fn upload(&mut self, gpu: &GPUInterface, vertices: &[OverlayVertex]) {
+        self.len = vertices.len();
+        if vertices.is_empty() {
+            return;
+        }
+        if vertices.len() > self.capacity || self.buffer.is_none() {
+            self.capacity = grown_capacity(self.capacity, vertices.len());
+            self.buffer = Some(gpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(self.label),
+                size: (self.capacity * std::mem::size_of::<OverlayVertex>()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        if let Some(buffer) = &self.buffer {
+            gpu.queue
+                .write_buffer(buffer, 0, bytemuck::cast_slice(vertices));
+        }
+    }
+
+    /// The buffer slice holding the last upload, or None if nothing was uploaded.
+    pub fn slice(&self) -> Option<(wgpu::BufferSlice<'_>, u32)> {
+        match &self.buffer {
+            Some(buffer) if self.len > 0 => {
+                let bytes =
+                    (self.len * std::mem::size_of::<OverlayVertex>()) as wgpu::BufferAddress;
+                Some((buffer.slice(..bytes), self.len as u32))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Capacity (in vertices) a buffer must be reallocated to in order to hold `required`.
+/// Grows to the next power of two so a slowly growing overlay doesn't reallocate every frame.
+pub fn grown_capacity(current: usize, required: usize) -> usize {
+    if required <= current {
+        current
+    } else {
+        required.next_power_of_two().max(64)
+    }
+}
+
+/// Immediate-mode collection of colored overlay geometry.
+/// Positions are given in window pixels and converted to clip space as they are added.
+/// Everything added during a frame is uploaded once by the `Renderer` and then cleared.
+pub struct OverlayBatch {
+    pub lines: Vec<OverlayVertex>,
+    pub triangles: Vec<OverlayVertex>,
+    pub line_buffer: GrowableBuffer,
+    pub triangle_buffer: GrowableBuffer,
+    screen_size: UVec2,
+}
+
+impl OverlayBatch {
+    pub fn new(screen_size: UVec2) -> OverlayBatch {
+        OverlayBatch {
+            lines: Vec::new(),
+            triangles: Vec::new(),
+            line_buffer: GrowableBuffer::new("Overlay line buffer"),
+            triangle_buffer: GrowableBuffer::new("Overlay triangle buffer"),
+            screen_size,
+        }
+    }
+
+    pub fn set_screen_size(&mut self, screen_size: UVec2) {
+        self.screen_size = screen_size;
+    }
+
+    pub fn upload(&mut self, gpu: &GPUInterface) {
+        self.line_buffer.upload(gpu, &self.lines);
+        self.triangle_buffer.upload(gpu, &self.triangles);
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.triangles.clear();
+    }
+
+    fn vertex(&self, p: FVec2, color: [f32; 4]) -> OverlayVertex {
+        self.stroke_vertex(p, color, [0.0; 4])
+    }
+
+    fn stroke_vertex(&self, p: FVec2, color: [f32; 4], stroke: [f32; 4]) -> OverlayVertex {
+        let x = p.x / self.screen_size.x as f32 * 2.0 - 1.0;
+        let y = 1.0 - p.y / self.screen_size.y as f32 * 2.0;
+        OverlayVertex {
+            position: [x, y, 0.0],
+            color,
+            stroke,
+        }
+    }
+
+    pub fn add_line(&mut self, a: FVec2, b: FVec2, color: [f32; 4]) {
+        let a = self.vertex(a, color);
+        let b = self.vertex(b, color);
+        self.lines.push(a);
+        self.lines.push(b);
+    }
+
+    pub fn add_polyline(&mut self, points: &[FVec2], color: [f32; 4]) {
+        for pair in points.windows(2) {
+            self.add_line(pair[0], pair[1], color);
+        }
+    }
+
+    /// Adds a quad textured with the glyph whose top-left atlas texel is `texel`.
+    /// Atlas coordinates stay in texels so glyphs remain valid if the atlas grows mid-frame.
+    pub fn add_glyph(&mut self, min: FVec2, max: FVec2, texel: FVec2, color: [f32; 4]) {
+        let (w, h) = (max.x - min.x, max.y - min.y);
+        let corner = |x: f32, y: f32| {
+            self.stroke_vertex(
+                FVec2 {
+                    x: min.x + x,
+                    y: min.y + y,
+                },
+                color,
+                // A negative half width marks the vertex as textured from the glyph atlas.
+                [texel.x + x, texel.y + y, 0.0, -1.0],
+            )
+        };
+        let a = corner(0.0, 0.0);
+        let b = corner(0.0, h);
+        let c = corner(w, h);
+        let d = corner(w, 0.0);
+        self.triangles.extend_from_slice(&[a, b, c, c, d, a]);
+    }
+
+    /// Adds an anti-aliased line `width` pixels wide with round caps.
+    pub fn add_wide_line(&mut self, a: FVec2, b: FVec2, width: f32, color: [f32; 4]) {
+        let vertices =
+            stroke_segment(a, b, width).map(|(p, stroke)| self.stroke_vertex(p, color, stroke));
+        let [a, b, c, d] = vertices;
+        self.triangles.extend_from_slice(&[a, b, c, c, d, a]);
+    }
+
+    /// Adds an anti-aliased polyline `width` pixels wide.
+    /// Every segment is a capsule, so overlapping ends form the round joins.
+    pub fn add_wide_polyline(&mut self, points: &[FVec2], width: f32, color: [f32; 4]) {
+        for pair in points.windows(2) {
+            self.add_wide_line(pair[0], pair[1], width, color);
+        }
+    }
+
+    /// Adds the outline of the axis-aligned rectangle spanning `min` to `max`.
+    pub fn add_rect_outline(&mut self, min: FVec2, max: FVec2, color: [f32; 4]) {
+        self.add_polyline(
+            &[
+                FVec2 { x: min.x, y: min.y },
+                FVec2 { x: max.x, y: min.y },
+                FVec2 { x: max.x, y: max.y },
+                FVec2 { x: min.x, y: max.y },
+                FVec2 { x: min.x, y: min.y },
+            ],
+            color,
+        );
+    }
+
+    /// Adds a filled axis-aligned rectangle spanning `min` to `max`.
+    pub fn add_rect(&mut self, min: FVec2, max: FVec2, color: [f32; 4]) {
+        let a = self.vertex(FVec2 { x: min.x, y: min.y }, color);
+        let b = self.vertex(FVec2 { x: min.x, y: max.y }, color);
+        let c = self.vertex(FVec2 { x: max.x, y: max.y }, color);
+        let d = self.vertex(FVec2 { x: max.x, y: min.y }, color);
+        // Counter-clockwise in clip space, matching the render quad.
+        self.triangles.extend_from_slice(&[a, b, c, c, d, a]);
+    }
+}
+
+/// Corners of the quad covering a round-capped segment from `a` to `b`, in pixels,
+/// each paired with the stroke data the overlay shader turns into coverage.
+/// The quad extends one pixel past the stroke so the anti-aliased edge isn't clipped.
+/// Corners wind counter-clockwise on screen, like `add_rect`.
+pub fn stroke_segment(a: FVec2, b: FVec2, width: f32) -> [(FVec2, [f32; 4]); 4] {
+    let length = (b - a).length();
+    // A degenerate segment still draws a round dot.
+    let FVec2 { x: ux, y: uy } = if length > f32::EPSILON {
+        (b - a) / length
+    } else {
+        FVec2::new(1.0, 0.0)
+    };
+    let half_width = width * 0.5;
+    let margin = half_width + 1.0;
+    let corner = |along: f32, across: f32| {
+        (
+            a + FVec2::new(ux * along - uy * across, uy * along + ux * across),
+            [along, across, length, half_width],
+        )
+    };
+    [
+        corner(-margin, -margin),
+        corner(-margin, margin),
+        corner(length + margin, margin),
+        corner(length + margin, -margin),
+    ]
+}
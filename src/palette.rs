@@ -0,0 +1,760 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::colorize::ColorParams;
+
+/// Number of entries in the lookup texture a palette is baked into.
+pub const LUT_SIZE: u32 = 256;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PaletteStop {
+    /// Position along the gradient in [0, 1].
+    pub position: f32,
+    /// sRGB color with components in [0, 1].
+    pub color: [f32; 3],
+}
+
+/// A gradient defined by color stops, sampled by normalized iteration value.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Palette {
+    pub name: String,
+    pub stops: Vec<PaletteStop>,
+}
+
+impl Palette {
+    pub fn from_rgb8(name: &str, colors: &[(f32, [u8; 3])]) -> Palette {
+        Palette {
+            name: name.to_string(),
+            stops: colors
+                .iter()
+                .map(|(position, [r, g, b])| PaletteStop {
+                    position: *position,
+                    color: [*r as f32 / 255.0, *g as f32 / 255.0, *b as f32 / 255.0],
+                })
+                .collect(),
+        }
+    }
+
+    /// Linearly interpolates the stops at `t`. Stops must be sorted by position.
+    pub fn sample(&self, t: f32) -> [f32; 3] {
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return [0.0; 3],
+        };
+        if t <= first.position {
+            return first.color;
+        }
+        for pair in self.stops.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            if t <= b.position {
+                let span = b.position - a.position;
+                let f = if span > 0.0 {
+                    (t - a.position) / span
+                } else {
+                    1.0
+                };
+                return [
+                    a.color[0] + (b.color[0] - a.color[0]) * f,
+                    a.color[1] + (b.color[1] - a.color[1]) * f,
+                    a.color[2] + (b.color[2] - a.color[2]) * f,
+                ];
+            }
+        }
+        last.color
+    }
+
+    pub fn save_json(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load_json(path: &Path) -> anyhow::Result<Palette> {
+        let mut palette: Palette = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if palette.stops.is_empty() {
+            anyhow::bail!("palette has no color stops");
+        }
+        palette.sort_stops();
+        Ok(palette)
+    }
+
+    /// A gradient through `colors` with evenly spaced stops.
+    pub fn from_colors(name: &str, colors: &[[u8; 3]]) -> Palette {
+        let last = (colors.len().max(2) - 1) as f32;
+        let stops: Vec<(f32, [u8; 3])> = colors
+            .iter()
+            .enumerate()
+            .map(|(i, color)| (i as f32 / last, *color))
+            .collect();
+        Palette::from_rgb8(name, &stops)
+    }
+
+    /// Reads a Fractint `.map` file: one whitespace separated "r g b" triplet per line.
+    /// Anything after the third number is a comment, as are blank lines.
+    pub fn load_map(path: &Path) -> anyhow::Result<Palette> {
+        let text = std::fs::read_to_string(path)?;
+        let mut colors = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().take(3).collect();
+            if fields.is_empty() {
+                continue;
+            }
+            let parse = |field: Option<&&str>| -> anyhow::Result<u8> {
+                field.and_then(|f| f.parse().ok()).ok_or_else(|| {
+                    anyhow::anyhow!("line {}: expected three values 0-255", number + 1)
+                })
+            };
+            colors.push([
+                parse(fields.first())?,
+                parse(fields.get(1))?,
+                parse(fields.get(2))?,
+            ]);
+        }
+        if colors.len() < 2 {
+            anyhow::bail!("needs at least two colors");
+        }
+        Ok(Palette::from_colors(&name_from_path(path), &colors))
+    }
+
+    /// Reads a gradient from a PNG that is a single row or column of pixels.
+    pub fn load_strip(path: &Path) -> anyhow::Result<Palette> {
+        let image = image::open(path)?.to_rgb8();
+        if image.width() != 1 && image.height() != 1 {
+            anyhow::bail!(
+                "{}x{} is not a 1xN strip; use --palette-from-image for pictures",
+                image.width(),
+                image.height()
+            );
+        }
+        let colors: Vec<[u8; 3]> = image.pixels().map(|p| p.0).collect();
+        if colors.len() < 2 {
+            anyhow::bail!("needs at least two pixels");
+        }
+        Ok(Palette::from_colors(&name_from_path(path), &colors))
+    }
+
+    /// Loads any supported palette file, picking the format by extension in any case.
+    pub fn load_file(path: &Path) -> anyhow::Result<Palette> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        if extension.eq_ignore_ascii_case("json") {
+            Palette::load_json(path)
+        } else if extension.eq_ignore_ascii_case("map") {
+            Palette::load_map(path)
+        } else if extension.eq_ignore_ascii_case("png") {
+            Palette::load_strip(path)
+        } else {
+            anyhow::bail!("unsupported palette format")
+        }
+    }
+
+    fn sort_stops(&mut self) {
+        self.stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+    }
+
+    /// Inserts a stop at `position` with the gradient's current color there,
+    /// returning its index.
+    pub fn add_stop(&mut self, position: f32) -> usize {
+        let position = position.clamp(0.0, 1.0);
+        let stop = PaletteStop {
+            position,
+            color: self.sample(position),
+        };
+        let index = self
+            .stops
+            .iter()
+            .position(|s| s.position > position)
+            .unwrap_or(self.stops.len());
+        self.stops.insert(index, stop);
+        index
+    }
+
+    pub fn nearest_stop(&self, position: f32) -> Option<usize> {
+        (0..self.stops.len()).min_by(|a, b| {
+            let da = (self.stops[*a].position - position).abs();
+            let db = (self.stops[*b].position - position).abs();
+            da.total_cmp(&db)
+        })
+    }
+
+    /// Removes a stop, refusing to drop below two stops so the gradient stays defined.
+    pub fn remove_stop(&mut self, index: usize) -> bool {
+        if self.stops.len() <= 2 || index >= self.stops.len() {
+            return false;
+        }
+        self.stops.remove(index);
+        true
+    }
+
+    /// Shifts a stop's color in HSL space. Hue wraps, saturation and lightness clamp.
+    pub fn nudge_hsl(&mut self, index: usize, hue: f32, saturation: f32, lightness: f32) {
+        if let Some(stop) = self.stops.get_mut(index) {
+            let [h, s, l] = rgb_to_hsl(stop.color);
+            stop.color = hsl_to_rgb([
+                (h + hue).rem_euclid(1.0),
+                (s + saturation).clamp(0.0, 1.0),
+                (l + lightness).clamp(0.0, 1.0),
+            ]);
+        }
+    }
+
+    /// Bakes the gradient into `LUT_SIZE` RGBA8 texels.
+    pub fn to_lut(&self) -> Vec<[u8; 4]> {
+        (0..LUT_SIZE)
+            .map(|i| {
+                let [r, g, b] = self.sample(i as f32 / (LUT_SIZE - 1) as f32);
+                let to_u8 = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+                [to_u8(r), to_u8(g), to_u8(b), 255]
+            })
+            .collect()
+    }
+}
+
+/// The palettes that ship with the app, in cycling order.
+pub fn builtin_palettes() -> Vec<Palette> {
+    vec![
+        // The original inline shader coloring: hsv(t, 0.5, 1) with red forced to 1.
+        Palette::from_rgb8(
+            "Classic",
+            &[
+                (0.0, [255, 128, 128]),
+                (1.0 / 3.0, [255, 128, 128]),
+                (0.5, [255, 255, 255]),
+                (5.0 / 6.0, [255, 255, 255]),
+                (1.0, [255, 128, 128]),
+            ],
+        ),
+        Palette::from_rgb8(
+            "Viridis",
+            &[
+                (0.0, [68, 1, 84]),
+                (0.1, [72, 36, 117]),
+                (0.2, [65, 68, 135]),
+                (0.3, [53, 95, 141]),
+                (0.4, [42, 120, 142]),
+                (0.5, [33, 145, 140]),
+                (0.6, [34, 168, 132]),
+                (0.7, [68, 191, 112]),
+                (0.8, [122, 209, 81]),
+                (0.9, [189, 223, 38]),
+                (1.0, [253, 231, 37]),
+            ],
+        ),
+        Palette::from_rgb8(
+            "Cividis",
+            &[
+                (0.0, [0, 34, 78]),
+                (0.1, [18, 53, 112]),
+                (0.2, [59, 73, 108]),
+                (0.3, [87, 92, 109]),
+                (0.4, [112, 113, 115]),
+                (0.5, [138, 134, 120]),
+                (0.6, [165, 156, 116]),
+                (0.7, [193, 179, 105]),
+                (0.8, [222, 204, 88]),
+                (0.9, [248, 226, 66]),
+                (1.0, [254, 232, 56]),
+            ],
+        ),
+        // Blue/yellow stays distinguishable under all common color vision deficiencies.
+        Palette::from_rgb8("High contrast", &[(0.0, [0, 32, 96]), (1.0, [255, 220, 0])]),
+        // The remaining gradients start and end on the same color so palette cycles wrap seamlessly.
+        Palette::from_rgb8(
+            "Blue gold",
+            &[
+                (0.0, [0, 7, 100]),
+                (0.16, [32, 107, 203]),
+                (0.42, [237, 255, 255]),
+                (0.6425, [255, 170, 0]),
+                (0.8575, [0, 2, 0]),
+                (1.0, [0, 7, 100]),
+            ],
+        ),
+        Palette::from_rgb8(
+            "Grayscale",
+            &[(0.0, [0, 0, 0]), (0.5, [255, 255, 255]), (1.0, [0, 0, 0])],
+        ),
+        Palette::from_rgb8(
+            "Fire",
+            &[
+                (0.0, [0, 0, 0]),
+                (0.25, [128, 0, 0]),
+                (0.5, [255, 80, 0]),
+                (0.75, [255, 220, 60]),
+                (0.9, [255, 255, 220]),
+                (1.0, [0, 0, 0]),
+            ],
+        ),
+    ]
+}
+
+/// Curve applied to smooth iteration values before density and offset.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MappingMode {
+    Linear = 0,
+    Log = 1,
+    Sqrt = 2,
+    /// `x^exponent` with a user-adjustable exponent.
+    Power = 3,
+    /// Position in the cumulative histogram of the frame, so every color covers about as
+    /// many pixels at any zoom. Ignores the density; see `histogram::Histogram`.
+    Histogram = 4,
+}
+
+impl MappingMode {
+    pub fn next(self) -> MappingMode {
+        match self {
+            MappingMode::Linear => MappingMode::Log,
+            MappingMode::Log => MappingMode::Sqrt,
+            MappingMode::Sqrt => MappingMode::Power,
+            MappingMode::Power => MappingMode::Histogram,
+            MappingMode::Histogram => MappingMode::Linear,
+        }
+    }
+}
+
+impl FromStr for MappingMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "linear" => Ok(MappingMode::Linear),
+            "log" => Ok(MappingMode::Log),
+            "sqrt" => Ok(MappingMode::Sqrt),
+            "power" => Ok(MappingMode::Power),
+            "histogram" => Ok(MappingMode::Histogram),
+            _ => anyhow::bail!(
+                "unknown mapping mode '{}', expected linear, log, sqrt, power or histogram",
+                s
+            ),
+        }
+    }
+}
+
+/// How smooth iteration values map onto the palette:
+/// `position = fract(mode(smooth_iterations) / density + offset)`.
+/// Saved with `state::ViewState`; fields missing from a file keep their defaults.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorMapping {
+    pub mode: MappingMode,
+    /// Exponent used by `MappingMode::Power`.
+    pub exponent: f32,
+    pub density: f32,
+    pub offset: f32,
+    /// Stretch one palette cycle over the visible iteration range instead of using density.
+    pub auto_range: bool,
+}
+
+impl Default for ColorMapping {
+    fn default() -> Self {
+        Self {
+            mode: MappingMode::Linear,
+            exponent: 0.5,
+            density: 64.0,
+            offset: 0.0,
+            auto_range: false,
+        }
+    }
+}
+
+impl ColorMapping {
+    const DENSITY_STEP: f32 = 1.25;
+    const MIN_DENSITY: f32 = 0.25;
+    const MAX_DENSITY: f32 = 1.0e7;
+
+    /// Uniforms for `colorize::Colorizer`.
+    pub fn color_params(&self) -> ColorParams {
+        ColorParams {
+            palette_density: self.density,
+            palette_offset: self.offset,
+            mapping_mode: self.mode as u32,
+            mapping_exponent: self.exponent,
+            color_interior: 0,
+            boundary_only: 0,
+            relief: 0,
+            light_angle: 0.0,
+            light_height: 1.0,
+            auto_range: self.auto_range as u32,
+            roots: 0,
+            _padding: 0,
+        }
+    }
+
+    /// Scales the density by `DENSITY_STEP` to the power of `steps`.
+    pub fn adjust_density(&mut self, steps: i32) {
+        self.density = (self.density * Self::DENSITY_STEP.powi(steps))
+            .clamp(Self::MIN_DENSITY, Self::MAX_DENSITY);
+    }
+
+    pub fn adjust_offset(&mut self, delta: f32) {
+        self.offset = (self.offset + delta).rem_euclid(1.0);
+    }
+
+    pub fn adjust_exponent(&mut self, delta: f32) {
+        self.exponent = (self.exponent + delta).clamp(0.05, 4.0);
+    }
+
+    /// Rejects mappings the shader can't use, e.g. ones read from a hand-edited file.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.density.is_finite() && self.density > 0.0,
+            "density must be positive, got {}",
+            self.density
+        );
+        anyhow::ensure!(
+            self.exponent.is_finite() && self.exponent > 0.0,
+            "exponent must be positive, got {}",
+            self.exponent
+        );
+        anyhow::ensure!(
+            self.offset.is_finite(),
+            "offset must be finite, got {}",
+            self.offset
+        );
+        Ok(())
+    }
+
+    /// The curve the shader applies before density and offset, mirrored on the CPU.
+    pub fn apply_mode(&self, iterations: f32) -> f32 {
+        let x = iterations.max(0.0);
+        match self.mode {
+            MappingMode::Linear => x,
+            MappingMode::Log => (1.0 + x).log2(),
+            MappingMode::Sqrt => x.sqrt(),
+            MappingMode::Power => x.powf(self.exponent),
+            // The histogram is only known on the GPU.
+            MappingMode::Histogram => x,
+        }
+    }
+}
+
+/// Palette cycling: animates `ColorMapping::offset` so colors flow along the iteration bands.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PaletteCycling {
+    pub enabled: bool,
+    /// Palette cycles per second.
+    pub speed: f32,
+}
+
+impl Default for PaletteCycling {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            speed: 0.1,
+        }
+    }
+}
+
+impl PaletteCycling {
+    const SPEED_STEP: f32 = 1.5;
+    const MIN_SPEED: f32 = 0.005;
+    const MAX_SPEED: f32 = 5.0;
+
+    /// Scales the speed by `SPEED_STEP` to the power of `steps`.
+    pub fn adjust_speed(&mut self, steps: i32) {
+        self.speed =
+            (self.speed * Self::SPEED_STEP.powi(steps)).clamp(Self::MIN_SPEED, Self::MAX_SPEED);
+    }
+
+    /// Moves the offset of `mapping` on by `seconds` of animation, if enabled.
+    pub fn advance(&self, mapping: &mut ColorMapping, seconds: f32) {
+        if self.enabled {
+            mapping.adjust_offset(self.speed * seconds);
+        }
+    }
+}
+
+/// Directory user palettes are saved to and loaded from at startup.
+pub const PALETTE_DIR: &str = "palettes";
+
+/// State of the interactive palette editor: a phase cursor along the gradient
+/// and the stop currently being edited.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PaletteEditor {
+    pub phase: f32,
+    pub selected: Option<usize>,
+}
+
+/// Extensions `Palette::load_file` understands.
+const PALETTE_EXTENSIONS: [&str; 3] = ["json", "map", "png"];
+
+/// Paths of every supported palette file in `dir`, in no particular order.
+fn palette_files(dir: &Path) -> Vec<PathBuf> {
+    match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| PALETTE_EXTENSIONS.iter().any(|x| x.eq_ignore_ascii_case(e)))
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The most recently modified palette file in `dir`.
+pub fn newest_palette_file(dir: &Path) -> Option<PathBuf> {
+    palette_files(dir)
+        .into_iter()
+        .filter_map(|p| Some((std::fs::metadata(&p).ok()?.modified().ok()?, p)))
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, p)| p)
+}
+
+fn name_from_path(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Untitled".to_string())
+}
+
+/// Loads every palette file in `dir`, skipping (and logging) files that fail to parse.
+pub fn load_palette_dir(dir: &Path) -> Vec<Palette> {
+    let mut paths = palette_files(dir);
+    paths.sort();
+    paths
+        .iter()
+        .filter_map(|path| match Palette::load_file(path) {
+            Ok(palette) => Some(palette),
+            Err(e) => {
+                eprintln!("Skipping palette {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// File name a palette is saved under in the palettes directory.
+pub fn palette_file_name(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}.json", stem)
+}
+
+/// Builds a gradient from the dominant colors of an image: k-means in OKLab,
+/// with the cluster centers ordered dark to light.
+pub fn palette_from_image(path: &Path, k: usize) -> anyhow::Result<Palette> {
+    let image = image::open(path)?.to_rgb8();
+    // A few thousand pixels are plenty to find dominant colors.
+    let step = ((image.width() * image.height()) as usize / 16384).max(1);
+    let samples: Vec<[f32; 3]> = image
+        .pixels()
+        .step_by(step)
+        .map(|p| srgb_to_oklab([p[0], p[1], p[2]]))
+        .collect();
+    if samples.is_empty() {
+        anyhow::bail!("{} contains no pixels", path.display());
+    }
+
+    let mut centers = kmeans(&samples, k, 32, KMEANS_SEED);
+    centers.sort_by(|a, b| a[0].total_cmp(&b[0]));
+
+    let name = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Image".to_string());
+    let last = (centers.len() - 1).max(1) as f32;
+    Ok(Palette {
+        name,
+        stops: centers
+            .iter()
+            .enumerate()
+            .map(|(i, lab)| PaletteStop {
+                position: i as f32 / last,
+                color: oklab_to_srgb(*lab),
+            })
+            .collect(),
+    })
+}
+
+const KMEANS_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Small xorshift generator so k-means initialization is deterministic.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+fn distance_squared(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+fn nearest(centers: &[[f32; 3]], p: [f32; 3]) -> usize {
+    let mut best = 0;
+    for (i, c) in centers.iter().enumerate() {
+        if distance_squared(*c, p) < distance_squared(centers[best], p) {
+            best = i;
+        }
+    }
+    best
+}
+
+/// Lloyd's k-means with k-means++ seeding. Returns at most `k` centers; clusters that end up
+/// empty are dropped, so fewer are returned when the points have fewer distinct colors.
+pub fn kmeans(points: &[[f32; 3]], k: usize, iterations: usize, seed: u64) -> Vec<[f32; 3]> {
+    let mut rng = XorShift(seed.max(1));
+    let mut centers = Vec::with_capacity(k);
+    if points.is_empty() || k == 0 {
+        return centers;
+    }
+    centers.push(points[(rng.next_f32() * points.len() as f32) as usize % points.len()]);
+    while centers.len() < k {
+        let weights: Vec<f32> = points
+            .iter()
+            .map(|p| distance_squared(centers[nearest(&centers, *p)], *p))
+            .collect();
+        let total: f32 = weights.iter().sum();
+        if total <= 0.0 {
+            break;
+        }
+        let mut target = rng.next_f32() * total;
+        let mut chosen = points.len() - 1;
+        for (i, w) in weights.iter().enumerate() {
+            if target < *w {
+                chosen = i;
+                break;
+            }
+            target -= w;
+        }
+        centers.push(points[chosen]);
+    }
+
+    for _ in 0..iterations {
+        let mut sums = vec![[0.0f32; 3]; centers.len()];
+        let mut counts = vec![0usize; centers.len()];
+        for p in points {
+            let i = nearest(&centers, *p);
+            for c in 0..3 {
+                sums[i][c] += p[c];
+            }
+            counts[i] += 1;
+        }
+        let mut moved = false;
+        for i in 0..centers.len() {
+            if counts[i] > 0 {
+                let n = counts[i] as f32;
+                let center = [sums[i][0] / n, sums[i][1] / n, sums[i][2] / n];
+                moved |= distance_squared(center, centers[i]) > 1e-10;
+                centers[i] = center;
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+
+    let mut counts = vec![0usize; centers.len()];
+    for p in points {
+        counts[nearest(&centers, *p)] += 1;
+    }
+    centers
+        .into_iter()
+        .zip(counts)
+        .filter(|(_, count)| *count > 0)
+        .map(|(center, _)| center)
+        .collect()
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_to_oklab([r, g, b]: [u8; 3]) -> [f32; 3] {
+    let (r, g, b) = (
+        srgb_to_linear(r as f32 / 255.0),
+        srgb_to_linear(g as f32 / 255.0),
+        srgb_to_linear(b as f32 / 255.0),
+    );
+    let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+/// Converts OKLab back to sRGB components in [0, 1].
+pub fn oklab_to_srgb([l, a, b]: [f32; 3]) -> [f32; 3] {
+    let l_ = (l + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+    let m_ = (l - 0.105_561_346 * a - 0.063_854_17 * b).powi(3);
+    let s_ = (l - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+    let r = 4.076_741_7 * l_ - 3.307_711_6 * m_ + 0.230_969_94 * s_;
+    let g = -1.268_438 * l_ + 2.609_757_4 * m_ - 0.341_319_38 * s_;
+    let b = -0.004_196_086_3 * l_ - 0.703_418_6 * m_ + 1.707_614_7 * s_;
+    [
+        linear_to_srgb(r).clamp(0.0, 1.0),
+        linear_to_srgb(g).clamp(0.0, 1.0),
+        linear_to_srgb(b).clamp(0.0, 1.0),
+    ]
+}
+
+/// Converts sRGB in [0, 1] to (hue, saturation, lightness), all in [0, 1].
+pub fn rgb_to_hsl([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    let d = max - min;
+    if d <= f32::EPSILON {
+        return [0.0, 0.0, l];
+    }
+    let s = d / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        ((g - b) / d).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+    [h / 6.0, s.clamp(0.0, 1.0), l]
+}
+
+pub fn hsl_to_rgb([h, s, l]: [f32; 3]) -> [f32; 3] {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let h6 = h.rem_euclid(1.0) * 6.0;
+    let x = c * (1.0 - (h6 % 2.0 - 1.0).abs());
+    let (r, g, b) = match h6 as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c / 2.0;
+    [r + m, g + m, b + m]
+}
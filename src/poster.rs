@@ -0,0 +1,322 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    colorize::{ColorParams, Colorizer},
+    computer::{ComputeSetup, Computer, MandelbrotParams},
+    gpu_interface::GPUInterface,
+    math::{DVec2, UVec2},
+    palette::{MappingMode, Palette},
+};
+
+/// Upper bound on the tile edge. Tiles also respect the device's texture size limit, but an
+/// Rgba32Float iteration texture at that limit would already take gigabytes.
+const MAX_TILE_SIZE: u32 = 2048;
+
+/// Everything that decides a poster's pixels, recorded in its checkpoint so only the same
+/// poster is resumed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct PosterSettings {
+    width: u32,
+    height: u32,
+    tile_size: u32,
+    /// `MandelbrotParams` word for word, so they compare exactly.
+    params: Vec<u32>,
+    /// `ColorParams` word for word.
+    color_params: Vec<u32>,
+    palette: Palette,
+    precision: String,
+    formula: Option<String>,
+}
+
+impl PosterSettings {
+    /// Names of the fields that differ from `other`.
+    fn differences(&self, other: &PosterSettings) -> Vec<&'static str> {
+        [
+            (
+                "size",
+                self.width != other.width || self.height != other.height,
+            ),
+            ("tile size", self.tile_size != other.tile_size),
+            ("view", self.params != other.params),
+            ("coloring", self.color_params != other.color_params),
+            ("palette", self.palette != other.palette),
+            ("precision", self.precision != other.precision),
+            ("formula", self.formula != other.formula),
+        ]
+        .into_iter()
+        .filter_map(|(name, differs)| differs.then_some(name))
+        .collect()
+    }
+}
+
+/// Contents of `manifest.json` in a poster's checkpoint directory, rewritten after every tile.
+#[derive(Serialize, Deserialize)]
+struct PosterManifest {
+    settings: PosterSettings,
+    /// Tiles finished so far; tile `i` is saved next to the manifest by `tile_file_name`.
+    done: u32,
+}
+
+/// Directory where a poster written to `path` keeps its checkpoint, e.g. "poster.tiles".
+pub fn checkpoint_dir(path: &Path) -> PathBuf {
+    path.with_extension("tiles")
+}
+
+fn tile_file_name(index: u32) -> String {
+    format!("tile_{:05}.png", index)
+}
+
+/// Renders the view at an arbitrary size, one tile per `step`, and stitches the tiles into a
+/// single image on the CPU. Like `BracketJob` it has its own `Computer` and `Colorizer`, so
+/// the live view is untouched.
+pub struct PosterJob {
+    computer: Computer,
+    colorizer: Colorizer,
+    params: MandelbrotParams,
+    color_params: ColorParams,
+    size: UVec2,
+    tile_size: u32,
+    next: u32,
+    /// Tightly packed RGBA of the whole poster.
+    pixels: Vec<u8>,
+    settings: PosterSettings,
+    /// Whether finished tiles are saved to `checkpoint_dir`, see `with_checkpoint`.
+    checkpoint: bool,
+    pub path: PathBuf,
+}
+
+impl PosterJob {
+    /// `params` must describe the view at the poster's aspect ratio. `formula` is the one
+    /// `FractalKind::Custom` params render.
+    pub fn new(
+        gpu: &GPUInterface,
+        size: UVec2,
+        params: MandelbrotParams,
+        color_params: ColorParams,
+        setup: &ComputeSetup,
+        palette: &Palette,
+        path: PathBuf,
+    ) -> anyhow::Result<PosterJob> {
+        let tile_size = gpu
+            .device
+            .limits()
+            .max_texture_dimension_2d
+            .min(MAX_TILE_SIZE)
+            .min(size.x.max(size.y));
+        let tile = UVec2::new(tile_size, tile_size);
+        let mut computer = setup.build(gpu, tile)?;
+        let colorizer = Colorizer::new(
+            gpu,
+            tile,
+            &computer.iteration_view,
+            computer.histogram.cdf_buffer(),
+            computer.range.range_buffer(),
+        );
+        colorizer.set_palette(gpu, palette);
+        let histogram = color_params.mapping_mode == MappingMode::Histogram as u32;
+        if histogram || color_params.auto_range == 1 {
+            // Every tile is equalized by the histogram and stretched over the range of the
+            // whole poster at tile resolution, so the tiles match up.
+            computer.run(gpu, &params);
+            if histogram {
+                computer.build_histogram(gpu, params.max_iterations);
+            }
+            computer.build_range(gpu);
+        }
+        Ok(PosterJob {
+            computer,
+            colorizer,
+            params,
+            color_params,
+            size,
+            tile_size,
+            next: 0,
+            pixels: vec![0; size.x as usize * size.y as usize * 4],
+            settings: PosterSettings {
+                width: size.x,
+                height: size.y,
+                tile_size,
+                params: bytemuck::cast_slice(bytemuck::bytes_of(&params)).to_vec(),
+                color_params: bytemuck::cast_slice(bytemuck::bytes_of(&color_params)).to_vec(),
+                palette: palette.clone(),
+                precision: format!("{:?}", setup.precision),
+                formula: setup.formula.as_ref().map(|formula| formula.to_string()),
+            },
+            checkpoint: false,
+            path,
+        })
+    }
+
+    /// Saves each finished tile and a manifest of the settings to `checkpoint_dir`, so an
+    /// interrupted poster can be finished later. With `resume` the tiles a run of the same
+    /// poster left there are loaded instead of rendered again; a poster with other settings
+    /// is refused. Without it, any old checkpoint is cleared.
+    pub fn with_checkpoint(mut self, resume: bool) -> anyhow::Result<PosterJob> {
+        let dir = checkpoint_dir(&self.path);
+        if resume {
+            let manifest_path = dir.join("manifest.json");
+            let text = std::fs::read_to_string(&manifest_path)
+                .with_context(|| format!("nothing to resume in {}", dir.display()))?;
+            let manifest: PosterManifest = serde_json::from_str(&text)
+                .with_context(|| format!("could not read {}", manifest_path.display()))?;
+            let differences = manifest.settings.differences(&self.settings);
+            anyhow::ensure!(
+                differences.is_empty(),
+                "refusing to resume {}: its {} differ from this render's",
+                dir.display(),
+                differences.join(", ")
+            );
+            for index in 0..manifest.done {
+                let tile_path = dir.join(tile_file_name(index));
+                let tile = image::open(&tile_path)
+                    .with_context(|| format!("could not load {}", tile_path.display()))?
+                    .into_rgba8();
+                anyhow::ensure!(
+                    tile.dimensions() == (self.tile_size, self.tile_size),
+                    "{} is not a {}x{} tile",
+                    tile_path.display(),
+                    self.tile_size,
+                    self.tile_size
+                );
+                self.place_tile(index, &tile);
+            }
+            self.next = manifest.done;
+        } else {
+            if dir.exists() {
+                std::fs::remove_dir_all(&dir)
+                    .with_context(|| format!("could not clear {}", dir.display()))?;
+            }
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("could not create {}", dir.display()))?;
+        }
+        self.checkpoint = true;
+        Ok(self)
+    }
+
+    fn tile_counts(&self) -> UVec2 {
+        UVec2::new(
+            self.size.x.div_ceil(self.tile_size),
+            self.size.y.div_ceil(self.tile_size),
+        )
+    }
+
+    /// Number of tiles finished and the total.
+    pub fn progress(&self) -> (u32, u32) {
+        let counts = self.tile_counts();
+        (self.next, counts.x * counts.y)
+    }
+
+    pub fn is_done(&self) -> bool {
+        let (done, total) = self.progress();
+        done >= total
+    }
+
+    /// Pixel of the poster at the top left of tile `index`.
+    fn tile_origin(&self, index: u32) -> UVec2 {
+        let counts = self.tile_counts();
+        UVec2::new(
+            index % counts.x * self.tile_size,
+            index / counts.x * self.tile_size,
+        )
+    }
+
+    /// Renders the next tile and copies the part inside the poster into place. With a
+    /// checkpoint, the tile is saved before the manifest counts it.
+    pub fn step(&mut self, gpu: &GPUInterface) -> anyhow::Result<()> {
+        let origin = self.tile_origin(self.next);
+        let params = tile_params(&self.params, self.size, origin, self.tile_size);
+        self.computer.run(gpu, &params);
+        self.colorizer.run(gpu, &self.color_params);
+        let tile = self.colorizer.read_output(gpu)?;
+        self.place_tile(self.next, &tile);
+        if self.checkpoint {
+            let dir = checkpoint_dir(&self.path);
+            tile.save(dir.join(tile_file_name(self.next)))?;
+            let manifest = serde_json::to_string_pretty(&PosterManifest {
+                settings: self.settings.clone(),
+                done: self.next + 1,
+            })?;
+            // Renamed into place, so a crash mid-write leaves the previous manifest intact.
+            let temporary = dir.join("manifest.json.tmp");
+            std::fs::write(&temporary, manifest)?;
+            std::fs::rename(&temporary, dir.join("manifest.json"))?;
+        }
+        self.next += 1;
+        Ok(())
+    }
+
+    /// Copies the part of `tile` inside the poster to where tile `index` goes.
+    fn place_tile(&mut self, index: u32, tile: &image::RgbaImage) {
+        let origin = self.tile_origin(index);
+        // Edge tiles overhang the poster; only the covered part is kept.
+        let width = self.tile_size.min(self.size.x - origin.x) as usize;
+        let height = self.tile_size.min(self.size.y - origin.y);
+        let tile_row = self.tile_size as usize * 4;
+        let poster_row = self.size.x as usize * 4;
+        for y in 0..height {
+            let src = y as usize * tile_row;
+            let dst = (origin.y + y) as usize * poster_row + origin.x as usize * 4;
+            self.pixels[dst..dst + width * 4].copy_from_slice(&tile.as_raw()[src..src + width * 4]);
+        }
+    }
+
+    /// Starts the tiles over with every sample offset by `jitter` pixels, keeping the
+    /// histogram and range of the first pass so every pass is colored alike.
+    pub fn restart(&mut self, jitter: [f32; 2]) {
+        self.params.jitter = jitter;
+        self.next = 0;
+    }
+
+    /// Tightly packed RGBA of the poster so far.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// The finished poster. Only complete once `is_done`.
+    pub fn into_image(self) -> image::RgbaImage {
+        image::RgbaImage::from_raw(self.size.x, self.size.y, self.pixels)
+            .expect("pixels hold exactly one RGBA image")
+    }
+}
+
+/// Params that render the `tile_size` square at pixel `origin` of a `size` image of `params`.
+/// The view maps [-1, 1] across the whole image, so the tile gets a scaled down transform and
+/// a center moved to its own middle. The center is computed in f64 and split again, so the
+/// double precision paths keep their accuracy.
+fn tile_params(
+    params: &MandelbrotParams,
+    size: UVec2,
+    origin: UVec2,
+    tile_size: u32,
+) -> MandelbrotParams {
+    let scale_x = tile_size as f32 / size.x as f32;
+    let scale_y = tile_size as f32 / size.y as f32;
+    let [c0x, c0y, c1x, c1y] = params.transform;
+    let uv_x = (2.0 * origin.x as f64 + tile_size as f64) / size.x as f64 - 1.0;
+    let uv_y = (2.0 * origin.y as f64 + tile_size as f64) / size.y as f64 - 1.0;
+    let center = DVec2::new(
+        params.center[0] as f64
+            + params.center_lo[0] as f64
+            + c0x as f64 * uv_x
+            + c1x as f64 * uv_y,
+        params.center[1] as f64
+            + params.center_lo[1] as f64
+            + c0y as f64 * uv_x
+            + c1y as f64 * uv_y,
+    );
+    let (center, center_lo) = center.split();
+    MandelbrotParams {
+        transform: [c0x * scale_x, c0y * scale_x, c1x * scale_y, c1y * scale_y],
+        center: center.into(),
+        center_lo: center_lo.into(),
+        ..*params
+    }
+}
+
+/// Parses a poster size such as "8192x8192".
+pub fn parse_size(text: &str) -> Result<UVec2, String> {
+    text.parse()
+}
@@ -0,0 +1,466 @@
+use std::any::Any;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::{gpu_interface::GPUInterface, math::UVec2};
+
+/// Format of the intermediate textures passes render into.
+pub const POST_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// A fullscreen effect in the post-processing chain.
+/// Each pass reads the previous stage's texture and renders the next one.
+pub trait PostPass {
+    fn name(&self) -> &'static str;
+
+    /// Lets the chain hand back the concrete pass so its settings can be changed.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Called whenever the chain's intermediate textures change size.
+    fn resize(&mut self, gpu: &GPUInterface, size: UVec2);
+
+    /// Records the commands that render `input` through this effect into `output`.
+    fn encode(
+        &self,
+        gpu: &GPUInterface,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+    );
+}
+
+struct ChainEntry {
+    pass: Box<dyn PostPass>,
+    enabled: bool,
+}
+
+/// Ordered list of post-processing passes run between the colored fractal and the surface.
+/// Passes ping-pong between two window-sized textures; disabled passes are skipped entirely.
+pub struct PostChain {
+    passes: Vec<ChainEntry>,
+    targets: [wgpu::TextureView; 2],
+    size: UVec2,
+}
+
+impl PostChain {
+    pub fn new(gpu: &GPUInterface, size: UVec2) -> PostChain {
+        PostChain {
+            passes: Vec::new(),
+            targets: [
+                create_target(gpu, size, "Post target A"),
+                create_target(gpu, size, "Post target B"),
+            ],
+            size,
+        }
+    }
+
+    /// Appends a pass to the end of the chain, enabled or not.
+    pub fn push(&mut self, gpu: &GPUInterface, mut pass: Box<dyn PostPass>, enabled: bool) {
+        pass.resize(gpu, self.size);
+        self.passes.push(ChainEntry { pass, enabled });
+    }
+
+    /// Flips the named pass on or off, returning its new state, or None if there is no such pass.
+    pub fn toggle(&mut self, name: &str) -> Option<bool> {
+        let entry = self.passes.iter_mut().find(|e| e.pass.name() == name)?;
+        entry.enabled = !entry.enabled;
+        Some(entry.enabled)
+    }
+
+    /// The first pass of type `T` in the chain.
+    pub fn pass_mut<T: PostPass + 'static>(&mut self) -> Option<&mut T> {
+        self.passes
+            .iter_mut()
+            .find_map(|e| e.pass.as_any_mut().downcast_mut::<T>())
+    }
+
+    pub fn resize(&mut self, gpu: &GPUInterface, size: UVec2) {
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+        self.targets = [
+            create_target(gpu, size, "Post target A"),
+            create_target(gpu, size, "Post target B"),
+        ];
+        for entry in &mut self.passes {
+            entry.pass.resize(gpu, size);
+        }
+    }
+
+    /// Records every enabled pass and returns the index into `targets` of the final image.
+    /// With nothing enabled this is None and the image is `input` itself, so the chain costs
+    /// nothing when idle.
+    pub fn run(
+        &self,
+        gpu: &GPUInterface,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+    ) -> Option<usize> {
+        let mut current = None;
+        for entry in self.passes.iter().filter(|e| e.enabled) {
+            let next = current.map_or(0, |i| 1 - i);
+            let source = current.map_or(input, |i| &self.targets[i]);
+            entry.pass.encode(gpu, encoder, source, &self.targets[next]);
+            current = Some(next);
+        }
+        current
+    }
+
+    /// The two textures passes render into, replaced on `resize`.
+    pub fn targets(&self) -> &[wgpu::TextureView; 2] {
+        &self.targets
+    }
+}
+
+fn create_target(gpu: &GPUInterface, size: UVec2, label: &str) -> wgpu::TextureView {
+    create_texture(gpu, size, label).create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn create_texture(gpu: &GPUInterface, size: UVec2, label: &str) -> wgpu::Texture {
+    gpu.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size.x.max(1),
+            height: size.y.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: POST_TEXTURE_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    })
+}
+
+/// Creates a pipeline drawing a single fullscreen triangle with the given fragment entry point.
+/// The vertex shader must be named `vs_fullscreen` and needs no vertex buffers.
+pub fn create_fullscreen_pipeline(
+    gpu: &GPUInterface,
+    label: &str,
+    shader: &wgpu::ShaderModule,
+    fragment_entry: &str,
+    layout: &wgpu::BindGroupLayout,
+) -> wgpu::RenderPipeline {
+    let pipeline_layout = gpu
+        .device
+        .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts: &[layout],
+            push_constant_ranges: &[],
+        });
+    gpu.device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: fragment_entry,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: POST_TEXTURE_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+}
+
+/// Records a render pass drawing one fullscreen triangle into `target`.
+pub fn draw_fullscreen(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    target: &wgpu::TextureView,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    });
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct BloomParams {
+    direction: [f32; 2],
+    threshold: f32,
+    strength: f32,
+}
+
+/// Bloom: bright-pass and separable Gaussian blur at half resolution, added back onto the input.
+pub struct Bloom {
+    pub threshold: f32,
+    pub strength: f32,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    bright_pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    // Params for the bright pass and composite, then the horizontal and vertical blur.
+    params_buffer: wgpu::Buffer,
+    blur_h_buffer: wgpu::Buffer,
+    blur_v_buffer: wgpu::Buffer,
+    // Half resolution scratch textures: bright pass output / vertical blur output, and horizontal blur output.
+    half_a: wgpu::TextureView,
+    half_b: wgpu::TextureView,
+}
+
+impl Bloom {
+    pub const NAME: &'static str = "bloom";
+
+    pub fn new(gpu: &GPUInterface) -> Bloom {
+        let shader = gpu
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Bloom shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("shaders/bloom.wgsl").into()),
+            });
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let layout = gpu
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Bloom bind group layout"),
+                entries: &[
+                    texture_entry(0),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    texture_entry(3),
+                ],
+            });
+        let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Bloom sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform = |label| {
+            gpu.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents: bytemuck::bytes_of(&BloomParams::zeroed()),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                })
+        };
+
+        let bloom = Bloom {
+            threshold: 0.6,
+            strength: 0.8,
+            bright_pipeline: create_fullscreen_pipeline(
+                gpu,
+                "Bloom bright pass",
+                &shader,
+                "fs_bright",
+                &layout,
+            ),
+            blur_pipeline: create_fullscreen_pipeline(
+                gpu,
+                "Bloom blur",
+                &shader,
+                "fs_blur",
+                &layout,
+            ),
+            composite_pipeline: create_fullscreen_pipeline(
+                gpu,
+                "Bloom composite",
+                &shader,
+                "fs_composite",
+                &layout,
+            ),
+            layout,
+            sampler,
+            params_buffer: uniform("Bloom params"),
+            blur_h_buffer: uniform("Bloom horizontal blur params"),
+            blur_v_buffer: uniform("Bloom vertical blur params"),
+            half_a: create_target(gpu, UVec2::new(1, 1), "Bloom scratch A"),
+            half_b: create_target(gpu, UVec2::new(1, 1), "Bloom scratch B"),
+        };
+        bloom.write_params(gpu);
+        bloom
+    }
+
+    /// Changes the luminance above which pixels start to glow, clamped to [0, 1].
+    pub fn adjust_threshold(&mut self, gpu: &GPUInterface, delta: f32) {
+        self.threshold = (self.threshold + delta).clamp(0.0, 1.0);
+        self.write_params(gpu);
+    }
+
+    /// Changes how much of the blurred glow is added back, clamped to [0, 4].
+    pub fn adjust_strength(&mut self, gpu: &GPUInterface, delta: f32) {
+        self.strength = (self.strength + delta).clamp(0.0, 4.0);
+        self.write_params(gpu);
+    }
+
+    fn write_params(&self, gpu: &GPUInterface) {
+        gpu.queue.write_buffer(
+            &self.params_buffer,
+            0,
+            bytemuck::bytes_of(&BloomParams {
+                direction: [0.0, 0.0],
+                threshold: self.threshold,
+                strength: self.strength,
+            }),
+        );
+    }
+
+    fn bind_group(
+        &self,
+        gpu: &GPUInterface,
+        input: &wgpu::TextureView,
+        params: &wgpu::Buffer,
+        bloom: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bloom bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(bloom),
+                },
+            ],
+        })
+    }
+}
+
+impl PostPass for Bloom {
+    fn name(&self) -> &'static str {
+        Bloom::NAME
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn resize(&mut self, gpu: &GPUInterface, size: UVec2) {
+        let half = UVec2::new((size.x / 2).max(1), (size.y / 2).max(1));
+        self.half_a = create_target(gpu, half, "Bloom scratch A");
+        self.half_b = create_target(gpu, half, "Bloom scratch B");
+        let texel = [1.0 / half.x as f32, 1.0 / half.y as f32];
+        for (buffer, direction) in [
+            (&self.blur_h_buffer, [texel[0], 0.0]),
+            (&self.blur_v_buffer, [0.0, texel[1]]),
+        ] {
+            gpu.queue.write_buffer(
+                buffer,
+                0,
+                bytemuck::bytes_of(&BloomParams {
+                    direction,
+                    threshold: self.threshold,
+                    strength: self.strength,
+                }),
+            );
+        }
+        self.write_params(gpu);
+    }
+
+    fn encode(
+        &self,
+        gpu: &GPUInterface,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+    ) {
+        // The bloom binding is unused until the composite, so any texture other than the target will do.
+        let bright = self.bind_group(gpu, input, &self.params_buffer, &self.half_b);
+        draw_fullscreen(
+            encoder,
+            "Bloom bright pass",
+            &self.bright_pipeline,
+            &bright,
+            &self.half_a,
+        );
+        let blur_h = self.bind_group(gpu, &self.half_a, &self.blur_h_buffer, &self.half_a);
+        draw_fullscreen(
+            encoder,
+            "Bloom horizontal blur",
+            &self.blur_pipeline,
+            &blur_h,
+            &self.half_b,
+        );
+        let blur_v = self.bind_group(gpu, &self.half_b, &self.blur_v_buffer, &self.half_b);
+        draw_fullscreen(
+            encoder,
+            "Bloom vertical blur",
+            &self.blur_pipeline,
+            &blur_v,
+            &self.half_a,
+        );
+        let composite = self.bind_group(gpu, input, &self.params_buffer, &self.half_a);
+        draw_fullscreen(
+            encoder,
+            "Bloom composite",
+            &self.composite_pipeline,
+            &composite,
+            output,
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use crate::{
+    computer::{MandelbrotParams, ITERATION_TEXEL_SIZE},
+    gpu_interface::GPUInterface,
+    math::{DVec2, UVec2},
+    readback::{Readback, ReadbackId},
+};
+
+/// Raw values the compute shader stored for one pixel of the iteration texture.
+#[derive(Copy, Clone, Debug)]
+pub struct PixelSample {
+    pub smooth_iterations: f32,
+    pub z_abs: f32,
+    pub escaped: bool,
+}
+
+/// An in-flight readback of a single pixel of the iteration texture, copied through a
+/// [`Readback`] ring; call `poll` once per frame.
+pub struct PixelProbe {
+    pub pixel: UVec2,
+    pub point: DVec2,
+    /// Params the probed frame was computed with.
+    pub params: MandelbrotParams,
+    id: ReadbackId,
+}
+
+impl PixelProbe {
+    /// Submits the copy, or returns None when every buffer of `readback` is still in flight.
+    pub fn new(
+        gpu: &GPUInterface,
+        readback: &mut Readback,
+        texture: &wgpu::Texture,
+        pixel: UVec2,
+        point: DVec2,
+        params: MandelbrotParams,
+    ) -> Option<PixelProbe> {
+        let id = readback.request(gpu, texture, pixel, UVec2::new(1, 1), ITERATION_TEXEL_SIZE)?;
+        Some(PixelProbe {
+            pixel,
+            point,
+            params,
+            id,
+        })
+    }
+
+    /// Returns Some once the readback has completed, without blocking the frame.
+    pub fn poll(
+        &self,
+        gpu: &GPUInterface,
+        readback: &mut Readback,
+    ) -> Option<Result<PixelSample, wgpu::BufferAsyncError>> {
+        Some(readback.take(gpu, self.id)?.map(|bytes| {
+            let texel: &[f32] = bytemuck::cast_slice(&bytes[..ITERATION_TEXEL_SIZE]);
+            PixelSample {
+                smooth_iterations: texel[0],
+                z_abs: texel[1],
+                escaped: texel[2] > 0.5,
+            }
+        }))
+    }
+}
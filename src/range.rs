@@ -0,0 +1,178 @@
+use std::sync::mpsc::channel;
+
+use crate::gpu_interface::GPUInterface;
+
+/// Bytes of the `IterationRange` struct in `range.wgsl`.
+const RANGE_SIZE: u64 = 8;
+
+/// Workgroups of the first pass, one partial range each. Must match `WORKGROUPS` in
+/// `range.wgsl`.
+const WORKGROUPS: u32 = 256;
+
+/// Range of smooth iteration counts of the escaped pixels, for `ColorParams::auto_range`.
+/// A shared-memory reduction folds an iteration texture into partial ranges and a second pass
+/// folds the partials, all on the GPU; `colorize::Colorizer` reads the result straight from
+/// `range_buffer`, so nothing waits on a readback.
+pub struct RangeReduction {
+    workgroups_pipeline: wgpu::ComputePipeline,
+    partials_pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    partials_buffer: wgpu::Buffer,
+    range_buffer: wgpu::Buffer,
+}
+
+impl RangeReduction {
+    pub fn new(gpu: &GPUInterface) -> RangeReduction {
+        let shader = gpu
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Range shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("shaders/range.wgsl").into()),
+            });
+
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Range bind group layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            },
+                            count: None,
+                        },
+                        storage_entry(1),
+                        storage_entry(2),
+                    ],
+                });
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Range pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let create_pipeline = |entry_point| {
+            gpu.device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Range pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+        };
+
+        let partials_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Range partials buffer"),
+            size: WORKGROUPS as u64 * RANGE_SIZE,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        // Zeroed until the first build; colorize falls back to density for a range without span.
+        let range_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Range result buffer"),
+            size: RANGE_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        RangeReduction {
+            workgroups_pipeline: create_pipeline("reduce_workgroups"),
+            partials_pipeline: create_pipeline("reduce_partials"),
+            bind_group_layout,
+            partials_buffer,
+            range_buffer,
+        }
+    }
+
+    /// The range found by the last `build`, bound by `colorize::Colorizer`.
+    pub fn range_buffer(&self) -> &wgpu::Buffer {
+        &self.range_buffer
+    }
+
+    /// Reduces `iteration_view`, an iteration texture of any size.
+    pub fn build(&self, gpu: &GPUInterface, iteration_view: &wgpu::TextureView) {
+        // Like the histogram, builds follow computes rather than frames.
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Range bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(iteration_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.partials_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.range_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Range encoder"),
+            });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Range pass"),
+            });
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.set_pipeline(&self.workgroups_pipeline);
+            compute_pass.dispatch_workgroups(WORKGROUPS, 1, 1);
+            compute_pass.set_pipeline(&self.partials_pipeline);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+        }
+        gpu.queue.submit(Some(encoder.finish()));
+    }
+
+    /// Reads the range back, blocking until the GPU is done. None if no pixel escaped.
+    pub fn read_range(
+        &self,
+        gpu: &GPUInterface,
+    ) -> Result<Option<(f32, f32)>, wgpu::BufferAsyncError> {
+        let staging_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Range staging buffer"),
+            size: RANGE_SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Range readback encoder"),
+            });
+        encoder.copy_buffer_to_buffer(&self.range_buffer, 0, &staging_buffer, 0, RANGE_SIZE);
+        gpu.queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = channel();
+        staging_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        gpu.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().unwrap_or(Err(wgpu::BufferAsyncError))?;
+        let data = staging_buffer.slice(..).get_mapped_range();
+        let values: &[f32] = bytemuck::cast_slice(&data);
+        Ok((values[0] <= values[1]).then_some((values[0], values[1])))
+    }
+}
@@ -0,0 +1,249 @@
+//! Texture readbacks that never block the frame.
+//!
+//! A [`Readback`] records `copy_texture_to_buffer` into an encoder, maps the buffer once the
+//! encoder is submitted and hands the bytes over on a later frame. Buffers are recycled
+//! through a small ring, so a readback every frame neither allocates nor waits: when all of
+//! them are still in flight, the request is refused instead.
+
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+use crate::{computer::padded_bytes_per_row, gpu_interface::GPUInterface, math::UVec2};
+
+/// Identifies one readback of a [`Readback`] ring.
+pub type ReadbackId = u64;
+
+type MapResult = (ReadbackId, Result<(), wgpu::BufferAsyncError>);
+
+/// A copy that was recorded but hasn't been handed back yet.
+struct InFlight {
+    id: ReadbackId,
+    buffer: wgpu::Buffer,
+    buffer_size: u64,
+    size: UVec2,
+    bytes_per_pixel: usize,
+    /// Whether `map_async` was called, which has to wait until the copy is submitted.
+    mapping: bool,
+}
+
+/// A ring of at most `capacity` mappable buffers, shared by readbacks of any size.
+pub struct Readback {
+    label: &'static str,
+    capacity: usize,
+    /// Unmapped buffers ready for reuse, with their sizes.
+    free: Vec<(wgpu::Buffer, u64)>,
+    in_flight: Vec<InFlight>,
+    /// Finished readbacks, with the row padding stripped, waiting for `take`.
+    done: VecDeque<(ReadbackId, Result<Vec<u8>, wgpu::BufferAsyncError>)>,
+    sender: Sender<MapResult>,
+    receiver: Receiver<MapResult>,
+    next_id: ReadbackId,
+}
+
+impl Readback {
+    pub fn new(label: &'static str, capacity: usize) -> Readback {
+        assert!(capacity > 0, "a readback ring needs at least one buffer");
+        let (sender, receiver) = channel();
+        Readback {
+            label,
+            capacity,
+            free: Vec::new(),
+            in_flight: Vec::new(),
+            done: VecDeque::new(),
+            sender,
+            receiver,
+            next_id: 0,
+        }
+    }
+
+    /// Records a copy of the `size` texels of `texture` at `origin` into `encoder`. Returns
+    /// None when every buffer of the ring is still in flight. Call `submitted` once the
+    /// encoder is submitted.
+    pub fn copy(
+        &mut self,
+        gpu: &GPUInterface,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        origin: UVec2,
+        size: UVec2,
+        bytes_per_pixel: usize,
+    ) -> Option<ReadbackId> {
+        let bytes_per_row = padded_bytes_per_row(size.x, bytes_per_pixel);
+        let needed = bytes_per_row as u64 * size.y as u64;
+        let (buffer, buffer_size) = self.buffer(gpu, needed)?;
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: origin.x,
+                    y: origin.y,
+                    z: 0,
+                },
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(bytes_per_row as u32),
+                    rows_per_image: std::num::NonZeroU32::new(size.y),
+                },
+            },
+            wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.in_flight.push(InFlight {
+            id,
+            buffer,
+            buffer_size,
+            size,
+            bytes_per_pixel,
+            mapping: false,
+        });
+        Some(id)
+    }
+
+    /// Starts mapping the copies recorded since the last call. Their encoder must have been
+    /// submitted, as a buffer can't be used by the GPU once mapping started.
+    pub fn submitted(&mut self) {
+        for in_flight in self.in_flight.iter_mut().filter(|f| !f.mapping) {
+            in_flight.mapping = true;
+            let sender = self.sender.clone();
+            let id = in_flight.id;
+            in_flight
+                .buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    // The receiver lives as long as the buffer, so this only fails on teardown.
+                    let _ = sender.send((id, result));
+                });
+        }
+    }
+
+    /// Copies in an encoder of its own and submits it right away, for readbacks that aren't
+    /// tied to a frame.
+    pub fn request(
+        &mut self,
+        gpu: &GPUInterface,
+        texture: &wgpu::Texture,
+        origin: UVec2,
+        size: UVec2,
+        bytes_per_pixel: usize,
+    ) -> Option<ReadbackId> {
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some(self.label),
+            });
+        let id = self.copy(gpu, &mut encoder, texture, origin, size, bytes_per_pixel)?;
+        gpu.queue.submit(Some(encoder.finish()));
+        self.submitted();
+        Some(id)
+    }
+
+    /// Returns the rows of readback `id`, tightly packed, once it has finished. Never blocks;
+    /// returns None while the copy is in flight.
+    pub fn take(
+        &mut self,
+        gpu: &GPUInterface,
+        id: ReadbackId,
+    ) -> Option<Result<Vec<u8>, wgpu::BufferAsyncError>> {
+        self.poll(gpu);
+        if let Some(index) = self.done.iter().position(|(done, _)| *done == id) {
+            return self.done.remove(index).map(|(_, result)| result);
+        }
+        if self.in_flight.iter().any(|f| f.id == id) {
+            None
+        } else {
+            // Never requested, or dropped because nobody took it in time.
+            Some(Err(wgpu::BufferAsyncError))
+        }
+    }
+
+    /// Waits for readback `id`, for callers that need the result right away.
+    pub fn wait(
+        &mut self,
+        gpu: &GPUInterface,
+        id: ReadbackId,
+    ) -> Result<Vec<u8>, wgpu::BufferAsyncError> {
+        gpu.device.poll(wgpu::Maintain::Wait);
+        self.take(gpu, id).unwrap_or(Err(wgpu::BufferAsyncError))
+    }
+
+    /// Collects the mappings that finished, returning their buffers to the ring.
+    fn poll(&mut self, gpu: &GPUInterface) {
+        gpu.device.poll(wgpu::Maintain::Poll);
+        while let Ok((id, result)) = self.receiver.try_recv() {
+            let Some(index) = self.in_flight.iter().position(|f| f.id == id) else {
+                continue;
+            };
+            let in_flight = self.in_flight.swap_remove(index);
+            let result = result.map(|()| {
+                let bytes = unpad(&in_flight);
+                in_flight.buffer.unmap();
+                bytes
+            });
+            if result.is_ok() {
+                self.free.push((in_flight.buffer, in_flight.buffer_size));
+            }
+            self.done.push_back((id, result));
+            // Results nobody asked for, e.g. of a replaced probe, only keep the latest few.
+            while self.done.len() > self.capacity {
+                self.done.pop_front();
+            }
+        }
+    }
+
+    /// A free buffer of at least `needed` bytes, allocating one while the ring isn't full.
+    fn buffer(&mut self, gpu: &GPUInterface, needed: u64) -> Option<(wgpu::Buffer, u64)> {
+        if let Some(index) = self.free.iter().position(|(_, size)| *size >= needed) {
+            return Some(self.free.swap_remove(index));
+        }
+        if self.buffer_count() == self.capacity {
+            // Only buffers too small for this copy are free, so replace one of them.
+            self.free.pop()?;
+        }
+        let buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(self.label),
+            size: needed,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        Some((buffer, needed))
+    }
+
+    /// Number of copies that haven't finished yet.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Number of buffers the ring holds, which never exceeds its capacity.
+    pub fn buffer_count(&self) -> usize {
+        self.free.len() + self.in_flight.len()
+    }
+}
+
+/// Copies the rows of a mapped readback without the padding wgpu requires between them.
+fn unpad(in_flight: &InFlight) -> Vec<u8> {
+    let padded_bytes_per_row = padded_bytes_per_row(in_flight.size.x, in_flight.bytes_per_pixel);
+    let bytes_per_row = in_flight.size.x as usize * in_flight.bytes_per_pixel;
+    let data = in_flight.buffer.slice(..).get_mapped_range();
+    let mut bytes = Vec::with_capacity(bytes_per_row * in_flight.size.y as usize);
+    for row in data
+        .chunks_exact(padded_bytes_per_row)
+        .take(in_flight.size.y as usize)
+    {
+        bytes.extend_from_slice(&row[..bytes_per_row]);
+    }
+    bytes
+}
@@ -0,0 +1,61 @@
+use dashu_float::FBig;
+
+use crate::math::DVec2;
+
+/// Mantissa bits to carry for a view of `zoom`: enough to resolve a pixel at that zoom with
+/// a generous guard for the error that accumulates over many iterations.
+pub fn precision_bits(zoom: f32) -> usize {
+    let zoom_bits = (1.0 / zoom.max(f32::MIN_POSITIVE)).log2().max(0.0) as usize;
+    64 + zoom_bits
+}
+
+fn big(x: f64, bits: usize) -> FBig {
+    FBig::try_from(x)
+        .expect("view coordinates are finite")
+        .with_precision(bits)
+        .value()
+}
+
+/// Sum of a double-double value as a bigfloat.
+fn big_sum((hi, lo): (f64, f64), bits: usize) -> FBig {
+    (big(hi, bits) + big(lo, bits)).with_precision(bits).value()
+}
+
+/// Iterates z = conj?(z)^2 + c at `bits` of precision and returns every z rounded to f32,
+/// starting with the initial value. Stops after `max_iterations` or once z escapes
+/// `escape_radius`, so a short orbit means the reference point itself escaped.
+///
+/// For the Mandelbrot set `z0` is 0 and `c` is the view center; for Julia sets `z0` is the
+/// center and `c` is the constant.
+pub fn reference_orbit(
+    z0: (DVec2, DVec2),
+    c: (DVec2, DVec2),
+    conjugate: bool,
+    max_iterations: i32,
+    escape_radius: f32,
+    bits: usize,
+) -> Vec<[f32; 2]> {
+    let round = |x: FBig| x.with_precision(bits).value();
+    let mut x = big_sum((z0.0.x, z0.1.x), bits);
+    let mut y = big_sum((z0.0.y, z0.1.y), bits);
+    let cx = big_sum((c.0.x, c.1.x), bits);
+    let cy = big_sum((c.0.y, c.1.y), bits);
+    let two = big(2.0, bits);
+    let radius_squared = (escape_radius as f64).powi(2);
+
+    let mut orbit = Vec::with_capacity(max_iterations.max(0) as usize + 1);
+    for _ in 0..=max_iterations {
+        let point = [x.to_f64().value(), y.to_f64().value()];
+        orbit.push([point[0] as f32, point[1] as f32]);
+        if point[0] * point[0] + point[1] * point[1] > radius_squared {
+            break;
+        }
+        if conjugate {
+            y = -y;
+        }
+        let x_next = round(&x * &x - &y * &y + &cx);
+        y = round(&two * &x * &y + &cy);
+        x = x_next;
+    }
+    orbit
+}
@@ -1,61 +1,138 @@
-use std::iter;
+use crate::{
+    accumulate::Accumulator,
+    colorize::{ColorParams, Colorizer, OutputReadback},
+    computer::{Computer, PREVIEW_SIZE},
+    math::{letterbox, FVec2, UVec2},
+    overlay::OverlayBatch,
+    palette::Palette,
+    postprocess::{Bloom, PostChain},
+    readback::Readback,
+    text::TextRenderer,
+};
 
-use crate::math::UVec2;
+#[cfg(feature = "hot-reload")]
+use crate::hot_reload;
 
 use super::{
     gpu_interface::GPUInterface,
-    math::{IVec2, Vertex},
+    math::{OverlayVertex, Vertex},
 };
 use bytemuck::{Pod, Zeroable};
-use wgpu::{util::DeviceExt, Buffer, SurfaceTexture};
+use wgpu::{util::DeviceExt, Buffer};
 
 // main.rs
 
-use winit::{
-    event::{Event, WindowEvent},
-    window::Window,
-};
+use winit::window::Window;
+
+/// Color vision deficiency simulated on the final image, for previewing palettes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CvdSimulation {
+    Off = 0,
+    Protanopia = 1,
+    Deuteranopia = 2,
+}
+
+impl CvdSimulation {
+    pub fn next(self) -> CvdSimulation {
+        match self {
+            CvdSimulation::Off => CvdSimulation::Protanopia,
+            CvdSimulation::Protanopia => CvdSimulation::Deuteranopia,
+            CvdSimulation::Deuteranopia => CvdSimulation::Off,
+        }
+    }
+}
+
+/// Which image `Renderer::render` shows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrameSource {
+    /// The iteration texture.
+    Full,
+    /// The computer's lower resolution draft texture.
+    Draft,
+    /// The iteration texture blended into the accumulated average as this frame index, then
+    /// the average.
+    Accumulate(u32),
+    /// The accumulated average as it is.
+    Accumulated,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct DisplayParams {
+    pub cvd_simulation: u32,
+    pub _padding: [u32; 3],
+}
 
 pub struct Renderer {
     pub render_pipeline: wgpu::RenderPipeline,
+    /// Kept to rebuild `render_pipeline` when render.wgsl changes on disk.
+    #[cfg(feature = "hot-reload")]
+    render_pipeline_layout: wgpu::PipelineLayout,
     pub texture_bind_group_layout: wgpu::BindGroupLayout,
+    /// Quad the fractal image is drawn on, letterboxed when its aspect ratio doesn't match the
+    /// surface. Rebuilt on resize.
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub num_indices: u32,
     pub sampler: wgpu::Sampler,
+    /// Size of the surface.
     pub size: UVec2,
+    /// Size of the colorized image, the computer's output size, which is letterboxed into
+    /// the surface.
+    pub image_size: UVec2,
+    pub overlay_line_pipeline: wgpu::RenderPipeline,
+    pub overlay_triangle_pipeline: wgpu::RenderPipeline,
+    pub overlay: OverlayBatch,
+    pub cvd_simulation: CvdSimulation,
+    /// Fills the surface before anything is drawn, showing around a letterboxed image.
+    pub clear_color: wgpu::Color,
+    display_params_buffer: wgpu::Buffer,
+    /// Maps the iteration texture to colors at the image size.
+    colorizer: Colorizer,
+    /// `Computer::front` of the iteration texture `colorizer` is bound to.
+    iteration_front: usize,
+    /// Colors the computer's draft texture, drawn stretched over the window.
+    draft_colorizer: Colorizer,
+    /// Averages jittered frames of `colorizer` for `FrameSource::Accumulate`.
+    accumulator: Accumulator,
+    /// Buffers screenshots of `colorizer` are copied into.
+    screenshots: Readback,
+    preview_colorizer: Colorizer,
+    /// Quad of the Julia preview inset, rebuilt when the window is resized.
+    preview_vertex_buffer: wgpu::Buffer,
+    /// Effects applied to the fractal image before it is drawn to the surface.
+    pub post_chain: PostChain,
+    pub text: TextRenderer,
+    overlay_bind_group_layout: wgpu::BindGroupLayout,
+    glyph_sampler: wgpu::Sampler,
+    /// Binds the image drawn to the surface: the colorized fractal, the colorized draft, the
+    /// accumulated average, then each post chain target. Rebuilt on resize, which replaces
+    /// the targets.
+    display_bind_groups: [wgpu::BindGroup; 5],
+    preview_bind_group: wgpu::BindGroup,
+    /// Binds the glyph atlas; None until the first upload and whenever the atlas grows.
+    overlay_bind_group: Option<wgpu::BindGroup>,
 }
 
-const VERTICES: &[Vertex] = &[
-    Vertex {
-        position: [-1.0, 1.0, 0.0],
-        tex_coords: [0.0, 0.0],
-    }, // A
-    Vertex {
-        position: [-1.0, -1.0, 0.0],
-        tex_coords: [0.0, 1.0],
-    }, // B
-    Vertex {
-        position: [1.0, -1.0, 0.0],
-        tex_coords: [1.0, 1.0],
-    }, // C
-    Vertex {
-        position: [1.0, 1.0, 0.0],
-        tex_coords: [1.0, 0.0],
-    }, // D
-];
-
 const INDICES: &[u16] = &[0, 1, 2, 2, 3, 0];
 
+/// Gap in pixels between the Julia preview inset and the window corner.
+const PREVIEW_MARGIN: f32 = 16.0;
+
+/// Screenshots that can be in flight at once; further requests wait for a free buffer.
+const SCREENSHOT_BUFFERS: usize = 2;
+
 impl Renderer {
-    // Creating some of the wgpu types requires async code
-    pub fn new(gpu: &GPUInterface, size: UVec2, window: &Window) -> Self {
+    /// Draws `computer`'s iteration and preview textures, whose views are bound once here.
+    pub fn new(gpu: &GPUInterface, size: UVec2, window: &Window, computer: &Computer) -> Self {
+        let image_size = computer.output_size();
         let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::Repeat,
             address_mode_v: wgpu::AddressMode::Repeat,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
             mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
+            // Smooths an image computed larger than the surface, see `set_image_size`.
+            min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Nearest,
             ..Default::default()
         });
@@ -89,6 +166,16 @@ impl Renderer {
                             ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                             count: None,
                         },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
                     ],
                     label: Some("Renderer_texture_bind_group_layout"),
                 });
@@ -102,96 +189,326 @@ impl Renderer {
                     push_constant_ranges: &[],
                 });
 
-        let render_pipeline = gpu
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("Render Pipeline"),
-                layout: Some(&render_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    entry_point: "vs_main",     // 1.
-                    buffers: &[Vertex::desc()], // 2.
-                },
-                fragment: Some(wgpu::FragmentState {
-                    // 3.
-                    module: &shader,
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        // 4.
-                        format: gpu.config.format,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Ccw, // 2.
-                    cull_mode: Some(wgpu::Face::Back),
-                    // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    // Requires Features::DEPTH_CLIP_CONTROL
-                    unclipped_depth: false,
-                    // Requires Features::CONSERVATIVE_RASTERIZATION
-                    conservative: false,
-                },
-                depth_stencil: None, // 1.
-                multisample: wgpu::MultisampleState {
-                    count: 1,                         // 2.
-                    mask: !0,                         // 3.
-                    alpha_to_coverage_enabled: false, // 4.
-                },
-                multiview: None, // 5.
-            });
+        let render_pipeline = create_render_pipeline(gpu, &shader, &render_pipeline_layout);
 
         let vertex_buffer = gpu
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(&VERTICES),
-                usage: wgpu::BufferUsages::VERTEX,
+                contents: bytemuck::cast_slice(&image_vertices(image_size, size)),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
             });
 
         let index_buffer: Buffer =
             gpu.device
                 .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                     label: Some("Index buffer"),
-                    contents: bytemuck::cast_slice(&INDICES),
+                    contents: bytemuck::cast_slice(INDICES),
                     usage: wgpu::BufferUsages::INDEX,
                 });
         let num_indices = INDICES.len() as u32;
 
+        let preview_vertex_buffer =
+            gpu.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Preview vertex buffer"),
+                    contents: bytemuck::cast_slice(&preview_vertices(size)),
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                });
+
+        let display_params_buffer =
+            gpu.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Display params buffer"),
+                    contents: bytemuck::bytes_of(&DisplayParams {
+                        cvd_simulation: CvdSimulation::Off as u32,
+                        _padding: [0; 3],
+                    }),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
+
+        let overlay_shader = gpu
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Overlay shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("shaders/overlay.wgsl").into()),
+            });
+        let overlay_bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                            count: None,
+                        },
+                    ],
+                    label: Some("Overlay bind group layout"),
+                });
+        let overlay_pipeline_layout =
+            gpu.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Overlay Pipeline Layout"),
+                    bind_group_layouts: &[&overlay_bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+        let overlay_line_pipeline = create_overlay_pipeline(
+            gpu,
+            &overlay_shader,
+            &overlay_pipeline_layout,
+            wgpu::PrimitiveTopology::LineList,
+        );
+        let overlay_triangle_pipeline = create_overlay_pipeline(
+            gpu,
+            &overlay_shader,
+            &overlay_pipeline_layout,
+            wgpu::PrimitiveTopology::TriangleList,
+        );
+
+        // Glyph quads are pixel aligned, so this only matters for fractional DPI offsets.
+        let glyph_sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Glyph sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let mut post_chain = PostChain::new(gpu, image_size);
+        post_chain.push(gpu, Box::new(Bloom::new(gpu)), false);
+
+        let histogram = computer.histogram.cdf_buffer();
+        let range = computer.range.range_buffer();
+        let colorizer = Colorizer::new(gpu, image_size, &computer.iteration_view, histogram, range);
+        let draft_colorizer = Colorizer::new(
+            gpu,
+            UVec2::new(computer.draft_size.width, computer.draft_size.height),
+            &computer.draft_view,
+            histogram,
+            range,
+        );
+        let accumulator = Accumulator::new(gpu, image_size, colorizer.output_view());
+        // The preview shares the histogram and range of the main view.
+        let preview_colorizer = Colorizer::new(
+            gpu,
+            UVec2::new(PREVIEW_SIZE, PREVIEW_SIZE),
+            &computer.preview_view,
+            histogram,
+            range,
+        );
+        let display_bind_groups = create_display_bind_groups(
+            gpu,
+            &texture_bind_group_layout,
+            &sampler,
+            &display_params_buffer,
+            [
+                colorizer.output_view(),
+                draft_colorizer.output_view(),
+                accumulator.output_view(),
+            ],
+            &post_chain,
+        );
+        let preview_bind_group = create_texture_bind_group(
+            gpu,
+            &texture_bind_group_layout,
+            preview_colorizer.output_view(),
+            &sampler,
+            &display_params_buffer,
+        );
+
         Self {
             render_pipeline,
+            #[cfg(feature = "hot-reload")]
+            render_pipeline_layout,
             texture_bind_group_layout,
             vertex_buffer,
             index_buffer,
             num_indices,
             sampler,
             size,
+            image_size,
+            overlay_line_pipeline,
+            overlay_triangle_pipeline,
+            overlay: OverlayBatch::new(size),
+            cvd_simulation: CvdSimulation::Off,
+            clear_color: wgpu::Color::BLACK,
+            display_params_buffer,
+            colorizer,
+            draft_colorizer,
+            accumulator,
+            iteration_front: computer.front(),
+            screenshots: Readback::new("Screenshot readback buffer", SCREENSHOT_BUFFERS),
+            preview_colorizer,
+            preview_vertex_buffer,
+            post_chain,
+            text: TextRenderer::new(window.scale_factor() as f32),
+            overlay_bind_group_layout,
+            glyph_sampler,
+            display_bind_groups,
+            preview_bind_group,
+            overlay_bind_group: None,
+        }
+    }
+
+    pub fn set_cvd_simulation(&mut self, gpu: &GPUInterface, cvd_simulation: CvdSimulation) {
+        self.cvd_simulation = cvd_simulation;
+        gpu.queue.write_buffer(
+            &self.display_params_buffer,
+            0,
+            bytemuck::bytes_of(&DisplayParams {
+                cvd_simulation: cvd_simulation as u32,
+                _padding: [0; 3],
+            }),
+        );
+    }
+
+    /// Returns Some once `screenshot` has been read back, without blocking the frame.
+    pub fn poll_screenshot(
+        &mut self,
+        gpu: &GPUInterface,
+        screenshot: &OutputReadback,
+    ) -> Option<Result<image::RgbaImage, wgpu::BufferAsyncError>> {
+        screenshot.poll(gpu, &mut self.screenshots)
+    }
+
+    /// Colors `computer`'s iteration texture from now on, after it was replaced.
+    pub fn set_iteration_view(&mut self, gpu: &GPUInterface, computer: &Computer) {
+        self.colorizer.set_input(gpu, &computer.iteration_view);
+        self.iteration_front = computer.front();
+    }
+
+    /// Rebinds the colorizer if a double buffered `computer` made its other iteration texture
+    /// current since the last frame.
+    pub fn sync_iteration_view(&mut self, gpu: &GPUInterface, computer: &Computer) {
+        if computer.front() != self.iteration_front {
+            self.set_iteration_view(gpu, computer);
+        }
+    }
+
+    /// Switches the palette used to colorize subsequent frames.
+    pub fn set_palette(&self, gpu: &GPUInterface, palette: &Palette) {
+        self.colorizer.set_palette(gpu, palette);
+        self.draft_colorizer.set_palette(gpu, palette);
+        self.preview_colorizer.set_palette(gpu, palette);
+    }
+
+    /// Top-left and bottom-right corners in pixels of the Julia preview inset.
+    pub fn preview_rect(&self) -> (FVec2, FVec2) {
+        preview_rect(self.size)
+    }
+
+    /// Rebuilds `render_pipeline` from render.wgsl on disk, keeping the old one if the
+    /// shader fails to load or validate.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shaders(&mut self, gpu: &GPUInterface) {
+        let result = hot_reload::read_shader("render.wgsl").and_then(|source| {
+            gpu.validated(|| {
+                let shader = gpu
+                    .device
+                    .create_shader_module(wgpu::ShaderModuleDescriptor {
+                        label: Some("Shader"),
+                        source: wgpu::ShaderSource::Wgsl(source.into()),
+                    });
+                create_render_pipeline(gpu, &shader, &self.render_pipeline_layout)
+            })
+        });
+        match result {
+            Ok(pipeline) => {
+                self.render_pipeline = pipeline;
+                println!("Reloaded render.wgsl");
+            }
+            Err(e) => eprintln!("Could not reload render.wgsl: {:#}", e),
         }
     }
 
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>, gpu: &mut GPUInterface) {
+    /// Resizes the surface and fits the image to it. The image itself is only rebuilt when
+    /// `computer` got a new output size, see `Computer::set_output_size`.
+    pub fn resize(
+        &mut self,
+        new_size: winit::dpi::PhysicalSize<u32>,
+        gpu: &mut GPUInterface,
+        computer: &Computer,
+    ) {
         if new_size.width > 0 && new_size.height > 0 {
             gpu.size = new_size;
             gpu.config.width = new_size.width;
             gpu.config.height = new_size.height;
-            gpu.surface.configure(&gpu.device, &gpu.config);
-            self.size = UVec2::new(new_size.width as u32, new_size.height as u32);
+            if let Some(surface) = &gpu.surface {
+                surface.configure(&gpu.device, &gpu.config);
+            }
+            self.size = UVec2::new(new_size.width, new_size.height);
+            self.overlay.set_screen_size(self.size);
+            self.set_image_size(gpu, computer);
+            gpu.queue.write_buffer(
+                &self.preview_vertex_buffer,
+                0,
+                bytemuck::cast_slice(&preview_vertices(self.size)),
+            );
         }
     }
 
-    pub fn handle_events(&mut self, event: &Event<()>) {
-        //self.gui.handle_events(event);
+    /// Resizes the colorized image and its effects to `computer`'s output size, after
+    /// `Computer::set_output_size`, and letterboxes it into the surface.
+    pub fn set_image_size(&mut self, gpu: &GPUInterface, computer: &Computer) {
+        if computer.output_size() != self.image_size {
+            self.image_size = computer.output_size();
+            self.colorizer
+                .resize(gpu, self.image_size, &computer.iteration_view);
+            self.iteration_front = computer.front();
+            self.draft_colorizer.resize(
+                gpu,
+                UVec2::new(computer.draft_size.width, computer.draft_size.height),
+                &computer.draft_view,
+            );
+            self.accumulator = Accumulator::new(gpu, self.image_size, self.colorizer.output_view());
+            self.post_chain.resize(gpu, self.image_size);
+            self.display_bind_groups = create_display_bind_groups(
+                gpu,
+                &self.texture_bind_group_layout,
+                &self.sampler,
+                &self.display_params_buffer,
+                [
+                    self.colorizer.output_view(),
+                    self.draft_colorizer.output_view(),
+                    self.accumulator.output_view(),
+                ],
+                &self.post_chain,
+            );
+        }
+        gpu.queue.write_buffer(
+            &self.vertex_buffer,
+            0,
+            bytemuck::cast_slice(&image_vertices(self.image_size, self.size)),
+        );
     }
 
+    /// Colors and draws the image chosen by `source`, with the Julia preview inset when
+    /// `show_preview` is set. With `screenshot`, the full-resolution colors, without overlays
+    /// or post-processing, are copied for reading back in the same submission; None is
+    /// returned instead when the previous screenshots are still in flight.
     pub fn render(
         &mut self,
         gpu: &GPUInterface,
-        mandelbrot_texture: &wgpu::Texture,
-    ) -> Result<(), wgpu::SurfaceError> {
-        let output = gpu.surface.get_current_texture().unwrap();
+        color_params: &ColorParams,
+        source: FrameSource,
+        show_preview: bool,
+        screenshot: bool,
+    ) -> Result<Option<OutputReadback>, wgpu::SurfaceError> {
+        let output = gpu
+            .surface
+            .as_ref()
+            .expect("the renderer is only used with a window")
+            .get_current_texture()?;
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
@@ -201,21 +518,65 @@ impl Renderer {
                 label: Some("Render Encoder"),
             });
 
-        let texture_bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &self.texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(
-                        &mandelbrot_texture.create_view(&wgpu::TextureViewDescriptor::default()),
-                    ), // CHANGED!
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&self.sampler), // CHANGED!
-                },
-            ],
-            label: Some("Texture bind group"),
+        let (image, image_index) = match source {
+            FrameSource::Full => {
+                self.colorizer.encode(gpu, &mut encoder, color_params);
+                (self.colorizer.output_view(), 0)
+            }
+            FrameSource::Draft => {
+                self.draft_colorizer.encode(gpu, &mut encoder, color_params);
+                (self.draft_colorizer.output_view(), 1)
+            }
+            FrameSource::Accumulate(frame_index) => {
+                self.colorizer.encode(gpu, &mut encoder, color_params);
+                self.accumulator.encode(gpu, &mut encoder, frame_index);
+                (self.accumulator.output_view(), 2)
+            }
+            FrameSource::Accumulated => (self.accumulator.output_view(), 2),
+        };
+        let screenshot = match (screenshot, source) {
+            (false, _) => None,
+            // The colorizer only holds the last jittered frame; the average is what is shown.
+            (true, FrameSource::Accumulate(_) | FrameSource::Accumulated) => self
+                .accumulator
+                .encode_readback(gpu, &mut encoder, &mut self.screenshots),
+            (true, _) => self
+                .colorizer
+                .encode_readback(gpu, &mut encoder, &mut self.screenshots),
+        };
+        let final_target = self.post_chain.run(gpu, &mut encoder, image);
+        let texture_bind_group =
+            &self.display_bind_groups[final_target.map_or(image_index, |i| i + 3)];
+
+        // The preview is small and drawn as an inset, so it skips the post chain.
+        if show_preview {
+            self.preview_colorizer
+                .encode(gpu, &mut encoder, color_params);
+        }
+
+        self.overlay.upload(gpu);
+        if self.text.upload(gpu) {
+            self.overlay_bind_group = None;
+        }
+        let overlay_bind_group = self.overlay_bind_group.get_or_insert_with(|| {
+            let glyph_view = self
+                .text
+                .atlas_view()
+                .expect("glyph atlas was just uploaded");
+            gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.overlay_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&glyph_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.glyph_sampler),
+                    },
+                ],
+                label: Some("Overlay bind group"),
+            })
         });
 
         {
@@ -228,7 +589,7 @@ impl Renderer {
                         view: &view,
                         resolve_target: None,
                         ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Load,
+                            load: wgpu::LoadOp::Clear(self.clear_color),
                             store: true,
                         },
                     }),
@@ -237,13 +598,217 @@ impl Renderer {
             });
 
             render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &texture_bind_group, &[]);
+            render_pass.set_bind_group(0, texture_bind_group, &[]);
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
             render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+
+            if show_preview {
+                render_pass.set_bind_group(0, &self.preview_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, self.preview_vertex_buffer.slice(..));
+                render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+            }
+
+            // Overlay geometry is drawn on top of the fractal, triangles first so lines stay visible.
+            render_pass.set_bind_group(0, overlay_bind_group, &[]);
+            if let Some((slice, count)) = self.overlay.triangle_buffer.slice() {
+                render_pass.set_pipeline(&self.overlay_triangle_pipeline);
+                render_pass.set_vertex_buffer(0, slice);
+                render_pass.draw(0..count, 0..1);
+            }
+            if let Some((slice, count)) = self.overlay.line_buffer.slice() {
+                render_pass.set_pipeline(&self.overlay_line_pipeline);
+                render_pass.set_vertex_buffer(0, slice);
+                render_pass.draw(0..count, 0..1);
+            }
         }
         gpu.queue.submit([encoder.finish()]);
+        self.screenshots.submitted();
         output.present();
-        Ok(())
+        self.overlay.clear();
+        Ok(screenshot)
     }
 }
+
+fn create_texture_bind_group(
+    gpu: &GPUInterface,
+    layout: &wgpu::BindGroupLayout,
+    view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+    display_params_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: display_params_buffer.as_entire_binding(),
+            },
+        ],
+        label: Some("Texture bind group"),
+    })
+}
+
+/// One bind group per image `render` may draw: the colorized, draft and accumulated images, then
+/// each target of `post_chain`, in the order `PostChain::run`'s result indexes them.
+fn create_display_bind_groups(
+    gpu: &GPUInterface,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    display_params_buffer: &wgpu::Buffer,
+    [colorized, draft, accumulated]: [&wgpu::TextureView; 3],
+    post_chain: &PostChain,
+) -> [wgpu::BindGroup; 5] {
+    let [a, b] = post_chain.targets();
+    [colorized, draft, accumulated, a, b]
+        .map(|view| create_texture_bind_group(gpu, layout, view, sampler, display_params_buffer))
+}
+
+/// The inset sits in the bottom-right corner at one texel per pixel.
+fn preview_rect(size: UVec2) -> (FVec2, FVec2) {
+    let max = FVec2 {
+        x: size.x as f32 - PREVIEW_MARGIN,
+        y: size.y as f32 - PREVIEW_MARGIN,
+    };
+    let min = FVec2 {
+        x: max.x - PREVIEW_SIZE as f32,
+        y: max.y - PREVIEW_SIZE as f32,
+    };
+    (min, max)
+}
+
+/// The quad of a `image_size` image fitted into a `size` surface, see `letterbox`.
+fn image_vertices(image_size: UVec2, size: UVec2) -> [Vertex; 4] {
+    let (min, max) = letterbox(image_size, size);
+    rect_vertices(min, max, size)
+}
+
+fn preview_vertices(size: UVec2) -> [Vertex; 4] {
+    let (min, max) = preview_rect(size);
+    rect_vertices(min, max, size)
+}
+
+/// A textured quad covering the pixel rectangle from `min` to `max` of a `size` surface.
+fn rect_vertices(min: FVec2, max: FVec2, size: UVec2) -> [Vertex; 4] {
+    let left = min.x / size.x as f32 * 2.0 - 1.0;
+    let right = max.x / size.x as f32 * 2.0 - 1.0;
+    let top = 1.0 - min.y / size.y as f32 * 2.0;
+    let bottom = 1.0 - max.y / size.y as f32 * 2.0;
+    [
+        Vertex {
+            position: [left, top, 0.0],
+            tex_coords: [0.0, 0.0],
+        },
+        Vertex {
+            position: [left, bottom, 0.0],
+            tex_coords: [0.0, 1.0],
+        },
+        Vertex {
+            position: [right, bottom, 0.0],
+            tex_coords: [1.0, 1.0],
+        },
+        Vertex {
+            position: [right, top, 0.0],
+            tex_coords: [1.0, 0.0],
+        },
+    ]
+}
+
+fn create_render_pipeline(
+    gpu: &GPUInterface,
+    shader: &wgpu::ShaderModule,
+    layout: &wgpu::PipelineLayout,
+) -> wgpu::RenderPipeline {
+    gpu.device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",     // 1.
+                buffers: &[Vertex::desc()], // 2.
+            },
+            fragment: Some(wgpu::FragmentState {
+                // 3.
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    // 4.
+                    format: gpu.config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw, // 2.
+                cull_mode: Some(wgpu::Face::Back),
+                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+                polygon_mode: wgpu::PolygonMode::Fill,
+                // Requires Features::DEPTH_CLIP_CONTROL
+                unclipped_depth: false,
+                // Requires Features::CONSERVATIVE_RASTERIZATION
+                conservative: false,
+            },
+            depth_stencil: None, // 1.
+            multisample: wgpu::MultisampleState {
+                count: 1,                         // 2.
+                mask: !0,                         // 3.
+                alpha_to_coverage_enabled: false, // 4.
+            },
+            multiview: None, // 5.
+        })
+}
+
+fn create_overlay_pipeline(
+    gpu: &GPUInterface,
+    shader: &wgpu::ShaderModule,
+    layout: &wgpu::PipelineLayout,
+    topology: wgpu::PrimitiveTopology,
+) -> wgpu::RenderPipeline {
+    gpu.device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Overlay Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[OverlayVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: gpu.config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // Overlay geometry is built in pixel space, so don't rely on winding.
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+}
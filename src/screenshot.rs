@@ -0,0 +1,59 @@
+use std::{
+    path::PathBuf,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// File name for a screenshot taken at `time`, e.g. `mandelbrot_2024-05-01_123456.png`.
+/// The time is UTC, since the standard library can't tell the local time zone. A counter is
+/// appended if a file of that name already exists.
+pub fn screenshot_path(time: SystemTime) -> PathBuf {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let time_of_day = secs % 86_400;
+    let stem = format!(
+        "mandelbrot_{:04}-{:02}-{:02}_{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60
+    );
+    let mut path = PathBuf::from(format!("{}.png", stem));
+    let mut counter = 1;
+    while path.exists() {
+        path = PathBuf::from(format!("{}_{}.png", stem, counter));
+        counter += 1;
+    }
+    path
+}
+
+/// Gregorian (year, month, day) of a count of days since 1970-01-01.
+/// Howard Hinnant's `civil_from_days`, working in 400 year eras starting on March 1st.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month as u32, day as u32)
+}
+
+/// Encodes and writes `image` on its own thread so the render loop isn't held up.
+/// Failures are logged rather than fatal.
+pub fn save_in_background(image: image::RgbaImage, path: PathBuf) {
+    thread::spawn(move || match image.save(&path) {
+        Ok(()) => println!("Saved screenshot {}", path.display()),
+        Err(e) => eprintln!("Could not save screenshot {}: {}", path.display(), e),
+    });
+}
@@ -0,0 +1,28 @@
+// Temporal anti-aliasing: blends the newest colorized frame, computed with a sub-pixel jitter,
+// into a running average of the frames before it.
+
+struct AccumulateParams {
+    // Index of the newest frame; 0 restarts the average
+    frame_index: u32,
+};
+
+@group(0) @binding(0) var frame_texture : texture_2d<f32>;
+@group(0) @binding(1) var history_texture : texture_2d<f32>;
+@group(0) @binding(2) var<uniform> params : AccumulateParams;
+@group(0) @binding(3) var output_texture : texture_storage_2d<rgba16float, write>;
+
+@compute @workgroup_size(16,16)
+fn main(
+  @builtin(global_invocation_id) global_id : vec3<u32>,
+) {
+    let dimensions = textureDimensions(output_texture);
+    let coords = vec2<i32>(global_id.xy);
+    if(coords.x >= dimensions.x || coords.y >= dimensions.y) {
+        return;
+    }
+
+    let frame = textureLoad(frame_texture, coords, 0);
+    let history = textureLoad(history_texture, coords, 0);
+    // Each of the frame_index + 1 frames ends up with the same weight.
+    textureStore(output_texture, coords, mix(history, frame, 1.0 / f32(params.frame_index + 1u)));
+}
@@ -0,0 +1,60 @@
+// Bloom post-processing: bright pass, separable blur and additive composite,
+// each drawn as a fullscreen triangle.
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.tex_coords = uv;
+    return out;
+}
+
+struct BloomParams {
+    // Texel step of the blur, in uv units
+    direction: vec2<f32>,
+    threshold: f32,
+    strength: f32,
+};
+
+@group(0) @binding(0) var t_input: texture_2d<f32>;
+@group(0) @binding(1) var s_input: sampler;
+@group(0) @binding(2) var<uniform> bloom: BloomParams;
+@group(0) @binding(3) var t_bloom: texture_2d<f32>;
+
+fn luminance(c: vec3<f32>) -> f32 {
+    return dot(c, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+@fragment
+fn fs_bright(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.tex_coords).rgb;
+    // Soft knee so the threshold doesn't produce hard edges.
+    let excess = max(luminance(color) - bloom.threshold, 0.0);
+    let weight = excess / max(luminance(color), 0.0001);
+    return vec4<f32>(color * weight, 1.0);
+}
+
+@fragment
+fn fs_blur(in: VertexOutput) -> @location(0) vec4<f32> {
+    var weights = array<f32, 5>(0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+    var color = textureSample(t_input, s_input, in.tex_coords).rgb * weights[0];
+    for (var i = 1; i < 5; i = i + 1) {
+        let offset = bloom.direction * f32(i);
+        color = color + textureSample(t_input, s_input, in.tex_coords + offset).rgb * weights[i];
+        color = color + textureSample(t_input, s_input, in.tex_coords - offset).rgb * weights[i];
+    }
+    return vec4<f32>(color, 1.0);
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    let base = textureSample(t_input, s_input, in.tex_coords);
+    let glow = textureSample(t_bloom, s_input, in.tex_coords).rgb;
+    return vec4<f32>(base.rgb + glow * bloom.strength, base.a);
+}
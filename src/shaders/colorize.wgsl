@@ -0,0 +1,159 @@
+// Maps the raw iteration texture written by mandelbrot.wgsl to colors through the palette.
+// Cheap enough to rerun every frame, so palette and mapping changes need no fractal recompute.
+
+struct ColorParams {
+    // Smooth iterations per full palette cycle
+    palette_density: f32,
+    // Palette phase shift in cycles
+    palette_offset: f32,
+    // 0 = linear, 1 = log, 2 = sqrt, 3 = power, 4 = histogram
+    mapping_mode: u32,
+    mapping_exponent: f32,
+    // 1 = color points that never escaped too, whose value is an orbit trap distance
+    color_interior: u32,
+    // 1 = black boundary lines on white; the value is then a distance estimate in pixels
+    boundary_only: u32,
+    // 1 = light escaped pixels as a relief; |z| is then replaced by the surface normal angle
+    relief: u32,
+    // Direction of the light on the complex plane in radians
+    light_angle: f32,
+    // Height of the light over the plane relative to its horizontal distance
+    light_height: f32,
+    // 1 = one palette cycle spans the escaped iteration range, replacing density
+    auto_range: u32,
+    // Number of roots of a Newton fractal, which is colored by root; 0 for other kinds
+    roots: u32,
+};
+
+@group(0) @binding(0) var iteration_texture : texture_2d<f32>;
+@group(0) @binding(1) var palette_texture : texture_2d<f32>;
+@group(0) @binding(2) var<uniform> params : ColorParams;
+@group(0) @binding(3) var output_texture : texture_storage_2d<rgba8unorm, write>;
+
+// Must match HISTOGRAM_BINS in histogram.rs.
+let BINS: u32 = 1024u;
+
+// Written by histogram.wgsl.
+struct Cdf {
+    max_iterations: f32,
+    // Fraction of escaped pixels in this bin or below
+    values: array<f32, 1024>,
+};
+
+// Written by range.wgsl.
+struct IterationRange {
+    min: f32,
+    max: f32,
+};
+
+@group(1) @binding(0) var<storage, read> histogram : Cdf;
+@group(1) @binding(1) var<storage, read> range : IterationRange;
+
+// Fraction of escaped pixels below x, interpolated within its histogram bin.
+fn equalize(x: f32) -> f32 {
+    let position = clamp(x / histogram.max_iterations, 0.0, 1.0) * f32(BINS);
+    let bin = min(u32(position), BINS - 1u);
+    var below = 0.0;
+    if (bin > 0u) {
+        below = histogram.values[bin - 1u];
+    }
+    return mix(below, histogram.values[bin], fract(position));
+}
+
+// Curve applied to the smooth iteration count before density and offset.
+fn map_iterations(x: f32) -> f32 {
+    let v = max(x, 0.0);
+    switch (params.mapping_mode) {
+        case 1u: { return log2(1.0 + v); }
+        case 2u: { return sqrt(v); }
+        case 3u: { return pow(v, params.mapping_exponent); }
+        default: { return v; }
+    }
+}
+
+// Looks up the palette lookup texture at t in [0, 1].
+fn palette(t: f32) -> vec4<f32> {
+    let size = textureDimensions(palette_texture).x;
+    let index = clamp(i32(t * f32(size - 1) + 0.5), 0, size - 1);
+    return textureLoad(palette_texture, vec2<i32>(index, 0), 0);
+}
+
+// Lambert and Blinn-Phong lighting of a surface tilted 45 degrees towards normal_angle,
+// Milnor's normal mapping of the potential. The viewer looks straight down.
+fn relief_lighting(color: vec4<f32>, normal_angle: f32) -> vec4<f32> {
+    let normal = normalize(vec3<f32>(cos(normal_angle), sin(normal_angle), 1.0));
+    let light = normalize(vec3<f32>(cos(params.light_angle), sin(params.light_angle), params.light_height));
+    let diffuse = max(dot(normal, light), 0.0);
+    let halfway = normalize(light + vec3<f32>(0.0, 0.0, 1.0));
+    let specular = pow(max(dot(normal, halfway), 0.0), 32.0);
+    let lit = color.rgb * (0.25 + 0.75 * diffuse) + vec3<f32>(0.4 * specular);
+    return vec4<f32>(min(lit, vec3<f32>(1.0, 1.0, 1.0)), color.a);
+}
+
+// Newton fractal pixel: the palette color of the root it converged to, split evenly over
+// the palette, darkened the more steps it took. Density stretches the shading.
+fn root_color(texel: vec4<f32>) -> vec4<f32> {
+    if (texel.z < 0.5) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    let color = palette(fract((texel.y + 0.5) / f32(params.roots) + params.palette_offset));
+    let shade = max(exp(-4.0 * texel.x / params.palette_density), 0.1);
+    return vec4<f32>(color.rgb * shade, color.a);
+}
+
+fn texel_color(coords: vec2<i32>) -> vec4<f32> {
+    // (palette value, |z| or normal angle, escaped, glitched)
+    let texel = textureLoad(iteration_texture, coords, 0);
+    if (texel.w > 0.5) {
+        // Perturbation lost precision here, see deep.wgsl
+        return vec4<f32>(1.0, 0.0, 1.0, 1.0);
+    }
+    if (params.roots > 0u) {
+        return root_color(texel);
+    }
+    if (params.boundary_only == 1u) {
+        if (texel.z > 0.5 && texel.x < 1.0) {
+            return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+        }
+        return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+    }
+    if (texel.z > 0.5 || params.color_interior == 1u) {
+        // Histogram mapping spreads one palette cycle over the pixels, so density doesn't apply.
+        var position = map_iterations(texel.x) / params.palette_density;
+        if (params.mapping_mode == 4u) {
+            position = equalize(texel.x);
+        } else if (params.auto_range == 1u && range.max > range.min) {
+            let low = map_iterations(range.min);
+            position = (map_iterations(texel.x) - low) / max(map_iterations(range.max) - low, 1.0e-6);
+        }
+        let color = palette(fract(position + params.palette_offset));
+        if (params.relief == 1u && texel.z > 0.5) {
+            return relief_lighting(color, texel.y);
+        }
+        return color;
+    }
+    return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+}
+
+@compute @workgroup_size(16,16)
+fn main(
+  @builtin(global_invocation_id) global_id : vec3<u32>,
+) {
+    let dimensions = textureDimensions(output_texture);
+    let coords = vec2<i32>(global_id.xy);
+    if(coords.x >= dimensions.x || coords.y >= dimensions.y) {
+        return;
+    }
+
+    // A supersampled iteration texture is an integer multiple of the output size. Its samples
+    // are colored before averaging, since averaging iteration counts would blend unrelated
+    // palette entries.
+    let samples = max(textureDimensions(iteration_texture) / dimensions, vec2<i32>(1, 1));
+    var color = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    for (var y = 0; y < samples.y; y = y + 1) {
+        for (var x = 0; x < samples.x; x = x + 1) {
+            color = color + texel_color(coords * samples + vec2<i32>(x, y));
+        }
+    }
+    textureStore(output_texture, coords, color / f32(samples.x * samples.y));
+}
@@ -0,0 +1,207 @@
+// Perturbation rendering for zooms beyond the precision of mandelbrot.wgsl and its variants.
+// The reference orbit Z_n of the view center is computed on the CPU in arbitrary precision;
+// each pixel only iterates its small difference delta_n = z_n - Z_n, which fits in f32:
+//   delta_{n+1} = 2 Z_n delta_n + delta_n^2 + delta_c
+
+struct MandelbrotParams {
+    // Column-major 2x2 view transform: (c0.x, c0.y, c1.x, c1.y)
+    transform: vec4<f32>,
+    center: vec2<f32>,
+    max_iterations: i32,
+    // Bailout radius, large so the smooth iteration count is accurate
+    escape_radius: f32,
+    // Constant c of the Julia set, used when fractal_mode is 1
+    julia_c: vec2<f32>,
+    // 0 = Mandelbrot, 1 = Julia
+    fractal_mode: u32,
+    // Exponent d of z^d + c, always 2 here
+    power: f32,
+    // 0 = Mandelbrot, 1 = Tricorn
+    kind: u32,
+    // Unused: the cardioid test of mandelbrot.wgsl would need the exact pixel coordinate
+    interior_check: u32,
+    // Unused: the center is baked into the reference orbit
+    center_lo: vec2<f32>,
+    // Sub-pixel offset of every sample in pixels, nonzero while accumulating anti-aliasing
+    jitter: vec2<f32>,
+    // 0 = escape time, 1 = point trap, 2 = line trap, 3 = circle trap,
+    // 4 = distance estimate, 5 = boundary lines, 6 = stripe average
+    coloring_mode: u32,
+    // Complex-plane step between output pixels, the unit of distance estimates
+    pixel_size: f32,
+    // Trap position (x, y), circle radius and line angle in radians
+    trap_params: vec4<f32>,
+    // 1 = write the surface normal angle in place of |z| for relief lighting
+    relief: u32,
+    // Pixel the dispatch starts at, nonzero for all but the first tile of a tiled compute
+    tile_origin: vec2<u32>,
+    // Stripe mode: frequency k of sin(k arg z) and how much of it to mix into the smooth
+    // iteration count
+    stripe_density: f32,
+    stripe_mix: f32,
+};
+
+fn complex_mult(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+fn lerp(input: f32, in_min: f32, in_max: f32, out_min: f32, out_max: f32) -> f32 {
+    let in_range = in_max - in_min;
+    let in_normal = (input - in_min) / in_range;
+    let out_range = out_max - out_min;
+    return in_normal * out_range + out_min;
+}
+
+// Distance from z to the orbit trap of coloring_mode.
+fn trap_distance(z: vec2<f32>, coloring_mode: u32, trap: vec4<f32>) -> f32 {
+    let p = z - trap.xy;
+    switch (coloring_mode) {
+        case 2u: { return abs(dot(p, vec2<f32>(-sin(trap.w), cos(trap.w)))); }
+        case 3u: { return abs(length(p) - trap.z); }
+        default: { return length(p); }
+    }
+}
+
+// Palette value of the closest approach to a trap or of a stripe average, written in place of
+// the smooth iteration count. One unit spans a palette cycle at the default density of 64.
+fn trap_value(distance: f32) -> f32 {
+    return 64.0 * distance;
+}
+
+// Stripe mode's term for one point of the orbit.
+fn stripe_term(z: vec2<f32>, density: f32) -> f32 {
+    return 0.5 + 0.5 * sin(density * atan2(z.y, z.x));
+}
+
+// Average stripe term of an orbit, from the sum of its n terms and the last of them. The last
+// term of an escaping orbit only counts by the fraction of the step the smooth iteration
+// count adds, so the average is continuous across iteration bands.
+fn stripe_average(sum: f32, last: f32, n: i32, z_abs: f32, radius: f32, power: f32) -> f32 {
+    let average = sum / f32(max(n, 1));
+    if (z_abs <= radius || n < 2) {
+        return average;
+    }
+    let previous = (sum - last) / f32(n - 1);
+    let fraction = clamp(1.0 + log(log(radius) / log(z_abs)) / log(power), 0.0, 1.0);
+    return mix(previous, average, fraction);
+}
+
+// d z^(d-1) dz, the chain rule step of the derivative through z^d. Taken in f32 at every
+// precision, since only the magnitude of the derivative is used.
+fn derivative_step(z: vec2<f32>, dz: vec2<f32>, power: f32) -> vec2<f32> {
+    var zd1 = z;
+    if (power != 2.0) {
+        let r = length(z);
+        let theta = atan2(z.y, z.x) * (power - 1.0);
+        zd1 = pow(r, power - 1.0) * vec2<f32>(cos(theta), sin(theta));
+    }
+    return power * vec2<f32>(zd1.x * dz.x - zd1.y * dz.y, zd1.x * dz.y + zd1.y * dz.x);
+}
+
+// Exterior distance estimate to the set boundary in pixels, 0 for points that never escaped.
+fn boundary_distance(z_abs: f32, derivative: f32, radius: f32, pixel_size: f32) -> f32 {
+    if (z_abs <= radius) {
+        return 0.0;
+    }
+    return 0.5 * z_abs * log(z_abs) / (derivative * pixel_size);
+}
+
+// Direction of the potential's surface normal, arg(z / dz), which colorize.wgsl lights as
+// a relief.
+fn normal_angle(z: vec2<f32>, dz: vec2<f32>) -> f32 {
+    return atan2(z.y, z.x) - atan2(dz.y, dz.x);
+}
+
+@group(0) @binding(0) var<uniform> params : MandelbrotParams;
+@group(0) @binding(1) var iteration_texture : texture_storage_2d<rgba32float, write>;
+// Z_0, Z_1, ... rounded to f32. Shorter than max_iterations + 1 if the reference escaped.
+@group(0) @binding(2) var<storage, read> orbit : array<vec2<f32>>;
+
+@compute @workgroup_size(16,16)
+fn main(
+  @builtin(global_invocation_id) global_id : vec3<u32>,
+) {
+    let dimensions = textureDimensions(iteration_texture);
+    let coords = vec2<i32>(global_id.xy + params.tile_origin);
+    if(coords.x >= dimensions.x || coords.y >= dimensions.y) {
+        return;
+    }
+
+    let xnorm = (f32(coords.x) + params.jitter.x) / f32(dimensions.x);
+    let ynorm = (f32(coords.y) + params.jitter.y) / f32(dimensions.y);
+    let uv = vec2<f32>(lerp(xnorm,0.0,1.0,-1.0,1.0), lerp(ynorm,0.0,1.0,-1.0,1.0));
+    let transform = mat2x2<f32>(params.transform.xy, params.transform.zw);
+    let offset = transform * uv;
+
+    // Mandelbrot: z starts equal to the reference and c is offset.
+    // Julia: c is shared with the reference and z starts offset.
+    var delta = vec2<f32>(0.0, 0.0);
+    var delta_c = offset;
+    if (params.fractal_mode == 1u) {
+        delta = offset;
+        delta_c = vec2<f32>(0.0, 0.0);
+    }
+
+    let orbit_length = i32(arrayLength(&orbit));
+    var n: i32 = 0;
+    var z = orbit[0] + delta;
+    var glitched = false;
+    var closest = 1.0e20;
+    var stripe_sum = 0.0;
+    var last_stripe = 0.0;
+    // dz/dc, or dz/dz0 for Julia sets, of the full z = Z_n + delta_n
+    var dz = vec2<f32>(f32(params.fractal_mode), 0.0);
+    let dc = 1.0 - f32(params.fractal_mode);
+    while ((length(z) <= params.escape_radius) && (n < params.max_iterations)) {
+        if (n + 1 >= orbit_length) {
+            // The reference escaped before this pixel did, so there is nothing to perturb.
+            glitched = true;
+            break;
+        }
+        var reference = orbit[n];
+        var d = delta;
+        if (params.kind == 1u) {
+            // Tricorn: iterate the complex conjugate
+            reference.y = -reference.y;
+            d.y = -d.y;
+            dz.y = -dz.y;
+        }
+        if (params.coloring_mode == 4u || params.coloring_mode == 5u || params.relief == 1u) {
+            dz = derivative_step(reference + d, dz, 2.0) + vec2<f32>(dc, 0.0);
+        }
+        delta = 2.0 * complex_mult(reference, d) + complex_mult(d, d) + delta_c;
+        n += 1;
+        z = orbit[n] + delta;
+        if (params.coloring_mode == 6u) {
+            last_stripe = stripe_term(z, params.stripe_density);
+            stripe_sum += last_stripe;
+        } else if (params.coloring_mode != 0u) {
+            closest = min(closest, trap_distance(z, params.coloring_mode, params.trap_params));
+        }
+        // Pauldelbrot's criterion: z got so close to 0 relative to the reference that delta
+        // has lost all its precision.
+        if (dot(z, z) < 1.0e-6 * dot(orbit[n], orbit[n])) {
+            glitched = true;
+            break;
+        }
+    }
+
+    let z_abs = length(z);
+    var smooth_iter = f32(n);
+    if (z_abs > params.escape_radius) {
+        smooth_iter = f32(n) + 1.0 - log2(log2(z_abs));
+    }
+    if (params.coloring_mode == 6u) {
+        let stripes = stripe_average(stripe_sum, last_stripe, n, z_abs, params.escape_radius, 2.0);
+        smooth_iter = mix(smooth_iter, trap_value(stripes), params.stripe_mix);
+    } else if (params.coloring_mode >= 4u) {
+        smooth_iter = boundary_distance(z_abs, length(dz), params.escape_radius, params.pixel_size);
+    } else if (params.coloring_mode != 0u) {
+        smooth_iter = trap_value(closest);
+    }
+    var shape = z_abs;
+    if (params.relief == 1u) {
+        shape = normal_angle(z, dz);
+    }
+    textureStore(iteration_texture, coords.xy, vec4<f32>(smooth_iter, shape, f32(z_abs > params.escape_radius), f32(glitched)));
+}
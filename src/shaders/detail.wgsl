@@ -0,0 +1,69 @@
+// Detail scoring of the iteration texture.
+// sobel writes the gradient magnitude of the smooth iteration count per pixel,
+// reduce_blocks averages those over a DETAIL_GRID x DETAIL_GRID grid of blocks.
+
+let DETAIL_GRID: u32 = 8u;
+
+@group(0) @binding(0) var iteration_texture : texture_2d<f32>;
+@group(0) @binding(1) var<storage, read_write> gradients : array<f32>;
+@group(0) @binding(2) var<storage, read_write> scores : array<f32>;
+
+var<workgroup> scratch : array<f32, 256>;
+
+fn smooth_at(coords: vec2<i32>, dimensions: vec2<i32>) -> f32 {
+    let clamped = clamp(coords, vec2<i32>(0, 0), dimensions - vec2<i32>(1, 1));
+    return textureLoad(iteration_texture, clamped, 0).x;
+}
+
+@compute @workgroup_size(16,16)
+fn sobel(
+  @builtin(global_invocation_id) global_id : vec3<u32>,
+) {
+    let dimensions = textureDimensions(iteration_texture);
+    let c = vec2<i32>(global_id.xy);
+    if (c.x >= dimensions.x || c.y >= dimensions.y) {
+        return;
+    }
+    let tl = smooth_at(c + vec2<i32>(-1, -1), dimensions);
+    let t = smooth_at(c + vec2<i32>(0, -1), dimensions);
+    let tr = smooth_at(c + vec2<i32>(1, -1), dimensions);
+    let l = smooth_at(c + vec2<i32>(-1, 0), dimensions);
+    let r = smooth_at(c + vec2<i32>(1, 0), dimensions);
+    let bl = smooth_at(c + vec2<i32>(-1, 1), dimensions);
+    let b = smooth_at(c + vec2<i32>(0, 1), dimensions);
+    let br = smooth_at(c + vec2<i32>(1, 1), dimensions);
+    let gx = (tr + 2.0 * r + br) - (tl + 2.0 * l + bl);
+    let gy = (bl + 2.0 * b + br) - (tl + 2.0 * t + tr);
+    // Compressed so a few huge steps at the set boundary don't drown out fine filaments.
+    gradients[u32(c.y) * u32(dimensions.x) + u32(c.x)] = log2(1.0 + length(vec2<f32>(gx, gy)));
+}
+
+@compute @workgroup_size(256)
+fn reduce_blocks(
+  @builtin(workgroup_id) block : vec3<u32>,
+  @builtin(local_invocation_index) local_index : u32,
+) {
+    let dimensions = vec2<u32>(textureDimensions(iteration_texture));
+    let block_min = block.xy * dimensions / DETAIL_GRID;
+    let block_max = (block.xy + vec2<u32>(1u, 1u)) * dimensions / DETAIL_GRID;
+    let block_size = block_max - block_min;
+    let count = block_size.x * block_size.y;
+
+    var sum = 0.0;
+    for (var i = local_index; i < count; i = i + 256u) {
+        let p = block_min + vec2<u32>(i % block_size.x, i / block_size.x);
+        sum = sum + gradients[p.y * dimensions.x + p.x];
+    }
+    scratch[local_index] = sum;
+    workgroupBarrier();
+    for (var stride = 128u; stride > 0u; stride = stride / 2u) {
+        if (local_index < stride) {
+            scratch[local_index] = scratch[local_index] + scratch[local_index + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if (local_index == 0u) {
+        scores[block.y * DETAIL_GRID + block.x] = scratch[0] / f32(max(count, 1u));
+    }
+}
@@ -0,0 +1,57 @@
+// Histogram equalization of the iteration texture. `count` bins the smooth iteration count of
+// every escaped pixel, `accumulate` turns the bins into the cumulative distribution that
+// colorize.wgsl maps iteration counts through.
+
+// Must match HISTOGRAM_BINS in histogram.rs and colorize.wgsl.
+let BINS: u32 = 1024u;
+
+struct HistogramParams {
+    // The bins evenly cover smooth iteration counts from 0 to max_iterations
+    max_iterations: f32,
+};
+
+struct Cdf {
+    // max_iterations of the counted texture, so colorize.wgsl bins values the same way
+    max_iterations: f32,
+    // Fraction of escaped pixels in this bin or below
+    values: array<f32, 1024>,
+};
+
+@group(0) @binding(0) var iteration_texture : texture_2d<f32>;
+@group(0) @binding(1) var<uniform> params : HistogramParams;
+@group(0) @binding(2) var<storage, read_write> bins : array<atomic<u32>, 1024>;
+@group(0) @binding(3) var<storage, read_write> cdf : Cdf;
+
+@compute @workgroup_size(16,16)
+fn count(
+  @builtin(global_invocation_id) global_id : vec3<u32>,
+) {
+    let dimensions = textureDimensions(iteration_texture);
+    let coords = vec2<i32>(global_id.xy);
+    if(coords.x >= dimensions.x || coords.y >= dimensions.y) {
+        return;
+    }
+
+    // (smooth iteration, |z|, escaped, glitched)
+    let texel = textureLoad(iteration_texture, coords, 0);
+    if (texel.z < 0.5 || texel.w > 0.5) {
+        return;
+    }
+    let position = clamp(texel.x / params.max_iterations, 0.0, 1.0) * f32(BINS);
+    atomicAdd(&bins[min(u32(position), BINS - 1u)], 1u);
+}
+
+// A single invocation: 1024 bins are too few to be worth a parallel scan.
+@compute @workgroup_size(1)
+fn accumulate() {
+    var total = 0u;
+    for (var i = 0u; i < BINS; i = i + 1u) {
+        total = total + atomicLoad(&bins[i]);
+    }
+    var sum = 0u;
+    for (var i = 0u; i < BINS; i = i + 1u) {
+        sum = sum + atomicLoad(&bins[i]);
+        cdf.values[i] = f32(sum) / f32(max(total, 1u));
+    }
+    cdf.max_iterations = params.max_iterations;
+}
@@ -0,0 +1,322 @@
+// Double precision variant of mandelbrot.wgsl, used when the device supports SHADER_FLOAT64.
+// Only the pixel coordinate and the iteration run in f64; the view offset from the center and
+// the smooth iteration count stay in f32, since most backends lack f64 transcendentals.
+
+struct MandelbrotParams {
+    // Column-major 2x2 view transform: (c0.x, c0.y, c1.x, c1.y)
+    transform: vec4<f32>,
+    center: vec2<f32>,
+    max_iterations: i32,
+    // Bailout radius, large so the smooth iteration count is accurate
+    escape_radius: f32,
+    // Constant c of the Julia set, used when fractal_mode is 1
+    julia_c: vec2<f32>,
+    // 0 = Mandelbrot, 1 = Julia
+    fractal_mode: u32,
+    // Exponent d of z^d + c
+    power: f32,
+    // 0 = Mandelbrot, 1 = Tricorn, 2 = Newton
+    kind: u32,
+    // 1 = skip points in the main cardioid and period-2 bulb, only valid for z^2 + c
+    interior_check: u32,
+    // Rounding error of center; center + center_lo is the view center in double precision
+    center_lo: vec2<f32>,
+    // Sub-pixel offset of every sample in pixels, nonzero while accumulating anti-aliasing
+    jitter: vec2<f32>,
+    // 0 = escape time, 1 = point trap, 2 = line trap, 3 = circle trap,
+    // 4 = distance estimate, 5 = boundary lines, 6 = stripe average
+    coloring_mode: u32,
+    // Complex-plane step between output pixels, the unit of distance estimates
+    pixel_size: f32,
+    // Trap position (x, y), circle radius and line angle in radians
+    trap_params: vec4<f32>,
+    // 1 = write the surface normal angle in place of |z| for relief lighting
+    relief: u32,
+    // Step size below which kind 2 counts a point as converged
+    newton_tolerance: f32,
+    // Pixel the dispatch starts at, nonzero for all but the first tile of a tiled compute
+    tile_origin: vec2<u32>,
+    // Stripe mode: frequency k of sin(k arg z) and how much of it to mix into the smooth
+    // iteration count
+    stripe_density: f32,
+    stripe_mix: f32,
+};
+
+fn complex_mult(a: vec2<f64>, b: vec2<f64>) -> vec2<f64> {
+    return vec2<f64>(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+// a^d: repeated multiplication for integer d up to 8. Other powers go through the f32
+// polar form, trading the extra precision for having no f64 trigonometry.
+fn complex_pow(a: vec2<f64>, d: f32) -> vec2<f64> {
+    if (d == floor(d) && d <= 8.0) {
+        var result = a;
+        for (var i = 1; i < i32(d); i += 1) {
+            result = complex_mult(result, a);
+        }
+        return result;
+    }
+    let a32 = vec2<f32>(f32(a.x), f32(a.y));
+    let r = length(a32);
+    if (r == 0.0) {
+        return vec2<f64>(f64(0.0), f64(0.0));
+    }
+    let theta = atan2(a32.y, a32.x) * d;
+    let p = pow(r, d) * vec2<f32>(cos(theta), sin(theta));
+    return vec2<f64>(f64(p.x), f64(p.y));
+}
+
+fn lerp(input: f32, in_min: f32, in_max: f32, out_min: f32, out_max: f32) -> f32 {
+    let in_range = in_max - in_min;
+    let in_normal = (input - in_min) / in_range;
+    let out_range = out_max - out_min;
+    return in_normal * out_range + out_min;
+}
+
+// Whether c lies in the main cardioid or the period-2 bulb, see mandelbrot.wgsl.
+fn in_main_interior(c: vec2<f64>) -> bool {
+    let x = c.x - f64(0.25);
+    let y2 = c.y * c.y;
+    let q = x * x + y2;
+    if (q * (q + x) < f64(0.25) * y2) {
+        return true;
+    }
+    let b = c.x + f64(1.0);
+    return b * b + y2 < f64(0.0625);
+}
+
+// Distance from z to the orbit trap of coloring_mode.
+fn trap_distance(z: vec2<f32>, coloring_mode: u32, trap: vec4<f32>) -> f32 {
+    let p = z - trap.xy;
+    switch (coloring_mode) {
+        case 2u: { return abs(dot(p, vec2<f32>(-sin(trap.w), cos(trap.w)))); }
+        case 3u: { return abs(length(p) - trap.z); }
+        default: { return length(p); }
+    }
+}
+
+// Palette value of the closest approach to a trap or of a stripe average, written in place of
+// the smooth iteration count. One unit spans a palette cycle at the default density of 64.
+fn trap_value(distance: f32) -> f32 {
+    return 64.0 * distance;
+}
+
+// Stripe mode's term for one point of the orbit.
+fn stripe_term(z: vec2<f32>, density: f32) -> f32 {
+    return 0.5 + 0.5 * sin(density * atan2(z.y, z.x));
+}
+
+// Average stripe term of an orbit, from the sum of its n terms and the last of them. The last
+// term of an escaping orbit only counts by the fraction of the step the smooth iteration
+// count adds, so the average is continuous across iteration bands.
+fn stripe_average(sum: f32, last: f32, n: i32, z_abs: f32, radius: f32, power: f32) -> f32 {
+    let average = sum / f32(max(n, 1));
+    if (z_abs <= radius || n < 2) {
+        return average;
+    }
+    let previous = (sum - last) / f32(n - 1);
+    let fraction = clamp(1.0 + log(log(radius) / log(z_abs)) / log(power), 0.0, 1.0);
+    return mix(previous, average, fraction);
+}
+
+// d z^(d-1) dz, the chain rule step of the derivative through z^d. Taken in f32 at every
+// precision, since only the magnitude of the derivative is used.
+fn derivative_step(z: vec2<f32>, dz: vec2<f32>, power: f32) -> vec2<f32> {
+    var zd1 = z;
+    if (power != 2.0) {
+        let r = length(z);
+        let theta = atan2(z.y, z.x) * (power - 1.0);
+        zd1 = pow(r, power - 1.0) * vec2<f32>(cos(theta), sin(theta));
+    }
+    return power * vec2<f32>(zd1.x * dz.x - zd1.y * dz.y, zd1.x * dz.y + zd1.y * dz.x);
+}
+
+// Exterior distance estimate to the set boundary in pixels, 0 for points that never escaped.
+fn boundary_distance(z_abs: f32, derivative: f32, radius: f32, pixel_size: f32) -> f32 {
+    if (z_abs <= radius) {
+        return 0.0;
+    }
+    return 0.5 * z_abs * log(z_abs) / (derivative * pixel_size);
+}
+
+// Direction of the potential's surface normal, arg(z / dz), which colorize.wgsl lights as
+// a relief.
+fn normal_angle(z: vec2<f32>, dz: vec2<f32>) -> f32 {
+    return atan2(z.y, z.x) - atan2(dz.y, dz.x);
+}
+
+struct EscapeResult {
+    n: i32,
+    z: vec2<f32>,
+    // Closest approach of the orbit to the trap, or its stripe average in stripe mode;
+    // unused in escape time mode
+    trap: f32,
+    // dz/dc, or dz/dz0 for Julia sets; only tracked for distance estimation and relief
+    dz: vec2<f32>,
+};
+
+// Iterates z = z^d + c from z0 like mandelbrot.wgsl, comparing squared magnitudes
+// so no f64 square root is needed. Trap distances and the derivative are taken in f32.
+// dc is the derivative of c with respect to the pixel: 1 for the Mandelbrot set, 0 for a
+// Julia set, whose derivative starts at 1 instead.
+fn escape_time(z0: vec2<f64>, c: vec2<f64>, dc: f32, max_iter: i32, radius: f32, power: f32, kind: u32, coloring_mode: u32, trap: vec4<f32>, stripe_density: f32, track_derivative: bool) -> EscapeResult {
+    let radius_squared = f64(radius) * f64(radius);
+    var z = z0;
+    var dz = vec2<f32>(1.0 - dc, 0.0);
+    var n:i32 = 0;
+    var closest = 1.0e20;
+    var stripe_sum = 0.0;
+    var last_stripe = 0.0;
+    while ((z.x * z.x + z.y * z.y <= radius_squared) && (n < max_iter)) {
+        if (kind == 1u) {
+            // Tricorn: iterate the complex conjugate
+            z.y = -z.y;
+            dz.y = -dz.y;
+        }
+        if (track_derivative) {
+            dz = derivative_step(vec2<f32>(f32(z.x), f32(z.y)), dz, power) + vec2<f32>(dc, 0.0);
+        }
+        z = complex_pow(z, power) + c;
+        n += 1;
+        if (coloring_mode == 6u) {
+            last_stripe = stripe_term(vec2<f32>(f32(z.x), f32(z.y)), stripe_density);
+            stripe_sum += last_stripe;
+        } else if (coloring_mode != 0u) {
+            closest = min(closest, trap_distance(vec2<f32>(f32(z.x), f32(z.y)), coloring_mode, trap));
+        }
+    }
+    if (coloring_mode == 6u) {
+        closest = stripe_average(stripe_sum, last_stripe, n, length(vec2<f32>(f32(z.x), f32(z.y))), radius, power);
+    }
+    return EscapeResult(n, vec2<f32>(f32(z.x), f32(z.y)), closest, dz);
+}
+
+// Renormalized iteration count, continuous across iteration bands.
+fn smooth_iterations(result: EscapeResult, radius: f32, power: f32) -> f32 {
+    let z_abs = length(result.z);
+    if (z_abs <= radius) {
+        return f32(result.n);
+    }
+    if (power == 2.0) {
+        return f32(result.n) + 1.0 - log2(log2(z_abs));
+    }
+    return f32(result.n) + 1.0 - log2(log2(z_abs)) / log2(power);
+}
+
+// f32 complex product, for the iterations that don't need the extra precision
+fn complex_mult_f32(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+// a / b
+fn complex_div_f32(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return complex_mult_f32(a, vec2<f32>(b.x, -b.y)) / dot(b, b);
+}
+
+struct NewtonResult {
+    n: i32,
+    z: vec2<f32>,
+    // Distance z moved in the last step
+    step: f32,
+};
+
+// Newton's method for z^d - 1 from z0, adding c after every step: 0 for the Newton fractal,
+// the Julia constant for the Nova fractal. d is the power rounded to a whole degree of at
+// least 2. Stops once a step moves z less than tolerance.
+fn newton(z0: vec2<f32>, c: vec2<f32>, max_iter: i32, power: f32, tolerance: f32) -> NewtonResult {
+    let degree = max(round(power), 2.0);
+    var z = z0;
+    var n: i32 = 0;
+    var step = 1.0e20;
+    while ((step >= tolerance) && (n < max_iter)) {
+        var zd1 = vec2<f32>(1.0, 0.0);
+        for (var i = 1; i < i32(degree); i += 1) {
+            zd1 = complex_mult_f32(zd1, z);
+        }
+        let derivative = degree * zd1;
+        if (dot(derivative, derivative) == 0.0) {
+            // z = 0, where the tangent is flat and the step undefined
+            break;
+        }
+        let f = complex_mult_f32(zd1, z) - vec2<f32>(1.0, 0.0);
+        let next = z - complex_div_f32(f, derivative) + c;
+        step = length(next - z);
+        z = next;
+        n += 1;
+    }
+    return NewtonResult(n, z, step);
+}
+
+// (steps to converge, root index, converged, 0), colored by root in colorize.wgsl. The step
+// count is smoothed like the escape time: convergence is quadratic, so the last step falls
+// between tolerance and its square.
+fn newton_texel(result: NewtonResult, power: f32, tolerance: f32) -> vec4<f32> {
+    let degree = max(round(power), 2.0);
+    if (result.step >= tolerance) {
+        return vec4<f32>(f32(result.n), 0.0, 0.0, 0.0);
+    }
+    let steps = f32(result.n) + 1.0 - log2(log(max(result.step, 1.0e-30)) / log(tolerance));
+    // The roots of z^d - 1 are at angles 2 pi k / d
+    let turn = atan2(result.z.y, result.z.x) / 6.28318530718;
+    let root = (i32(round(turn * degree)) + i32(degree)) % i32(degree);
+    return vec4<f32>(max(steps, 0.0), f32(root), 1.0, 0.0);
+}
+
+@group(0) @binding(0) var<uniform> params : MandelbrotParams;
+@group(0) @binding(1) var iteration_texture : texture_storage_2d<rgba32float, write>;
+
+@compute @workgroup_size(16,16)
+fn main(
+  @builtin(global_invocation_id) global_id : vec3<u32>,
+) {
+    let dimensions = textureDimensions(iteration_texture);
+    let coords = vec2<i32>(global_id.xy + params.tile_origin);
+    if(coords.x >= dimensions.x || coords.y >= dimensions.y) {
+        return;
+    }
+
+    let xnorm = (f32(coords.x) + params.jitter.x) / f32(dimensions.x);
+    let ynorm = (f32(coords.y) + params.jitter.y) / f32(dimensions.y);
+    let uv = vec2<f32>(lerp(xnorm,0.0,1.0,-1.0,1.0), lerp(ynorm,0.0,1.0,-1.0,1.0));
+    let transform = mat2x2<f32>(params.transform.xy, params.transform.zw);
+    let offset = transform * uv;
+    let p = vec2<f64>(
+        f64(params.center.x) + f64(params.center_lo.x) + f64(offset.x),
+        f64(params.center.y) + f64(params.center_lo.y) + f64(offset.y),
+    );
+    if (params.kind == 2u) {
+        var c = vec2<f32>(0.0, 0.0);
+        if (params.fractal_mode == 1u) {
+            c = params.julia_c;
+        }
+        let newton_result = newton(vec2<f32>(f32(p.x), f32(p.y)), c, params.max_iterations, params.power, params.newton_tolerance);
+        textureStore(iteration_texture, coords.xy, newton_texel(newton_result, params.power, params.newton_tolerance));
+        return;
+    }
+    let track_derivative = params.coloring_mode == 4u || params.coloring_mode == 5u || params.relief == 1u;
+    var result: EscapeResult;
+    if (params.fractal_mode == 1u) {
+        let c = vec2<f64>(f64(params.julia_c.x), f64(params.julia_c.y));
+        result = escape_time(p, c, 0.0, params.max_iterations, params.escape_radius, params.power, params.kind, params.coloring_mode, params.trap_params, params.stripe_density, track_derivative);
+    } else if (params.interior_check == 1u && in_main_interior(p)) {
+        result = EscapeResult(params.max_iterations, vec2<f32>(0.0, 0.0), 0.0, vec2<f32>(0.0, 0.0));
+    } else {
+        let z0 = vec2<f64>(f64(0.0), f64(0.0));
+        result = escape_time(z0, p, 1.0, params.max_iterations, params.escape_radius, params.power, params.kind, params.coloring_mode, params.trap_params, params.stripe_density, track_derivative);
+    }
+    let z_abs = length(result.z);
+    var value = smooth_iterations(result, params.escape_radius, params.power);
+    if (params.coloring_mode == 6u) {
+        value = mix(value, trap_value(result.trap), params.stripe_mix);
+    } else if (params.coloring_mode >= 4u) {
+        value = boundary_distance(z_abs, length(result.dz), params.escape_radius, params.pixel_size);
+    } else if (params.coloring_mode != 0u) {
+        value = trap_value(result.trap);
+    }
+    var shape = z_abs;
+    if (params.relief == 1u) {
+        shape = normal_angle(result.z, result.dz);
+    }
+    // Coloring happens in colorize.wgsl, so palette changes don't need a recompute.
+    textureStore(iteration_texture, coords.xy, vec4<f32>(value, shape, f32(z_abs > params.escape_radius), 0.0));
+}
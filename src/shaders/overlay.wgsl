@@ -0,0 +1,45 @@
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) stroke: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    // (along, across, segment length, half width) in pixels,
+    // or (atlas texel x, atlas texel y, 0, -1) for text
+    @location(1) stroke: vec4<f32>,
+};
+
+@group(0) @binding(0) var t_glyphs: texture_2d<f32>;
+@group(0) @binding(1) var s_glyphs: sampler;
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.color = model.color;
+    out.stroke = model.stroke;
+    out.clip_position = vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let half_width = in.stroke.w;
+    if (half_width < 0.0) {
+        let uv = in.stroke.xy / vec2<f32>(textureDimensions(t_glyphs));
+        let coverage = textureSampleLevel(t_glyphs, s_glyphs, uv, 0.0).r;
+        return vec4<f32>(in.color.rgb, in.color.a * coverage);
+    }
+    if (half_width == 0.0) {
+        return in.color;
+    }
+    // Distance to the segment as a capsule, which gives round caps and joins.
+    let along = max(max(-in.stroke.x, in.stroke.x - in.stroke.z), 0.0);
+    let distance = length(vec2<f32>(along, in.stroke.y));
+    let coverage = clamp(half_width - distance + 0.5, 0.0, 1.0);
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
@@ -0,0 +1,70 @@
+// Two-pass reduction of the iteration texture into the range of smooth iteration counts that
+// colorize.wgsl stretches one palette cycle over. reduce_workgroups strides a fixed number of
+// workgroups over the texture, each folding its pixels into one partial range, so any texture
+// size fits the partials; reduce_partials folds those with a single workgroup.
+
+struct IterationRange {
+    min: f32,
+    max: f32,
+};
+
+let EMPTY_MIN: f32 = 3.0e38;
+let EMPTY_MAX: f32 = -3.0e38;
+// Workgroups of reduce_workgroups, one partial each. Must match WORKGROUPS in range.rs.
+let WORKGROUPS: u32 = 256u;
+
+@group(0) @binding(0) var iteration_texture : texture_2d<f32>;
+@group(0) @binding(1) var<storage, read_write> partials : array<IterationRange, 256>;
+@group(0) @binding(2) var<storage, read_write> result : IterationRange;
+
+var<workgroup> scratch : array<IterationRange, 256>;
+
+fn combine(a: IterationRange, b: IterationRange) -> IterationRange {
+    return IterationRange(min(a.min, b.min), max(a.max, b.max));
+}
+
+fn reduce_scratch(index: u32) {
+    workgroupBarrier();
+    for (var stride = 128u; stride > 0u; stride = stride / 2u) {
+        if (index < stride) {
+            scratch[index] = combine(scratch[index], scratch[index + stride]);
+        }
+        workgroupBarrier();
+    }
+}
+
+@compute @workgroup_size(256)
+fn reduce_workgroups(
+  @builtin(global_invocation_id) global_id : vec3<u32>,
+  @builtin(workgroup_id) workgroup_id : vec3<u32>,
+  @builtin(local_invocation_index) local_index : u32,
+) {
+    let width = u32(textureDimensions(iteration_texture).x);
+    let count = width * u32(textureDimensions(iteration_texture).y);
+    var value = IterationRange(EMPTY_MIN, EMPTY_MAX);
+    for (var i = global_id.x; i < count; i = i + WORKGROUPS * 256u) {
+        // (smooth iteration, |z|, escaped, glitched)
+        let texel = textureLoad(iteration_texture, vec2<i32>(i32(i % width), i32(i / width)), 0);
+        if (texel.z > 0.5 && texel.w < 0.5) {
+            value = combine(value, IterationRange(texel.x, texel.x));
+        }
+    }
+    scratch[local_index] = value;
+    reduce_scratch(local_index);
+
+    if (local_index == 0u) {
+        partials[workgroup_id.x] = scratch[0];
+    }
+}
+
+@compute @workgroup_size(256)
+fn reduce_partials(
+  @builtin(local_invocation_index) local_index : u32,
+) {
+    scratch[local_index] = partials[local_index];
+    reduce_scratch(local_index);
+
+    if (local_index == 0u) {
+        result = scratch[0];
+    }
+}
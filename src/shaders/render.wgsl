@@ -27,12 +27,39 @@ var t_mandelbrot: texture_2d<f32>;
 @group(0) @binding(1)
 var s_mandelbrot: sampler;
 
+struct DisplayParams {
+    // 0 = off, 1 = protanopia, 2 = deuteranopia
+    cvd_simulation: u32,
+};
+
+@group(0) @binding(2)
+var<uniform> display: DisplayParams;
+
+// Full-severity color vision deficiency simulation (Machado et al. 2009), one row per channel.
+fn simulate_cvd(c: vec3<f32>, mode: u32) -> vec3<f32> {
+    if (mode == 1u) {
+        return vec3<f32>(
+            dot(vec3<f32>(0.152286, 1.052583, -0.204868), c),
+            dot(vec3<f32>(0.114503, 0.786281, 0.099216), c),
+            dot(vec3<f32>(-0.003882, -0.048116, 1.051998), c),
+        );
+    }
+    if (mode == 2u) {
+        return vec3<f32>(
+            dot(vec3<f32>(0.367322, 0.860646, -0.227968), c),
+            dot(vec3<f32>(0.280085, 0.672501, 0.047413), c),
+            dot(vec3<f32>(-0.011820, 0.042940, 0.968881), c),
+        );
+    }
+    return c;
+}
+
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let dimensions = textureDimensions(t_mandelbrot);
     let coords = vec2<f32>(in.tex_coords.x ,in.tex_coords.y);
-    var color = textureSample(t_mandelbrot, s_mandelbrot, coords);
-    color.r = 1.0;
-    return color;
+    let color = textureSample(t_mandelbrot, s_mandelbrot, coords);
+    let rgb = clamp(simulate_cvd(color.rgb, display.cvd_simulation), vec3<f32>(0.0), vec3<f32>(1.0));
+    return vec4<f32>(rgb, color.a);
 }
\ No newline at end of file
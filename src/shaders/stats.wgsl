@@ -0,0 +1,90 @@
+// Two-pass reduction of the iteration texture into per-frame statistics.
+// reduce_workgroups folds each 16x16 tile into one Partial, reduce_partials folds those
+// into a single result with one workgroup.
+
+struct Partial {
+    escaped: u32,
+    capped: u32,
+    escaped_sum: f32,
+    count: u32,
+    // Range of smooth iteration values among escaped pixels.
+    escaped_min: f32,
+    escaped_max: f32,
+};
+
+let EMPTY_MIN: f32 = 3.0e38;
+let EMPTY_MAX: f32 = -3.0e38;
+
+@group(0) @binding(0) var iteration_texture : texture_2d<f32>;
+@group(0) @binding(1) var<storage, read_write> partials : array<Partial>;
+@group(0) @binding(2) var<storage, read_write> result : Partial;
+
+var<workgroup> scratch : array<Partial, 256>;
+
+fn combine(a: Partial, b: Partial) -> Partial {
+    return Partial(
+        a.escaped + b.escaped,
+        a.capped + b.capped,
+        a.escaped_sum + b.escaped_sum,
+        a.count + b.count,
+        min(a.escaped_min, b.escaped_min),
+        max(a.escaped_max, b.escaped_max),
+    );
+}
+
+fn reduce_scratch(index: u32) {
+    workgroupBarrier();
+    for (var stride = 128u; stride > 0u; stride = stride / 2u) {
+        if (index < stride) {
+            scratch[index] = combine(scratch[index], scratch[index + stride]);
+        }
+        workgroupBarrier();
+    }
+}
+
+@compute @workgroup_size(16,16)
+fn reduce_workgroups(
+  @builtin(global_invocation_id) global_id : vec3<u32>,
+  @builtin(workgroup_id) workgroup_id : vec3<u32>,
+  @builtin(local_invocation_index) local_index : u32,
+) {
+    let dimensions = textureDimensions(iteration_texture);
+    let coords = vec2<i32>(global_id.xy);
+    var value = Partial(0u, 0u, 0.0, 0u, EMPTY_MIN, EMPTY_MAX);
+    if (coords.x < dimensions.x && coords.y < dimensions.y) {
+        let texel = textureLoad(iteration_texture, coords, 0);
+        if (texel.z > 0.5) {
+            value.escaped = 1u;
+            value.escaped_sum = texel.x;
+            value.escaped_min = texel.x;
+            value.escaped_max = texel.x;
+        } else {
+            value.capped = 1u;
+        }
+        value.count = 1u;
+    }
+    scratch[local_index] = value;
+    reduce_scratch(local_index);
+
+    if (local_index == 0u) {
+        let workgroups_x = (u32(dimensions.x) + 15u) / 16u;
+        partials[workgroup_id.y * workgroups_x + workgroup_id.x] = scratch[0];
+    }
+}
+
+@compute @workgroup_size(256)
+fn reduce_partials(
+  @builtin(local_invocation_index) local_index : u32,
+) {
+    var value = Partial(0u, 0u, 0.0, 0u, EMPTY_MIN, EMPTY_MAX);
+    let count = arrayLength(&partials);
+    for (var i = local_index; i < count; i = i + 256u) {
+        value = combine(value, partials[i]);
+    }
+    scratch[local_index] = value;
+    reduce_scratch(local_index);
+
+    if (local_index == 0u) {
+        result = scratch[0];
+    }
+}
@@ -0,0 +1,310 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    computer::{AutoIterations, FractalKind, MandelbrotParams, SampleLocation},
+    formula::CustomFormula,
+    math::{format_fixed, parse_fixed, DVec2, UVec2},
+    palette::ColorMapping,
+};
+
+/// File name the view is saved to with F5 and loaded from with F9.
+const VIEW_FILE_NAME: &str = "view.toml";
+/// File name bookmarks are kept in.
+const BOOKMARKS_FILE_NAME: &str = "bookmarks.toml";
+
+/// Everything needed to come back to a view: where it is, how many iterations it was
+/// rendered with, which formula and how it was colored. Kept apart from `App` so bookmarks,
+/// animation keyframes and exports can share the format.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ViewState {
+    pub fractal_kind: FractalKind,
+    /// Manual iteration count, used when `auto_iterations` is off.
+    pub max_iterations: i32,
+    #[serde(default = "default_auto_iterations")]
+    pub auto_iterations: bool,
+    pub view: SampleLocation,
+    /// Palette mapping, so a reloaded view is colored the same. Files without it get the
+    /// default mapping.
+    #[serde(default)]
+    pub color_mapping: ColorMapping,
+}
+
+fn default_auto_iterations() -> bool {
+    true
+}
+
+impl Default for ViewState {
+    fn default() -> Self {
+        let kind = FractalKind::Mandelbrot;
+        ViewState {
+            fractal_kind: kind,
+            max_iterations: kind.default_iterations(),
+            auto_iterations: true,
+            view: kind.default_view(),
+            color_mapping: ColorMapping::default(),
+        }
+    }
+}
+
+impl ViewState {
+    /// Rejects states that parse but can't be rendered.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.max_iterations > 0,
+            "max_iterations must be positive, got {}",
+            self.max_iterations
+        );
+        self.view.validate()?;
+        self.color_mapping.validate()
+    }
+
+    /// Params that render this state at `size` away from the live view, as headless renders
+    /// and bookmark thumbnails do. `formula` sets the power of a `FractalKind::Custom` state.
+    pub fn render_params(&self, size: UVec2, formula: Option<&CustomFormula>) -> MandelbrotParams {
+        let mut view = self.view.clone();
+        view.set_output_size(size);
+        let iterations = if self.auto_iterations {
+            let auto = AutoIterations {
+                base: self.fractal_kind.base_iterations(),
+                ..AutoIterations::default()
+            };
+            auto.iterations(view.zoom()) as i32
+        } else {
+            self.max_iterations
+        };
+        let mut params = view.to_mandlebrot_params(iterations);
+        params.kind = self.fractal_kind as u32;
+        params.power = match formula {
+            Some(formula) if self.fractal_kind == FractalKind::Custom => formula.escape_power(),
+            _ => self.fractal_kind.default_power(),
+        };
+        params
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// One line describing the view for sharing, e.g.
+    /// "re=-0.7436438870 im=0.1318259042 zoom=3.5e-7 iters=4000". The coordinates get enough
+    /// digits to place the center within a fraction of a pixel. The iteration count is left
+    /// out with automatic iterations on, so the receiver derives the same count from the zoom.
+    pub fn location_text(&self) -> String {
+        let (hi, lo) = self.view.center();
+        let zoom = self.view.zoom();
+        let digits = ((-zoom.log10()).ceil().max(0.0) as usize + 6).max(10);
+        let mut text = format!(
+            "re={} im={} zoom={:e}",
+            format_fixed((hi.x, lo.x), digits),
+            format_fixed((hi.y, lo.y), digits),
+            zoom
+        );
+        if !self.auto_iterations {
+            text += &format!(" iters={}", self.max_iterations);
+        }
+        text
+    }
+
+    /// This state moved to the location in `text`, in the format of `location_text`. Fields
+    /// may come in any order, separated by whitespace or commas; missing ones keep their values
+    /// here. An iteration count turns automatic iterations off.
+    pub fn with_location_text(&self, text: &str) -> anyhow::Result<ViewState> {
+        let (hi, lo) = self.view.center();
+        let (mut re, mut im, mut zoom) = ((hi.x, lo.x), (hi.y, lo.y), self.view.zoom());
+        let mut state = self.clone();
+        // Spaces around '=' would otherwise split a field in two.
+        let text = text.split('=').map(str::trim).collect::<Vec<_>>().join("=");
+        let mut fields = 0;
+        for field in text.split(|c: char| c.is_whitespace() || c == ',') {
+            if field.is_empty() {
+                continue;
+            }
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("expected KEY=VALUE but got '{}'", field))?;
+            let invalid = |e: &dyn std::fmt::Display| anyhow::anyhow!("invalid {}: {}", key, e);
+            match key {
+                "re" => re = parse_fixed(value).map_err(|e| invalid(&e))?,
+                "im" => im = parse_fixed(value).map_err(|e| invalid(&e))?,
+                "zoom" => zoom = value.parse().map_err(|e| invalid(&e))?,
+                "iters" => {
+                    state.max_iterations = value.parse().map_err(|e| invalid(&e))?;
+                    state.auto_iterations = false;
+                }
+                _ => anyhow::bail!("unknown field '{}'", key),
+            }
+            fields += 1;
+        }
+        anyhow::ensure!(fields > 0, "no location in '{}'", text.trim());
+        state.view =
+            SampleLocation::from_center((DVec2::new(re.0, im.0), DVec2::new(re.1, im.1)), zoom);
+        state.view.rotate(self.view.rotation());
+        state.validate()?;
+        Ok(state)
+    }
+
+    /// Parses and validates a state written by `to_toml`.
+    pub fn from_toml(text: &str) -> anyhow::Result<ViewState> {
+        let state: ViewState = toml::from_str(text)?;
+        state.validate()?;
+        Ok(state)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_toml()?)
+            .with_context(|| format!("could not write {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<ViewState> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+        ViewState::from_toml(&text).with_context(|| format!("invalid {}", path.display()))
+    }
+}
+
+/// Initial view given on the command line. Unset fields keep the defaults.
+#[derive(Clone, Debug, Default)]
+pub struct StartupOptions {
+    pub fractal_kind: Option<FractalKind>,
+    /// Start on the Julia set whose constant is `center`, as if J was pressed there.
+    pub julia: bool,
+    pub center: Option<DVec2>,
+    pub zoom: Option<f32>,
+    /// Fixed iteration count; turns automatic iterations off.
+    pub iterations: Option<i32>,
+    /// Formula of `FractalKind::Custom`.
+    pub formula: Option<CustomFormula>,
+    /// Location in the format of `ViewState::location_text`, applied over the other fields.
+    pub location: Option<String>,
+}
+
+impl StartupOptions {
+    /// The kind's default framing with the overrides applied. In Julia mode this is the
+    /// Mandelbrot view the constant is picked from. A location that doesn't parse is reported
+    /// and skipped.
+    pub fn view_state(&self) -> ViewState {
+        let kind = self.fractal_kind.unwrap_or(FractalKind::Mandelbrot);
+        let default_view = kind.default_view();
+        let (center, _) = default_view.center();
+        let state = ViewState {
+            fractal_kind: kind,
+            max_iterations: self.iterations.unwrap_or_else(|| kind.default_iterations()),
+            auto_iterations: self.iterations.is_none(),
+            view: SampleLocation::new(
+                self.center.unwrap_or(center),
+                self.zoom.unwrap_or_else(|| default_view.zoom()),
+            ),
+            color_mapping: ColorMapping::default(),
+        };
+        match &self.location {
+            Some(text) => state.with_location_text(text).unwrap_or_else(|e| {
+                eprintln!("Warning: ignoring --location '{}': {:#}", text, e);
+                state
+            }),
+            None => state,
+        }
+    }
+}
+
+/// Views saved to numbered slots with Ctrl+1..9 and recalled with 1..9.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Bookmarks {
+    /// Keyed by slot number as a string, since TOML table keys are strings.
+    #[serde(default)]
+    slots: BTreeMap<String, ViewState>,
+    /// Name of the palette each slot's thumbnail was rendered with, keyed like `slots`.
+    #[serde(default)]
+    thumbnails: BTreeMap<String, String>,
+}
+
+impl Bookmarks {
+    pub fn get(&self, slot: u8) -> Option<&ViewState> {
+        self.slots.get(&slot.to_string())
+    }
+
+    /// Stores `state` in `slot`. Its old thumbnail no longer matches, so it is stale.
+    pub fn set(&mut self, slot: u8, state: ViewState) {
+        self.slots.insert(slot.to_string(), state);
+        self.thumbnails.remove(&slot.to_string());
+    }
+
+    /// Records that the thumbnail of `slot` was just rendered with `palette`.
+    pub fn set_thumbnail_palette(&mut self, slot: u8, palette: &str) {
+        self.thumbnails
+            .insert(slot.to_string(), palette.to_string());
+    }
+
+    /// Slots whose thumbnail next to the bookmarks file `path` is missing, or was rendered
+    /// with a palette other than `palette` or before the slot was last set.
+    pub fn stale_thumbnails(&self, path: &Path, palette: &str) -> Vec<u8> {
+        self.slots
+            .keys()
+            .filter_map(|key| key.parse::<u8>().ok())
+            .filter(|&slot| {
+                self.thumbnails.get(&slot.to_string()).map(String::as_str) != Some(palette)
+                    || !thumbnail_path(path, slot).exists()
+            })
+            .collect()
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// Parses bookmarks written by `to_toml`, validating every slot.
+    pub fn from_toml(text: &str) -> anyhow::Result<Bookmarks> {
+        let bookmarks: Bookmarks = toml::from_str(text)?;
+        for (slot, state) in &bookmarks.slots {
+            state
+                .validate()
+                .with_context(|| format!("bookmark {}", slot))?;
+        }
+        Ok(bookmarks)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_toml()?)
+            .with_context(|| format!("could not write {}", path.display()))
+    }
+
+    /// Loads bookmarks from `path`. A missing file is no bookmarks rather than an error.
+    pub fn load(path: &Path) -> anyhow::Result<Bookmarks> {
+        if !path.exists() {
+            return Ok(Bookmarks::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+        Bookmarks::from_toml(&text).with_context(|| format!("invalid {}", path.display()))
+    }
+}
+
+/// `file_name` in the directory of the executable, or the working directory if that is unknown.
+pub fn path_next_to_binary(file_name: &str) -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(file_name)))
+        .unwrap_or_else(|| PathBuf::from(file_name))
+}
+
+/// Where F5 saves and F9 loads the view.
+pub fn view_state_path() -> PathBuf {
+    path_next_to_binary(VIEW_FILE_NAME)
+}
+
+/// Where bookmarks are persisted.
+pub fn bookmarks_path() -> PathBuf {
+    path_next_to_binary(BOOKMARKS_FILE_NAME)
+}
+
+/// Thumbnail of bookmark `slot`, next to the bookmarks file `path` and named after it, e.g.
+/// "bookmarks_3.png".
+pub fn thumbnail_path(path: &Path, slot: u8) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}_{}.png", stem, slot))
+}
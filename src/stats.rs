@@ -0,0 +1,238 @@
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+use crate::{computer::Computer, gpu_interface::GPUInterface};
+
+/// Size of one `Partial` in `stats.wgsl`.
+const PARTIAL_SIZE: u64 = 24;
+
+/// Statistics of one rendered frame, stamped with the frame they were computed for.
+#[derive(Copy, Clone, Debug)]
+pub struct FrameStats {
+    pub frame: u64,
+    pub escaped_fraction: f64,
+    pub capped_fraction: f64,
+    /// Mean smooth iteration count of the pixels that escaped.
+    pub mean_iterations: f64,
+    /// Range of smooth iteration counts of the pixels that escaped, if any did.
+    pub iteration_range: Option<(f64, f64)>,
+}
+
+/// GPU reduction of the iteration texture into `FrameStats`.
+/// At most one reduction is in flight; its result is read back asynchronously.
+pub struct StatsReduction {
+    workgroups_pipeline: wgpu::ComputePipeline,
+    partials_pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    partials_buffer: wgpu::Buffer,
+    result_buffer: wgpu::Buffer,
+    staging_buffer: wgpu::Buffer,
+    workgroups: (u32, u32),
+    pending: Option<(u64, Receiver<Result<(), wgpu::BufferAsyncError>>)>,
+}
+
+impl StatsReduction {
+    pub fn new(gpu: &GPUInterface, computer: &Computer) -> StatsReduction {
+        let size = computer.texture_size;
+        let workgroups = (size.width.div_ceil(16), size.height.div_ceil(16));
+
+        let shader = gpu
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Stats shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("shaders/stats.wgsl").into()),
+            });
+
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Stats bind group layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            // Rgba32Float isn't filterable, which the derived layout would require.
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            },
+                            count: None,
+                        },
+                        storage_entry(1),
+                        storage_entry(2),
+                    ],
+                });
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Stats pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let create_pipeline = |entry_point| {
+            gpu.device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Stats pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+        };
+        let workgroups_pipeline = create_pipeline("reduce_workgroups");
+        let partials_pipeline = create_pipeline("reduce_partials");
+
+        let partials_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Stats partials buffer"),
+            size: (workgroups.0 * workgroups.1) as u64 * PARTIAL_SIZE,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let result_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Stats result buffer"),
+            size: PARTIAL_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Stats staging buffer"),
+            size: PARTIAL_SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        StatsReduction {
+            workgroups_pipeline,
+            partials_pipeline,
+            bind_group_layout,
+            partials_buffer,
+            result_buffer,
+            staging_buffer,
+            workgroups,
+            pending: None,
+        }
+    }
+
+    /// Reduces the current contents of the iteration texture for `frame`.
+    /// Returns false without doing anything if the previous readback hasn't finished yet.
+    pub fn dispatch(
+        &mut self,
+        gpu: &GPUInterface,
+        iteration_view: &wgpu::TextureView,
+        frame: u64,
+    ) -> bool {
+        if self.pending.is_some() {
+            return false;
+        }
+        // Bound per dispatch, as a double buffered computer alternates between two textures.
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Stats bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(iteration_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.partials_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.result_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Stats encoder"),
+            });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Stats pass"),
+            });
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.set_pipeline(&self.workgroups_pipeline);
+            compute_pass.dispatch_workgroups(self.workgroups.0, self.workgroups.1, 1);
+            compute_pass.set_pipeline(&self.partials_pipeline);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(
+            &self.result_buffer,
+            0,
+            &self.staging_buffer,
+            0,
+            PARTIAL_SIZE,
+        );
+        gpu.queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = channel();
+        self.staging_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.pending = Some((frame, receiver));
+        true
+    }
+
+    /// Whether a readback is in flight, so `poll` still has work to do.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Returns the finished statistics once the readback completes, without blocking.
+    pub fn poll(&mut self, gpu: &GPUInterface) -> Option<FrameStats> {
+        let (frame, receiver) = self.pending.as_ref()?;
+        gpu.device.poll(wgpu::Maintain::Poll);
+        let stats = match receiver.try_recv() {
+            Err(TryRecvError::Empty) => return None,
+            Ok(Ok(())) => {
+                let stats = {
+                    let data = self.staging_buffer.slice(..).get_mapped_range();
+                    let words: &[u32] = bytemuck::cast_slice(&data);
+                    let escaped = words[0] as f64;
+                    let capped = words[1] as f64;
+                    let escaped_sum = f32::from_bits(words[2]) as f64;
+                    let count = (words[3] as f64).max(1.0);
+                    let min = f32::from_bits(words[4]) as f64;
+                    let max = f32::from_bits(words[5]) as f64;
+                    FrameStats {
+                        frame: *frame,
+                        escaped_fraction: escaped / count,
+                        capped_fraction: capped / count,
+                        mean_iterations: if escaped > 0.0 {
+                            escaped_sum / escaped
+                        } else {
+                            0.0
+                        },
+                        iteration_range: if escaped > 0.0 {
+                            Some((min, max))
+                        } else {
+                            None
+                        },
+                    }
+                };
+                self.staging_buffer.unmap();
+                Some(stats)
+            }
+            Ok(Err(e)) => {
+                eprintln!("Stats readback failed: {:?}", e);
+                None
+            }
+            Err(TryRecvError::Disconnected) => None,
+        };
+        self.pending = None;
+        stats
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use fontdue::{Font, FontSettings, Metrics};
+
+use crate::{
+    gpu_interface::GPUInterface,
+    math::{FVec2, UVec2},
+    overlay::OverlayBatch,
+};
+
+const FONT_BYTES: &[u8] = include_bytes!("../assets/DejaVuSans.ttf");
+
+/// Width of the glyph atlas. Its height doubles whenever a new glyph doesn't fit.
+const ATLAS_WIDTH: u32 = 512;
+const ATLAS_INITIAL_HEIGHT: u32 = 128;
+const ATLAS_MAX_HEIGHT: u32 = 4096;
+/// Empty texels left around each glyph so linear filtering doesn't bleed between neighbours.
+const GLYPH_PADDING: u32 = 1;
+
+#[derive(Copy, Clone, Debug)]
+struct Glyph {
+    metrics: Metrics,
+    /// Top-left texel of the glyph in the atlas.
+    origin: UVec2,
+}
+
+/// Coverage bitmaps of every glyph rasterized so far, packed into rows ("shelves").
+/// The CPU copy is kept so the texture can be rebuilt when the atlas grows.
+struct GlyphAtlas {
+    pixels: Vec<u8>,
+    size: UVec2,
+    cursor: UVec2,
+    shelf_height: u32,
+    texture: Option<wgpu::Texture>,
+    dirty: bool,
+}
+
+impl GlyphAtlas {
+    fn new() -> GlyphAtlas {
+        GlyphAtlas {
+            pixels: vec![0; (ATLAS_WIDTH * ATLAS_INITIAL_HEIGHT) as usize],
+            size: UVec2::new(ATLAS_WIDTH, ATLAS_INITIAL_HEIGHT),
+            cursor: UVec2::new(0, 0),
+            shelf_height: 0,
+            texture: None,
+            dirty: true,
+        }
+    }
+
+    /// Copies a glyph bitmap into the atlas, returning its origin, or None if the atlas is full.
+    fn insert(&mut self, width: u32, height: u32, bitmap: &[u8]) -> Option<UVec2> {
+        let padded = UVec2::new(width + GLYPH_PADDING, height + GLYPH_PADDING);
+        if padded.x > self.size.x {
+            return None;
+        }
+        if self.cursor.x + padded.x > self.size.x {
+            self.cursor = UVec2::new(0, self.cursor.y + self.shelf_height);
+            self.shelf_height = 0;
+        }
+        while self.cursor.y + padded.y > self.size.y {
+            if self.size.y * 2 > ATLAS_MAX_HEIGHT {
+                return None;
+            }
+            // Rows are stored top to bottom, so growing only appends empty rows.
+            self.size.y *= 2;
+            self.pixels.resize((self.size.x * self.size.y) as usize, 0);
+            self.texture = None;
+        }
+
+        let origin = self.cursor;
+        for row in 0..height {
+            let src = (row * width) as usize;
+            let dst = ((origin.y + row) * self.size.x + origin.x) as usize;
+            self.pixels[dst..dst + width as usize]
+                .copy_from_slice(&bitmap[src..src + width as usize]);
+        }
+        self.cursor.x += padded.x;
+        self.shelf_height = self.shelf_height.max(padded.y);
+        self.dirty = true;
+        Some(origin)
+    }
+
+    /// Returns whether the texture was recreated.
+    fn upload(&mut self, gpu: &GPUInterface) -> bool {
+        if !self.dirty && self.texture.is_some() {
+            return false;
+        }
+        let recreated = self.texture.is_none();
+        let extent = wgpu::Extent3d {
+            width: self.size.x,
+            height: self.size.y,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.texture.get_or_insert_with(|| {
+            gpu.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Glyph atlas"),
+                size: extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            })
+        });
+        gpu.queue.write_texture(
+            texture.as_image_copy(),
+            &self.pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(self.size.x),
+                rows_per_image: std::num::NonZeroU32::new(self.size.y),
+            },
+            extent,
+        );
+        self.dirty = false;
+        recreated
+    }
+}
+
+/// Lays out strings into textured quads for the overlay pass.
+/// Glyphs are rasterized on first use at the pixel size they're drawn at and cached in the atlas.
+pub struct TextRenderer {
+    font: Font,
+    glyphs: HashMap<(char, u32), Glyph>,
+    atlas: GlyphAtlas,
+    /// Physical pixels per logical pixel; text sizes are given in logical pixels.
+    pub scale_factor: f32,
+}
+
+impl TextRenderer {
+    pub fn new(scale_factor: f32) -> TextRenderer {
+        TextRenderer {
+            font: Font::from_bytes(FONT_BYTES, FontSettings::default())
+                .expect("bundled font is valid"),
+            glyphs: HashMap::new(),
+            atlas: GlyphAtlas::new(),
+            scale_factor,
+        }
+    }
+
+    /// Physical pixel size used for text that is `size` logical pixels tall.
+    fn pixel_size(&self, size: f32) -> f32 {
+        (size * self.scale_factor).round().max(1.0)
+    }
+
+    fn glyph(&mut self, c: char, px: f32) -> Option<Glyph> {
+        let key = (c, px as u32);
+        if let Some(glyph) = self.glyphs.get(&key) {
+            return Some(*glyph);
+        }
+        let (metrics, bitmap) = self.font.rasterize(c, px);
+        let origin = self
+            .atlas
+            .insert(metrics.width as u32, metrics.height as u32, &bitmap)?;
+        let glyph = Glyph { metrics, origin };
+        self.glyphs.insert(key, glyph);
+        Some(glyph)
+    }
+
+    /// Advance width in physical pixels of the widest line of `text` at `size` logical pixels.
+    pub fn measure(&self, text: &str, size: f32) -> f32 {
+        let px = self.pixel_size(size);
+        text.lines()
+            .map(|line| {
+                let mut width = 0.0;
+                let mut previous = None;
+                for c in line.chars() {
+                    if let Some(p) = previous {
+                        width += self.font.horizontal_kern(p, c, px).unwrap_or(0.0);
+                    }
+                    width += self.font.metrics(c, px).advance_width;
+                    previous = Some(c);
+                }
+                width
+            })
+            .fold(0.0, f32::max)
+    }
+
+    /// Distance in physical pixels between the baselines of consecutive lines.
+    pub fn line_height(&self, size: f32) -> f32 {
+        let px = self.pixel_size(size);
+        self.font
+            .horizontal_line_metrics(px)
+            .map_or(px * 1.2, |m| m.new_line_size)
+    }
+
+    /// Adds `text` with its top-left corner at `position` (physical pixels) to the overlay.
+    /// The quads are ordinary overlay triangles, so all text shares the overlay's single draw call.
+    pub fn add_text(
+        &mut self,
+        overlay: &mut OverlayBatch,
+        text: &str,
+        position: FVec2,
+        size: f32,
+        color: [f32; 4],
+    ) {
+        let px = self.pixel_size(size);
+        let ascent = self
+            .font
+            .horizontal_line_metrics(px)
+            .map_or(px, |m| m.ascent);
+        let line_height = self.line_height(size);
+
+        let mut baseline = position.y + ascent;
+        for line in text.lines() {
+            let mut pen = position.x;
+            let mut previous = None;
+            for c in line.chars() {
+                if let Some(p) = previous {
+                    pen += self.font.horizontal_kern(p, c, px).unwrap_or(0.0);
+                }
+                previous = Some(c);
+                let glyph = match self.glyph(c, px) {
+                    Some(glyph) => glyph,
+                    None => continue,
+                };
+                let m = glyph.metrics;
+                if m.width > 0 && m.height > 0 {
+                    let min = FVec2 {
+                        x: (pen + m.xmin as f32).round(),
+                        y: (baseline - m.ymin as f32 - m.height as f32).round(),
+                    };
+                    let max = FVec2 {
+                        x: min.x + m.width as f32,
+                        y: min.y + m.height as f32,
+                    };
+                    let texel = FVec2 {
+                        x: glyph.origin.x as f32,
+                        y: glyph.origin.y as f32,
+                    };
+                    overlay.add_glyph(min, max, texel, color);
+                }
+                pen += m.advance_width;
+            }
+            baseline += line_height;
+        }
+    }
+
+    /// Uploads glyphs added since the last frame. Recreates the texture if the atlas grew,
+    /// and returns whether it did, so views of the old one can be replaced.
+    pub fn upload(&mut self, gpu: &GPUInterface) -> bool {
+        self.atlas.upload(gpu)
+    }
+
+    /// View of the atlas texture, available after the first `upload`.
+    pub fn atlas_view(&self) -> Option<wgpu::TextureView> {
+        self.atlas
+            .texture
+            .as_ref()
+            .map(|t| t.create_view(&wgpu::TextureViewDescriptor::default()))
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Number of frames the averages are taken over.
+const WINDOW_FRAMES: usize = 120;
+
+/// CPU time of one rendered frame, split by phase.
+#[derive(Copy, Clone, Debug)]
+struct FrameSample {
+    /// Time since the previous frame started, for the frame rate.
+    interval: Option<Duration>,
+    /// Compute dispatch and submit, zero when the iteration texture was reused.
+    compute: Duration,
+    /// Colorizing, drawing and presenting the surface.
+    present: Duration,
+}
+
+/// Averages over the last `WINDOW_FRAMES` frames, in milliseconds.
+#[derive(Copy, Clone, Debug)]
+pub struct TimingSummary {
+    pub fps: f64,
+    pub frame_ms: f64,
+    pub compute_ms: f64,
+    pub present_ms: f64,
+}
+
+/// Rolling window of frame times, shown with F3.
+#[derive(Default)]
+pub struct FrameTimer {
+    samples: VecDeque<FrameSample>,
+    last_frame: Option<Instant>,
+}
+
+impl FrameTimer {
+    /// Records a frame that started at `start`.
+    pub fn record(&mut self, start: Instant, compute: Duration, present: Duration) {
+        let interval = self.last_frame.map(|last| start.duration_since(last));
+        self.last_frame = Some(start);
+        if self.samples.len() == WINDOW_FRAMES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(FrameSample {
+            interval,
+            compute,
+            present,
+        });
+    }
+
+    /// Starts over, so that frames from before an idle gap don't skew the averages.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.last_frame = None;
+    }
+
+    pub fn summary(&self) -> Option<TimingSummary> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let count = self.samples.len() as f64;
+        let compute_ms = self.samples.iter().map(|s| ms(s.compute)).sum::<f64>() / count;
+        let present_ms = self.samples.iter().map(|s| ms(s.present)).sum::<f64>() / count;
+        let intervals: Vec<f64> = self
+            .samples
+            .iter()
+            .filter_map(|s| s.interval.map(ms))
+            .collect();
+        let fps = if intervals.is_empty() {
+            0.0
+        } else {
+            1000.0 * intervals.len() as f64 / intervals.iter().sum::<f64>()
+        };
+        Some(TimingSummary {
+            fps,
+            frame_ms: compute_ms + present_ms,
+            compute_ms,
+            present_ms,
+        })
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use winit::event::TouchPhase;
+
+use crate::math::FVec2;
+
+/// Longest a touch can last, and longest the gap between two taps can be, for a double tap.
+const DOUBLE_TAP_TIME: Duration = Duration::from_millis(300);
+
+/// Pixels a finger may wander and still tap.
+const TAP_SLOP: f32 = 16.0;
+
+/// Navigation a touch event amounts to, in pixels of the window.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TouchGesture {
+    /// One finger moved from `from` to `to`.
+    Pan { from: FVec2, to: FVec2 },
+    /// Two fingers moved their midpoint from `from` to `to` and their distance by 1 / `scale`,
+    /// so `scale` is the factor the zoom changes by: below 1 when the fingers spread.
+    Pinch { from: FVec2, to: FVec2, scale: f32 },
+    /// Two quick taps close together.
+    DoubleTap(FVec2),
+}
+
+/// Turns `WindowEvent::Touch` events into gestures, tracking every finger on the screen by id.
+/// Gestures are deltas between events, so fingers can come and go mid-gesture without the
+/// view jumping.
+#[derive(Default)]
+pub struct TouchTracker {
+    touches: BTreeMap<u64, FVec2>,
+    /// The only finger down, where and when it went down, while it could still be a tap.
+    tap_start: Option<(u64, FVec2, Instant)>,
+    /// Where and when the last tap ended, waiting for a second one.
+    last_tap: Option<(FVec2, Instant)>,
+}
+
+impl TouchTracker {
+    /// Whether any finger is on the screen.
+    pub fn is_active(&self) -> bool {
+        !self.touches.is_empty()
+    }
+
+    pub fn update(
+        &mut self,
+        id: u64,
+        phase: TouchPhase,
+        position: FVec2,
+        now: Instant,
+    ) -> Option<TouchGesture> {
+        match phase {
+            TouchPhase::Started => {
+                self.tap_start = self.touches.is_empty().then_some((id, position, now));
+                self.touches.insert(id, position);
+                None
+            }
+            TouchPhase::Moved => {
+                let before = self.pair();
+                let previous = self.touches.insert(id, position)?;
+                if self
+                    .tap_start
+                    .is_some_and(|(_, start, _)| (position - start).length() > TAP_SLOP)
+                {
+                    self.tap_start = None;
+                }
+                match (before, self.pair()) {
+                    (Some((a, b)), Some((c, d))) => Some(TouchGesture::Pinch {
+                        from: a.lerp(b, 0.5),
+                        to: c.lerp(d, 0.5),
+                        scale: (b - a).length() / (d - c).length().max(1.0),
+                    }),
+                    _ if self.touches.len() == 1 => Some(TouchGesture::Pan {
+                        from: previous,
+                        to: position,
+                    }),
+                    _ => None,
+                }
+            }
+            TouchPhase::Ended => {
+                self.touches.remove(&id);
+                let tap = self.tap_start.take().filter(|&(tap_id, _, started)| {
+                    tap_id == id && now.duration_since(started) < DOUBLE_TAP_TIME
+                })?;
+                match self.last_tap.take() {
+                    Some((last, ended))
+                        if now.duration_since(ended) < DOUBLE_TAP_TIME
+                            && (tap.1 - last).length() < 2.0 * TAP_SLOP =>
+                    {
+                        Some(TouchGesture::DoubleTap(tap.1))
+                    }
+                    _ => {
+                        self.last_tap = Some((tap.1, now));
+                        None
+                    }
+                }
+            }
+            TouchPhase::Cancelled => {
+                self.touches.remove(&id);
+                self.tap_start = None;
+                None
+            }
+        }
+    }
+
+    /// The two fingers that pinch, the first two down by id, if there are that many.
+    fn pair(&self) -> Option<(FVec2, FVec2)> {
+        let mut touches = self.touches.values();
+        Some((*touches.next()?, *touches.next()?))
+    }
+}
@@ -0,0 +1,856 @@
+use mandelbrot_compute::{
+    colorize::Colorizer,
+    compare::compare_precisions,
+    computer::{
+        ColoringMode, ComputeSetup, FractalKind, MandelbrotParams, Precision, Tiling,
+        ITERATION_TEXEL_SIZE, ITERATION_TEXTURE_FORMAT, NEWTON_TOLERANCE,
+    },
+    cpu_reference::{self, Formula},
+    detail::{DetailReduction, DetailScores, DETAIL_GRID},
+    headless::{self, HeadlessOptions},
+    histogram::{Histogram, HISTOGRAM_BINS},
+    palette::{builtin_palettes, ColorMapping, MappingMode, Palette, LUT_SIZE},
+    poster::{self, PosterJob},
+    range::RangeReduction,
+    readback::{Readback, ReadbackId},
+    state::StartupOptions,
+    Computer, DVec2, FVec2, GPUInterface, SampleLocation, UVec2,
+};
+
+const SIZE: u32 = 128;
+const MAX_ITERATIONS: i32 = 100;
+
+/// Largest difference in smooth iterations still counted as a match. The GPU iterates in f32,
+/// so values drift slightly from the f64 reference.
+const SMOOTH_TOLERANCE: f32 = 0.05;
+/// Fraction of pixels allowed to disagree. Points right at the boundary of the set can
+/// escape in one precision but not the other.
+const MAX_MISMATCH_FRACTION: f64 = 0.02;
+
+/// A headless device, or None when the machine has no GPU so the test can be skipped.
+fn gpu() -> Option<GPUInterface> {
+    match GPUInterface::headless() {
+        Ok(gpu) => Some(gpu),
+        Err(e) => {
+            eprintln!("Skipping GPU test: {}", e);
+            None
+        }
+    }
+}
+
+/// Renders `view` in f32 on the GPU and counts the pixels that disagree with the CPU.
+fn count_mismatches(gpu: &GPUInterface, view: &SampleLocation) -> usize {
+    let size = UVec2::new(SIZE, SIZE);
+    let mut computer = Computer::new(size, gpu);
+    assert!(computer.set_precision(Precision::F32));
+    let params = view.to_mandlebrot_params(MAX_ITERATIONS);
+    let bytes = computer.compute_to_vec(gpu, &params, size).unwrap();
+    assert_eq!(bytes.len(), (SIZE * SIZE) as usize * ITERATION_TEXEL_SIZE);
+    let texels: &[[f32; 4]] = bytemuck::cast_slice(&bytes);
+
+    let formula = Formula {
+        power: 2.0,
+        conjugate: false,
+    };
+    let mut mismatches = 0;
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let [smooth, _, escaped, _] = texels[(y * SIZE + x) as usize];
+            let DVec2 { x: re, y: im } = view.screen_to_plane(
+                FVec2 {
+                    x: x as f32,
+                    y: y as f32,
+                },
+                size,
+            );
+            let reference = cpu_reference::mandelbrot(
+                re,
+                im,
+                formula,
+                MAX_ITERATIONS,
+                params.escape_radius as f64,
+            );
+            let matches = (escaped > 0.5) == reference.escaped
+                && (smooth - reference.smooth_iterations as f32).abs() <= SMOOTH_TOLERANCE;
+            if !matches {
+                mismatches += 1;
+            }
+        }
+    }
+    mismatches
+}
+
+fn assert_matches_reference(view: SampleLocation) {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    let mismatches = count_mismatches(&gpu, &view);
+    let fraction = mismatches as f64 / (SIZE * SIZE) as f64;
+    assert!(
+        fraction <= MAX_MISMATCH_FRACTION,
+        "{} of {} pixels differ from the CPU reference",
+        mismatches,
+        SIZE * SIZE
+    );
+}
+
+#[test]
+fn full_set_matches_cpu_reference() {
+    assert_matches_reference(SampleLocation::default());
+}
+
+/// The set is symmetric about the real axis, so only an off-axis view shows which way up the
+/// GPU draws it: centered on -0.12+0.5i, the top row is above the period-3 bulb and escapes,
+/// while the bottom row lies on the real axis inside the main cardioid.
+#[test]
+fn positive_imaginary_renders_at_the_top() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    let size = UVec2::new(SIZE, SIZE);
+    let computer = Computer::new(size, &gpu);
+    let escaped_at = |view: &SampleLocation, x: u32, y: u32| {
+        let params = view.to_mandlebrot_params(MAX_ITERATIONS);
+        let bytes = computer.compute_to_vec(&gpu, &params, size).unwrap();
+        let texels: &[[f32; 4]] = bytemuck::cast_slice(&bytes);
+        texels[(y * SIZE + x) as usize][2] > 0.5
+    };
+
+    // -0.75+0i, in the set, at a quarter of the width on the middle row of the default view.
+    assert!(!escaped_at(&SampleLocation::default(), SIZE / 8, SIZE / 2));
+    assert!(escaped_at(&SampleLocation::default(), 0, 0));
+
+    let view = SampleLocation::new(DVec2::new(-0.12, 0.5), 0.5);
+    assert!(escaped_at(&view, SIZE / 2, 0));
+    assert!(!escaped_at(&view, SIZE / 2, SIZE - 1));
+}
+
+#[test]
+fn seahorse_valley_matches_cpu_reference() {
+    assert_matches_reference(SampleLocation::new(DVec2::new(-0.745, 0.11), 0.02));
+}
+
+#[test]
+fn point_trap_gives_interior_a_value() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    let size = UVec2::new(SIZE, SIZE);
+    let mut computer = Computer::new(size, &gpu);
+    assert!(computer.set_precision(Precision::F32));
+    let mut params = SampleLocation::default().to_mandlebrot_params(MAX_ITERATIONS);
+    params.coloring_mode = ColoringMode::PointTrap as u32;
+    params.interior_check = 0;
+    let bytes = computer.compute_to_vec(&gpu, &params, size).unwrap();
+    let texels: &[[f32; 4]] = bytemuck::cast_slice(&bytes);
+
+    let interior: Vec<f32> = texels
+        .iter()
+        .filter(|texel| texel[2] < 0.5)
+        .map(|texel| texel[0])
+        .collect();
+    assert!(!interior.is_empty());
+    // Interior orbits stay within |z| <= 2, so they never get further than 2 from the origin
+    // trap; the trap value is 64 per unit of distance.
+    assert!(interior.iter().all(|&value| (0.0..=128.0).contains(&value)));
+}
+
+#[test]
+fn stripes_mix_into_the_smooth_iteration_count() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    let size = UVec2::new(SIZE, SIZE);
+    let mut computer = Computer::new(size, &gpu);
+    let escape_time = SampleLocation::default().to_mandlebrot_params(MAX_ITERATIONS);
+    let mut stripes = escape_time;
+    stripes.coloring_mode = ColoringMode::Stripes as u32;
+    for precision in [Precision::F32, Precision::DoubleSingle, Precision::F64] {
+        if !computer.set_precision(precision) {
+            continue;
+        }
+        let compute = |params: &MandelbrotParams| {
+            let bytes = computer.compute_to_vec(&gpu, params, size).unwrap();
+            bytemuck::cast_slice::<u8, [f32; 4]>(&bytes).to_vec()
+        };
+        let smooth = compute(&escape_time);
+
+        // Without any mix, stripe mode writes the smooth iteration count unchanged.
+        stripes.stripe_mix = 0.0;
+        assert_eq!(compute(&stripes), smooth);
+
+        // Stripes only: each term lies in [0, 1], and so does their average, which the
+        // palette value scales by 64.
+        stripes.stripe_mix = 1.0;
+        let only_stripes = compute(&stripes);
+        let escaped: Vec<f32> = only_stripes
+            .iter()
+            .filter(|texel| texel[2] > 0.5)
+            .map(|texel| texel[0])
+            .collect();
+        assert!(!escaped.is_empty());
+        assert!(escaped.iter().all(|&value| (0.0..=64.0).contains(&value)));
+    }
+}
+
+#[test]
+fn orbit_ends_where_escape_time_stops() {
+    let formula = Formula {
+        power: 2.0,
+        conjugate: false,
+    };
+    let escaping = cpu_reference::orbit((0.0, 0.0), (0.5, 0.5), formula, MAX_ITERATIONS, 2.0);
+    let result = cpu_reference::mandelbrot(0.5, 0.5, formula, MAX_ITERATIONS, 2.0);
+    assert!(result.escaped);
+    let (last, inside) = escaping.split_last().unwrap();
+    assert!(inside.iter().all(|(x, y)| (x * x + y * y).sqrt() <= 2.0));
+    assert_eq!((last.0 * last.0 + last.1 * last.1).sqrt(), result.z_abs);
+
+    let bounded = cpu_reference::orbit((0.0, 0.0), (-1.0, 0.0), formula, MAX_ITERATIONS, 2.0);
+    assert_eq!(bounded.len(), MAX_ITERATIONS as usize + 1);
+}
+
+/// The params reach the shaders as raw uniform bytes, so every kind has to come back from
+/// them unchanged, with its fields at the offsets the WGSL struct reads them from.
+#[test]
+fn params_round_trip_through_uniform_bytes() {
+    let mut kind = FractalKind::Mandelbrot;
+    loop {
+        let mut params = kind
+            .default_view()
+            .to_mandlebrot_params(kind.default_iterations());
+        params.kind = kind as u32;
+        params.power = kind.default_power();
+        params.julia_c = [-0.8, 0.156];
+        params.coloring_mode = ColoringMode::Stripes as u32;
+        params.tile_origin = [512, 256];
+        let bytes = bytemuck::bytes_of(&params).to_vec();
+        assert_eq!(bytes.len(), 128);
+        let read: MandelbrotParams = bytemuck::pod_read_unaligned(&bytes);
+        assert_eq!(read, params);
+        let word =
+            |offset: usize| u32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        assert_eq!(word(24) as i32, kind.default_iterations());
+        assert_eq!(f32::from_bits(word(32)), -0.8);
+        assert_eq!(f32::from_bits(word(44)), kind.default_power());
+        assert_eq!(word(48), kind as u32);
+        assert_eq!(word(72), ColoringMode::Stripes as u32);
+        assert_eq!((word(104), word(108)), (512, 256));
+        kind = kind.next();
+        if kind == FractalKind::Mandelbrot {
+            break;
+        }
+    }
+}
+
+#[test]
+fn newton_reference_finds_the_cube_roots() {
+    let tolerance = NEWTON_TOLERANCE as f64;
+    // Start next to each root of z^3 - 1, at angles 0, 120 and 240 degrees.
+    for k in 0..3 {
+        let angle = std::f64::consts::TAU * k as f64 / 3.0;
+        let z0 = (1.1 * angle.cos(), 1.1 * angle.sin());
+        let result = cpu_reference::newton(z0, (0.0, 0.0), 3, MAX_ITERATIONS, tolerance);
+        assert_eq!(result.root, Some(k));
+        assert!(result.iterations < 10);
+    }
+    // The derivative vanishes at 0, so the step is undefined there.
+    let stuck = cpu_reference::newton((0.0, 0.0), (0.0, 0.0), 3, MAX_ITERATIONS, tolerance);
+    assert_eq!(stuck.root, None);
+}
+
+#[test]
+fn newton_roots_match_cpu_reference() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    let size = UVec2::new(SIZE, SIZE);
+    let mut computer = Computer::new(size, &gpu);
+    let view = FractalKind::Newton.default_view();
+    let mut params = view.to_mandlebrot_params(MAX_ITERATIONS);
+    params.kind = FractalKind::Newton as u32;
+    params.power = FractalKind::Newton.default_power();
+    params.interior_check = 0;
+    assert_eq!(params.newton_roots(), 3);
+    for precision in [Precision::F32, Precision::DoubleSingle, Precision::F64] {
+        if !computer.set_precision(precision) {
+            continue;
+        }
+        let bytes = computer.compute_to_vec(&gpu, &params, size).unwrap();
+        let texels: &[[f32; 4]] = bytemuck::cast_slice(&bytes);
+        let mut mismatches = 0;
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let [_, root, converged, _] = texels[(y * SIZE + x) as usize];
+                let point = view.screen_to_plane(
+                    FVec2 {
+                        x: x as f32,
+                        y: y as f32,
+                    },
+                    size,
+                );
+                let reference = cpu_reference::newton(
+                    (point.x, point.y),
+                    (0.0, 0.0),
+                    3,
+                    MAX_ITERATIONS,
+                    params.newton_tolerance as f64,
+                );
+                if (converged > 0.5).then_some(root as u32) != reference.root {
+                    mismatches += 1;
+                }
+            }
+        }
+        // Basin boundaries are fractal, so points on them can go either way.
+        let fraction = mismatches as f64 / (SIZE * SIZE) as f64;
+        assert!(
+            fraction <= MAX_MISMATCH_FRACTION,
+            "{:?}: {} pixels converged to another root than the CPU reference",
+            precision,
+            mismatches
+        );
+    }
+}
+
+#[test]
+fn custom_formula_matches_builtin_mandelbrot() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    let size = UVec2::new(SIZE, SIZE);
+    let mut computer = Computer::new(size, &gpu);
+    assert!(computer.set_precision(Precision::F32));
+    let mut params = SampleLocation::default().to_mandlebrot_params(MAX_ITERATIONS);
+    params.interior_check = 0;
+    let builtin = computer.compute_to_vec(&gpu, &params, size).unwrap();
+    computer
+        .set_formula(&gpu, "z = z^2 + c".parse().unwrap())
+        .unwrap();
+    params.kind = FractalKind::Custom as u32;
+    let custom = computer.compute_to_vec(&gpu, &params, size).unwrap();
+    let builtin: &[[f32; 4]] = bytemuck::cast_slice(&builtin);
+    let custom: &[[f32; 4]] = bytemuck::cast_slice(&custom);
+    for (builtin, custom) in builtin.iter().zip(custom) {
+        assert_eq!(builtin[2], custom[2]);
+        // The custom formula starts at c, one step ahead of the built-in one's 0.
+        if builtin[2] > 0.5 {
+            assert!((builtin[0] - (custom[0] + 1.0)).abs() < 1e-4);
+        }
+    }
+}
+
+#[test]
+fn tiled_compute_matches_single_dispatch() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    let size = UVec2::new(SIZE + 37, SIZE);
+    let mut computer = Computer::new(size, &gpu);
+    let params =
+        SampleLocation::new(DVec2::new(-0.745, 0.11), 0.02).to_mandlebrot_params(MAX_ITERATIONS);
+    let whole = computer.compute_to_vec(&gpu, &params, size).unwrap();
+    // Small tiles that don't divide the size, so the edge tiles are cut.
+    computer.set_tiling(Some(Tiling {
+        tile_size: 48,
+        wait_between: true,
+    }));
+    let tiled = computer.compute_to_vec(&gpu, &params, size).unwrap();
+    assert!(whole == tiled, "tiles differ from the single dispatch");
+}
+
+#[test]
+fn repeated_runs_reuse_the_params_bindings() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    let mut computer = Computer::new(UVec2::new(SIZE, SIZE), &gpu);
+    let created = computer.bindings_created();
+    let mut view = SampleLocation::default();
+    for _ in 0..100 {
+        view.zoom_in();
+        let params = view.to_mandlebrot_params(MAX_ITERATIONS);
+        computer.run(&gpu, &params);
+        computer.run_draft(&gpu, &params);
+        computer.run_preview(&gpu, &params);
+    }
+    assert_eq!(computer.bindings_created(), created);
+
+    // Only replacing a texture makes a new one, and double buffered runs swap between two.
+    computer.set_double_buffered(&gpu, true);
+    assert_eq!(computer.bindings_created(), created + 1);
+    for _ in 0..10 {
+        computer.run(&gpu, &view.to_mandlebrot_params(MAX_ITERATIONS));
+    }
+    assert_eq!(computer.bindings_created(), created + 1);
+    computer.set_output_size(&gpu, UVec2::new(SIZE / 2, SIZE / 2));
+    assert_eq!(computer.bindings_created(), created + 4);
+}
+
+#[test]
+fn double_buffered_runs_keep_the_previous_frame() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    let size = UVec2::new(SIZE, SIZE);
+    let mut computer = Computer::new(size, &gpu);
+    computer.set_double_buffered(&gpu, true);
+    let first = SampleLocation::default().to_mandlebrot_params(MAX_ITERATIONS);
+    let second =
+        SampleLocation::new(DVec2::new(-0.745, 0.11), 0.02).to_mandlebrot_params(MAX_ITERATIONS);
+
+    let front = computer.front();
+    computer.run(&gpu, &first);
+    assert_ne!(computer.front(), front);
+    computer.run(&gpu, &second);
+    assert_eq!(computer.front(), front);
+
+    // The second run went to the other texture, so the first frame is still intact.
+    let mut readback = Readback::new("Double buffer readback", 2);
+    let whole = |readback: &mut Readback, texture| {
+        let id = readback
+            .request(&gpu, texture, UVec2::new(0, 0), size, ITERATION_TEXEL_SIZE)
+            .unwrap();
+        readback.wait(&gpu, id).unwrap()
+    };
+    let current = whole(&mut readback, &computer.iteration_texture);
+    let previous = whole(&mut readback, computer.target_texture());
+    assert!(current == computer.compute_to_vec(&gpu, &second, size).unwrap());
+    assert!(previous == computer.compute_to_vec(&gpu, &first, size).unwrap());
+}
+
+#[test]
+fn histogram_matches_cpu_counts() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    let size = UVec2::new(SIZE, SIZE);
+    let mut computer = Computer::new(size, &gpu);
+    assert!(computer.set_precision(Precision::F32));
+    let params =
+        SampleLocation::new(DVec2::new(-0.745, 0.11), 0.02).to_mandlebrot_params(MAX_ITERATIONS);
+    computer.run(&gpu, &params);
+    computer.build_histogram(&gpu, MAX_ITERATIONS);
+    let (max_iterations, cdf) = computer.histogram.read_cdf(&gpu).unwrap();
+    assert_eq!(max_iterations, MAX_ITERATIONS as f32);
+
+    let bytes = computer.compute_to_vec(&gpu, &params, size).unwrap();
+    let texels: &[[f32; 4]] = bytemuck::cast_slice(&bytes);
+    let mut bins = vec![0u32; HISTOGRAM_BINS];
+    for [smooth, _, escaped, _] in texels {
+        if *escaped > 0.5 {
+            let position = (smooth / MAX_ITERATIONS as f32).clamp(0.0, 1.0) * HISTOGRAM_BINS as f32;
+            bins[(position as usize).min(HISTOGRAM_BINS - 1)] += 1;
+        }
+    }
+    let total: u32 = bins.iter().sum();
+    let mut sum = 0;
+    for (bin, &count) in bins.iter().enumerate() {
+        sum += count;
+        let expected = sum as f32 / total.max(1) as f32;
+        assert!(
+            (cdf[bin] - expected).abs() < 1e-6,
+            "bin {}: GPU {} CPU {}",
+            bin,
+            cdf[bin],
+            expected
+        );
+    }
+}
+
+/// An iteration texture holding `texels`, for feeding the reductions known data.
+fn synthetic_iteration_texture(
+    gpu: &GPUInterface,
+    size: wgpu::Extent3d,
+    texels: &[[f32; 4]],
+) -> wgpu::Texture {
+    let texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Synthetic iteration texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: ITERATION_TEXTURE_FORMAT,
+        usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+    gpu.queue.write_texture(
+        texture.as_image_copy(),
+        bytemuck::cast_slice(texels),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: std::num::NonZeroU32::new(size.width * ITERATION_TEXEL_SIZE as u32),
+            rows_per_image: std::num::NonZeroU32::new(size.height),
+        },
+        size,
+    );
+    texture
+}
+
+#[test]
+fn range_reduction_matches_cpu() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    // Larger than one texel per invocation of the first pass, and not a multiple of anything.
+    let size = wgpu::Extent3d {
+        width: 301,
+        height: 253,
+        depth_or_array_layers: 1,
+    };
+    // A hash keeps the values spread out without a random number dependency; glitched texels
+    // carry values outside the escaped range, which the reduction has to skip.
+    let texels: Vec<[f32; 4]> = (0..size.width * size.height)
+        .map(|i| {
+            let hash = i.wrapping_mul(2654435761) >> 8;
+            let value = (hash % 100_000) as f32 / 100.0 + 3.0;
+            match hash % 7 {
+                0 => [value, 0.0, 0.0, 0.0],
+                1 => [value * 10.0 - 5000.0, 0.0, 1.0, 1.0],
+                _ => [value, 2.0, 1.0, 0.0],
+            }
+        })
+        .collect();
+    let texture = synthetic_iteration_texture(&gpu, size, &texels);
+
+    let reduction = RangeReduction::new(&gpu);
+    reduction.build(
+        &gpu,
+        &texture.create_view(&wgpu::TextureViewDescriptor::default()),
+    );
+    let range = reduction.read_range(&gpu).unwrap();
+
+    let escaped = texels
+        .iter()
+        .filter(|texel| texel[2] > 0.5 && texel[3] < 0.5)
+        .map(|texel| texel[0]);
+    let expected = (
+        escaped.clone().fold(f32::INFINITY, f32::min),
+        escaped.fold(f32::NEG_INFINITY, f32::max),
+    );
+    assert_eq!(range, Some(expected));
+}
+
+/// Detail scores of a `SIZE` square texture whose smooth iteration count is `smooth(x, y)`.
+fn detail_scores(gpu: &GPUInterface, smooth: impl Fn(u32, u32) -> f32) -> DetailScores {
+    let computer = Computer::new(UVec2::new(SIZE, SIZE), gpu);
+    let texels: Vec<[f32; 4]> = (0..SIZE * SIZE)
+        .map(|i| [smooth(i % SIZE, i / SIZE), 0.0, 1.0, 0.0])
+        .collect();
+    let texture = synthetic_iteration_texture(gpu, computer.texture_size, &texels);
+    let mut reduction = DetailReduction::new(gpu, &computer);
+    assert!(reduction.dispatch(
+        gpu,
+        &texture.create_view(&wgpu::TextureViewDescriptor::default()),
+        7
+    ));
+    gpu.device.poll(wgpu::Maintain::Wait);
+    let scores = reduction.poll(gpu).unwrap();
+    assert_eq!(scores.frame, 7);
+    assert_eq!(scores.scores.len(), (DETAIL_GRID * DETAIL_GRID) as usize);
+    scores
+}
+
+#[test]
+fn flat_texture_has_no_detail() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    let scores = detail_scores(&gpu, |_, _| 42.0);
+    assert!(scores.scores.iter().all(|&score| score == 0.0));
+}
+
+#[test]
+fn step_edge_scores_only_its_blocks() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    // A vertical step inside the third column of blocks; the Sobel kernel only sees it in the
+    // two pixel columns either side, which both lie in that column.
+    let block = SIZE / DETAIL_GRID;
+    let edge = 2 * block + block / 2;
+    let scores = detail_scores(&gpu, |x, _| if x < edge { 10.0 } else { 30.0 });
+    for (i, &score) in scores.scores.iter().enumerate() {
+        if i as u32 % DETAIL_GRID == 2 {
+            assert!(score > 0.0, "block {} scored {}", i, score);
+        } else {
+            assert_eq!(score, 0.0, "block {}", i);
+        }
+    }
+    assert_eq!(scores.best().unwrap().0.x, 2);
+}
+
+/// Palette lookup index the colorizer picks at palette position `t`.
+fn lut_index(t: f32) -> i32 {
+    (t * (LUT_SIZE - 1) as f32 + 0.5) as i32
+}
+
+#[test]
+fn each_mapping_mode_matches_its_reference() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    const WIDTH: u32 = 256;
+    const MAX: i32 = 200;
+    let size = wgpu::Extent3d {
+        width: WIDTH,
+        height: 1,
+        depth_or_array_layers: 1,
+    };
+    let smooth: Vec<f32> = (0..WIDTH).map(|x| x as f32 * 0.75 + 0.5).collect();
+    let texels: Vec<[f32; 4]> = smooth.iter().map(|&s| [s, 0.0, 1.0, 0.0]).collect();
+    let texture = synthetic_iteration_texture(&gpu, size, &texels);
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let histogram = Histogram::new(&gpu);
+    histogram.build(&gpu, &view, size, MAX);
+    let (_, cdf) = histogram.read_cdf(&gpu).unwrap();
+    let range = RangeReduction::new(&gpu);
+    let colorizer = Colorizer::new(
+        &gpu,
+        UVec2::new(WIDTH, 1),
+        &view,
+        histogram.cdf_buffer(),
+        range.range_buffer(),
+    );
+    // A gray ramp turns every channel of the output into the palette index it came from.
+    let gray = Palette::from_rgb8("Gray", &[(0.0, [0, 0, 0]), (1.0, [255, 255, 255])]);
+    colorizer.set_palette(&gpu, &gray);
+
+    // Same interpolation as equalize in colorize.wgsl.
+    let equalize = |x: f32| {
+        let position = (x / MAX as f32).clamp(0.0, 1.0) * HISTOGRAM_BINS as f32;
+        let bin = (position as usize).min(HISTOGRAM_BINS - 1);
+        let below = if bin > 0 { cdf[bin - 1] } else { 0.0 };
+        below + (cdf[bin] - below) * position.fract()
+    };
+    let render = |mapping: &ColorMapping| {
+        colorizer.run(&gpu, &mapping.color_params());
+        let image = colorizer.read_output(&gpu).unwrap();
+        image.pixels().map(|p| p[0] as i32).collect::<Vec<i32>>()
+    };
+    let mut outputs = Vec::new();
+    for mode in [
+        MappingMode::Linear,
+        MappingMode::Log,
+        MappingMode::Sqrt,
+        MappingMode::Power,
+        MappingMode::Histogram,
+    ] {
+        let mapping = ColorMapping {
+            mode,
+            exponent: 0.7,
+            density: 20.0,
+            offset: 0.1,
+            ..Default::default()
+        };
+        let actual = render(&mapping);
+        for (x, (&s, &index)) in smooth.iter().zip(&actual).enumerate() {
+            let position = match mode {
+                MappingMode::Histogram => equalize(s),
+                _ => mapping.apply_mode(s) / mapping.density,
+            };
+            let expected = lut_index((position + mapping.offset).fract());
+            // Positions right at a palette wrap may round to either end.
+            let difference = (index - expected).rem_euclid(LUT_SIZE as i32);
+            assert!(
+                difference <= 1 || difference >= LUT_SIZE as i32 - 1,
+                "{:?} at x = {}: index {} expected {}",
+                mode,
+                x,
+                index,
+                expected
+            );
+        }
+        if mode == MappingMode::Histogram {
+            // Histogram mapping bypasses the density.
+            let denser = ColorMapping {
+                density: 3.0,
+                ..mapping
+            };
+            assert_eq!(render(&denser), actual);
+        }
+        assert!(
+            !outputs.contains(&actual),
+            "{:?} repeats another mode",
+            mode
+        );
+        outputs.push(actual);
+    }
+}
+
+#[test]
+fn readback_every_frame_recycles_its_buffers() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    const FRAMES: u32 = 300;
+    const BUFFERS: usize = 3;
+    let size = UVec2::new(SIZE, SIZE);
+    let mut computer = Computer::new(size, &gpu);
+    let params = SampleLocation::default().to_mandlebrot_params(MAX_ITERATIONS);
+    let expected = computer.compute_to_vec(&gpu, &params, size).unwrap();
+    let texel = |pixel: UVec2| {
+        let start = (pixel.y * SIZE + pixel.x) as usize * ITERATION_TEXEL_SIZE;
+        &expected[start..start + ITERATION_TEXEL_SIZE]
+    };
+
+    let mut readback = Readback::new("Stress readback buffer", BUFFERS);
+    let mut pending: Vec<(ReadbackId, UVec2, UVec2)> = Vec::new();
+    let mut completed = 0;
+    let mut check = |pixel: UVec2, region: UVec2, bytes: Vec<u8>| {
+        assert_eq!(
+            bytes.len(),
+            (region.x * region.y) as usize * ITERATION_TEXEL_SIZE
+        );
+        assert_eq!(&bytes[..ITERATION_TEXEL_SIZE], texel(pixel));
+        completed += 1;
+    };
+    for frame in 0..FRAMES {
+        // Like a frame of the explorer: some compute work, then a readback in its encoder.
+        computer.run(&gpu, &params);
+        let pixel = UVec2::new(frame * 7 % SIZE, frame * 13 % SIZE);
+        // Every tenth readback is a whole row, so buffers of both sizes go around the ring.
+        let region = if frame % 10 == 0 {
+            UVec2::new(SIZE - pixel.x, 1)
+        } else {
+            UVec2::new(1, 1)
+        };
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let id = readback.copy(
+            &gpu,
+            &mut encoder,
+            &computer.iteration_texture,
+            pixel,
+            region,
+            ITERATION_TEXEL_SIZE,
+        );
+        gpu.queue.submit(Some(encoder.finish()));
+        readback.submitted();
+        if let Some(id) = id {
+            pending.push((id, pixel, region));
+        }
+        assert!(readback.buffer_count() <= BUFFERS);
+
+        let mut still_pending = Vec::new();
+        for (id, pixel, region) in pending {
+            match readback.take(&gpu, id) {
+                Some(result) => check(pixel, region, result.unwrap()),
+                None => still_pending.push((id, pixel, region)),
+            }
+        }
+        pending = still_pending;
+    }
+
+    for (id, pixel, region) in pending {
+        check(pixel, region, readback.wait(&gpu, id).unwrap());
+    }
+    assert_eq!(readback.in_flight(), 0);
+    assert!(readback.buffer_count() <= BUFFERS);
+    assert!(completed > 0);
+}
+
+#[test]
+fn deterministic_renders_are_bit_identical() {
+    let Some(_gpu) = gpu() else {
+        return;
+    };
+    let options = HeadlessOptions {
+        startup: StartupOptions {
+            center: Some(DVec2::new(-0.7436, 0.1318)),
+            zoom: Some(1e-3),
+            ..StartupOptions::default()
+        },
+        size: UVec2::new(SIZE, SIZE),
+        output: "unused.png".into(),
+        deterministic: Some(7),
+        resume: false,
+    };
+    let first = headless::render_image(&options).unwrap();
+    assert_eq!(first, headless::render_image(&options).unwrap());
+
+    // The seed matters, or it couldn't be told apart in the metadata.
+    let other_seed = HeadlessOptions {
+        deterministic: Some(8),
+        ..options
+    };
+    assert_ne!(first, headless::render_image(&other_seed).unwrap());
+}
+
+#[test]
+fn resumed_posters_match_uninterrupted_ones() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    // Wider than the largest tile, so the poster takes several.
+    let size = UVec2::new(4097, 8);
+    let mut view = SampleLocation::default();
+    view.set_output_size(size);
+    let path = std::env::temp_dir().join(format!("poster_resume_{}.png", std::process::id()));
+    let new_job = |view: &SampleLocation| {
+        PosterJob::new(
+            &gpu,
+            size,
+            view.to_mandlebrot_params(MAX_ITERATIONS),
+            ColorMapping::default().color_params(),
+            &ComputeSetup {
+                precision: Precision::F32,
+                formula: None,
+            },
+            &builtin_palettes()[0],
+            path.clone(),
+        )
+        .unwrap()
+    };
+    let finish = |mut job: PosterJob| {
+        while !job.is_done() {
+            job.step(&gpu).unwrap();
+        }
+        job.into_image()
+    };
+    let uninterrupted = finish(new_job(&view));
+
+    let mut interrupted = new_job(&view).with_checkpoint(false).unwrap();
+    assert!(interrupted.progress().1 > 1);
+    interrupted.step(&gpu).unwrap();
+    drop(interrupted);
+    let resumed = new_job(&view).with_checkpoint(true).unwrap();
+    assert_eq!(resumed.progress().0, 1);
+    assert_eq!(finish(resumed), uninterrupted);
+
+    view.zoom_in();
+    let error = new_job(&view).with_checkpoint(true).err().unwrap();
+    assert!(error.to_string().contains("view"), "{}", error);
+    std::fs::remove_dir_all(poster::checkpoint_dir(&path)).unwrap();
+}
+
+#[test]
+fn double_single_agrees_with_f32_at_shallow_zoom() {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    let size = UVec2::new(SIZE, SIZE);
+    let params = SampleLocation::default().to_mandlebrot_params(MAX_ITERATIONS);
+    let setup = ComputeSetup {
+        precision: Precision::F32,
+        formula: None,
+    };
+    let difference = compare_precisions(
+        &gpu,
+        &setup,
+        &params,
+        size,
+        [Precision::F32, Precision::DoubleSingle],
+    )
+    .unwrap();
+    assert_eq!(difference.differences.len(), (SIZE * SIZE) as usize);
+    assert!(
+        difference.mean() < SMOOTH_TOLERANCE as f64,
+        "{}",
+        difference.summary()
+    );
+    assert!(
+        (difference.escape_mismatches() as f64) < MAX_MISMATCH_FRACTION * (SIZE * SIZE) as f64,
+        "{}",
+        difference.summary()
+    );
+}
@@ -0,0 +1,355 @@
+use mandelbrot_compute::{
+    accumulate::{jitter, ACCUMULATION_FRAMES},
+    compare::PrecisionDifference,
+    computer::{Precision, Tiling},
+    math::{
+        content_to_target, format_length, grid_spacing, letterbox, nice_length, target_to_content,
+        OverlayVertex,
+    },
+    overlay::{grown_capacity, stroke_segment, OverlayBatch},
+    text::TextRenderer,
+    DVec2, FVec2, IVec2, Mat2, UVec2,
+};
+
+#[test]
+fn vector_arithmetic_is_componentwise() {
+    let a = FVec2::new(1.0, -2.0);
+    let b = FVec2::new(0.5, 4.0);
+    assert_eq!(a + b, FVec2::new(1.5, 2.0));
+    assert_eq!(a - b, FVec2::new(0.5, -6.0));
+    assert_eq!(-a, FVec2::new(-1.0, 2.0));
+    assert_eq!(a * 3.0, FVec2::new(3.0, -6.0));
+    assert_eq!(b / 2.0, FVec2::new(0.25, 2.0));
+    let mut c = a;
+    c += b;
+    c -= a;
+    assert_eq!(c, b);
+
+    let a = DVec2::new(1.0, -2.0);
+    let b = DVec2::new(0.5, 4.0);
+    assert_eq!(a + b, DVec2::new(1.5, 2.0));
+    assert_eq!(a - b, DVec2::new(0.5, -6.0));
+    assert_eq!(-a, DVec2::new(-1.0, 2.0));
+    assert_eq!(a * 3.0, DVec2::new(3.0, -6.0));
+    assert_eq!(b / 2.0, DVec2::new(0.25, 2.0));
+}
+
+#[test]
+fn length_and_lerp() {
+    assert_eq!(FVec2::new(3.0, 4.0).length(), 5.0);
+    assert_eq!(DVec2::new(-3.0, 4.0).length(), 5.0);
+    assert_eq!(DVec2::new(1.0, 2.0).dot(DVec2::new(3.0, -1.0)), 1.0);
+    let (a, b) = (FVec2::new(0.0, 10.0), FVec2::new(4.0, -2.0));
+    assert_eq!(a.lerp(b, 0.0), a);
+    assert_eq!(a.lerp(b, 1.0), b);
+    assert_eq!(a.lerp(b, 0.25), FVec2::new(1.0, 7.0));
+}
+
+#[test]
+fn conversions_between_vector_types() {
+    assert_eq!(FVec2::from(UVec2::new(3, 4)), FVec2::new(3.0, 4.0));
+    assert_eq!(FVec2::from(IVec2::new(-3, 4)), FVec2::new(-3.0, 4.0));
+    assert_eq!(DVec2::from(UVec2::new(3, 4)), DVec2::new(3.0, 4.0));
+    assert_eq!(DVec2::from(IVec2::new(-3, 4)), DVec2::new(-3.0, 4.0));
+    assert_eq!(DVec2::from(FVec2::new(0.5, -0.25)), DVec2::new(0.5, -0.25));
+    assert_eq!(FVec2::from(DVec2::new(0.1, 0.2)), FVec2::new(0.1, 0.2));
+}
+
+#[test]
+fn split_sums_back_to_the_double() {
+    let v = DVec2::new(-0.7436438870371587, 0.1318259042053968);
+    let (hi, lo) = v.split();
+    let sum = DVec2::from(hi) + DVec2::from(lo);
+    assert!((sum - v).length() < 1e-14);
+    assert_eq!(FVec2::from(v), hi);
+}
+
+fn assert_close(a: FVec2, b: FVec2) {
+    assert!((a - b).length() < 1e-6, "{} != {}", a, b);
+}
+
+#[test]
+fn rotation_turns_vectors_counterclockwise() {
+    let quarter = Mat2::from_rotation(std::f32::consts::FRAC_PI_2);
+    assert_close(quarter * FVec2::new(1.0, 0.0), FVec2::new(0.0, 1.0));
+    assert_close(quarter * FVec2::new(0.0, 1.0), FVec2::new(-1.0, 0.0));
+    assert!((quarter.determinant() - 1.0).abs() < 1e-6);
+    assert_eq!(Mat2::from_scale(2.0, -3.0).determinant(), -6.0);
+    assert_eq!(
+        Mat2::from_scale(2.0, 3.0) * FVec2::new(1.0, -1.0),
+        FVec2::new(2.0, -3.0)
+    );
+}
+
+#[test]
+fn inverse_undoes_the_transform() {
+    let m = Mat2::from_rotation(0.7) * Mat2::from_scale(2.0, -0.5);
+    let inverse = m.inverse().unwrap();
+    let v = FVec2::new(0.3, -1.25);
+    assert_close(inverse * (m * v), v);
+    assert_close(m * (inverse * v), v);
+    assert!((m.determinant() * inverse.determinant() - 1.0).abs() < 1e-6);
+    assert_eq!(Mat2::from_scale(1.0, 0.0).inverse(), None);
+    assert_eq!(Mat2::from_scale(f32::INFINITY, 1.0).inverse(), None);
+}
+
+#[test]
+fn letterbox_centers_content_with_bars_on_the_spare_sides() {
+    // Same aspect ratio: fills the target.
+    let (min, max) = letterbox(UVec2::new(200, 100), UVec2::new(400, 200));
+    assert_eq!((min, max), (FVec2::new(0.0, 0.0), FVec2::new(400.0, 200.0)));
+    // Wider target: bars left and right.
+    let (min, max) = letterbox(UVec2::new(100, 100), UVec2::new(4000, 100));
+    assert_eq!(
+        (min, max),
+        (FVec2::new(1950.0, 0.0), FVec2::new(2050.0, 100.0))
+    );
+    // Taller target: bars above and below.
+    let (min, max) = letterbox(UVec2::new(300, 100), UVec2::new(300, 900));
+    assert_eq!(
+        (min, max),
+        (FVec2::new(0.0, 400.0), FVec2::new(300.0, 500.0))
+    );
+}
+
+#[test]
+fn letterbox_positions_map_between_window_and_image() {
+    // A 2048x1024 image shown in a 1000x1000 window: half scale, bars of 250 above and below.
+    let (content, target) = (UVec2::new(2048, 1024), UVec2::new(1000, 1000));
+    let image = target_to_content(FVec2::new(500.0, 250.0), content, target);
+    assert_eq!(image, FVec2::new(1024.0, 0.0));
+    let bar = target_to_content(FVec2::new(0.0, 100.0), content, target);
+    assert!(bar.y < 0.0);
+    let back = content_to_target(FVec2::new(512.0, 768.0), content, target);
+    assert_eq!(back, FVec2::new(250.0, 625.0));
+    assert_eq!(
+        target_to_content(back, content, target),
+        FVec2::new(512.0, 768.0)
+    );
+}
+
+#[test]
+fn tiles_cover_the_image_once() {
+    let tiling = Tiling {
+        tile_size: 512,
+        wait_between: false,
+    };
+    let size = UVec2::new(1300, 600);
+    let tiles: Vec<_> = tiling.tiles(size).collect();
+    assert_eq!(tiles.len(), 6);
+    assert_eq!(tiles[0], (UVec2::new(0, 0), UVec2::new(512, 512)));
+    assert_eq!(tiles[2], (UVec2::new(1024, 0), UVec2::new(276, 512)));
+    assert_eq!(tiles[5], (UVec2::new(1024, 512), UVec2::new(276, 88)));
+    let area: u32 = tiles.iter().map(|(_, size)| size.x * size.y).sum();
+    assert_eq!(area, size.x * size.y);
+}
+
+#[test]
+fn half_floats_decode() {
+    use mandelbrot_compute::colorize::f16_to_f32;
+    assert_eq!(f16_to_f32(0x0000), 0.0);
+    assert_eq!(f16_to_f32(0x3c00), 1.0);
+    assert_eq!(f16_to_f32(0x3800), 0.5);
+    assert_eq!(f16_to_f32(0xc000), -2.0);
+    assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+    assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+    assert!(f16_to_f32(0x7e00).is_nan());
+}
+
+#[test]
+fn nice_lengths_are_round() {
+    assert_eq!(nice_length(1.0), 1.0);
+    assert_eq!(nice_length(1000.0), 1000.0);
+    assert_eq!(nice_length(0.999), 0.5);
+    assert_eq!(nice_length(3.7), 2.0);
+    assert_eq!(nice_length(7.5e-5), 5e-5);
+    assert_eq!(nice_length(1.9e-12), 1e-12);
+    for max in [0.013, 0.3, 42.0, 6.1e-9] {
+        let length = nice_length(max);
+        assert!(
+            length <= max && length * 2.5 > max,
+            "{} for {}",
+            length,
+            max
+        );
+    }
+    assert_eq!(format_length(nice_length(7.5e-5)), "5e-5");
+    assert_eq!(format_length(nice_length(0.07)), "0.05");
+    assert_eq!(format_length(200.0), "200");
+    assert_eq!(format_length(20000.0), "2e4");
+}
+
+#[test]
+fn grid_spacing_gives_5_to_15_lines() {
+    assert_eq!(grid_spacing(3.0), 0.5);
+    assert_eq!(grid_spacing(10.0), 1.0);
+    let mut span = 1.0e-9;
+    while span < 1.0e9 {
+        let lines = span / grid_spacing(span);
+        assert!(
+            (5.0..=15.0).contains(&lines),
+            "{} lines across {}",
+            lines,
+            span
+        );
+        span *= 1.37;
+    }
+}
+
+#[test]
+fn overlay_buffers_grow_by_powers_of_two() {
+    assert_eq!(grown_capacity(0, 1), 64);
+    assert_eq!(grown_capacity(0, 100), 128);
+    assert_eq!(grown_capacity(128, 100), 128);
+    assert_eq!(grown_capacity(128, 128), 128);
+    assert_eq!(grown_capacity(128, 129), 256);
+    assert_eq!(grown_capacity(256, 5000), 8192);
+}
+
+#[test]
+fn overlay_vertex_layout_matches_its_size() {
+    assert_eq!(std::mem::size_of::<OverlayVertex>(), 44);
+    let layout = OverlayVertex::desc();
+    assert_eq!(layout.array_stride, 44);
+    let offsets: Vec<u64> = layout.attributes.iter().map(|a| a.offset).collect();
+    assert_eq!(offsets, [0, 12, 28]);
+}
+
+#[test]
+fn stroke_quad_covers_the_width_plus_a_pixel() {
+    let (a, b) = (FVec2::new(10.0, 20.0), FVec2::new(13.0, 24.0));
+    let (along, normal) = (FVec2::new(0.6, 0.8), FVec2::new(-0.8, 0.6));
+    let width = 6.0;
+    let margin = width / 2.0 + 1.0;
+    let corners = stroke_segment(a, b, width);
+    let expected = [
+        a - along * margin - normal * margin,
+        a - along * margin + normal * margin,
+        b + along * margin + normal * margin,
+        b + along * margin - normal * margin,
+    ];
+    for ((corner, stroke), expected) in corners.iter().zip(expected) {
+        assert!(
+            (*corner - expected).length() < 1e-4,
+            "{} != {}",
+            corner,
+            expected
+        );
+        assert_eq!((stroke[2], stroke[3]), (5.0, 3.0));
+        // The stroke data is the corner's position in the segment's own frame.
+        let frame = FVec2::new((*corner - a).dot(along), (*corner - a).dot(normal));
+        assert!((FVec2::new(stroke[0], stroke[1]) - frame).length() < 1e-4);
+    }
+}
+
+#[test]
+fn wide_polyline_segments_meet_at_the_joints() {
+    let size = UVec2::new(200, 100);
+    let mut batch = OverlayBatch::new(size);
+    let points = [
+        FVec2::new(20.0, 20.0),
+        FVec2::new(80.0, 50.0),
+        FVec2::new(120.0, 30.0),
+        FVec2::new(150.0, 90.0),
+    ];
+    batch.add_wide_polyline(&points, 4.0, [1.0; 4]);
+    assert_eq!(batch.triangles.len(), 6 * (points.len() - 1));
+    let to_pixels = |v: &OverlayVertex| {
+        FVec2::new(
+            (v.position[0] + 1.0) * 0.5 * size.x as f32,
+            (1.0 - v.position[1]) * 0.5 * size.y as f32,
+        )
+    };
+    // Map each quad's stroke frame back to pixels to find where its segment starts and ends.
+    let ends: Vec<(FVec2, FVec2)> = batch
+        .triangles
+        .chunks(6)
+        .map(|quad| {
+            let (a, b, d) = (
+                to_pixels(&quad[0]),
+                to_pixels(&quad[1]),
+                to_pixels(&quad[4]),
+            );
+            let [along, across, length, _] = quad[0].stroke;
+            let u = (d - a) / (d - a).length();
+            let n = (b - a) / (b - a).length();
+            let start = a - u * along - n * across;
+            (start, start + u * length)
+        })
+        .collect();
+    for (segment, pair) in ends.iter().zip(points.windows(2)) {
+        assert!((segment.0 - pair[0]).length() < 1e-3);
+        assert!((segment.1 - pair[1]).length() < 1e-3);
+    }
+    for pair in ends.windows(2) {
+        assert!((pair[0].1 - pair[1].0).length() < 1e-3);
+    }
+}
+
+#[test]
+fn text_width_adds_up_per_character() {
+    let text = TextRenderer::new(1.0);
+    let (a, b) = (text.measure("a", 14.0), text.measure("b", 14.0));
+    assert!(a > 0.0 && b > 0.0);
+    assert!((text.measure("ab", 14.0) - (a + b)).abs() < 1e-3);
+    assert_eq!(text.measure("", 14.0), 0.0);
+    // The widest line sets the width.
+    assert_eq!(text.measure("a\nab\nb", 14.0), text.measure("ab", 14.0));
+    let hidpi = TextRenderer::new(2.0);
+    assert_eq!(hidpi.measure("ab", 14.0), text.measure("ab", 28.0));
+}
+
+#[test]
+fn seeded_jitter_stays_in_the_pixel() {
+    assert_eq!(jitter(0, 0), [0.0, 0.0]);
+    assert_eq!(jitter(1, 0), [0.5, 1.0 / 3.0]);
+    for seed in [0, 1, 2, 12345, u32::MAX] {
+        for frame_index in 0..ACCUMULATION_FRAMES {
+            let offset = jitter(frame_index, seed);
+            assert!(
+                offset.iter().all(|v| (0.0..1.0).contains(v)),
+                "{:?}",
+                offset
+            );
+            assert_eq!(offset, jitter(frame_index, seed));
+        }
+    }
+    assert_ne!(jitter(0, 1), jitter(0, 2));
+}
+
+#[test]
+fn precision_difference_skips_escape_mismatches_in_its_statistics() {
+    // Escaped in both, inside in both, escaped in one only, escaped in both.
+    let a = [
+        [10.0, 0.0, 1.0, 0.0],
+        [0.0; 4],
+        [5.0, 0.0, 1.0, 0.0],
+        [3.0, 0.0, 1.0, 0.0],
+    ];
+    let b = [
+        [10.5, 0.0, 1.0, 0.0],
+        [0.0; 4],
+        [0.0; 4],
+        [3.0, 0.0, 1.0, 0.0],
+    ];
+    let difference = PrecisionDifference::from_texels(
+        [Precision::F32, Precision::F64],
+        UVec2::new(2, 2),
+        &a,
+        &b,
+    );
+    assert_eq!(
+        difference.differences,
+        [Some(0.5), Some(0.0), None, Some(0.0)]
+    );
+    assert_eq!(difference.max(), 0.5);
+    assert!((difference.mean() - 0.5 / 3.0).abs() < 1e-12);
+    assert_eq!(difference.escape_mismatches(), 1);
+    assert!(difference.summary().starts_with("F32 vs F64: max 5.000e-1"));
+
+    let heat_map = difference.heat_map();
+    assert_eq!(heat_map.dimensions(), (2, 2));
+    assert_eq!(heat_map.get_pixel(0, 0).0, [255, 255, 255, 255]);
+    assert_eq!(heat_map.get_pixel(1, 0).0, [0, 0, 0, 255]);
+    assert_eq!(heat_map.get_pixel(0, 1).0, [0, 128, 255, 255]);
+}
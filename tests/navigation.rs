@@ -0,0 +1,389 @@
+use dashu_float::FBig;
+use mandelbrot_compute::SampleLocation;
+use winit::dpi::PhysicalPosition;
+
+const BITS: usize = 256;
+
+fn big(x: f64) -> FBig {
+    FBig::try_from(x).unwrap().with_precision(BITS).value()
+}
+
+/// Alternating zooms and pans shrink each step by half, so after a few dozen steps a single
+/// f64 can no longer hold the center. The double-double center must still match the exact
+/// sum of the steps.
+#[test]
+fn zoom_and_pan_sequence_keeps_exact_center() {
+    let mut view = SampleLocation::default();
+    let mut expected = big(0.0);
+    let step = 0.05f32 as f64;
+    for i in 0..60 {
+        if i % 2 == 0 {
+            view.zoom_in();
+            view.update(f32::INFINITY);
+        } else {
+            expected = (expected + big(step * view.zoom() as f64))
+                .with_precision(BITS)
+                .value();
+            view.right();
+        }
+    }
+    let (hi, lo) = view.center();
+    let actual = (big(hi.x) + big(lo.x)).with_precision(BITS).value();
+    assert_eq!(actual, expected);
+    assert_eq!((hi.y, lo.y), (0.0, 0.0));
+}
+
+#[test]
+fn interpolate_reaches_both_ends() {
+    let start = SampleLocation::default();
+    let end = SampleLocation::new(mandelbrot_compute::DVec2::new(-0.75, 0.1), 0.01);
+    assert_eq!(start.interpolate(&end, 0.0).center(), start.center());
+    assert_eq!(start.interpolate(&end, 1.0).zoom(), end.zoom());
+    let (hi, _) = start.interpolate(&end, 1.0).center();
+    assert!((hi.x - -0.75).abs() < 1e-12 && (hi.y - 0.1).abs() < 1e-12);
+}
+
+#[test]
+fn pixel_size_matches_screen_step() {
+    let size = mandelbrot_compute::UVec2::new(300, 200);
+    let mut view = SampleLocation::new(mandelbrot_compute::DVec2::new(-0.5, 0.25), 0.1);
+    view.set_output_size(size);
+    let pixel = |x, y| view.screen_to_plane(mandelbrot_compute::FVec2 { x, y }, size);
+    let step = pixel(11.0, 7.0).x - pixel(10.0, 7.0).x;
+    assert!((step - view.pixel_size(size) as f64).abs() < 1e-7);
+    let step = pixel(10.0, 8.0).y - pixel(10.0, 7.0).y;
+    assert!((step.abs() - view.pixel_size(size) as f64).abs() < 1e-7);
+}
+
+#[test]
+fn short_axis_spans_zoom_and_long_axis_follows_the_aspect() {
+    use mandelbrot_compute::{DVec2, FVec2, UVec2};
+    let center = DVec2::new(-0.5, 0.25);
+    let zoom = 0.1;
+    for (width, height) in [(400, 200), (200, 400), (300, 300)] {
+        let size = UVec2::new(width, height);
+        let mut view = SampleLocation::new(center, zoom);
+        view.set_output_size(size);
+        let (w, h) = (width as f32, height as f32);
+        let point = |x, y| view.screen_to_plane(FVec2 { x, y }, size);
+        // Half the distance across the screen through the center, along each axis.
+        let half_width = (point(w, h / 2.0).x - point(0.0, h / 2.0).x) / 2.0;
+        let half_height = (point(w / 2.0, 0.0).y - point(w / 2.0, h).y) / 2.0;
+        let short = width.min(height) as f64;
+        let expected_x = zoom as f64 * width as f64 / short;
+        let expected_y = zoom as f64 * height as f64 / short;
+        assert!(
+            (half_width - expected_x).abs() < 1e-6,
+            "{}x{}: half width {}",
+            width,
+            height,
+            half_width
+        );
+        assert!(
+            (half_height - expected_y).abs() < 1e-6,
+            "{}x{}: half height {}",
+            width,
+            height,
+            half_height
+        );
+        let middle = point(w / 2.0, h / 2.0);
+        assert!((middle.x - center.x).abs() < 1e-7 && (middle.y - center.y).abs() < 1e-7);
+    }
+}
+
+#[test]
+fn home_views_frame_the_whole_set() {
+    use mandelbrot_compute::computer::FractalKind;
+    let size = mandelbrot_compute::UVec2::new(400, 400);
+    let edge = |view: &SampleLocation, x| {
+        view.plane_to_screen(mandelbrot_compute::DVec2::new(x, 0.0), size)
+            .x
+    };
+    let mandelbrot = FractalKind::Mandelbrot.default_view();
+    assert!(edge(&mandelbrot, -2.5).abs() < 1e-3);
+    assert!((edge(&mandelbrot, 1.5) - 400.0).abs() < 1e-3);
+    // The Julia set of c = -2 is the real segment [-2, 2].
+    let julia = FractalKind::Mandelbrot.julia_view();
+    assert!(edge(&julia, -2.0) > -1e-3 && edge(&julia, 2.0) < 400.0 + 1e-3);
+}
+
+#[test]
+fn plane_to_screen_inverts_screen_to_plane() {
+    let size = mandelbrot_compute::UVec2::new(300, 200);
+    let mut view = SampleLocation::new(mandelbrot_compute::DVec2::new(-0.5, 0.25), 0.1);
+    view.set_output_size(size);
+    view.rotate(0.3);
+    let pixel = mandelbrot_compute::FVec2 { x: 37.0, y: 151.0 };
+    let back = view.plane_to_screen(view.screen_to_plane(pixel, size), size);
+    assert!((back.x - pixel.x).abs() < 1e-3 && (back.y - pixel.y).abs() < 1e-3);
+}
+
+#[test]
+fn pan_steps_matches_arrow_steps() {
+    let mut stepped = SampleLocation::default();
+    stepped.right();
+    stepped.right();
+    let mut glided = SampleLocation::default();
+    for _ in 0..8 {
+        glided.pan_steps(mandelbrot_compute::FVec2 { x: 1.0, y: 0.0 }, 0.25);
+    }
+    let (a, _) = stepped.center();
+    let (b, _) = glided.center();
+    assert!((a.x - b.x).abs() < 1e-9 && a.y == b.y);
+}
+
+#[test]
+fn animated_zoom_accumulates_and_keeps_anchor() {
+    let size = mandelbrot_compute::UVec2::new(300, 200);
+    let mut view = SampleLocation::new(mandelbrot_compute::DVec2::new(-0.5, 0.25), 0.1);
+    view.set_output_size(size);
+    let cursor = mandelbrot_compute::FVec2 { x: 40.0, y: 150.0 };
+    let anchored = view.screen_to_plane(cursor, size);
+    view.animate_zoom_about(cursor, size, 0.8);
+    view.update(0.01);
+    view.animate_zoom_about(cursor, size, 0.8);
+    let mut frames = 0;
+    while view.is_animating() {
+        view.update(1.0 / 60.0);
+        frames += 1;
+        let point = view.screen_to_plane(cursor, size);
+        assert!((point.x - anchored.x).abs() < 1e-6 && (point.y - anchored.y).abs() < 1e-6);
+    }
+    assert!(frames > 1 && frames < 120);
+    assert!((view.zoom() - 0.1 * 0.8 * 0.8).abs() < 1e-7);
+}
+
+/// Error in pixels of the real coordinate each formulation gives the pixels of one row, next
+/// to the f64 mapping of `screen_to_plane`. The box one is the `x_min`/`x_max` uniform the
+/// shader used to take, evaluated in f32; the center one is what `mandelbrot_ds.wgsl` computes
+/// from `to_mandlebrot_params`: the double-single center plus an f32 offset.
+fn row_errors(center: (f64, f64), zoom: f32) -> (f64, f64) {
+    let size = mandelbrot_compute::UVec2::new(256, 256);
+    let mut view = SampleLocation::new(mandelbrot_compute::DVec2::new(center.0, center.1), zoom);
+    view.set_output_size(size);
+    let params = view.to_mandlebrot_params(100);
+    let pixel_size = view.pixel_size(size) as f64;
+    let x_min = (center.0 - zoom as f64) as f32;
+    let x_max = (center.0 + zoom as f64) as f32;
+    let (mut box_error, mut center_error) = (0.0f64, 0.0f64);
+    for i in 0..size.x {
+        let u = i as f32 / size.x as f32;
+        let expected = view
+            .screen_to_plane(
+                mandelbrot_compute::FVec2 {
+                    x: i as f32,
+                    y: 0.0,
+                },
+                size,
+            )
+            .x;
+        let boxed = x_min + (x_max - x_min) * u;
+        let offset = params.transform[0] * (u * 2.0 - 1.0);
+        let centered = params.center[0] as f64 + params.center_lo[0] as f64 + offset as f64;
+        box_error = box_error.max((boxed as f64 - expected).abs() / pixel_size);
+        center_error = center_error.max((centered - expected).abs() / pixel_size);
+    }
+    (box_error, center_error)
+}
+
+#[test]
+fn center_params_match_box_params_at_shallow_zoom() {
+    let (box_error, center_error) = row_errors((-0.5, 0.0), 1.5);
+    assert!(box_error < 0.01, "box error {box_error} px");
+    assert!(center_error < 0.01, "center error {center_error} px");
+}
+
+#[test]
+fn center_params_stay_exact_where_box_params_collapse() {
+    let (box_error, center_error) = row_errors((-0.743643887, 0.131825904), 1e-9);
+    assert!(box_error > 10.0, "box error {box_error} px");
+    assert!(center_error < 0.01, "center error {center_error} px");
+}
+
+#[test]
+fn viewport_maps_center_and_corners() {
+    let size = mandelbrot_compute::UVec2::new(400, 200);
+    for zoom in [2.0, 1e-3, 1e-8] {
+        let mut view = SampleLocation::new(mandelbrot_compute::DVec2::new(-0.75, 0.1), zoom);
+        view.set_output_size(size);
+        let viewport = view.viewport(size);
+        let center = viewport.screen_to_complex(PhysicalPosition::new(200.0, 100.0));
+        assert_eq!((center.x, center.y), (-0.75, 0.1));
+        // The wide axis is widened by the aspect ratio; the imaginary axis points up.
+        let corner = viewport.screen_to_complex(PhysicalPosition::new(400.0, 200.0));
+        let zoom = zoom as f64;
+        assert!((corner.x - (-0.75 + 2.0 * zoom)).abs() < zoom * 1e-9);
+        assert!((corner.y - (0.1 - zoom)).abs() < zoom * 1e-9);
+    }
+}
+
+#[test]
+fn positive_imaginary_is_up() {
+    let size = mandelbrot_compute::UVec2::new(128, 128);
+    let mut view = SampleLocation::default();
+    view.set_output_size(size);
+    let pixel = view.plane_to_screen(mandelbrot_compute::DVec2::new(-0.75, 0.0), size);
+    assert_eq!((pixel.x, pixel.y), (16.0, 64.0));
+    let top = view.screen_to_plane(mandelbrot_compute::FVec2::new(64.0, 0.0), size);
+    assert_eq!((top.x, top.y), (0.0, 1.0));
+
+    // Up moves the view towards +i, and the content under the cursor down the screen.
+    view.up();
+    assert!(view.center().0.y > 0.0);
+    assert!(view.plane_to_screen(top, size).y > 0.0);
+    view.down();
+    assert!(view.center().0.y.abs() < 1e-12);
+}
+
+#[test]
+fn viewport_round_trips_points_off_screen() {
+    let size = mandelbrot_compute::UVec2::new(300, 200);
+    for zoom in [1.0, 1e-4, 1e-9] {
+        let mut view = SampleLocation::new(mandelbrot_compute::DVec2::new(-1.25, 0.3), zoom);
+        view.set_output_size(size);
+        view.rotate(1.1);
+        let viewport = view.viewport(size);
+        for (x, y) in [(0.0, 0.0), (150.0, 100.0), (-250.0, 90.0), (1000.0, -400.0)] {
+            let point = viewport.screen_to_complex(PhysicalPosition::new(x, y));
+            let back = viewport.complex_to_screen(point);
+            assert!(
+                (back.x as f64 - x).abs() < 1e-3 && (back.y as f64 - y).abs() < 1e-3,
+                "zoom {zoom}: ({x}, {y}) came back as {back:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn viewport_transform_matches_shader_params() {
+    let size = mandelbrot_compute::UVec2::new(300, 200);
+    let mut view = SampleLocation::new(mandelbrot_compute::DVec2::new(0.25, -0.5), 0.01);
+    view.set_output_size(size);
+    view.rotate(0.4);
+    let params = view.to_mandlebrot_params(100);
+    assert_eq!(params.transform, view.viewport(size).transform().as_vec4());
+    let uv = mandelbrot_compute::DVec2::new(0.3, -0.8);
+    let offset = view.viewport(size).offset(uv);
+    let shader = view.transform() * mandelbrot_compute::FVec2 { x: 0.3, y: -0.8 };
+    assert!((offset.x - shader.x as f64).abs() < 1e-8);
+    assert!((offset.y - shader.y as f64).abs() < 1e-8);
+}
+
+#[test]
+fn history_goes_back_and_forward() {
+    let mut history = mandelbrot_compute::history::History::new(100);
+    assert_eq!(history.back(), None);
+    for i in 0..3 {
+        history.push(i);
+    }
+    assert_eq!(history.back(), Some(&1));
+    assert_eq!(history.back(), Some(&0));
+    assert_eq!(history.back(), None);
+    assert_eq!(history.current(), Some(&0));
+    assert_eq!(history.forward(), Some(&1));
+    assert_eq!(history.forward(), Some(&2));
+    assert_eq!(history.forward(), None);
+}
+
+#[test]
+fn history_push_after_back_drops_redo() {
+    let mut history = mandelbrot_compute::history::History::new(100);
+    for i in 0..3 {
+        history.push(i);
+    }
+    history.back();
+    history.back();
+    history.push(10);
+    assert_eq!(history.forward(), None);
+    assert_eq!(history.back(), Some(&0));
+}
+
+#[test]
+fn history_keeps_the_newest_entries() {
+    let mut history = mandelbrot_compute::history::History::new(3);
+    for i in 0..5 {
+        history.push(i);
+    }
+    assert_eq!(history.current(), Some(&4));
+    assert_eq!(history.back(), Some(&3));
+    assert_eq!(history.back(), Some(&2));
+    assert_eq!(history.back(), None);
+}
+
+#[test]
+fn touch_pans_and_pinches() {
+    use mandelbrot_compute::{
+        touch::{TouchGesture, TouchTracker},
+        FVec2,
+    };
+    use std::time::Instant;
+    use winit::event::TouchPhase;
+
+    let now = Instant::now();
+    let mut touches = TouchTracker::default();
+    assert_eq!(
+        touches.update(1, TouchPhase::Started, FVec2::new(100.0, 100.0), now),
+        None
+    );
+    assert_eq!(
+        touches.update(1, TouchPhase::Moved, FVec2::new(110.0, 90.0), now),
+        Some(TouchGesture::Pan {
+            from: FVec2::new(100.0, 100.0),
+            to: FVec2::new(110.0, 90.0),
+        })
+    );
+    touches.update(2, TouchPhase::Started, FVec2::new(210.0, 90.0), now);
+    // Spreading the second finger to twice the distance zooms in by half about the midpoint.
+    assert_eq!(
+        touches.update(2, TouchPhase::Moved, FVec2::new(310.0, 90.0), now),
+        Some(TouchGesture::Pinch {
+            from: FVec2::new(160.0, 90.0),
+            to: FVec2::new(210.0, 90.0),
+            scale: 0.5,
+        })
+    );
+    touches.update(1, TouchPhase::Ended, FVec2::new(110.0, 90.0), now);
+    assert!(touches.is_active());
+    touches.update(2, TouchPhase::Cancelled, FVec2::new(310.0, 90.0), now);
+    assert!(!touches.is_active());
+}
+
+#[test]
+fn touch_double_tap_needs_two_quick_taps() {
+    use mandelbrot_compute::{
+        touch::{TouchGesture, TouchTracker},
+        FVec2,
+    };
+    use std::time::{Duration, Instant};
+    use winit::event::TouchPhase;
+
+    let start = Instant::now();
+    let at = |ms| start + Duration::from_millis(ms);
+    let p = FVec2::new(50.0, 60.0);
+    let mut touches = TouchTracker::default();
+    let mut tap = |id, down, up| {
+        touches.update(id, TouchPhase::Started, p, at(down));
+        touches.update(id, TouchPhase::Ended, p, at(up))
+    };
+    assert_eq!(tap(1, 0, 80), None);
+    assert_eq!(tap(2, 150, 230), Some(TouchGesture::DoubleTap(p)));
+    // Too far apart in time.
+    assert_eq!(tap(3, 1000, 1080), None);
+    assert_eq!(tap(4, 1500, 1580), None);
+    // A long press isn't a tap.
+    assert_eq!(tap(5, 1650, 2500), None);
+}
+
+#[test]
+fn pinch_keeps_the_point_between_the_fingers() {
+    let size = mandelbrot_compute::UVec2::new(300, 200);
+    let mut view = SampleLocation::new(mandelbrot_compute::DVec2::new(-0.5, 0.25), 0.1);
+    view.set_output_size(size);
+    let from = mandelbrot_compute::FVec2::new(100.0, 120.0);
+    let to = mandelbrot_compute::FVec2::new(130.0, 100.0);
+    let point = view.screen_to_plane(from, size);
+    view.drag(from, to, size);
+    view.zoom_about(to, size, 0.7);
+    let moved = view.screen_to_plane(to, size);
+    assert!((moved - point).length() < 1e-9);
+}
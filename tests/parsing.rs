@@ -0,0 +1,538 @@
+use mandelbrot_compute::{
+    computer::FractalKind,
+    computer::WorkgroupSize,
+    config::Config,
+    formula::{BinaryOp, CustomFormula, Expr, Function},
+    gpu_interface::AdapterChoice,
+    keymap::{parse_key, Action, Keymap},
+    math::format_fixed,
+    palette::{
+        kmeans, newest_palette_file, palette_from_image, srgb_to_oklab, ColorMapping, MappingMode,
+        Palette,
+    },
+    state::{thumbnail_path, Bookmarks, StartupOptions, ViewState},
+    DVec2, FVec2, SampleLocation, UVec2,
+};
+use std::path::PathBuf;
+use winit::event::VirtualKeyCode;
+
+#[test]
+fn dvec2_parses_re_im() {
+    assert_eq!(
+        "-0.743,0.131".parse::<DVec2>().unwrap(),
+        DVec2::new(-0.743, 0.131)
+    );
+    assert_eq!(
+        " -7.43e-1 , 1.31e-1 ".parse::<DVec2>().unwrap(),
+        DVec2::new(-0.743, 0.131)
+    );
+}
+
+#[test]
+fn dvec2_rejects_malformed_input() {
+    for text in ["", "0.5", "0.5;0.1", "a,b", "1,2,3"] {
+        assert!(text.parse::<DVec2>().is_err(), "accepted '{}'", text);
+    }
+}
+
+#[test]
+fn fvec2_parses_like_dvec2() {
+    let v: FVec2 = "0.25,-1.5".parse().unwrap();
+    assert_eq!((v.x, v.y), (0.25, -1.5));
+    assert!("0.25".parse::<FVec2>().is_err());
+}
+
+#[test]
+fn vectors_display_what_they_parse() {
+    let v = DVec2::new(-0.7436438870371587, 1e-300);
+    assert_eq!(v.to_string().parse::<DVec2>().unwrap(), v);
+    let v = FVec2::new(0.1, -3.5e7);
+    assert_eq!(v.to_string().parse::<FVec2>().unwrap(), v);
+}
+
+#[test]
+fn view_state_round_trips_through_toml() {
+    let mut view = SampleLocation::new(DVec2::new(-0.743643887037151, 0.13182590420533), 1e-9);
+    view.rotate(0.5);
+    let state = ViewState {
+        fractal_kind: FractalKind::Tricorn,
+        max_iterations: 5000,
+        auto_iterations: false,
+        view,
+        color_mapping: ColorMapping {
+            mode: MappingMode::Log,
+            density: 12.5,
+            offset: 0.25,
+            ..Default::default()
+        },
+    };
+    let text = state.to_toml().unwrap();
+    assert!(text.contains("mode = \"log\""), "{}", text);
+    assert_eq!(ViewState::from_toml(&text).unwrap(), state);
+}
+
+#[test]
+fn view_state_without_color_mapping_gets_the_default() {
+    let mut text = ViewState::default().to_toml().unwrap();
+    let start = text.find("[color_mapping]").unwrap();
+    text.truncate(start);
+    let state = ViewState::from_toml(&text).unwrap();
+    assert_eq!(state.color_mapping, ColorMapping::default());
+
+    let text = ViewState::default()
+        .to_toml()
+        .unwrap()
+        .replace("density = 64.0", "density = 0.0");
+    assert!(ViewState::from_toml(&text).is_err());
+}
+
+#[test]
+fn view_state_rejects_invalid_zoom() {
+    let text = ViewState::default()
+        .to_toml()
+        .unwrap()
+        .replace("zoom = 2.0", "zoom = -1.0");
+    assert!(text.contains("zoom = -1.0"));
+    assert!(ViewState::from_toml(&text).is_err());
+}
+
+#[test]
+fn bookmarks_round_trip_through_toml() {
+    let mut bookmarks = Bookmarks::default();
+    bookmarks.set(3, ViewState::default());
+    let text = bookmarks.to_toml().unwrap();
+    let loaded = Bookmarks::from_toml(&text).unwrap();
+    assert_eq!(loaded.get(3), Some(&ViewState::default()));
+    assert_eq!(loaded.get(4), None);
+}
+
+#[test]
+fn thumbnails_are_stale_when_missing_reset_or_of_another_palette() {
+    let path = temp_path("bookmarks.toml");
+    let mut bookmarks = Bookmarks::default();
+    bookmarks.set(1, ViewState::default());
+    bookmarks.set(2, ViewState::default());
+    assert_eq!(bookmarks.stale_thumbnails(&path, "Fire"), vec![1, 2]);
+
+    for slot in [1, 2] {
+        std::fs::write(thumbnail_path(&path, slot), b"").unwrap();
+        bookmarks.set_thumbnail_palette(slot, "Fire");
+    }
+    assert!(bookmarks.stale_thumbnails(&path, "Fire").is_empty());
+    assert_eq!(bookmarks.stale_thumbnails(&path, "Ocean"), vec![1, 2]);
+
+    // The palettes survive a round trip through the file.
+    let mut loaded = Bookmarks::from_toml(&bookmarks.to_toml().unwrap()).unwrap();
+    assert!(loaded.stale_thumbnails(&path, "Fire").is_empty());
+    loaded.set(2, ViewState::default());
+    std::fs::remove_file(thumbnail_path(&path, 1)).unwrap();
+    assert_eq!(loaded.stale_thumbnails(&path, "Fire"), vec![1, 2]);
+    std::fs::remove_file(thumbnail_path(&path, 2)).unwrap();
+}
+
+#[test]
+fn empty_config_is_default() {
+    assert_eq!(Config::from_toml("").unwrap(), Config::default());
+}
+
+#[test]
+fn config_rejects_unknown_keys_and_bad_values() {
+    assert!(Config::from_toml("[compute]\nworkgroup = 8").is_err());
+    assert!(Config::from_toml("[compute]\nworkgroup_size = \"32x16\"").is_err());
+    assert!(Config::from_toml("[controls]\nzoom_step = 2.0").is_err());
+    assert!(Config::from_toml("[compute]\ntile_size = 0").is_err());
+    assert!(Config::from_toml("[rendering]\nclear_color = [0.0, 1.5, 0.0]").is_err());
+    let config = Config::from_toml("[rendering]\npresent_mode = \"immediate\"").unwrap();
+    assert_eq!(
+        config.rendering.present_mode,
+        mandelbrot_compute::gpu_interface::PresentMode::Immediate
+    );
+}
+
+#[test]
+fn workgroup_size_parses_fixed_and_auto() {
+    let config = Config::from_toml("[compute]\nworkgroup_size = \"16x8\"").unwrap();
+    assert_eq!(
+        config.compute.workgroup_size,
+        WorkgroupSize::Fixed(UVec2::new(16, 8))
+    );
+    assert_eq!("auto".parse(), Ok(WorkgroupSize::Auto));
+    assert!("0x8".parse::<WorkgroupSize>().is_err());
+    let text = toml::to_string(&Config::default()).unwrap();
+    assert_eq!(Config::from_toml(&text).unwrap(), Config::default());
+}
+
+#[test]
+fn adapter_choice_parses_index_or_name() {
+    assert_eq!("1".parse(), Ok(AdapterChoice::Index(1)));
+    assert_eq!(
+        " GeForce ".parse(),
+        Ok(AdapterChoice::Name("GeForce".to_string()))
+    );
+    assert!("".parse::<AdapterChoice>().is_err());
+    let config = Config::from_toml("[rendering]\nadapter = \"nvidia\"").unwrap();
+    assert_eq!(
+        config.rendering.adapter,
+        Some(AdapterChoice::Name("nvidia".to_string()))
+    );
+}
+
+#[test]
+fn default_keymap_zooms_with_both_plus_and_minus_keys() {
+    let keymap = Keymap::default();
+    for key in [VirtualKeyCode::NumpadAdd, VirtualKeyCode::Equals] {
+        assert_eq!(keymap.action(key), Some(Action::ZoomIn));
+    }
+    for key in [VirtualKeyCode::NumpadSubtract, VirtualKeyCode::Minus] {
+        assert_eq!(keymap.action(key), Some(Action::ZoomOut));
+    }
+}
+
+#[test]
+fn keymap_applies_config_overrides() {
+    let config =
+        Config::from_toml("[keys]\nw = \"pan_up\"\nNumpadAdd = \"none\"\nF5 = \"zoom_in\"")
+            .unwrap();
+    let (keymap, warnings) = Keymap::with_overrides(&config.keys);
+    assert!(warnings.is_empty(), "{:?}", warnings);
+    assert_eq!(keymap.action(VirtualKeyCode::W), Some(Action::PanUp));
+    assert_eq!(keymap.action(VirtualKeyCode::NumpadAdd), None);
+    assert_eq!(keymap.action(VirtualKeyCode::F5), Some(Action::ZoomIn));
+    // Untouched keys keep their defaults.
+    assert_eq!(keymap.action(VirtualKeyCode::Up), Some(Action::PanUp));
+}
+
+#[test]
+fn keymap_warns_about_unknown_keys_and_actions() {
+    let config = Config::from_toml("[keys]\nHyper = \"zoom_in\"\nQ = \"levitate\"").unwrap();
+    let (keymap, warnings) = Keymap::with_overrides(&config.keys);
+    assert_eq!(warnings.len(), 2, "{:?}", warnings);
+    assert_eq!(keymap, Keymap::default());
+}
+
+#[test]
+fn action_names_parse_back() {
+    for name in ["pan_left", "zoom_out", "reset_view", "print_keys"] {
+        assert_eq!(name.parse::<Action>().unwrap().name(), name);
+    }
+    assert!("zoom".parse::<Action>().is_err());
+    assert_eq!(parse_key(" numpadadd "), Some(VirtualKeyCode::NumpadAdd));
+    assert_eq!(parse_key("Key1"), Some(VirtualKeyCode::Key1));
+    assert_eq!(parse_key("Hyper"), None);
+}
+
+#[test]
+fn format_fixed_keeps_double_double_digits() {
+    assert_eq!(format_fixed((-0.75, 0.0), 3), "-0.750");
+    assert_eq!(format_fixed((1.0e-9, 0.0), 10), "0.0000000010");
+    assert_eq!(format_fixed((-1.0e-9, 0.0), 3), "0.000");
+    assert_eq!(format_fixed((2.5, 0.0), 0), "3");
+    // 1 + 2^-60 needs more than the 53 bits of one f64.
+    assert_eq!(
+        format_fixed((1.0, 2f64.powi(-60)), 20),
+        "1.00000000000000000087"
+    );
+}
+
+#[test]
+fn location_text_round_trips() {
+    let state = ViewState {
+        view: SampleLocation::new(DVec2::new(-0.7436438870371587, 0.1318259042053968), 3.5e-7),
+        max_iterations: 4000,
+        auto_iterations: false,
+        ..Default::default()
+    };
+    let text = state.location_text();
+    assert!(
+        text.starts_with("re=-0.7436438870372 im=0.1318259042054 "),
+        "{}",
+        text
+    );
+    assert!(text.ends_with(" zoom=3.5e-7 iters=4000"), "{}", text);
+    let parsed = ViewState::default().with_location_text(&text).unwrap();
+    let ((a, _), (b, _)) = (parsed.view.center(), state.view.center());
+    // Within a thousandth of a pixel of a 1000 pixel window.
+    assert!((a.x - b.x).abs() < 1e-12 && (a.y - b.y).abs() < 1e-12);
+    assert_eq!(parsed.view.zoom(), 3.5e-7);
+    assert_eq!(parsed.max_iterations, 4000);
+    assert!(!parsed.auto_iterations);
+}
+
+#[test]
+fn deep_location_text_keeps_the_double_double_center() {
+    let center = (
+        DVec2::new(-1.7400623825793398, 0.028175339779211),
+        DVec2::new(1.3e-17, -4.1e-18),
+    );
+    let state = ViewState {
+        view: SampleLocation::from_center(center, 1e-22),
+        ..Default::default()
+    };
+    assert!(state.auto_iterations);
+    let text = state.location_text();
+    assert!(!text.contains("iters"), "{}", text);
+    let parsed = ViewState::default().with_location_text(&text).unwrap();
+    let (hi, lo) = parsed.view.center();
+    // A pixel is about 1e-25 here; the f64 part alone would be off by 1e-17.
+    assert!(((hi.x - center.0.x) + (lo.x - center.1.x)).abs() < 1e-27);
+    assert!(((hi.y - center.0.y) + (lo.y - center.1.y)).abs() < 1e-27);
+    assert!(parsed.auto_iterations);
+}
+
+#[test]
+fn location_text_tolerates_spacing_and_missing_fields() {
+    let start = ViewState::default();
+    let parsed = start
+        .with_location_text("  zoom = 0.01,\n re=-1.25 ")
+        .unwrap();
+    let (center, _) = parsed.view.center();
+    assert_eq!((center.x, center.y), (-1.25, 0.0));
+    assert_eq!(parsed.view.zoom(), 0.01);
+    assert_eq!(parsed.max_iterations, start.max_iterations);
+    assert!(parsed.auto_iterations);
+}
+
+#[test]
+fn location_text_rejects_malformed_input() {
+    let start = ViewState::default();
+    for text in [
+        "",
+        "   ",
+        "re",
+        "re=abc",
+        "zoom=0",
+        "zoom=-1",
+        "iters=0",
+        "iters=1.5",
+        "re=0.1 depth=3",
+    ] {
+        assert!(
+            start.with_location_text(text).is_err(),
+            "accepted '{}'",
+            text
+        );
+    }
+}
+
+#[test]
+fn startup_location_overrides_the_other_options() {
+    let startup = StartupOptions {
+        zoom: Some(0.5),
+        location: Some("re=-0.75 im=0.1 iters=900".to_string()),
+        ..Default::default()
+    };
+    let state = startup.view_state();
+    let (center, _) = state.view.center();
+    assert_eq!((center.x, center.y), (-0.75, 0.1));
+    assert_eq!(state.view.zoom(), 0.5);
+    assert_eq!(state.max_iterations, 900);
+    assert!(!state.auto_iterations);
+
+    // A location that doesn't parse leaves the rest of the options in place.
+    let startup = StartupOptions {
+        zoom: Some(0.5),
+        location: Some("re=oops".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(startup.view_state().view.zoom(), 0.5);
+}
+
+#[test]
+fn formula_parses_with_precedence() {
+    let formula: CustomFormula = "z = -z^2 + 2c sin(z)".parse().unwrap();
+    let z_squared = Expr::Binary(
+        BinaryOp::Pow,
+        Box::new(Expr::Z),
+        Box::new(Expr::Constant(2.0, 0.0)),
+    );
+    let product = Expr::Binary(
+        BinaryOp::Mul,
+        Box::new(Expr::Binary(
+            BinaryOp::Mul,
+            Box::new(Expr::Constant(2.0, 0.0)),
+            Box::new(Expr::C),
+        )),
+        Box::new(Expr::Call(Function::Sin, Box::new(Expr::Z))),
+    );
+    assert_eq!(
+        formula.expr(),
+        &Expr::Binary(
+            BinaryOp::Add,
+            Box::new(Expr::Neg(Box::new(z_squared))),
+            Box::new(product)
+        )
+    );
+    assert_eq!(formula.to_string(), "-z^2 + 2c sin(z)");
+    assert_eq!(
+        "z^-1".parse::<CustomFormula>().unwrap().expr(),
+        &Expr::Binary(
+            BinaryOp::Pow,
+            Box::new(Expr::Z),
+            Box::new(Expr::Constant(-1.0, -0.0))
+        )
+    );
+}
+
+#[test]
+fn formula_errors_point_at_the_problem() {
+    let error = |text: &str| text.parse::<CustomFormula>().unwrap_err().position;
+    assert_eq!(error("z*z + foo(c)"), 6);
+    assert_eq!(error("z*(z + c"), 8);
+    assert_eq!(error("z + $"), 4);
+    assert_eq!(error("c = z"), 0);
+    assert_eq!(error("sin z"), 4);
+}
+
+#[test]
+fn formula_escape_power_follows_the_degree() {
+    let power = |text: &str| text.parse::<CustomFormula>().unwrap().escape_power();
+    assert_eq!(power("z*z*z + c*sin(z)"), 2.0);
+    assert_eq!(power("z^3 + c"), 3.0);
+    assert_eq!(power("z^4/(z + c) + c"), 3.0);
+    assert_eq!(power("exp(z) + c"), 2.0);
+}
+
+#[test]
+fn formula_splices_into_the_f32_shader() {
+    let template = include_str!("../src/shaders/mandelbrot.wgsl");
+    let formula: CustomFormula = "z*z*z + c*sin(z)".parse().unwrap();
+    let source = formula.splice(template).unwrap();
+    assert!(source.contains(&format!("return {};", formula.to_wgsl())));
+    assert!(formula.splice("fn main() {}").is_err());
+}
+
+/// A path in the temp directory unique to this test process.
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("mandelbrot_{}_{}", std::process::id(), name))
+}
+
+fn max_difference(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (0..3).map(|i| (a[i] - b[i]).abs()).fold(0.0, f32::max)
+}
+
+#[test]
+fn kmeans_finds_two_clusters() {
+    let (a, b) = ([0.2, 0.1, -0.1], [0.8, -0.05, 0.2]);
+    let points: Vec<[f32; 3]> = (0..200)
+        .map(|i| {
+            let jitter = (i % 5) as f32 * 0.002 - 0.004;
+            let base = if i % 3 == 0 { a } else { b };
+            [base[0] + jitter, base[1], base[2] - jitter]
+        })
+        .collect();
+    let mut centers = kmeans(&points, 2, 32, 7);
+    assert_eq!(centers, kmeans(&points, 2, 32, 7));
+    centers.sort_by(|x, y| x[0].total_cmp(&y[0]));
+    assert_eq!(centers.len(), 2);
+    for (center, expected) in centers.iter().zip([a, b]) {
+        assert!(max_difference(*center, expected) < 1e-3, "{:?}", centers);
+    }
+    // Asking for more clusters than there are distinct colors drops the empty ones.
+    assert_eq!(kmeans(&[a, a, b], 4, 32, 7).len(), 2);
+}
+
+#[test]
+fn palette_from_two_color_image_has_those_colors() {
+    let (dark, light) = ([20u8, 60, 180], [230u8, 200, 40]);
+    let image =
+        image::RgbImage::from_fn(32, 16, |x, _| image::Rgb(if x < 8 { dark } else { light }));
+    let path = temp_path("two_colors.png");
+    image.save(&path).unwrap();
+    let palette = palette_from_image(&path, 4);
+    std::fs::remove_file(&path).unwrap();
+    let palette = palette.unwrap();
+    assert_eq!(palette.stops.len(), 2);
+    assert!(srgb_to_oklab(dark)[0] < srgb_to_oklab(light)[0]);
+    for (stop, expected) in palette.stops.iter().zip([dark, light]) {
+        let color = stop.color.map(|c| c * 255.0);
+        assert!(
+            max_difference(color, expected.map(f32::from)) < 0.5,
+            "{:?}",
+            palette.stops
+        );
+    }
+    assert_eq!(
+        (palette.stops[0].position, palette.stops[1].position),
+        (0.0, 1.0)
+    );
+}
+
+#[test]
+fn palette_extensions_match_in_any_case() {
+    let dir = temp_path("palettes");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("Fire.MAP");
+    std::fs::write(&path, "0 0 0\n255 128 0\n").unwrap();
+    let palette = Palette::load_file(&path).unwrap();
+    assert_eq!(palette.stops.len(), 2);
+    assert_eq!(newest_palette_file(&dir), Some(path));
+    assert!(Palette::load_file(&dir.join("Fire.txt")).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn black_to_white() -> Palette {
+    Palette::from_rgb8("Gray", &[(0.0, [0, 0, 0]), (1.0, [255, 255, 255])])
+}
+
+#[test]
+fn added_stops_keep_the_gradient_and_order() {
+    let mut palette = black_to_white();
+    assert_eq!(palette.add_stop(0.25), 1);
+    assert_eq!(palette.add_stop(0.75), 2);
+    assert_eq!(palette.add_stop(1.5), 4);
+    assert_eq!(palette.stops[1].color, [0.25; 3]);
+    assert_eq!(palette.stops[4].position, 1.0);
+    assert_eq!(palette.nearest_stop(0.7), Some(2));
+    assert_eq!(palette.sample(0.5), black_to_white().sample(0.5));
+}
+
+#[test]
+fn remove_stop_keeps_at_least_two() {
+    let mut palette = black_to_white();
+    palette.add_stop(0.5);
+    assert!(!palette.remove_stop(3));
+    assert!(palette.remove_stop(1));
+    assert_eq!(palette, black_to_white());
+    assert!(!palette.remove_stop(0));
+    assert_eq!(palette.stops.len(), 2);
+}
+
+#[test]
+fn nudge_hsl_wraps_hue_and_clamps_lightness() {
+    let mut palette = Palette::from_rgb8("Red", &[(0.0, [255, 0, 0]), (1.0, [0, 0, 255])]);
+    palette.nudge_hsl(0, -1.0 / 3.0, 0.0, 0.0);
+    assert!(max_difference(palette.stops[0].color, [0.0, 0.0, 1.0]) < 1e-5);
+    palette.nudge_hsl(1, 0.0, 0.0, 2.0);
+    assert_eq!(palette.stops[1].color, [1.0; 3]);
+    palette.nudge_hsl(5, 0.5, 0.0, 0.0);
+}
+
+#[test]
+fn palette_json_round_trips_and_rejects_bad_files() {
+    let path = temp_path("palette.json");
+    let mut palette = black_to_white();
+    palette.add_stop(0.4);
+    palette.save_json(&path).unwrap();
+    let loaded = Palette::load_json(&path);
+
+    let unsorted = r#"{"name": "Flip", "stops": [
+        {"position": 1.0, "color": [1.0, 1.0, 1.0]},
+        {"position": 0.0, "color": [0.0, 0.0, 0.0]}]}"#;
+    std::fs::write(&path, unsorted).unwrap();
+    let sorted = Palette::load_json(&path);
+    let mut rejected = Vec::new();
+    for text in [
+        "",
+        r#"{"name": "Empty", "stops": []}"#,
+        r#"{"name": "Short", "stops": [{"position": 0.5}]}"#,
+        r#"{"stops": ["#,
+    ] {
+        std::fs::write(&path, text).unwrap();
+        rejected.push(Palette::load_json(&path).is_err());
+    }
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.unwrap(), palette);
+    assert_eq!(sorted.unwrap().stops[0].position, 0.0);
+    assert_eq!(rejected, [true; 4]);
+}